@@ -9,7 +9,7 @@ use phylo::prelude::*;
 use phylo::tree::PhyloTree;
 use rand::rngs::StdRng;
 use rand::seq::IteratorRandom;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 
 /// Taxa counts for benchmarks that scale near-linearly.
 ///
@@ -24,6 +24,13 @@ pub const TAXA: &[usize] = &[1_000, 4_000, 16_000];
 /// smaller sweep to stay runnable.
 pub const QUADRATIC_TAXA: &[usize] = &[250, 500, 1_000];
 
+/// Numbers of trees for the all-pairs matrix benchmarks, which are quadratic
+/// in the number of trees rather than taxa.
+pub const TREE_SET_SIZES: &[usize] = &[100, 400, 1_600];
+
+/// Taxa per tree in the all-pairs matrix benchmarks.
+pub const TREE_SET_TAXA: usize = 250;
+
 /// Normalisation exponent used by the cophenetic distance benchmarks.
 pub const NORM: u32 = 1;
 
@@ -39,11 +46,33 @@ pub fn yule(taxa: usize) -> PhyloTree {
     PhyloTree::yule(taxa)
 }
 
+/// `count` independent Yule trees with `taxa` leaves each, encoded into one
+/// [`TreeSet`].
+pub fn yule_set(count: usize, taxa: usize) -> TreeSet {
+    TreeSet::from_trees(&(0..count).map(|_| PhyloTree::yule(taxa)).collect_vec())
+        .expect("Yule trees of one size share their taxa")
+}
+
 /// Two independent Yule trees of the same size, for the comparison metrics.
 pub fn yule_pair(taxa: usize) -> (PhyloTree, PhyloTree) {
     (PhyloTree::yule(taxa), PhyloTree::yule(taxa))
 }
 
+/// Two Yule trees of the same size with random edge lengths in `[0.1, 2)`,
+/// for the metrics that read branch lengths.
+pub fn weighted_yule_pair(taxa: usize) -> (PhyloTree, PhyloTree) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let (mut t1, mut t2) = yule_pair(taxa);
+    for tree in [&mut t1, &mut t2] {
+        for id in tree.get_node_ids().collect_vec() {
+            if let Some(parent) = tree.get_node_parent_id(id) {
+                tree.set_edge_weight((parent, id), Some(rng.gen_range(0.1..2.0)));
+            }
+        }
+    }
+    (t1, t2)
+}
+
 /// Two Yule trees with node depths written into zeta.
 ///
 /// Cophenetic distance reads zeta, so it must be populated before timing.
@@ -58,6 +87,27 @@ pub fn yule_pair_with_zeta(taxa: usize) -> (PhyloTree, PhyloTree) {
     (t1, t2)
 }
 
+/// Number of SPR moves separating the trees of an [`spr_pair`].
+pub const SPR_MOVES: usize = 8;
+
+/// A Yule tree and a copy of it moved `moves` random leaves away, for the
+/// agreement-forest distances, which are exponential in the distance.
+pub fn spr_pair(taxa: usize, moves: usize) -> (PhyloTree, PhyloTree) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let t1 = PhyloTree::yule(taxa);
+    let mut t2 = t1.clone();
+    for _ in 0..moves {
+        let leaves = t2.get_leaf_ids().choose_multiple(&mut rng, 2);
+        let [from, to] =
+            [leaves[0], leaves[1]].map(|leaf| (t2.get_node_parent_id(leaf).unwrap(), leaf));
+        t2.spr(from, to).unwrap();
+        // Rebuild to drop the unary and detached nodes the move leaves.
+        let newick = t2.to_newick().to_string();
+        t2 = PhyloTree::from_newick(newick.as_bytes()).unwrap();
+    }
+    (t1, t2)
+}
+
 /// The `(parent, leaf)` edges of the first two leaves — a pair of SPR endpoints.
 pub fn first_two_leaf_edges(tree: &PhyloTree) -> ((usize, usize), (usize, usize)) {
     let edges = tree
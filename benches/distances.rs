@@ -12,7 +12,10 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Through
 use phylo::prelude::*;
 
 mod common;
-use common::{yule_pair, yule_pair_with_zeta, NORM, QUADRATIC_TAXA};
+use common::{
+    spr_pair, weighted_yule_pair, yule_pair, yule_pair_with_zeta, yule_set, NORM, QUADRATIC_TAXA,
+    SPR_MOVES, TREE_SET_SIZES, TREE_SET_TAXA,
+};
 
 /// Robinson-Foulds distance.
 fn robinson_foulds(c: &mut Criterion) {
@@ -53,6 +56,45 @@ fn cluster_affinity(c: &mut Criterion) {
     group.finish();
 }
 
+/// Quartet distance, over every pair of internal nodes.
+fn quartet_distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quartet_distance");
+    for &taxa in QUADRATIC_TAXA {
+        let (t1, t2) = yule_pair(taxa);
+        group.throughput(Throughput::Elements(taxa as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(taxa), &taxa, |b, _| {
+            b.iter(|| black_box(t1.quartet_distance(&t2)))
+        });
+    }
+    group.finish();
+}
+
+/// BHV geodesic distance, edges read into tree space inside the timed closure.
+fn bhv_distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bhv_distance");
+    for &taxa in QUADRATIC_TAXA {
+        let (t1, t2) = weighted_yule_pair(taxa);
+        group.throughput(Throughput::Elements(taxa as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(taxa), &taxa, |b, _| {
+            b.iter(|| black_box(t1.bhv_dist(&t2)))
+        });
+    }
+    group.finish();
+}
+
+/// Exact rooted SPR distance between trees `SPR_MOVES` leaf moves apart.
+fn spr_distance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spr_distance");
+    for &taxa in QUADRATIC_TAXA {
+        let (t1, t2) = spr_pair(taxa, SPR_MOVES);
+        group.throughput(Throughput::Elements(taxa as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(taxa), &taxa, |b, _| {
+            b.iter(|| black_box(t1.spr_forest(&t2).unwrap()))
+        });
+    }
+    group.finish();
+}
+
 /// Cophenetic distance, serial.
 ///
 /// Zeta is populated in setup, outside the timed closure — the metric reads it
@@ -89,6 +131,38 @@ fn cophenetic_distance_parallel(c: &mut Criterion) {
 #[cfg(not(feature = "parallel"))]
 fn cophenetic_distance_parallel(_: &mut Criterion) {}
 
+/// Leaf-to-leaf patristic distance matrix, through one path oracle.
+///
+/// The oracle build is inside the timed closure: it is O(n) against the O(n²)
+/// fill, and callers pay it once per matrix.
+fn leaf_distance_matrix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("leaf_distance_matrix");
+    for &taxa in QUADRATIC_TAXA {
+        let (t1, _) = yule_pair(taxa);
+        group.throughput(Throughput::Elements(taxa as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(taxa), &taxa, |b, _| {
+            b.iter(|| black_box(t1.path_oracle().leaf_distance_matrix()))
+        });
+    }
+    group.finish();
+}
+
+/// Robinson-Foulds matrix over a collection of trees.
+///
+/// Encoding is setup, outside the timed closure; throughput is per tree, so it
+/// falls linearly as each row grows with the collection.
+fn tree_set_rf_matrix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tree_set_rf_matrix");
+    for &trees in TREE_SET_SIZES {
+        let set = yule_set(trees, TREE_SET_TAXA);
+        group.throughput(Throughput::Elements(trees as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(trees), &trees, |b, _| {
+            b.iter(|| black_box(set.matrix(SetMetric::RobinsonFoulds)))
+        });
+    }
+    group.finish();
+}
+
 /// Quadratic metrics need fewer, longer samples than criterion's defaults.
 ///
 /// `cophenetic_distance` at 1000 taxa runs for seconds per iteration, so 10 —
@@ -108,7 +182,12 @@ criterion_group! {
     targets = robinson_foulds,
               cluster_matching,
               cluster_affinity,
+              quartet_distance,
+              bhv_distance,
+              spr_distance,
               cophenetic_distance,
-              cophenetic_distance_parallel
+              cophenetic_distance_parallel,
+              leaf_distance_matrix,
+              tree_set_rf_matrix
 }
 criterion_main!(distances);
@@ -1,77 +1,69 @@
-#[cfg(feature = "parallel")]
-use rayon::prelude::*;
-
-use indicatif::{ProgressBar, ProgressStyle};
-use itertools::Itertools;
 use phylo::prelude::*;
-use std::fs::{read_to_string, File};
-use std::io::Write;
-#[cfg(feature = "parallel")]
-use std::sync::Mutex;
+use std::fs::{read_to_string, write, File};
+use std::io::BufWriter;
 
-#[cfg(feature = "parallel")]
 fn main() {
     let trees = read_to_string("examples/pairwise-distances/sample-trees.trees")
         .unwrap()
         .lines()
-        .enumerate()
-        .map(|(y, z)| (y, PhyloTree::from_newick(z.as_bytes()).unwrap()))
-        .collect_vec();
+        .map(|line| PhyloTree::from_newick(line.as_bytes()).unwrap())
+        .collect::<Vec<_>>();
 
-    let output_file = File::create("examples/tree-space.out").unwrap();
+    // Every tree is encoded once; rows of the matrix are then streamed to disk
+    // without holding the whole matrix.
+    let set = TreeSet::from_trees(&trees).unwrap();
+    println!(
+        "{} trees on {} taxa, {} distinct splits",
+        set.len(),
+        set.taxa().len(),
+        set.num_splits()
+    );
 
-    let file = Mutex::new(output_file);
+    let output_file = BufWriter::new(File::create("examples/tree-space.phy").unwrap());
+    write_matrix(&set, output_file);
 
-    let bar = ProgressBar::new((trees.len() * (trees.len() - 1) / 2) as u64);
-    bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg} [eta: {eta}]",
-        )
-        .unwrap()
-        .progress_chars("##-"),
+    // Classical MDS coordinates for plotting, and clusters of trees with the
+    // number of clusters chosen by silhouette width.
+    let matrix = set.labelled_matrix(SetMetric::RobinsonFoulds);
+    let embedding = Embedding::classical_mds(&matrix, 2);
+    println!(
+        "2-D embedding keeps {:.1}% of the variance",
+        100.0 * embedding.explained()
     );
+    write("examples/tree-space.tsv", embedding.to_tsv()).unwrap();
 
-    trees
-        .iter()
-        .combinations(2)
-        .par_bridge()
-        .map(|v| (v[0], v[1]))
-        .for_each(|(x, y)| {
-            let out = format!("{}-{}-{}\n", x.0, y.0, x.1.ca(&y.1));
-            file.lock().unwrap().write_all(out.as_bytes()).unwrap();
-            bar.inc(1);
-        });
-    bar.finish();
+    let clustering = (2..=5.min(set.len() - 1))
+        .map(|k| Clustering::k_medoids(&matrix, k).unwrap())
+        .max_by(|a, b| a.silhouette(&matrix).total_cmp(&b.silhouette(&matrix)))
+        .unwrap();
+    for summary in set.summarize(&clustering, 0.5) {
+        println!(
+            "{} trees around {}, consensus {}",
+            summary.members.len(),
+            set.labels()[summary.medoid],
+            summary.consensus.tree.to_newick()
+        );
+    }
 }
 
-#[cfg(not(feature = "parallel"))]
-fn main() {
-    let trees = read_to_string(format!("examples/pairwise-distances/sample-trees.trees"))
-        .unwrap()
-        .lines()
-        .enumerate()
-        .map(|(y, z)| (y, PhyloTree::from_newick(z.as_bytes()).unwrap()))
-        .collect_vec();
-
-    let mut output_file = File::create("examples/tree-space.out").unwrap();
-
-    let bar = ProgressBar::new((trees.len() * (trees.len() - 1) / 2) as u64);
-    bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg} [eta: {eta}]",
-        )
-        .unwrap()
-        .progress_chars("##-"),
-    );
+/// Writes the tree-by-tree Robinson-Foulds matrix, labelled by line number in
+/// the input file, as a lower-triangular PHYLIP matrix.
+#[cfg(feature = "parallel")]
+fn write_matrix(set: &TreeSet, output_file: BufWriter<File>) {
+    set.write_phylip_par(
+        SetMetric::RobinsonFoulds,
+        PhylipLayout::LowerTriangular,
+        output_file,
+    )
+    .unwrap();
+}
 
-    trees
-        .iter()
-        .combinations(2)
-        .map(|v| (v[0], v[1]))
-        .for_each(|(x, y)| {
-            let out = format!("{}-{}-{}\n", x.0, y.0, x.1.ca(&y.1));
-            output_file.write_all(out.as_bytes()).unwrap();
-            bar.inc(1);
-        });
-    bar.finish();
+#[cfg(not(feature = "parallel"))]
+fn write_matrix(set: &TreeSet, output_file: BufWriter<File>) {
+    set.write_phylip(
+        SetMetric::RobinsonFoulds,
+        PhylipLayout::LowerTriangular,
+        output_file,
+    )
+    .unwrap();
 }
@@ -45,26 +45,18 @@ fn main() {
         let mut pds = vec![];
         for year in 2015..2023 {
             let tree = trees.get(&year.to_string());
-            match tree {
+            let pd = match tree {
                 Some(t) => {
-                    println!(
-                        "{}: {}",
-                        year,
-                        t.get_nodes()
-                            .map(|n| n.get_weight().unwrap_or(0.0))
-                            .sum::<f32>()
+                    let community = Community::from_taxa(
+                        t.get_leaf_ids()
+                            .filter_map(|leaf| t.get_node_taxa_cloned(leaf)),
                     );
-                    pds.push(
-                        t.get_nodes()
-                            .map(|n| n.get_weight().unwrap_or(0.0))
-                            .sum::<f32>(),
-                    );
-                }
-                _ => {
-                    println!("{}: {}", year, 0.0);
-                    pds.push(0.0);
+                    t.faith_pd(&community, RootRule::Include).unwrap()
                 }
+                _ => 0.0,
             };
+            println!("{}: {}", year, pd);
+            pds.push(pd);
         }
         let out = format!(
             "{}: {}\n",
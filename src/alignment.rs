@@ -1,85 +1,86 @@
-use crate::error::AsrError;
+use crate::error::{AlignmentError, AsrError};
 use std::collections::HashMap;
+use std::ops::Range;
+
+/// Alignment file formats: FASTA, PHYLIP, Nexus, Stockholm and Clustal.
+pub mod formats;
+/// Alignment trimming, masking, subsetting and concatenation, with per-taxon
+/// and composition summaries.
+pub mod ops;
 
 /// A multiple sequence alignment.
+///
+/// Rows keep the order they were read or built in, so row `i` is the same
+/// taxon on every run. Each row has a name, an optional description (the rest
+/// of a FASTA header, say) and a weight, 1 unless set. Residues are stored in
+/// one row-major buffer, so a row is a slice and a column a strided walk.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Alignment {
-    /// Map from taxon name to sequence bytes.
-    pub seqs: HashMap<String, Vec<u8>>,
-    /// Width of the alignment in sites.
-    pub width: usize,
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    descriptions: Vec<Option<String>>,
+    weights: Vec<f64>,
+    /// Row-major residues, `width` per row.
+    residues: Vec<u8>,
+    width: usize,
 }
 
 impl Alignment {
-    /// Parses a FASTA formatted byte slice.
-    pub fn from_fasta_bytes(data: &[u8]) -> Result<Self, AsrError> {
-        let mut seqs = HashMap::new();
-        let mut current_id = String::new();
-        let mut current_seq = Vec::new();
-
-        let lines = data.split(|&b| b == b'\n');
-        for line in lines {
-            let line = line.trim_ascii();
-            if line.is_empty() {
-                continue;
-            }
-
-            if line[0] == b'>' {
-                if !current_id.is_empty() {
-                    if seqs.contains_key(&current_id) {
-                        return Err(AsrError::InvalidAlignment(format!(
-                            "Duplicate taxon ID: {}",
-                            current_id
-                        )));
-                    }
-                    seqs.insert(current_id.clone(), current_seq);
-                }
-                // Header is everything from '>' to first whitespace
-                let header = &line[1..];
-                let end = header
-                    .iter()
-                    .position(|&b| b.is_ascii_whitespace())
-                    .unwrap_or(header.len());
-                let raw_name = String::from_utf8_lossy(&header[..end]);
-                if raw_name.is_empty() {
-                    return Err(AsrError::InvalidAlignment(
-                        "Empty taxon ID in FASTA header".to_string(),
-                    ));
-                }
-                current_id = raw_name.into_owned();
-                current_seq = Vec::new();
-            } else {
-                // Normalize to uppercase
-                let normalized: Vec<u8> = line.iter().map(|&b| b.to_ascii_uppercase()).collect();
-                current_seq.extend(normalized);
-            }
+    /// Builds an alignment from names and rows, one row per name. Every row
+    /// must be as long as the first.
+    pub fn new(names: Vec<String>, rows: Vec<Vec<u8>>) -> Result<Self, AlignmentError> {
+        if rows.len() != names.len() {
+            return Err(AlignmentError::TaxonCount {
+                expected: names.len(),
+                found: rows.len(),
+            });
         }
-
-        if !current_id.is_empty() {
-            if seqs.contains_key(&current_id) {
-                return Err(AsrError::InvalidAlignment(format!(
-                    "Duplicate taxon ID: {}",
-                    current_id
-                )));
+        let width = rows.first().map_or(0, Vec::len);
+        let mut residues = Vec::with_capacity(width * rows.len());
+        for (row, seq) in rows.into_iter().enumerate() {
+            if seq.len() != width {
+                return Err(AlignmentError::RowLength {
+                    row,
+                    expected: width,
+                    found: seq.len(),
+                });
             }
-            seqs.insert(current_id, current_seq);
+            residues.extend(seq);
         }
-
-        if seqs.is_empty() {
-            return Err(AsrError::InvalidAlignment("Empty FASTA file".to_string()));
+        let mut index = HashMap::with_capacity(names.len());
+        for (i, name) in names.iter().enumerate() {
+            if index.insert(name.clone(), i).is_some() {
+                return Err(AlignmentError::DuplicateName(name.clone()));
+            }
         }
+        Ok(Self::from_parts(names, index, residues, width))
+    }
 
-        let width = seqs
-            .values()
-            .next()
-            .expect("invariant: the empty case returned above")
-            .len();
-        if !seqs.values().all(|s| s.len() == width) {
-            return Err(AsrError::InvalidAlignment(
-                "Ragged alignment: sequences have different lengths".to_string(),
-            ));
+    /// Assembles an alignment whose names are distinct and indexed, with no
+    /// descriptions and unit weights.
+    pub(crate) fn from_parts(
+        names: Vec<String>,
+        index: HashMap<String, usize>,
+        residues: Vec<u8>,
+        width: usize,
+    ) -> Self {
+        let n = names.len();
+        Alignment {
+            names,
+            index,
+            descriptions: vec![None; n],
+            weights: vec![1.0; n],
+            residues,
+            width,
         }
+    }
 
-        Ok(Self { seqs, width })
+    /// Parses a FASTA formatted byte slice.
+    ///
+    /// See [`Alignment::from_fasta`] for the positions of errors.
+    pub fn from_fasta_bytes(data: &[u8]) -> Result<Self, AsrError> {
+        Self::from_fasta(&String::from_utf8_lossy(data))
+            .map_err(|e| AsrError::InvalidAlignment(e.to_string()))
     }
 
     /// Helper for reading FASTA from a file (std only).
@@ -89,21 +90,181 @@ impl Alignment {
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
     }
 
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns true if the alignment has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Width of the alignment in sites.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Row names, in row order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Row index of `name`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    /// Residue at row `i`, site `j`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn get(&self, i: usize, j: usize) -> u8 {
+        assert!(i < self.len() && j < self.width, "index out of bounds");
+        self.residues[i * self.width + j]
+    }
+
+    /// Row of `name`.
+    pub fn row(&self, name: &str) -> Option<&[u8]> {
+        self.index_of(name).map(|i| self.row_at(i))
+    }
+
+    /// Row `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn row_at(&self, i: usize) -> &[u8] {
+        assert!(i < self.len(), "index out of bounds");
+        &self.residues[i * self.width..(i + 1) * self.width]
+    }
+
+    /// Iterator over rows, in row order.
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        (0..self.len()).map(|i| self.row_at(i))
+    }
+
+    /// Residues of site `j`, in row order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `j` is out of bounds.
+    pub fn column(&self, j: usize) -> impl ExactSizeIterator<Item = u8> + '_ {
+        assert!(j < self.width, "index out of bounds");
+        (0..self.len()).map(move |i| self.residues[i * self.width + j])
+    }
+
+    /// Description of row `i`, if it has one.
+    pub fn description(&self, i: usize) -> Option<&str> {
+        self.descriptions[i].as_deref()
+    }
+
+    /// Sets the description of row `i`.
+    pub fn set_description(&mut self, i: usize, description: Option<String>) {
+        self.descriptions[i] = description;
+    }
+
+    /// Weight of row `i`.
+    pub fn weight(&self, i: usize) -> f64 {
+        self.weights[i]
+    }
+
+    /// Row weights, in row order.
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Sets the weight of row `i`.
+    pub fn set_weight(&mut self, i: usize, weight: f64) {
+        self.weights[i] = weight;
+    }
+
+    /// The alignment of `rows`, in the order given, with their descriptions
+    /// and weights.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of bounds or repeated.
+    pub fn select_rows(&self, rows: &[usize]) -> Self {
+        let mut out = Alignment {
+            names: Vec::with_capacity(rows.len()),
+            index: HashMap::with_capacity(rows.len()),
+            descriptions: Vec::with_capacity(rows.len()),
+            weights: Vec::with_capacity(rows.len()),
+            residues: Vec::with_capacity(rows.len() * self.width),
+            width: self.width,
+        };
+        for (k, &i) in rows.iter().enumerate() {
+            let repeated = out.index.insert(self.names[i].clone(), k).is_some();
+            assert!(!repeated, "row {i} selected twice");
+            out.names.push(self.names[i].clone());
+            out.descriptions.push(self.descriptions[i].clone());
+            out.weights.push(self.weights[i]);
+            out.residues.extend_from_slice(self.row_at(i));
+        }
+        out
+    }
+
+    /// The alignment of sites `columns`, in the order given. Sites may repeat,
+    /// as in a bootstrap resample.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of bounds.
+    pub fn select_columns(&self, columns: &[usize]) -> Self {
+        assert!(
+            columns.iter().all(|&j| j < self.width),
+            "index out of bounds"
+        );
+        let residues = self
+            .rows()
+            .flat_map(|row| columns.iter().map(move |&j| row[j]))
+            .collect();
+        self.with_residues(residues, columns.len())
+    }
+
+    /// The alignment of the contiguous sites `columns`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice_columns(&self, columns: Range<usize>) -> Self {
+        assert!(
+            columns.start <= columns.end && columns.end <= self.width,
+            "index out of bounds"
+        );
+        let mut residues = Vec::with_capacity(self.len() * columns.len());
+        for row in self.rows() {
+            residues.extend_from_slice(&row[columns.clone()]);
+        }
+        self.with_residues(residues, columns.len())
+    }
+
+    /// The same rows over new residues.
+    fn with_residues(&self, residues: Vec<u8>, width: usize) -> Self {
+        Alignment {
+            names: self.names.clone(),
+            index: self.index.clone(),
+            descriptions: self.descriptions.clone(),
+            weights: self.weights.clone(),
+            residues,
+            width,
+        }
+    }
+
     /// Compresses the alignment into unique patterns with multiplicities.
+    ///
+    /// Patterns are numbered in the order of their first site and taxa are in
+    /// row order, so the result depends only on the alignment.
     pub fn compress_columns(&self) -> CompressedColumns {
         let mut pattern_to_idx = HashMap::new();
         let mut patterns = Vec::new();
         let mut multiplicity = Vec::new();
         let mut site_to_pattern = Vec::with_capacity(self.width);
 
-        // Order of leaves for the ASR process
-        let leaf_order: Vec<String> = self.seqs.keys().cloned().collect();
-
         for i in 0..self.width {
-            let mut col = Vec::with_capacity(leaf_order.len());
-            for name in &leaf_order {
-                col.push(self.seqs[name][i]);
-            }
+            let col: Vec<u8> = self.column(i).collect();
 
             let idx = *pattern_to_idx.entry(col.clone()).or_insert_with(|| {
                 let p_idx = patterns.len();
@@ -120,12 +281,13 @@ impl Alignment {
             patterns,
             site_to_pattern,
             multiplicity,
-            leaf_order,
+            leaf_order: self.names.clone(),
         }
     }
 }
 
 /// Compressed representation of an alignment for performance.
+#[derive(Debug, Clone)]
 pub struct CompressedColumns {
     /// The unique sequence patterns found in the alignment.
     pub patterns: Vec<Vec<u8>>,
@@ -145,17 +307,18 @@ mod tests {
     fn test_fasta_parse() {
         let data = b">Seq1\nACGT\n>Seq2\nAGGT\n";
         let aln = Alignment::from_fasta_bytes(data).unwrap();
-        assert_eq!(aln.width, 4);
-        assert_eq!(aln.seqs["Seq1"], b"ACGT");
-        assert_eq!(aln.seqs["Seq2"], b"AGGT");
+        assert_eq!(aln.width(), 4);
+        assert_eq!(aln.row("Seq1").unwrap(), b"ACGT");
+        assert_eq!(aln.row("Seq2").unwrap(), b"AGGT");
     }
 
     #[test]
     fn test_compression() {
-        let mut seqs = HashMap::new();
-        seqs.insert("S1".to_string(), b"AAT".to_vec());
-        seqs.insert("S2".to_string(), b"ATT".to_vec());
-        let aln = Alignment { seqs, width: 3 };
+        let aln = Alignment::new(
+            vec!["S1".to_string(), "S2".to_string()],
+            vec![b"AAT".to_vec(), b"ATT".to_vec()],
+        )
+        .unwrap();
 
         let comp = aln.compress_columns();
         // Col 0: A,A (P0)
@@ -164,4 +327,37 @@ mod tests {
         assert_eq!(comp.patterns.len(), 3);
         assert_eq!(comp.multiplicity, vec![1, 1, 1]);
     }
+
+    #[test]
+    fn rows_keep_their_order_and_metadata() {
+        let data = b">zeta first row\nACGTA\n>alpha\nAGGTA\n>mid  third\nACCTA\n";
+        let mut aln = Alignment::from_fasta_bytes(data).unwrap();
+        assert_eq!(aln.names(), ["zeta", "alpha", "mid"]);
+        assert_eq!(aln.description(0), Some("first row"));
+        assert_eq!(aln.description(1), None);
+        assert_eq!(aln.description(2), Some("third"));
+        aln.set_weight(2, 0.5);
+
+        // Compression follows row order and first occurrence, every time.
+        let comp = aln.compress_columns();
+        assert_eq!(comp.leaf_order, ["zeta", "alpha", "mid"]);
+        assert_eq!(comp.patterns[0], b"AAA");
+        assert_eq!(comp.site_to_pattern, vec![0, 1, 2, 3, 0]);
+        assert_eq!(comp.multiplicity, vec![2, 1, 1, 1]);
+
+        let sub = aln.select_rows(&[2, 0]).slice_columns(1..4);
+        assert_eq!(sub.names(), ["mid", "zeta"]);
+        assert_eq!(sub.rows().collect::<Vec<_>>(), [b"CCT", b"CGT"]);
+        assert_eq!(sub.weights(), [0.5, 1.0]);
+        assert_eq!(sub.description(0), Some("third"));
+        assert_eq!(sub.index_of("zeta"), Some(1));
+        let resampled = aln.select_columns(&[4, 4, 1]);
+        assert_eq!(resampled.column(2).collect::<Vec<_>>(), b"CGC");
+        assert_eq!(resampled.row_at(1), b"AAG");
+
+        assert_eq!(
+            Alignment::new(vec!["a".into(), "a".into()], vec![vec![], vec![]]),
+            Err(AlignmentError::DuplicateName("a".to_string()))
+        );
+    }
 }
@@ -0,0 +1,1253 @@
+//! Alignment file formats.
+//!
+//! [`Alignment`](crate::alignment::Alignment) reads and writes:
+//!
+//! * **FASTA**. The header text after the name is the row's description.
+//!   Sequences are written in lines of 60 residues.
+//! * **PHYLIP**, relaxed or strict
+//!   ([`PhylipFormat`](crate::alignment::formats::PhylipFormat)), sequential
+//!   or interleaved. Relaxed names end at the first whitespace; strict names
+//!   are the first ten characters of a row. The reader tells the two layouts
+//!   apart: rows are read sequentially if that accounts for every line, and
+//!   as interleaved blocks otherwise.
+//! * **Nexus** `DATA` and `CHARACTERS` blocks: `DIMENSIONS`, the `MISSING`,
+//!   `GAP`, `MATCHCHAR` and `INTERLEAVE` subcommands of `FORMAT`, and
+//!   `MATRIX`. Custom gap and missing symbols are read as `-` and `?`. The
+//!   `CHARSET`s of any block are read as
+//!   [`CharSet`](crate::alignment::formats::CharSet)s, and written in a `SETS`
+//!   block.
+//! * **Stockholm**. `#=GS <name> DE` lines are row descriptions; other
+//!   markup is skipped. Only the first alignment of a file is read, and `.`
+//!   gaps are read as `-`.
+//! * **Clustal**, whose conservation lines and residue counts are skipped.
+//!
+//! [`AlignmentFormat::detect`](crate::alignment::formats::AlignmentFormat::detect)
+//! tells the format from the first line, and
+//! [`Alignment::parse`](crate::alignment::Alignment::parse) reads whichever it
+//! finds. As in
+//! [`Alignment::from_fasta_bytes`](crate::alignment::Alignment::from_fasta_bytes),
+//! residues are read in upper case. Errors carry the line and column at which
+//! reading stopped.
+//!
+//! Rows are read and written in file order.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::alignment::Alignment;
+use crate::error::AlignmentError;
+
+/// Residues per line of FASTA output and per block of interleaved output.
+const LINE_WIDTH: usize = 60;
+
+/// The alignment file formats [`Alignment`] reads and writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlignmentFormat {
+    /// FASTA
+    Fasta,
+    /// Relaxed PHYLIP; see [`PhylipFormat`] for strict names and interleaving
+    Phylip,
+    /// Nexus `DATA` or `CHARACTERS` block
+    Nexus,
+    /// Stockholm
+    Stockholm,
+    /// Clustal
+    Clustal,
+}
+
+impl AlignmentFormat {
+    /// The format of `input`, told from its first non-blank line: `>` opens
+    /// FASTA, `#NEXUS` Nexus, `# STOCKHOLM` Stockholm, a `CLUSTAL` (or
+    /// `MUSCLE`, `PROBCONS`) header Clustal, and two counts PHYLIP.
+    pub fn detect(input: &str) -> Option<Self> {
+        let first = input.lines().map(str::trim).find(|l| !l.is_empty())?;
+        let upper = first.to_ascii_uppercase();
+        if first.starts_with('>') {
+            Some(AlignmentFormat::Fasta)
+        } else if upper.starts_with("#NEXUS") {
+            Some(AlignmentFormat::Nexus)
+        } else if upper.starts_with("# STOCKHOLM") {
+            Some(AlignmentFormat::Stockholm)
+        } else if ["CLUSTAL", "MUSCLE", "PROBCONS"]
+            .iter()
+            .any(|h| upper.starts_with(h))
+        {
+            Some(AlignmentFormat::Clustal)
+        } else {
+            let mut counts = first.split_whitespace().map(str::parse::<usize>);
+            match (counts.next(), counts.next()) {
+                (Some(Ok(_)), Some(Ok(_))) => Some(AlignmentFormat::Phylip),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// How PHYLIP names are laid out, and whether rows are interleaved.
+///
+/// The reader only uses [`PhylipFormat::strict`]; it detects interleaving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PhylipFormat {
+    strict: bool,
+    interleaved: bool,
+}
+
+impl PhylipFormat {
+    /// Relaxed names, sequential rows.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether names take exactly ten characters. Longer names are cut when
+    /// written.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Whether rows are written in blocks of 60 sites.
+    pub fn with_interleaved(mut self, interleaved: bool) -> Self {
+        self.interleaved = interleaved;
+        self
+    }
+
+    /// Whether names take exactly ten characters.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Whether rows are written in blocks.
+    pub fn interleaved(&self) -> bool {
+        self.interleaved
+    }
+}
+
+/// A named set of sites, as declared by a Nexus `CHARSET`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharSet {
+    /// Name of the set
+    pub name: String,
+    /// Zero-based sites, ascending
+    pub sites: Vec<usize>,
+}
+
+impl Alignment {
+    /// Reads an alignment in the format [`AlignmentFormat::detect`] finds.
+    pub fn parse(input: &str) -> Result<Self, AlignmentError> {
+        let format = AlignmentFormat::detect(input).ok_or(AlignmentError::UnknownFormat)?;
+        Self::parse_as(input, format)
+    }
+
+    /// Reads an alignment in `format`.
+    pub fn parse_as(input: &str, format: AlignmentFormat) -> Result<Self, AlignmentError> {
+        match format {
+            AlignmentFormat::Fasta => Self::from_fasta(input),
+            AlignmentFormat::Phylip => Self::from_phylip(input, PhylipFormat::new()),
+            AlignmentFormat::Nexus => Self::from_nexus(input),
+            AlignmentFormat::Stockholm => Self::from_stockholm(input),
+            AlignmentFormat::Clustal => Self::from_clustal(input),
+        }
+    }
+
+    /// Reads an alignment file, detecting its format.
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::parse(&String::from_utf8_lossy(&bytes))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the alignment in `format`.
+    pub fn to_format(&self, format: AlignmentFormat) -> String {
+        match format {
+            AlignmentFormat::Fasta => self.to_fasta(),
+            AlignmentFormat::Phylip => self.to_phylip(PhylipFormat::new()),
+            AlignmentFormat::Nexus => self.to_nexus(),
+            AlignmentFormat::Stockholm => self.to_stockholm(),
+            AlignmentFormat::Clustal => self.to_clustal(),
+        }
+    }
+
+    /// Writes the alignment to a file in `format`.
+    pub fn to_file(&self, path: &Path, format: AlignmentFormat) -> std::io::Result<()> {
+        std::fs::write(path, self.to_format(format))
+    }
+
+    /// Reads a FASTA alignment.
+    pub fn from_fasta(input: &str) -> Result<Self, AlignmentError> {
+        let mut rows: Vec<Row> = vec![];
+        for (line, text) in numbered_lines(input) {
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let lead = text.len() - text.trim_start().len();
+            match trimmed.strip_prefix('>') {
+                Some(header) => {
+                    let name = header.split_whitespace().next().unwrap_or_default();
+                    if name.is_empty() || header.starts_with(char::is_whitespace) {
+                        return Err(syntax(line, lead + 2, "empty taxon name"));
+                    }
+                    let mut row = Row::new(name, line);
+                    let description = header.trim()[name.len()..].trim();
+                    if !description.is_empty() {
+                        row.description = Some(description.to_string());
+                    }
+                    rows.push(row);
+                }
+                None => {
+                    let row = rows
+                        .last_mut()
+                        .ok_or_else(|| syntax(line, lead + 1, "sequence before any header"))?;
+                    push_residues(&mut row.seq, trimmed, line, lead + 1)?;
+                }
+            }
+        }
+        build(rows, None)
+    }
+
+    /// Writes the alignment as FASTA.
+    pub fn to_fasta(&self) -> String {
+        let mut out = String::new();
+        for (i, (name, seq)) in self.named_rows().into_iter().enumerate() {
+            out.push('>');
+            out.push_str(name);
+            if let Some(description) = self.description(i) {
+                out.push(' ');
+                out.push_str(description);
+            }
+            out.push('\n');
+            for chunk in seq.chunks(LINE_WIDTH) {
+                out.push_str(&String::from_utf8_lossy(chunk));
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Reads a PHYLIP alignment with names laid out as `format` says.
+    pub fn from_phylip(input: &str, format: PhylipFormat) -> Result<Self, AlignmentError> {
+        let mut lines = numbered_lines(input).filter(|(_, text)| !text.trim().is_empty());
+        let (header_line, header) = lines.next().ok_or(AlignmentError::Empty)?;
+        let mut counts = header.split_whitespace();
+        let mut count = |what: &str| {
+            let token = counts.next().unwrap_or_default();
+            token.parse::<usize>().map_err(|_| {
+                let column = header.find(token).unwrap_or(header.len()) + 1;
+                syntax(
+                    header_line,
+                    column,
+                    format!("expected the number of {what}"),
+                )
+            })
+        };
+        let ntax = count("taxa")?;
+        if ntax == 0 {
+            let column = header.find('0').unwrap_or(0) + 1;
+            return Err(syntax(header_line, column, "expected at least one taxon"));
+        }
+        let nchar = count("sites")?;
+        let body: Vec<_> = lines.collect();
+        if body.len() < ntax {
+            return Err(AlignmentError::TaxonCount {
+                expected: ntax,
+                found: body.len(),
+            });
+        }
+        let rows = match phylip_sequential(&body, ntax, nchar, format.strict) {
+            Ok(rows) => rows,
+            // Interleaved blocks take the same number of lines per taxon.
+            Err(e) if body.len() % ntax != 0 => return Err(e),
+            Err(_) => phylip_interleaved(&body, ntax, format.strict)?,
+        };
+        build(rows, Some(nchar))
+    }
+
+    /// Writes the alignment as PHYLIP, laid out as `format` says. Whitespace
+    /// inside a name is written as `_`.
+    pub fn to_phylip(&self, format: PhylipFormat) -> String {
+        let rows = self.named_rows();
+        let names: Vec<String> = rows
+            .iter()
+            .map(|(name, _)| {
+                let name = name.replace(char::is_whitespace, "_");
+                match format.strict {
+                    true => format!("{:<10}", name.chars().take(10).collect::<String>()),
+                    false => name,
+                }
+            })
+            .collect();
+        let pad = match format.strict {
+            true => 10,
+            false => names.iter().map(|n| n.len()).max().unwrap_or(0) + 1,
+        };
+        let block = match format.interleaved {
+            true => LINE_WIDTH,
+            false => self.width.max(1),
+        };
+        let mut out = format!("{} {}\n", rows.len(), self.width);
+        for (b, start) in (0..self.width.max(1)).step_by(block).enumerate() {
+            if b > 0 {
+                out.push('\n');
+            }
+            for (name, (_, seq)) in names.iter().zip(&rows) {
+                let end = (start + block).min(seq.len());
+                let chunk = String::from_utf8_lossy(&seq[start.min(end)..end]);
+                match b {
+                    0 => out.push_str(&format!("{name:<pad$}{chunk}\n")),
+                    _ => out.push_str(&format!("{:pad$}{chunk}\n", "")),
+                }
+            }
+        }
+        out
+    }
+
+    /// Reads the `DATA` or `CHARACTERS` block of a Nexus file.
+    pub fn from_nexus(input: &str) -> Result<Self, AlignmentError> {
+        Self::from_nexus_with_charsets(input).map(|(alignment, _)| alignment)
+    }
+
+    /// Reads the `DATA` or `CHARACTERS` block of a Nexus file, and the
+    /// `CHARSET`s of all its blocks.
+    pub fn from_nexus_with_charsets(input: &str) -> Result<(Self, Vec<CharSet>), AlignmentError> {
+        let tokens = tokenize(input)?;
+        match tokens.first() {
+            Some(t) if t.kind == TokenKind::Word && t.text.eq_ignore_ascii_case("#NEXUS") => {}
+            Some(t) => return Err(syntax(t.line, t.column, "expected #NEXUS")),
+            None => return Err(AlignmentError::Empty),
+        }
+        let mut block = String::new();
+        let mut taxa_ntax = None;
+        let mut data = NexusData::default();
+        let mut alignment = None;
+        let mut charsets = vec![];
+        for command in tokens[1..].split(|t| t.kind == TokenKind::Semicolon) {
+            let Some(keyword) = command.first() else {
+                continue;
+            };
+            let args = &command[1..];
+            match keyword.text.to_ascii_uppercase().as_str() {
+                "BEGIN" => {
+                    block = args
+                        .first()
+                        .map(|t| t.text.to_ascii_uppercase())
+                        .unwrap_or_default();
+                }
+                "END" | "ENDBLOCK" => block.clear(),
+                "CHARSET" => charsets.push(command),
+                "DIMENSIONS" if block == "TAXA" => {
+                    taxa_ntax = nexus_count(args, "NTAX")?;
+                }
+                keyword if alignment.is_none() && (block == "DATA" || block == "CHARACTERS") => {
+                    match keyword {
+                        "DIMENSIONS" => {
+                            data.ntax = nexus_count(args, "NTAX")?.or(data.ntax);
+                            data.nchar = nexus_count(args, "NCHAR")?.or(data.nchar);
+                        }
+                        "FORMAT" => data.format(args)?,
+                        "MATRIX" => {
+                            data.ntax = data.ntax.or(taxa_ntax);
+                            alignment = Some(data.matrix(&command[0], args)?);
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        let alignment = alignment.ok_or(AlignmentError::Empty)?;
+        let mut sets: Vec<CharSet> = vec![];
+        for command in charsets {
+            let set = parse_charset(command, alignment.width, &sets)?;
+            sets.push(set);
+        }
+        Ok((alignment, sets))
+    }
+
+    /// Writes the alignment as a Nexus `DATA` block.
+    pub fn to_nexus(&self) -> String {
+        self.to_nexus_with_charsets(&[])
+    }
+
+    /// Writes the alignment as a Nexus `DATA` block, followed by `charsets` in
+    /// a `SETS` block.
+    pub fn to_nexus_with_charsets(&self, charsets: &[CharSet]) -> String {
+        let rows = self.named_rows();
+        let names: Vec<Cow<str>> = rows.iter().map(|(name, _)| nexus_name(name)).collect();
+        let pad = names.iter().map(|n| n.len()).max().unwrap_or(0) + 2;
+        let mut out = String::from("#NEXUS\n\nBEGIN DATA;\n");
+        out.push_str(&format!(
+            "\tDIMENSIONS NTAX={} NCHAR={};\n",
+            rows.len(),
+            self.width
+        ));
+        out.push_str(&format!(
+            "\tFORMAT DATATYPE={} MISSING=? GAP=-;\n\tMATRIX\n",
+            self.datatype()
+        ));
+        for (name, (_, seq)) in names.iter().zip(&rows) {
+            out.push_str(&format!("\t{name:<pad$}{}\n", String::from_utf8_lossy(seq)));
+        }
+        out.push_str("\t;\nEND;\n");
+        if !charsets.is_empty() {
+            out.push_str("\nBEGIN SETS;\n");
+            for set in charsets {
+                out.push_str(&format!(
+                    "\tCHARSET {} = {};\n",
+                    nexus_name(&set.name),
+                    site_ranges(&set.sites)
+                ));
+            }
+            out.push_str("END;\n");
+        }
+        out
+    }
+
+    /// Reads the first alignment of a Stockholm file.
+    pub fn from_stockholm(input: &str) -> Result<Self, AlignmentError> {
+        let mut lines = numbered_lines(input).filter(|(_, text)| !text.trim().is_empty());
+        match lines.next() {
+            Some((_, text)) if text.trim_start().starts_with("# STOCKHOLM") => {}
+            Some((line, _)) => return Err(syntax(line, 1, "expected # STOCKHOLM header")),
+            None => return Err(AlignmentError::Empty),
+        }
+        let mut rows = RowsByName::default();
+        let mut descriptions: HashMap<&str, String> = HashMap::new();
+        for (line, text) in lines {
+            let trimmed = text.trim_start();
+            if trimmed.starts_with("//") {
+                break;
+            }
+            if let Some(markup) = trimmed.strip_prefix("#=GS") {
+                // Names are padded to a common width, so fields are split on
+                // runs of whitespace; the description is the rest of the line.
+                let (name, rest, _) = split_name(markup);
+                let (tag, text, _) = split_name(rest);
+                if !name.is_empty() && tag == "DE" {
+                    let description = descriptions.entry(name).or_default();
+                    if !description.is_empty() {
+                        description.push(' ');
+                    }
+                    description.push_str(text.trim());
+                }
+            }
+            if trimmed.starts_with('#') {
+                continue;
+            }
+            let (name, rest, column) = split_name(text);
+            let seq = rows.row(name, line);
+            let start = seq.len();
+            push_residues(seq, rest, line, column)?;
+            for residue in &mut seq[start..] {
+                if *residue == b'.' {
+                    *residue = b'-';
+                }
+            }
+        }
+        let mut alignment = build(rows.rows, None)?;
+        for (name, description) in descriptions {
+            if let Some(i) = alignment.index_of(name) {
+                alignment.set_description(i, Some(description));
+            }
+        }
+        Ok(alignment)
+    }
+
+    /// Writes the alignment as Stockholm.
+    pub fn to_stockholm(&self) -> String {
+        let rows = self.named_rows();
+        let names: Vec<String> = rows
+            .iter()
+            .map(|(name, _)| name.replace(char::is_whitespace, "_"))
+            .collect();
+        let pad = names.iter().map(|n| n.len()).max().unwrap_or(0) + 1;
+        let mut out = String::from("# STOCKHOLM 1.0\n\n");
+        for (i, name) in names.iter().enumerate() {
+            if let Some(description) = self.description(i) {
+                out.push_str(&format!("#=GS {name:<pad$}DE {description}\n"));
+            }
+        }
+        for (name, (_, seq)) in names.iter().zip(&rows) {
+            out.push_str(&format!("{name:<pad$}{}\n", String::from_utf8_lossy(seq)));
+        }
+        out.push_str("//\n");
+        out
+    }
+
+    /// Reads a Clustal alignment.
+    pub fn from_clustal(input: &str) -> Result<Self, AlignmentError> {
+        let mut lines = numbered_lines(input).filter(|(_, text)| !text.trim().is_empty());
+        match lines.next() {
+            Some((_, text)) if AlignmentFormat::detect(text) == Some(AlignmentFormat::Clustal) => {}
+            Some((line, _)) => return Err(syntax(line, 1, "expected a CLUSTAL header")),
+            None => return Err(AlignmentError::Empty),
+        }
+        let mut rows = RowsByName::default();
+        for (line, text) in lines {
+            // Conservation lines start in the name column.
+            if text.starts_with(char::is_whitespace) {
+                continue;
+            }
+            let (name, rest, column) = split_name(text);
+            // A row may end with a running count of residues.
+            let rest = rest.trim_end();
+            let rest = match rest.rsplit_once(char::is_whitespace) {
+                Some((seq, count)) if count.bytes().all(|b| b.is_ascii_digit()) => seq,
+                _ => rest,
+            };
+            push_residues(rows.row(name, line), rest, line, column)?;
+        }
+        build(rows.rows, None)
+    }
+
+    /// Writes the alignment as Clustal, in blocks of 60 sites with a line
+    /// marking fully conserved sites with `*`.
+    pub fn to_clustal(&self) -> String {
+        let rows = self.named_rows();
+        let names: Vec<String> = rows
+            .iter()
+            .map(|(name, _)| name.replace(char::is_whitespace, "_"))
+            .collect();
+        let pad = names.iter().map(|n| n.len()).max().unwrap_or(0) + 6;
+        let mut out = String::from("CLUSTAL multiple sequence alignment\n\n");
+        for start in (0..self.width).step_by(LINE_WIDTH) {
+            let end = (start + LINE_WIDTH).min(self.width);
+            out.push('\n');
+            for (name, (_, seq)) in names.iter().zip(&rows) {
+                out.push_str(&format!(
+                    "{name:<pad$}{}\n",
+                    String::from_utf8_lossy(&seq[start..end])
+                ));
+            }
+            let conserved: String = (start..end)
+                .map(
+                    |i| match rows.iter().all(|(_, seq)| seq[i] == rows[0].1[i]) {
+                        true => '*',
+                        false => ' ',
+                    },
+                )
+                .collect();
+            out.push_str(&format!("{:pad$}{}\n", "", conserved.trim_end()));
+        }
+        out
+    }
+
+    /// Rows with their names, in row order.
+    fn named_rows(&self) -> Vec<(&String, &[u8])> {
+        self.names.iter().zip(self.rows()).collect()
+    }
+
+    /// Nexus `DATATYPE` of the residues: `DNA` or `RNA` if they are all
+    /// nucleotide codes, `PROTEIN` otherwise.
+    fn datatype(&self) -> &'static str {
+        let residues: BTreeSet<u8> = self.residues.iter().copied().collect();
+        if !residues.iter().all(|r| b"ACGTURYKMSWBDHVN-?".contains(r)) {
+            "PROTEIN"
+        } else if residues.contains(&b'U') && !residues.contains(&b'T') {
+            "RNA"
+        } else {
+            "DNA"
+        }
+    }
+}
+
+/// A row being read, with the line it starts on.
+struct Row {
+    name: String,
+    description: Option<String>,
+    seq: Vec<u8>,
+    line: usize,
+}
+
+impl Row {
+    fn new(name: &str, line: usize) -> Self {
+        Row {
+            name: name.to_string(),
+            description: None,
+            seq: vec![],
+            line,
+        }
+    }
+}
+
+/// Rows of a format that spreads each over blocks, found by name.
+#[derive(Default)]
+struct RowsByName {
+    rows: Vec<Row>,
+    index: HashMap<String, usize>,
+}
+
+impl RowsByName {
+    fn row(&mut self, name: &str, line: usize) -> &mut Vec<u8> {
+        let idx = *self.index.entry(name.to_string()).or_insert_with(|| {
+            self.rows.push(Row::new(name, line));
+            self.rows.len() - 1
+        });
+        &mut self.rows[idx].seq
+    }
+}
+
+/// Checks that the rows are distinct and `width` long, or as long as the
+/// first if no width was declared.
+fn build(rows: Vec<Row>, width: Option<usize>) -> Result<Alignment, AlignmentError> {
+    let Some(first) = rows.first() else {
+        return Err(AlignmentError::Empty);
+    };
+    let width = width.unwrap_or(first.seq.len());
+    let mut names = Vec::with_capacity(rows.len());
+    let mut index = HashMap::with_capacity(rows.len());
+    let mut descriptions = Vec::with_capacity(rows.len());
+    // Sized from the rows read, not the declared width, which a header can
+    // overstate without bound.
+    let mut residues = Vec::with_capacity(rows.iter().map(|row| row.seq.len()).sum());
+    for row in rows {
+        if index.contains_key(&row.name) {
+            return Err(AlignmentError::DuplicateTaxon {
+                line: row.line,
+                name: row.name,
+            });
+        }
+        if row.seq.len() != width {
+            return Err(AlignmentError::Length {
+                line: row.line,
+                expected: width,
+                found: row.seq.len(),
+                name: row.name,
+            });
+        }
+        index.insert(row.name.clone(), names.len());
+        names.push(row.name);
+        descriptions.push(row.description);
+        residues.extend(row.seq);
+    }
+    let mut alignment = Alignment::from_parts(names, index, residues, width);
+    alignment.descriptions = descriptions;
+    Ok(alignment)
+}
+
+fn syntax(line: usize, column: usize, reason: impl Into<String>) -> AlignmentError {
+    AlignmentError::Syntax {
+        line,
+        column,
+        reason: reason.into(),
+    }
+}
+
+/// Lines of `input` with their 1-based numbers.
+fn numbered_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input.lines().enumerate().map(|(i, text)| (i + 1, text))
+}
+
+/// Appends the residues of `text`, which starts at `column` of `line`, in
+/// upper case, skipping whitespace. Residues are letters or one of `-.?*~_`.
+fn push_residues(
+    out: &mut Vec<u8>,
+    text: &str,
+    line: usize,
+    column: usize,
+) -> Result<(), AlignmentError> {
+    for (i, b) in text.bytes().enumerate() {
+        if b.is_ascii_whitespace() {
+            continue;
+        }
+        if !b.is_ascii_alphabetic() && !b"-.?*~_".contains(&b) {
+            let c = text[i..].chars().next().unwrap_or_default();
+            return Err(syntax(line, column + i, format!("invalid residue {c:?}")));
+        }
+        out.push(b.to_ascii_uppercase());
+    }
+    Ok(())
+}
+
+/// Splits a row into its name, which ends at the first whitespace, and the
+/// rest, with the column at which the rest starts.
+fn split_name(text: &str) -> (&str, &str, usize) {
+    let trimmed = text.trim_start();
+    let lead = text.len() - trimmed.len();
+    let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+    (&trimmed[..end], &trimmed[end..], lead + end + 1)
+}
+
+/// Splits a PHYLIP row into its name and the rest, as [`split_name`] does.
+fn split_phylip_name(
+    text: &str,
+    strict: bool,
+    line: usize,
+) -> Result<(&str, &str, usize), AlignmentError> {
+    let (name, rest, column) = match strict {
+        true => {
+            let end = text.char_indices().nth(10).map_or(text.len(), |(i, _)| i);
+            (text[..end].trim(), &text[end..], end + 1)
+        }
+        false => split_name(text),
+    };
+    match name.is_empty() {
+        true => Err(syntax(line, 1, "empty taxon name")),
+        false => Ok((name, rest, column)),
+    }
+}
+
+/// Reads PHYLIP rows one after the other, each running on until it has
+/// `nchar` sites.
+fn phylip_sequential(
+    body: &[(usize, &str)],
+    ntax: usize,
+    nchar: usize,
+    strict: bool,
+) -> Result<Vec<Row>, AlignmentError> {
+    let mut lines = body.iter();
+    let mut rows = Vec::with_capacity(ntax);
+    for _ in 0..ntax {
+        let Some(&(line, text)) = lines.next() else {
+            return Err(AlignmentError::TaxonCount {
+                expected: ntax,
+                found: rows.len(),
+            });
+        };
+        let (name, rest, column) = split_phylip_name(text, strict, line)?;
+        let mut row = Row::new(name, line);
+        push_residues(&mut row.seq, rest, line, column)?;
+        while row.seq.len() < nchar {
+            let Some(&(line, text)) = lines.next() else {
+                break;
+            };
+            push_residues(&mut row.seq, text, line, 1)?;
+        }
+        if row.seq.len() != nchar {
+            return Err(AlignmentError::Length {
+                line: row.line,
+                expected: nchar,
+                found: row.seq.len(),
+                name: row.name,
+            });
+        }
+        rows.push(row);
+    }
+    match lines.next() {
+        Some(&(line, _)) => Err(syntax(line, 1, format!("more than {ntax} taxa"))),
+        None => Ok(rows),
+    }
+}
+
+/// Reads PHYLIP rows in blocks: the first names every taxon, and each later
+/// one continues the rows in the same order.
+fn phylip_interleaved(
+    body: &[(usize, &str)],
+    ntax: usize,
+    strict: bool,
+) -> Result<Vec<Row>, AlignmentError> {
+    let mut rows = Vec::with_capacity(ntax);
+    for &(line, text) in &body[..ntax] {
+        let (name, rest, column) = split_phylip_name(text, strict, line)?;
+        let mut row = Row::new(name, line);
+        push_residues(&mut row.seq, rest, line, column)?;
+        rows.push(row);
+    }
+    for (i, &(line, text)) in body[ntax..].iter().enumerate() {
+        push_residues(&mut rows[i % ntax].seq, text, line, 1)?;
+    }
+    Ok(rows)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Semicolon,
+    Equals,
+}
+
+/// A Nexus token. Quoted words have their quotes removed.
+struct Token<'a> {
+    kind: TokenKind,
+    text: Cow<'a, str>,
+    line: usize,
+    column: usize,
+}
+
+/// Splits Nexus input into words, `;` and `=`, dropping `[...]` comments.
+fn tokenize(input: &str) -> Result<Vec<Token<'_>>, AlignmentError> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut chars = input.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let column = at - line_start + 1;
+        let token = |kind, text| Token {
+            kind,
+            text,
+            line,
+            column,
+        };
+        match c {
+            '\n' => {
+                line += 1;
+                line_start = at + 1;
+            }
+            c if c.is_whitespace() => {}
+            ';' => tokens.push(token(TokenKind::Semicolon, Cow::Borrowed(";"))),
+            '=' => tokens.push(token(TokenKind::Equals, Cow::Borrowed("="))),
+            '[' => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        None => return Err(syntax(line, column, "unterminated comment")),
+                        Some((i, '\n')) => {
+                            line += 1;
+                            line_start = i + 1;
+                        }
+                        Some((_, '[')) => depth += 1,
+                        Some((_, ']')) => depth -= 1,
+                        Some(_) => {}
+                    }
+                }
+            }
+            '\'' => {
+                let (start_line, mut text) = (line, String::new());
+                loop {
+                    match chars.next() {
+                        None => return Err(syntax(start_line, column, "unterminated quote")),
+                        Some((_, '\'')) if chars.peek().is_some_and(|&(_, c)| c == '\'') => {
+                            chars.next();
+                            text.push('\'');
+                        }
+                        Some((_, '\'')) => break,
+                        Some((i, c)) => {
+                            if c == '\n' {
+                                line += 1;
+                                line_start = i + 1;
+                            }
+                            text.push(c);
+                        }
+                    }
+                }
+                tokens.push(Token {
+                    kind: TokenKind::Word,
+                    text: Cow::Owned(text),
+                    line: start_line,
+                    column,
+                });
+            }
+            _ => {
+                let mut end = at + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, ';' | '=' | '[') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(token(TokenKind::Word, Cow::Borrowed(&input[at..end])));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// `key = value` pairs of a Nexus command; a key without `=` has no value.
+fn nexus_pairs<'t, 'a>(args: &'t [Token<'a>]) -> Vec<(&'t Token<'a>, Option<&'t Token<'a>>)> {
+    let mut pairs = vec![];
+    let mut i = 0;
+    while i < args.len() {
+        match args.get(i + 1) {
+            Some(t) if t.kind == TokenKind::Equals => {
+                pairs.push((&args[i], args.get(i + 2)));
+                i += 3;
+            }
+            _ => {
+                pairs.push((&args[i], None));
+                i += 1;
+            }
+        }
+    }
+    pairs
+}
+
+/// The value of `key` in a `DIMENSIONS` command, if given.
+fn nexus_count(args: &[Token<'_>], key: &str) -> Result<Option<usize>, AlignmentError> {
+    for (k, value) in nexus_pairs(args) {
+        if !k.text.eq_ignore_ascii_case(key) {
+            continue;
+        }
+        let value = value.ok_or_else(|| syntax(k.line, k.column, format!("{key} has no value")))?;
+        return value
+            .text
+            .parse()
+            .map(Some)
+            .map_err(|_| syntax(value.line, value.column, format!("invalid {key}")));
+    }
+    Ok(None)
+}
+
+/// What a `DATA` or `CHARACTERS` block has declared before its matrix.
+#[derive(Default)]
+struct NexusData {
+    ntax: Option<usize>,
+    nchar: Option<usize>,
+    interleave: bool,
+    gap: Option<u8>,
+    missing: Option<u8>,
+    matchchar: Option<u8>,
+}
+
+impl NexusData {
+    fn format(&mut self, args: &[Token<'_>]) -> Result<(), AlignmentError> {
+        for (key, value) in nexus_pairs(args) {
+            let symbol = || {
+                let value = value.ok_or_else(|| {
+                    syntax(key.line, key.column, format!("{} has no value", key.text))
+                })?;
+                match value.text.as_bytes() {
+                    [b] => Ok(Some(b.to_ascii_uppercase())),
+                    _ => Err(syntax(value.line, value.column, "expected one symbol")),
+                }
+            };
+            match key.text.to_ascii_uppercase().as_str() {
+                "GAP" => self.gap = symbol()?,
+                "MISSING" => self.missing = symbol()?,
+                "MATCHCHAR" => self.matchchar = symbol()?,
+                "INTERLEAVE" => {
+                    self.interleave = value.is_none_or(|v| !v.text.eq_ignore_ascii_case("NO"))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the rows of a `MATRIX` command with arguments `args`.
+    fn matrix(&self, keyword: &Token<'_>, args: &[Token<'_>]) -> Result<Alignment, AlignmentError> {
+        let nchar = self
+            .nchar
+            .ok_or_else(|| syntax(keyword.line, keyword.column, "MATRIX before NCHAR"))?;
+        let mut rows = RowsByName::default();
+        if self.interleave {
+            // Each line holds a name and a piece of its row.
+            let mut line = 0;
+            let mut current = None;
+            for token in args {
+                if token.line != line {
+                    line = token.line;
+                    rows.row(&token.text, line);
+                    current = Some(rows.index[token.text.as_ref()]);
+                    continue;
+                }
+                let idx = current.expect("invariant: the first token of a line names a row");
+                push_residues(
+                    &mut rows.rows[idx].seq,
+                    &token.text,
+                    token.line,
+                    token.column,
+                )?;
+            }
+        } else {
+            // Each row runs on until it has NCHAR sites.
+            let mut tokens = args.iter();
+            while let Some(name) = tokens.next() {
+                let mut row = Row::new(&name.text, name.line);
+                while row.seq.len() < nchar {
+                    let Some(token) = tokens.next() else {
+                        break;
+                    };
+                    push_residues(&mut row.seq, &token.text, token.line, token.column)?;
+                }
+                rows.rows.push(row);
+            }
+        }
+        let mut rows = rows.rows;
+        if let Some(ntax) = self.ntax {
+            if rows.len() != ntax {
+                return Err(AlignmentError::TaxonCount {
+                    expected: ntax,
+                    found: rows.len(),
+                });
+            }
+        }
+        for residue in rows.iter_mut().flat_map(|row| row.seq.iter_mut()) {
+            if Some(*residue) == self.gap {
+                *residue = b'-';
+            } else if Some(*residue) == self.missing {
+                *residue = b'?';
+            }
+        }
+        // Match characters copy the first row as translated above, so a gap
+        // there reads as `-` rather than as the file's own gap symbol.
+        let first: Vec<u8> = rows.first().map(|r| r.seq.clone()).unwrap_or_default();
+        for row in &mut rows {
+            for (residue, &reference) in row.seq.iter_mut().zip(&first) {
+                if Some(*residue) == self.matchchar {
+                    *residue = reference;
+                }
+            }
+        }
+        build(rows, Some(nchar))
+    }
+}
+
+/// Reads `CHARSET name = 1-100 101-300\3 ...;` over `width` sites. A set may
+/// name earlier ones, and `.` is the last site.
+fn parse_charset(
+    command: &[Token<'_>],
+    width: usize,
+    earlier: &[CharSet],
+) -> Result<CharSet, AlignmentError> {
+    let keyword = &command[0];
+    let name = command[1..]
+        .iter()
+        .find(|t| t.kind == TokenKind::Word && t.text != "*")
+        .ok_or_else(|| syntax(keyword.line, keyword.column, "CHARSET has no name"))?;
+    let spec_at = command
+        .iter()
+        .rposition(|t| t.kind == TokenKind::Equals)
+        .ok_or_else(|| syntax(name.line, name.column, "CHARSET has no '='"))?;
+    // Join the spec so that `1 - 10 \ 3` reads like `1-10\3`.
+    let mut items: Vec<(String, &Token<'_>)> = vec![];
+    for token in &command[spec_at + 1..] {
+        match items.last_mut() {
+            Some((item, _))
+                if item.ends_with(['-', '\\']) || token.text.starts_with(['-', '\\']) =>
+            {
+                item.push_str(&token.text)
+            }
+            _ => items.push((token.text.to_string(), token)),
+        }
+    }
+    let mut sites = BTreeSet::new();
+    for (item, token) in items {
+        let invalid = || {
+            syntax(
+                token.line,
+                token.column,
+                format!("invalid site range {item:?}"),
+            )
+        };
+        let site = |s: &str| match s {
+            "." => Ok(width),
+            s => s
+                .parse::<usize>()
+                .ok()
+                .filter(|&site| (1..=width).contains(&site))
+                .ok_or_else(invalid),
+        };
+        if let Some(set) = earlier.iter().find(|s| s.name.eq_ignore_ascii_case(&item)) {
+            sites.extend(set.sites.iter().copied());
+            continue;
+        }
+        let (range, step) = match item.split_once('\\') {
+            Some((range, step)) => (
+                range,
+                step.parse::<usize>()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .ok_or_else(invalid)?,
+            ),
+            None => (item.as_str(), 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (site(start)?, site(end)?),
+            None => (site(range)?, site(range)?),
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        sites.extend((start - 1..end).step_by(step));
+    }
+    Ok(CharSet {
+        name: name.text.to_string(),
+        sites: sites.into_iter().collect(),
+    })
+}
+
+/// Writes zero-based `sites` as 1-based Nexus ranges, with steps where three
+/// or more sites are evenly spaced.
+fn site_ranges(sites: &[usize]) -> String {
+    let mut ranges = vec![];
+    let mut i = 0;
+    while i < sites.len() {
+        let start = sites[i];
+        if let Some(&next) = sites.get(i + 1) {
+            let step = next - start;
+            let mut j = i + 1;
+            while sites.get(j + 1).is_some_and(|&s| s - sites[j] == step) {
+                j += 1;
+            }
+            if step == 1 || j - i >= 2 {
+                let range = format!("{}-{}", start + 1, sites[j] + 1);
+                ranges.push(match step {
+                    1 => range,
+                    _ => format!("{range}\\{step}"),
+                });
+                i = j + 1;
+                continue;
+            }
+        }
+        ranges.push((start + 1).to_string());
+        i += 1;
+    }
+    ranges.join(" ")
+}
+
+/// Quotes a Nexus name that holds whitespace or punctuation.
+fn nexus_name(name: &str) -> Cow<'_, str> {
+    if name.is_empty()
+        || name
+            .chars()
+            .any(|c| c.is_whitespace() || "()[]{}/\\,;:=*'\"`+-<>".contains(c))
+    {
+        Cow::Owned(format!("'{}'", name.replace('\'', "''")))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Alignment {
+        Alignment::from_fasta(
+            ">Seq1 first  row\nACGTACGTAC\n>Sequence2 second\nACGAACG-AC\n>Homo sapiens\nTCGTAC?TAC\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let aln = example();
+        let formats = [
+            AlignmentFormat::Fasta,
+            AlignmentFormat::Phylip,
+            AlignmentFormat::Nexus,
+            AlignmentFormat::Stockholm,
+            AlignmentFormat::Clustal,
+        ];
+        for format in formats {
+            let text = aln.to_format(format);
+            assert_eq!(AlignmentFormat::detect(&text), Some(format));
+            let read = Alignment::parse(&text).unwrap();
+            assert_eq!(read.width(), aln.width());
+            assert_eq!(read.row_at(1), aln.row_at(1), "{format:?}");
+            if matches!(format, AlignmentFormat::Fasta | AlignmentFormat::Stockholm) {
+                assert_eq!(read, aln, "{format:?} keeps descriptions");
+            }
+        }
+        let strict = PhylipFormat::new().with_strict(true).with_interleaved(true);
+        let wide = Alignment::from_fasta(&format!(
+            ">a\n{}\n>b\n{}\n",
+            "AC".repeat(40),
+            "GT".repeat(40)
+        ))
+        .unwrap();
+        let text = wide.to_phylip(strict);
+        assert_eq!(text.lines().count(), 6);
+        let read = Alignment::from_phylip(&text, strict).unwrap();
+        assert_eq!(read, wide);
+    }
+
+    #[test]
+    fn nexus_interleave_symbols_and_charsets() {
+        let input = "#NEXUS
+[written by hand]
+BEGIN TAXA;
+    DIMENSIONS NTAX=3;
+END;
+BEGIN CHARACTERS;
+    DIMENSIONS NCHAR=8;
+    FORMAT DATATYPE=DNA GAP=~ MISSING=N MATCHCHAR=. INTERLEAVE;
+    MATRIX
+    'Homo sapiens' ACGT
+    pan            ..~N
+    gorilla        TC.A
+    'Homo sapiens' TTTT
+    pan            ....
+    gorilla        ...A [a comment]
+    ;
+END;
+BEGIN SETS;
+    CHARSET first = 1-4;
+    CHARSET codon3 = 3 - .\\3;
+    CHARSET both = first codon3 8;
+END;
+";
+        let (aln, sets) = Alignment::from_nexus_with_charsets(input).unwrap();
+        assert_eq!(aln.row("Homo sapiens").unwrap(), b"ACGTTTTT");
+        assert_eq!(aln.row("pan").unwrap(), b"AC-?TTTT");
+        assert_eq!(aln.row("gorilla").unwrap(), b"TCGATTTA");
+        assert_eq!(sets[1].sites, vec![2, 5]);
+        assert_eq!(sets[2].sites, vec![0, 1, 2, 3, 5, 7]);
+
+        let written = aln.to_nexus_with_charsets(&sets);
+        assert!(written.contains("CHARSET both = 1-4 6 8;"));
+        let (read, reread_sets) = Alignment::from_nexus_with_charsets(&written).unwrap();
+        assert_eq!(read, aln);
+        assert_eq!(reread_sets, sets);
+    }
+
+    #[test]
+    fn nexus_match_characters_copy_translated_symbols() {
+        let input = "#NEXUS
+BEGIN DATA;
+    DIMENSIONS NTAX=2 NCHAR=4;
+    FORMAT DATATYPE=DNA GAP=~ MISSING=N MATCHCHAR=.;
+    MATRIX
+    a A~GN
+    b ....
+    ;
+END;
+";
+        let aln = Alignment::from_nexus(input).unwrap();
+        assert_eq!(aln.row("a").unwrap(), b"A-G?");
+        assert_eq!(aln.row("b").unwrap(), b"A-G?");
+    }
+
+    #[test]
+    fn errors_carry_positions() {
+        let err = |input: &str| Alignment::parse(input).unwrap_err();
+        assert_eq!(
+            err(">a\nACGT\n>b\nAC#T\n"),
+            AlignmentError::Syntax {
+                line: 4,
+                column: 3,
+                reason: "invalid residue '#'".to_string()
+            }
+        );
+        assert_eq!(
+            err("2 4\na ACGT\na ACGA\n"),
+            AlignmentError::DuplicateTaxon {
+                line: 3,
+                name: "a".to_string()
+            }
+        );
+        assert!(matches!(
+            err("# STOCKHOLM 1.0\na ACGT\nb ACG\n//\n"),
+            AlignmentError::Length { line: 3, .. }
+        ));
+        assert!(matches!(
+            err("#NEXUS\nBEGIN DATA;\nDIMENSIONS NTAX=2 NCHAR=x;\n"),
+            AlignmentError::Syntax {
+                line: 3,
+                column: 25,
+                ..
+            }
+        ));
+        assert_eq!(err("hello"), AlignmentError::UnknownFormat);
+
+        // Headers are checked against the rows before anything is sized
+        // from them.
+        assert_eq!(
+            err("0 4\na ACGT\n"),
+            AlignmentError::Syntax {
+                line: 1,
+                column: 1,
+                reason: "expected at least one taxon".to_string()
+            }
+        );
+        assert!(matches!(
+            err("1 18446744073709551615\na A\n"),
+            AlignmentError::Length {
+                line: 2,
+                found: 1,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,553 @@
+//! Alignment operations: trimming, masking, subsetting and concatenation,
+//! with per-taxon and composition summaries.
+//!
+//! Gaps are `-`, `.` and `~`. Together with `?` they are *missing* data, which
+//! is what [`Alignment::gap_fractions`](crate::alignment::Alignment::gap_fractions)
+//! and the trimming methods count. Residues that are neither missing nor a
+//! canonical state of the [`Alphabet`](crate::alphabet::Alphabet), such as `N`
+//! or `R`, are ambiguous.
+//!
+//! [`Trim::Gappyout`](crate::alignment::ops::Trim::Gappyout) and
+//! [`Trim::Strict`](crate::alignment::ops::Trim::Strict) follow trimAl's
+//! automated methods of the same names: rather than take a threshold, they
+//! find one where the distribution of a per-site score bends most sharply.
+//! Sites are always kept in their original order.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::alignment::formats::CharSet;
+use crate::alignment::Alignment;
+use crate::alphabet::Alphabet;
+use crate::error::AlignmentError;
+use crate::models::gamma::incomplete_gamma;
+#[cfg(feature = "simple_rooted_tree")]
+use crate::prelude::{PhyloTree, RootedMetaTree, RootedTree};
+
+/// Fills the rows a taxon has no sequence for in
+/// [`Alignment::concatenate`].
+const MISSING: u8 = b'?';
+
+/// Whether `c` is missing data: a gap or `?`.
+fn is_missing(c: u8) -> bool {
+    matches!(c, b'-' | b'.' | b'~' | b'?')
+}
+
+/// Automated trimming methods, after trimAl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trim {
+    /// Drops the sites past the point where the sorted gap fractions of the
+    /// sites bend most sharply.
+    Gappyout,
+    /// Drops what [`Trim::Gappyout`] drops, and the sites past the same kind
+    /// of bend in their similarity scores. A site's similarity is the fraction
+    /// of pairs of its residues that are the same canonical state, times the
+    /// fraction of rows not missing.
+    Strict,
+}
+
+/// Residue counts of one row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TaxonStats {
+    /// Canonical states
+    pub residues: usize,
+    /// Ambiguity codes and other characters outside the alphabet
+    pub ambiguous: usize,
+    /// Gaps and `?`
+    pub gaps: usize,
+}
+
+impl TaxonStats {
+    /// Number of sites counted.
+    pub fn len(&self) -> usize {
+        self.residues + self.ambiguous + self.gaps
+    }
+
+    /// Returns true if no sites were counted.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fraction of sites that are gaps or `?`; 0 for an empty row.
+    pub fn gap_fraction(&self) -> f64 {
+        fraction(self.gaps, self.len())
+    }
+
+    /// Fraction of sites that are ambiguous; 0 for an empty row.
+    pub fn ambiguous_fraction(&self) -> f64 {
+        fraction(self.ambiguous, self.len())
+    }
+}
+
+/// State composition of an alignment and of each of its rows.
+///
+/// Only canonical states are counted. Each row is tested against the overall
+/// frequencies with a chi-squared test on its counts, as IQ-TREE does before
+/// an analysis; a small p-value flags a row whose composition stands out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composition {
+    /// Overall state frequencies, in the alphabet's state order, with each
+    /// row's counts scaled by its weight
+    pub frequencies: Vec<f64>,
+    /// State frequencies of each row, all 0 for a row with no canonical states
+    pub taxa: Vec<Vec<f64>>,
+    /// Chi-squared statistic of each row against `frequencies`
+    pub chi_square: Vec<f64>,
+    /// Upper-tail p-value of each statistic
+    pub p_values: Vec<f64>,
+}
+
+fn fraction(count: usize, total: usize) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => count as f64 / total as f64,
+    }
+}
+
+impl Alignment {
+    /// Fraction of rows with a gap or `?`, at each site.
+    pub fn gap_fractions(&self) -> Vec<f64> {
+        (0..self.width)
+            .map(|j| {
+                fraction(
+                    self.column(j).filter(|&c| is_missing(c)).count(),
+                    self.len(),
+                )
+            })
+            .collect()
+    }
+
+    /// The alignment without the sites where more than `max_gap_fraction` of
+    /// the rows are gaps or `?`.
+    pub fn drop_gappy_columns(&self, max_gap_fraction: f64) -> Self {
+        let keep: Vec<usize> = self
+            .gap_fractions()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, f)| f <= max_gap_fraction)
+            .map(|(j, _)| j)
+            .collect();
+        self.select_columns(&keep)
+    }
+
+    /// The invariant sites: those at which one state of `A` is compatible with
+    /// every residue, so a site of `A`, `R` and gaps is invariant. Characters
+    /// `A` does not know are compatible with every state.
+    pub fn invariant_sites<A: Alphabet>(&self) -> Vec<usize> {
+        (0..self.width)
+            .filter(|&j| {
+                let mut states = vec![true; A::N_STATES];
+                for c in self.column(j) {
+                    if is_missing(c) {
+                        continue;
+                    }
+                    if let Some(profile) = A::profile(c) {
+                        for (s, p) in states.iter_mut().zip(profile) {
+                            *s &= p > 0.0;
+                        }
+                    }
+                }
+                states.contains(&true)
+            })
+            .collect()
+    }
+
+    /// The alignment without its [invariant sites](Alignment::invariant_sites).
+    pub fn remove_invariant_sites<A: Alphabet>(&self) -> Self {
+        let invariant = self.invariant_sites::<A>();
+        let mut invariant = invariant.into_iter().peekable();
+        let keep: Vec<usize> = (0..self.width)
+            .filter(|&j| invariant.next_if_eq(&j).is_none())
+            .collect();
+        self.select_columns(&keep)
+    }
+
+    /// Overwrites sites `columns` of every row with `mask`, such as `N` or `?`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a site is out of bounds.
+    pub fn mask_columns(&mut self, columns: &[usize], mask: u8) {
+        assert!(
+            columns.iter().all(|&j| j < self.width),
+            "index out of bounds"
+        );
+        for row in self.residues.chunks_exact_mut(self.width.max(1)) {
+            for &j in columns {
+                row[j] = mask;
+            }
+        }
+    }
+
+    /// Overwrites `sites` of row `i` with `mask`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row or the range is out of bounds.
+    pub fn mask_row(&mut self, i: usize, sites: Range<usize>, mask: u8) {
+        assert!(
+            i < self.len() && sites.start <= sites.end && sites.end <= self.width,
+            "index out of bounds"
+        );
+        let start = i * self.width;
+        self.residues[start + sites.start..start + sites.end].fill(mask);
+    }
+
+    /// The rows named `names`, in the order given.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AlignmentError::UnknownTaxon`] for a name with no row, and
+    /// [`AlignmentError::DuplicateName`] for a name given twice.
+    pub fn select_taxa<S: AsRef<str>>(&self, names: &[S]) -> Result<Self, AlignmentError> {
+        let mut rows = Vec::with_capacity(names.len());
+        let mut seen = vec![false; self.len()];
+        for name in names {
+            let name = name.as_ref();
+            let i = self
+                .index_of(name)
+                .ok_or_else(|| AlignmentError::UnknownTaxon(name.to_string()))?;
+            if std::mem::replace(&mut seen[i], true) {
+                return Err(AlignmentError::DuplicateName(name.to_string()));
+            }
+            rows.push(i);
+        }
+        Ok(self.select_rows(&rows))
+    }
+
+    /// The rows of the leaves of `tree`, in row order, leaving out taxa the
+    /// tree does not have.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AlignmentError::UnknownTaxon`] for a leaf with no row.
+    #[cfg(feature = "simple_rooted_tree")]
+    pub fn match_tree(&self, tree: &PhyloTree) -> Result<Self, AlignmentError> {
+        let mut keep = vec![false; self.len()];
+        for leaf in tree.get_leaf_ids() {
+            let name = tree.get_node_taxa(leaf).map_or("", String::as_str);
+            let i = self
+                .index_of(name)
+                .ok_or_else(|| AlignmentError::UnknownTaxon(name.to_string()))?;
+            keep[i] = true;
+        }
+        let rows: Vec<usize> = (0..self.len()).filter(|&i| keep[i]).collect();
+        Ok(self.select_rows(&rows))
+    }
+
+    /// Concatenates named alignments, such as genes, into a supermatrix, and
+    /// returns it with one [`CharSet`] per part, under the part's name.
+    ///
+    /// Rows are the taxa of every part, in order of first appearance, and a
+    /// taxon absent from a part is `?` across it. A row takes its description
+    /// and weight from the first part that has it.
+    pub fn concatenate<'a>(
+        parts: impl IntoIterator<Item = (&'a str, &'a Alignment)>,
+    ) -> (Self, Vec<CharSet>) {
+        let parts: Vec<_> = parts.into_iter().collect();
+        let mut names = vec![];
+        let mut index = HashMap::new();
+        let mut source = vec![];
+        for (_, part) in &parts {
+            for (i, name) in part.names.iter().enumerate() {
+                if !index.contains_key(name) {
+                    index.insert(name.clone(), names.len());
+                    names.push(name.clone());
+                    source.push((*part, i));
+                }
+            }
+        }
+        let width = parts.iter().map(|(_, part)| part.width).sum();
+        let mut residues = Vec::with_capacity(names.len() * width);
+        for name in &names {
+            for (_, part) in &parts {
+                match part.row(name) {
+                    Some(row) => residues.extend_from_slice(row),
+                    None => residues.resize(residues.len() + part.width, MISSING),
+                }
+            }
+        }
+        let mut out = Alignment::from_parts(names, index, residues, width);
+        for (k, (part, i)) in source.into_iter().enumerate() {
+            out.descriptions[k] = part.descriptions[i].clone();
+            out.weights[k] = part.weights[i];
+        }
+
+        let mut start = 0;
+        let charsets = parts
+            .iter()
+            .map(|(name, part)| {
+                let sites = (start..start + part.width).collect();
+                start += part.width;
+                CharSet {
+                    name: name.to_string(),
+                    sites,
+                }
+            })
+            .collect();
+        (out, charsets)
+    }
+
+    /// The sites `method` keeps, ascending.
+    pub fn trim_sites<A: Alphabet>(&self, method: Trim) -> Vec<usize> {
+        let gaps = self.gap_fractions();
+        let max_gaps = bend(&gaps);
+        let mut keep: Vec<usize> = (0..self.width).filter(|&j| gaps[j] <= max_gaps).collect();
+        if method == Trim::Strict {
+            let dissimilarity: Vec<f64> = (0..self.width)
+                .map(|j| 1.0 - self.similarity::<A>(j))
+                .collect();
+            let cutoff = bend(&dissimilarity);
+            keep.retain(|&j| dissimilarity[j] <= cutoff);
+        }
+        keep
+    }
+
+    /// The alignment of the sites `method` keeps.
+    pub fn trim<A: Alphabet>(&self, method: Trim) -> Self {
+        self.select_columns(&self.trim_sites::<A>(method))
+    }
+
+    /// Fraction of pairs of rows with the same canonical state at site `j`,
+    /// times the fraction of rows not missing there.
+    fn similarity<A: Alphabet>(&self, j: usize) -> f64 {
+        let mut counts = vec![0usize; A::N_STATES];
+        let mut present = 0;
+        for c in self.column(j) {
+            if is_missing(c) {
+                continue;
+            }
+            present += 1;
+            if let Some(s) = A::index_of(c) {
+                counts[s] += 1;
+            }
+        }
+        if present < 2 {
+            return 0.0;
+        }
+        let same: usize = counts.iter().map(|&n| n * n.saturating_sub(1)).sum();
+        fraction(same, present * (present - 1)) * fraction(present, self.len())
+    }
+
+    /// Residue counts of each row, in row order.
+    pub fn taxon_stats<A: Alphabet>(&self) -> Vec<TaxonStats> {
+        self.rows()
+            .map(|row| {
+                let mut stats = TaxonStats::default();
+                for &c in row {
+                    if is_missing(c) {
+                        stats.gaps += 1;
+                    } else if A::index_of(c).is_some() {
+                        stats.residues += 1;
+                    } else {
+                        stats.ambiguous += 1;
+                    }
+                }
+                stats
+            })
+            .collect()
+    }
+
+    /// State composition of the alignment and of each row.
+    pub fn composition<A: Alphabet>(&self) -> Composition {
+        let counts: Vec<Vec<usize>> = self
+            .rows()
+            .map(|row| {
+                let mut counts = vec![0; A::N_STATES];
+                for &c in row {
+                    if let Some(s) = A::index_of(c) {
+                        counts[s] += 1;
+                    }
+                }
+                counts
+            })
+            .collect();
+
+        let mut frequencies = vec![0.0; A::N_STATES];
+        for (row, &w) in counts.iter().zip(&self.weights) {
+            for (f, &n) in frequencies.iter_mut().zip(row) {
+                *f += w * n as f64;
+            }
+        }
+        let total: f64 = frequencies.iter().sum();
+        if total > 0.0 {
+            frequencies.iter_mut().for_each(|f| *f /= total);
+        }
+        let observed = frequencies.iter().filter(|&&f| f > 0.0).count();
+        let df = observed.saturating_sub(1) as f64;
+
+        let mut taxa = Vec::with_capacity(self.len());
+        let mut chi_square = Vec::with_capacity(self.len());
+        let mut p_values = Vec::with_capacity(self.len());
+        for row in &counts {
+            let n: usize = row.iter().sum();
+            taxa.push(row.iter().map(|&c| fraction(c, n)).collect());
+            let stat: f64 = row
+                .iter()
+                .zip(&frequencies)
+                .filter(|&(_, &f)| f > 0.0)
+                .map(|(&o, &f)| {
+                    let expected = n as f64 * f;
+                    (o as f64 - expected).powi(2) / expected
+                })
+                .sum();
+            let stat = if n == 0 { 0.0 } else { stat };
+            chi_square.push(stat);
+            p_values.push(match df > 0.0 && stat > 0.0 {
+                true => 1.0 - incomplete_gamma(stat / 2.0, df / 2.0),
+                false => 1.0,
+            });
+        }
+        Composition {
+            frequencies,
+            taxa,
+            chi_square,
+            p_values,
+        }
+    }
+}
+
+/// The largest score to keep: the level at which the curve of sorted scores
+/// against the fraction of sites at or below them turns up most sharply.
+///
+/// Each distinct score is a point on the curve, and the cut is at the point
+/// where the slope after it most exceeds, as a ratio, the slope before it.
+/// With fewer than three distinct scores there is no bend and every site is
+/// kept.
+fn bend(scores: &[f64]) -> f64 {
+    let mut sorted = scores.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    // (fraction of sites at or below the level, level)
+    let mut points: Vec<(f64, f64)> = vec![];
+    for (k, &s) in sorted.iter().enumerate() {
+        let at = (k + 1) as f64 / sorted.len() as f64;
+        match points.last_mut() {
+            Some(last) if last.1 == s => last.0 = at,
+            _ => points.push((at, s)),
+        }
+    }
+    let slopes: Vec<f64> = points
+        .windows(2)
+        .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+        .collect();
+    let mut cut = f64::INFINITY;
+    let mut steepest = 0.0;
+    for (k, w) in slopes.windows(2).enumerate() {
+        let ratio = w[1] / w[0];
+        if ratio > steepest {
+            steepest = ratio;
+            cut = points[k + 1].1;
+        }
+    }
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Nucleotide;
+
+    fn alignment(rows: &[(&str, &[u8])]) -> Alignment {
+        Alignment::new(
+            rows.iter().map(|(n, _)| n.to_string()).collect(),
+            rows.iter().map(|(_, r)| r.to_vec()).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn gappy_and_invariant_sites() {
+        let mut aln = alignment(&[
+            ("a", b"ACGT-AAC"),
+            ("b", b"ACGA-AGC"),
+            ("c", b"AC-T-?TC"),
+            ("d", b"RCGTA-CT"),
+        ]);
+        assert_eq!(
+            aln.gap_fractions(),
+            [0.0, 0.0, 0.25, 0.0, 0.75, 0.5, 0.0, 0.0]
+        );
+        let dropped = aln.drop_gappy_columns(0.5);
+        assert_eq!(dropped.row_at(2), b"AC-T?TC");
+        // R is compatible with A; the gapped site 4 is all A.
+        assert_eq!(aln.invariant_sites::<Nucleotide>(), [0, 1, 2, 4, 5]);
+        assert_eq!(aln.remove_invariant_sites::<Nucleotide>().row_at(0), b"TAC");
+
+        aln.mask_columns(&[0], b'N');
+        aln.mask_row(3, 6..8, b'?');
+        assert_eq!(aln.row_at(3), b"NCGTA-??");
+        assert_eq!(aln.row_at(0), b"NCGT-AAC");
+
+        let stats = aln.taxon_stats::<Nucleotide>();
+        assert_eq!(
+            stats[3],
+            TaxonStats {
+                residues: 4,
+                ambiguous: 1,
+                gaps: 3
+            }
+        );
+        assert_eq!(stats[3].gap_fraction(), 0.375);
+    }
+
+    #[test]
+    fn gappyout_drops_the_gappy_tail() {
+        let rows: Vec<Vec<u8>> = (0..10)
+            .map(|i| {
+                // Twenty full sites, then sites missing in 1, 2 and 9 rows.
+                let mut row = b"ACGTACGTACGTACGTACGT".to_vec();
+                row.push(if i < 1 { b'-' } else { b'A' });
+                row.push(if i < 2 { b'-' } else { b'C' });
+                row.extend(std::iter::repeat_n(if i < 9 { b'-' } else { b'G' }, 3));
+                row
+            })
+            .collect();
+        let names = (0..10).map(|i| format!("t{i}")).collect();
+        let aln = Alignment::new(names, rows).unwrap();
+        let kept = aln.trim_sites::<Nucleotide>(Trim::Gappyout);
+        assert_eq!(kept, (0..22).collect::<Vec<_>>());
+        // Similarity falls off at the same sites, so strict agrees.
+        assert_eq!(
+            aln.trim::<Nucleotide>(Trim::Strict),
+            aln.slice_columns(0..22)
+        );
+    }
+
+    #[test]
+    fn concatenation_and_subsets() {
+        let mut gene1 = alignment(&[("a", b"AC"), ("b", b"AG")]);
+        gene1.set_weight(1, 2.0);
+        let gene2 = alignment(&[("c", b"TTT"), ("a", b"GGG")]);
+        let (matrix, parts) = Alignment::concatenate([("gene1", &gene1), ("gene2", &gene2)]);
+        assert_eq!(matrix.names(), ["a", "b", "c"]);
+        assert_eq!(matrix.row("b").unwrap(), b"AG???");
+        assert_eq!(matrix.row("c").unwrap(), b"??TTT");
+        assert_eq!(matrix.weights(), [1.0, 2.0, 1.0]);
+        assert_eq!(parts[1].name, "gene2");
+        assert_eq!(parts[1].sites, [2, 3, 4]);
+
+        let sub = matrix.select_taxa(&["c", "a"]).unwrap();
+        assert_eq!(sub.names(), ["c", "a"]);
+        assert_eq!(
+            matrix.select_taxa(&["x"]),
+            Err(AlignmentError::UnknownTaxon("x".to_string()))
+        );
+
+        #[cfg(feature = "simple_rooted_tree")]
+        {
+            use crate::prelude::Newick;
+            let tree = PhyloTree::from_newick("(c,a);".as_bytes()).unwrap();
+            assert_eq!(matrix.match_tree(&tree).unwrap().names(), ["a", "c"]);
+        }
+
+        let composition = matrix.composition::<Nucleotide>();
+        // a: A C G G G, b (weight 2): A G, c: T T T
+        let freq = |n: f64| n / 12.0;
+        assert_eq!(
+            composition.frequencies,
+            [freq(3.0), freq(1.0), freq(5.0), freq(3.0)]
+        );
+        assert_eq!(composition.taxa[2], [0.0, 0.0, 0.0, 1.0]);
+        assert!(composition.p_values[2] < composition.p_values[0]);
+    }
+}
@@ -46,6 +46,192 @@ pub enum NewickError {
     /// The input contained no tree
     #[error("empty input: no tree found")]
     Empty,
+    /// Two occurrences of the same extended Newick hybrid node both list
+    /// children
+    #[error("hybrid node #{tag} given children a second time at byte {idx}")]
+    DuplicateHybrid {
+        /// Byte offset at which the second occurrence started
+        idx: usize,
+        /// The hybrid tag, without its `#`
+        tag: String,
+    },
+    /// An extended Newick hybrid node lies below one of its own occurrences
+    #[error("hybrid node #{tag} is its own ancestor")]
+    HybridCycle {
+        /// The hybrid tag, without its `#`
+        tag: String,
+    },
+    /// An extended Newick inheritance probability is outside `[0, 1]`
+    #[error("inheritance probability at byte {idx} is outside [0, 1]: {text:?}")]
+    InvalidInheritance {
+        /// Byte offset at which the probability started
+        idx: usize,
+        /// The probability as written
+        text: String,
+    },
+}
+
+/// A type for errors when building, parsing or clustering a labelled distance
+/// matrix
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum MatrixError {
+    /// Two rows carry the same label
+    #[error("duplicate label {0:?}")]
+    DuplicateLabel(String),
+    /// A leaf has no taxon label to name its row
+    #[error("leaf {0} has no taxon label")]
+    UnlabelledLeaf(usize),
+    /// The number of rows differs from the number of labels
+    #[error("{actual} rows given for {expected} labels")]
+    RowCount {
+        /// Number of labels
+        expected: usize,
+        /// Number of rows
+        actual: usize,
+    },
+    /// A row has the wrong number of entries
+    #[error("row {row} has {actual} entries, expected {expected}")]
+    RowLength {
+        /// Zero-based row index
+        row: usize,
+        /// Number of entries the layout requires
+        expected: usize,
+        /// Number of entries found
+        actual: usize,
+    },
+    /// An entry did not parse as a number
+    #[error("invalid entry in row {row}: {text:?}")]
+    InvalidValue {
+        /// Zero-based row index
+        row: usize,
+        /// The text that failed to parse
+        text: String,
+    },
+    /// The header (taxon count, column labels or binary magic) is malformed
+    #[error("invalid header: {0}")]
+    InvalidHeader(String),
+    /// The input ended before every row was read
+    #[error("input ended after {0} complete rows")]
+    Truncated(usize),
+    /// The number of clusters is zero or exceeds the number of rows
+    #[error("cannot form {requested} clusters from {rows} rows")]
+    ClusterCount {
+        /// Number of clusters asked for
+        requested: usize,
+        /// Number of rows in the matrix
+        rows: usize,
+    },
+}
+
+/// A type for errors when building an alignment or reading one from a file.
+/// Lines and columns are 1-based; columns count bytes.
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum AlignmentError {
+    /// The input does not follow the format
+    #[error("line {line}, column {column}: {reason}")]
+    Syntax {
+        /// Line of the problem
+        line: usize,
+        /// Column of the problem
+        column: usize,
+        /// What was wrong
+        reason: String,
+    },
+    /// Two rows carry the same taxon name
+    #[error("line {line}: duplicate taxon {name:?}")]
+    DuplicateTaxon {
+        /// Line on which the second row starts
+        line: usize,
+        /// The repeated name
+        name: String,
+    },
+    /// A row has a different number of sites from the alignment
+    #[error("line {line}: taxon {name:?} has {found} sites, expected {expected}")]
+    Length {
+        /// Line on which the row starts
+        line: usize,
+        /// Taxon name of the row
+        name: String,
+        /// Declared width, or the width of the first row
+        expected: usize,
+        /// Number of sites read for the row
+        found: usize,
+    },
+    /// The number of rows differs from the declared number of taxa
+    #[error("expected {expected} taxa, found {found}")]
+    TaxonCount {
+        /// Declared number of taxa
+        expected: usize,
+        /// Number of rows read
+        found: usize,
+    },
+    /// Two rows given to [`Alignment::new`](crate::alignment::Alignment::new)
+    /// carry the same name
+    #[error("duplicate taxon name {0:?}")]
+    DuplicateName(String),
+    /// A row given to [`Alignment::new`](crate::alignment::Alignment::new) is
+    /// not as long as the first
+    #[error("row {row} has {found} sites, expected {expected}")]
+    RowLength {
+        /// Zero-based row index
+        row: usize,
+        /// Length of the first row
+        expected: usize,
+        /// Length of this row
+        found: usize,
+    },
+    /// No row carries the taxon name
+    #[error("no row for taxon {0:?}")]
+    UnknownTaxon(String),
+    /// The format could not be told from the start of the input
+    #[error("unrecognised alignment format")]
+    UnknownFormat,
+    /// The input holds no sequences
+    #[error("no sequences")]
+    Empty,
+}
+
+/// A type for errors when reading PhyloXML or NeXML documents
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum XmlError {
+    /// The input is not well-formed XML
+    #[error("malformed XML at byte {idx}: {reason}")]
+    Malformed {
+        /// Byte offset at which the problem was found
+        idx: usize,
+        /// What was wrong
+        reason: String,
+    },
+    /// An end tag does not close the element that is open
+    #[error("expected </{expected}> at byte {idx}, found </{found}>")]
+    MismatchedTag {
+        /// Byte offset of the end tag
+        idx: usize,
+        /// Name of the open element
+        expected: String,
+        /// Name in the end tag
+        found: String,
+    },
+    /// A branch length, confidence or label did not parse
+    #[error("invalid value at byte {idx}: {text:?}")]
+    InvalidValue {
+        /// Byte offset of the element or attribute holding the value
+        idx: usize,
+        /// The text that failed to parse
+        text: String,
+    },
+    /// A NeXML reference names no node or OTU in the document
+    #[error("unknown id {0:?}")]
+    UnknownId(String),
+    /// The nodes and edges of a NeXML tree do not form a rooted tree
+    #[error("not a rooted tree: {0}")]
+    NotATree(String),
+    /// The document holds no tree
+    #[error("no tree found")]
+    NoTree,
+    /// The XML declaration names an encoding other than UTF-8 or ISO-8859-1
+    #[error("unsupported encoding {0:?} (expected UTF-8 or ISO-8859-1)")]
+    UnsupportedEncoding(String),
 }
 
 /// A type for errors when parsing Nexus files
@@ -99,6 +285,13 @@ pub enum TreeError {
     /// The two trees do not span the same taxa, so they cannot be compared
     #[error("trees do not span the same taxa set")]
     TaxaSetMismatch,
+    /// The operation needs a binary tree, and this node has more than two
+    /// children
+    #[error("node {0} has more than two children; the operation needs a binary tree")]
+    NotBinary(usize),
+    /// The operation matches leaves by taxon, and this leaf has none
+    #[error("leaf {0} has no taxon label")]
+    UnlabelledLeaf(usize),
 }
 
 /// A type for errors during ancestral sequence reconstruction
@@ -122,4 +315,91 @@ pub enum AsrError {
     /// A substitution model parameter (e.g. kappa, alpha, p_inv) was out of range
     #[error("invalid model parameter: {0}")]
     InvalidModelParameter(String),
+    /// A node of the tree does not have the two children (three at the root)
+    /// a binary tree gives it
+    #[error("node {0} is not binary")]
+    NotBinary(usize),
+}
+
+/// A type for errors from comparative methods on continuous traits
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum ComparativeError {
+    /// A leaf of the tree has no trait value
+    #[error("no trait value for taxon {0:?}")]
+    MissingTrait(String),
+    /// A trait value was given for a taxon that is not a leaf of the tree
+    #[error("taxon {0:?} is not a leaf of this tree")]
+    UnknownTaxon(String),
+    /// A trait value is NaN or infinite
+    #[error("trait value for taxon {0:?} is not finite")]
+    NonFiniteTrait(String),
+    /// A non-root branch has no length, or a negative one
+    #[error("branch above node {0} has no length or a negative one")]
+    MissingBranchLength(usize),
+    /// The tree does not have enough taxa for the method
+    #[error("method needs at least {expected} taxa, tree has {actual}")]
+    TooFewTaxa {
+        /// Minimum number of taxa the method requires
+        expected: usize,
+        /// Number of taxa the tree actually has
+        actual: usize,
+    },
+    /// Two sister lineages have no branch length between them, so their
+    /// contrast has zero variance
+    #[error("zero-variance contrast at node {0}")]
+    ZeroLengthContrast(usize),
+    /// A model parameter (e.g. alpha, lambda) was out of range
+    #[error("invalid parameter: {0}")]
+    InvalidParameter(String),
+    /// The trait covariance matrix is not positive definite
+    #[error("trait covariance matrix is singular")]
+    SingularCovariance,
+}
+
+/// A type for errors when building a phylogenetic network
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum NetworkError {
+    /// The given id does not name a node of this network
+    #[error("no node with id {0} in this network")]
+    UnknownNode(usize),
+    /// The edge would lead back into the root
+    #[error("the root cannot be given a parent")]
+    RootParent,
+    /// The edge would close a directed cycle
+    #[error("an edge from {parent} to {child} would close a cycle")]
+    Cycle {
+        /// Node the edge would leave
+        parent: usize,
+        /// Node the edge would enter
+        child: usize,
+    },
+    /// The given pair is not an edge of this network
+    #[error("({0}, {1}) is not an edge of this network")]
+    UnknownEdge(usize, usize),
+    /// An inheritance probability is outside `[0, 1]`
+    #[error("inheritance probability {0} is outside [0, 1]")]
+    InvalidInheritance(f32),
+}
+
+/// A type for errors from gene tree / species tree reconciliation
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum ReconciliationError {
+    /// A gene tree leaf has no taxon label
+    #[error("gene tree leaf {0} has no taxon label")]
+    UnlabelledGene(usize),
+    /// A gene tree leaf has no entry in the gene to species map
+    #[error("gene {0:?} has no species in the map")]
+    UnmappedGene(String),
+    /// A gene maps to a species that is not a leaf of the species tree
+    #[error("species {0:?} is not a leaf of the species tree")]
+    UnknownSpecies(String),
+    /// An internal gene tree node does not have exactly two children
+    #[error("gene tree node {0} does not have exactly two children")]
+    NonBinaryGene(usize),
+    /// A species tree node has more than two children
+    #[error("species tree node {0} has more than two children")]
+    NonBinarySpecies(usize),
+    /// An event cost is negative or not finite
+    #[error("invalid event cost: {0}")]
+    InvalidCost(String),
 }
@@ -4,6 +4,9 @@ pub mod node_iter;
 /// Module with the borrowing constant-time LCA oracle
 pub mod lca;
 
+/// Module with the borrowing constant-time path-length oracle
+pub mod path;
+
 use crate::{node::Node, prelude::*};
 use itertools::Itertools;
 use std::collections::VecDeque;
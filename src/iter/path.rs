@@ -0,0 +1,243 @@
+//! Constant-time path lengths over an immutably borrowed tree.
+//!
+//! [`PathOracle`] pairs an [`LcaOracle`] with the weighted distance from the
+//! root to every node, computed once in a single preorder pass. The path
+//! between `u` and `v` runs through their LCA, so its length is
+//! `d(u) + d(v) - 2 d(lca)`: two array reads and one RMQ, with no walk to the
+//! root. The same holds for edge counts, read from the LCA oracle's own depth
+//! array. Build one with
+//! [`DistanceMatrix::path_oracle`](crate::tree::distances::DistanceMatrix::path_oracle);
+//! like the LCA oracle, it freezes the tree for its lifetime.
+
+use crate::iter::lca::LcaOracle;
+use crate::iter::node_iter::EulerWalk;
+use crate::node::simple_rnode::RootedWeightedNode;
+use crate::tree::simple_rtree::{RootedWeightedTree, TreeNodeID, TreeNodeWeight};
+
+use num::{One, Zero};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// A constant-time path-length oracle borrowing a tree immutably.
+///
+/// A missing edge weight counts as one, as in
+/// [`DistanceMatrix::pairwise_distance`](crate::tree::distances::DistanceMatrix::pairwise_distance),
+/// which [`DistanceMatrix::path_distance`](crate::tree::distances::DistanceMatrix::path_distance)
+/// answers through this oracle.
+pub struct PathOracle<'t, Tree>
+where
+    Tree: EulerWalk + RootedWeightedTree,
+    Tree::Node: RootedWeightedNode,
+{
+    /// LCA index over the same tree.
+    lca: LcaOracle<'t, Tree>,
+    /// Weighted distance from the root to each node, indexed by node id. Zero
+    /// for the root and for arena slots holding no node.
+    root_distance: Vec<TreeNodeWeight<Tree>>,
+    /// Leaf ids, in the order rows of the leaf matrix are filled.
+    leaves: Vec<TreeNodeID<Tree>>,
+}
+
+impl<'t, Tree> PathOracle<'t, Tree>
+where
+    Tree: EulerWalk + RootedWeightedTree,
+    Tree::Node: RootedWeightedNode,
+{
+    /// Builds the LCA index and root distances from a shared borrow of `tree`.
+    pub(crate) fn build(tree: &'t Tree) -> Self {
+        let max_id: usize = tree
+            .get_node_ids()
+            .map(Into::<usize>::into)
+            .max()
+            .expect("a tree always has at least a root node");
+
+        // Preorder, so a parent's distance is final before its children read it.
+        let mut root_distance = vec![TreeNodeWeight::<Tree>::zero(); max_id + 1];
+        let mut stack = vec![tree.get_root_id()];
+        while let Some(node_id) = stack.pop() {
+            let here = root_distance[Into::<usize>::into(node_id)];
+            for child_id in tree.get_node_children_ids(node_id) {
+                let edge = tree
+                    .get_edge_weight(node_id, child_id)
+                    .unwrap_or(TreeNodeWeight::<Tree>::one());
+                root_distance[Into::<usize>::into(child_id)] = here + edge;
+                stack.push(child_id);
+            }
+        }
+
+        PathOracle {
+            lca: tree.lca(),
+            root_distance,
+            leaves: tree.get_leaf_ids().collect(),
+        }
+    }
+
+    /// The LCA oracle this index is built on.
+    pub fn lca(&self) -> &LcaOracle<'t, Tree> {
+        &self.lca
+    }
+
+    /// Weighted distance from the root to `node_id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is outside the arena of the tree this oracle was
+    /// built from.
+    pub fn root_distance(&self, node_id: TreeNodeID<Tree>) -> TreeNodeWeight<Tree> {
+        self.root_distance[Into::<usize>::into(node_id)]
+    }
+
+    /// Number of edges between the root and `node_id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is not a node of the tree this oracle was built
+    /// from.
+    pub fn depth(&self, node_id: TreeNodeID<Tree>) -> usize {
+        self.lca.get_node_depth(node_id)
+    }
+
+    /// Weighted length of the path between two nodes, in O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if either id is not a node of the tree this oracle was built
+    /// from.
+    pub fn path_length(
+        &self,
+        node_id_1: TreeNodeID<Tree>,
+        node_id_2: TreeNodeID<Tree>,
+    ) -> TreeNodeWeight<Tree> {
+        let lca = self.lca.get_lca_id(&[node_id_1, node_id_2]);
+        let d_lca = self.root_distance(lca);
+        (self.root_distance(node_id_1) - d_lca) + (self.root_distance(node_id_2) - d_lca)
+    }
+
+    /// Number of edges on the path between two nodes, in O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if either id is not a node of the tree this oracle was built
+    /// from.
+    pub fn path_edges(&self, node_id_1: TreeNodeID<Tree>, node_id_2: TreeNodeID<Tree>) -> usize {
+        let lca = self.lca.get_lca_id(&[node_id_1, node_id_2]);
+        let d_lca = self.depth(lca);
+        (self.depth(node_id_1) - d_lca) + (self.depth(node_id_2) - d_lca)
+    }
+
+    /// Leaf ids, in the row and column order of
+    /// [`leaf_distance_matrix`](Self::leaf_distance_matrix).
+    pub fn leaf_ids(&self) -> &[TreeNodeID<Tree>] {
+        &self.leaves
+    }
+
+    /// Patristic distance between every pair of leaves, in the order of
+    /// [`leaf_ids`](Self::leaf_ids). O(n²) for n leaves.
+    pub fn leaf_distance_matrix(&self) -> Vec<Vec<TreeNodeWeight<Tree>>> {
+        self.leaves
+            .iter()
+            .map(|&leaf| self.leaf_row(leaf))
+            .collect()
+    }
+
+    #[cfg(feature = "parallel")]
+    /// Patristic distance between every pair of leaves, filling rows in
+    /// parallel.
+    pub fn leaf_distance_matrix_par(&self) -> Vec<Vec<TreeNodeWeight<Tree>>> {
+        self.leaves
+            .par_iter()
+            .map(|&leaf| self.leaf_row(leaf))
+            .collect()
+    }
+
+    fn leaf_row(&self, leaf: TreeNodeID<Tree>) -> Vec<TreeNodeWeight<Tree>> {
+        self.leaves
+            .iter()
+            .map(|&other| self.path_length(leaf, other))
+            .collect()
+    }
+
+    /// Returns the number of bytes this index has allocated on the heap,
+    /// including the LCA oracle.
+    pub fn heap_size(&self) -> usize {
+        self.lca.heap_size()
+            + self.root_distance.capacity() * std::mem::size_of::<TreeNodeWeight<Tree>>()
+            + self.leaves.capacity() * std::mem::size_of::<TreeNodeID<Tree>>()
+    }
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use crate::prelude::*;
+    use crate::tree::PhyloTree;
+
+    /// `((A:1,B:2)X:0.5,(C:3,D)Y:1.5)R;` — `D` has no weight, so counts one.
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick(b"((A:1,B:2)X:0.5,(C:3,D)Y:1.5)R;").unwrap()
+    }
+
+    fn id(tree: &PhyloTree, taxon: &str) -> usize {
+        tree.get_taxa_node_id(&taxon.to_string()).unwrap()
+    }
+
+    #[test]
+    fn path_lengths_and_edges() {
+        let tree = tree();
+        let oracle = tree.path_oracle();
+        let [a, b, c, d, x, r] = ["A", "B", "C", "D", "X", "R"].map(|t| id(&tree, t));
+        assert_eq!(oracle.path_length(a, b), 3.0);
+        assert_eq!(oracle.path_length(a, c), 1.0 + 0.5 + 1.5 + 3.0);
+        assert_eq!(oracle.path_length(a, d), 1.0 + 0.5 + 1.5 + 1.0);
+        assert_eq!(oracle.path_length(x, a), 1.0);
+        assert_eq!(oracle.path_length(c, c), 0.0);
+        assert_eq!(oracle.path_edges(a, b), 2);
+        assert_eq!(oracle.path_edges(a, c), 4);
+        assert_eq!(oracle.path_edges(r, d), 2);
+        assert_eq!(oracle.path_edges(b, b), 0);
+    }
+
+    #[test]
+    fn root_distances_and_depths() {
+        let tree = tree();
+        let oracle = tree.path_oracle();
+        let [a, d, x, y, r] = ["A", "D", "X", "Y", "R"].map(|t| id(&tree, t));
+        assert_eq!(oracle.root_distance(r), 0.0);
+        assert_eq!(oracle.root_distance(x), 0.5);
+        assert_eq!(oracle.root_distance(a), 1.5);
+        assert_eq!(oracle.root_distance(d), 2.5);
+        assert_eq!(oracle.depth(r), 0);
+        assert_eq!(oracle.depth(y), 1);
+        assert_eq!(oracle.depth(d), 2);
+    }
+
+    #[test]
+    fn unweighted_trees_count_edges() {
+        let tree = PhyloTree::from_newick(b"((A,B),(C,(D,E)));").unwrap();
+        let oracle = tree.path_oracle();
+        for &u in oracle.leaf_ids() {
+            for &v in oracle.leaf_ids() {
+                assert_eq!(oracle.path_length(u, v), oracle.path_edges(u, v) as f32);
+            }
+            assert_eq!(oracle.root_distance(u), oracle.depth(u) as f32);
+        }
+    }
+
+    #[test]
+    fn leaf_distance_matrix() {
+        let tree = tree();
+        let oracle = tree.path_oracle();
+        let leaves = oracle.leaf_ids();
+        let matrix = oracle.leaf_distance_matrix();
+        assert_eq!(matrix.len(), 4);
+        for (i, &u) in leaves.iter().enumerate() {
+            assert_eq!(matrix[i].len(), 4);
+            for (j, &v) in leaves.iter().enumerate() {
+                assert_eq!(matrix[i][j], oracle.path_length(u, v));
+                assert_eq!(matrix[i][j], matrix[j][i]);
+            }
+        }
+        #[cfg(feature = "parallel")]
+        assert_eq!(oracle.leaf_distance_matrix_par(), matrix);
+    }
+}
@@ -11,9 +11,9 @@
 //! - **Trait-first design** — compose narrow traits (`RootedTree`, `RootedMetaTree`, `EulerWalk`, `DFS`, `Clusters`, …) onto any type, or use the batteries-included [`PhyloTree`](crate::tree::PhyloTree).
 //! - **Arena-allocated trees** — cache-friendly `Vec`-backed storage with `usize` node IDs.
 //! - **Constant-time LCA** — an [`LcaOracle`](crate::iter::lca::LcaOracle) borrows the tree immutably and answers LCA queries in O(1) via an Euler tour + RMQ.
-//! - **Tree comparison** — Robinson-Foulds, weighted RF, cluster affinity, and cophenetic distance, with distance-matrix builders.
+//! - **Tree comparison** — Robinson-Foulds, weighted RF, cluster affinity, cophenetic and BHV geodesic distance, rooted SPR and TBR distance via agreement forests, maximum agreement subtrees and rogue taxon search, with distance-matrix builders.
 //! - **Maximum-likelihood modelling** — GTR+I+G substitution models (JC69 through GTR), Felsenstein-pruning log-likelihood, and marginal/joint ancestral sequence reconstruction.
-//! - **I/O** — Newick and Nexus parsing and serialization.
+//! - **I/O** — Newick, extended Newick (phylogenetic networks), Nexus, PhyloXML and NeXML trees; FASTA, PHYLIP, Nexus, Stockholm and Clustal alignments.
 //! - **Simulation** — random trees (Yule, uniform).
 //! - **Optional parallelism** — opt into `rayon`-backed computation with the `parallel` feature.
 //! - **Fallible by default** — operations that a caller can misuse return [`Result`] with a typed [`error::TreeError`]; the library does not panic on bad input.
@@ -150,14 +150,30 @@
 //! | Module | What it does |
 //! | --- | --- |
 //! | [`tree::simple_rtree`] | Core tree traits and `SimpleRootedTree`. |
+//! | [`tree::network`] | Rooted phylogenetic networks in extended Newick: displayed trees, softwired clusters, level and tree-child checks. |
 //! | [`tree::ops`] | Mutating operations: SPR, NNI, reroot, contraction, subtree extraction. |
-//! | [`tree::distances`] | RF, weighted RF, cluster affinity, cophenetic distance, distance matrices. |
-//! | [`tree::io`] | Newick and Nexus reading/writing. |
+//! | [`tree::distances`] | RF, weighted RF, cluster affinity, cophenetic distance, BHV geodesic distance with Fréchet mean and median, distance matrices. |
+//! | [`tree::agreement`] | Maximum agreement forests with exact and approximate rooted SPR and TBR distances. |
+//! | [`tree::species`] | ASTRAL-style species trees from gene trees, with quartet support and local posteriors. |
+//! | [`tree::splits`] | Split distances on one encoding: normalized RF, Nye, clustering and phylogenetic information, matching split, quartet and triplet. |
+//! | [`tree::treeset`] | Tree collections encoded once, with streamed all-pairs RF, weighted RF and cluster affinity matrices. |
+//! | [`tree::treespace`] | Classical MDS embedding and k-medoids clustering of tree distance matrices. |
+//! | [`tree::io`] | Newick, Nexus, PhyloXML and NeXML reading/writing. |
+//! | [`alignment::formats`] | Alignment reading and writing: FASTA, PHYLIP, Nexus, Stockholm and Clustal, with format detection. |
+//! | [`alignment::ops`] | Gappy and invariant site removal, trimAl-style trimming, masking, taxon subsetting, supermatrix concatenation, gap and composition summaries. |
 //! | [`tree::simulation`] | Random tree generation. |
-//! | [`iter`] | Traversals, Euler walks, and the LCA oracle. |
+//! | [`iter`] | Traversals, Euler walks, and the LCA and path-length oracles. |
 //! | [`models`] | GTR+I+G substitution models and their named special cases. |
-//! | [`tree::likelihood`] | Felsenstein-pruning log-likelihood. |
+//! | [`tree::likelihood`] | Felsenstein-pruning log-likelihood over blocks of patterns, optionally parallel, an engine caching partial likelihoods across branch and topology edits, stochastic character mapping, and SH-like aLRT and aBayes branch support. |
+//! | [`tree::mast`] | Maximum agreement subtrees of rooted trees and rogue taxon search over tree sets. |
+//! | [`tree::matrix`] | Labelled leaf distance matrices with PHYLIP, CSV/TSV and binary IO. |
 //! | [`tree::asr`] | Marginal and joint ancestral sequence reconstruction. |
+//! | [`tree::continuous`] | Continuous traits: independent contrasts, Brownian motion and OU fits, Pagel transforms. |
+//! | [`tree::diversity`] | Community phylogenetic diversity: Faith's PD, MPD, MNTD, endemism and effect sizes. |
+//! | [`tree::reconciliation`] | Gene tree / species tree reconciliation: LCA duplication-loss mapping, best rooting and DTL. |
+//! | [`tree::signal`] | Phylogenetic signal (Blomberg's K, Pagel's lambda, Moran's I) and PGLS regression. |
+//! | [`tree::bootstrap`] | Nonparametric bootstrap and delete-half jackknife over pattern multiplicities, with seeded, optionally parallel tree building and split support annotation. |
+//! | [`tree::parsimony`] | Bit-parallel Fitch scoring and NNI/SPR/TBR parsimony search. |
 //! | [`error`] | [`error::TreeError`] and the parsing/model error types. |
 //!
 //! # Examples
@@ -174,7 +190,7 @@
 //! cargo run --example phylogenetic-diversity
 //! ```
 //!
-//! **Visualizing tree space** — all pairwise distances across a set of trees. Run it, then plot with `examples/visualization/tree-space.py`:
+//! **Visualizing tree space** — all pairwise Robinson-Foulds distances across a set of trees, embedded in two dimensions by classical MDS and clustered by k-medoids, with each cluster's medoid and consensus tree. Run it, then plot with `examples/visualization/tree-space.py`:
 //!
 //! ```sh
 //! cargo run --example pairwise-distances
@@ -189,7 +205,7 @@
 //! | `lca` | `LcaOracle` construction, O(1) queries, and the uncached walk. |
 //! | `traversal` | Post-order traversal, bipartitions, median node. |
 //! | `construction` | Yule simulation, SPR, tree contraction. |
-//! | `distances` | RF, cluster matching, cluster affinity, cophenetic distance. |
+//! | `distances` | RF, cluster matching, cluster affinity, quartet distance, BHV distance, SPR distance, cophenetic distance, leaf distance matrix, tree-set RF matrix. |
 //!
 //! ```sh
 //! cargo bench                      # everything
@@ -254,6 +270,10 @@ pub mod models;
 
 /// Prelude module that imports all active and tested traits along with any required struct and type alias.
 pub mod prelude {
+    #[doc(no_inline)]
+    pub use crate::alignment::formats::*;
+    #[doc(no_inline)]
+    pub use crate::alignment::ops::*;
     #[doc(no_inline)]
     pub use crate::alignment::*;
     #[doc(no_inline)]
@@ -264,23 +284,56 @@ pub mod prelude {
     pub use crate::iter::lca::*;
     #[doc(no_inline)]
     pub use crate::iter::node_iter::*;
+    #[doc(no_inline)]
+    pub use crate::iter::path::*;
     pub use crate::models::*;
     #[doc(no_inline)]
     pub use crate::node::{simple_rnode::*, Node, PhyloNode};
     #[doc(no_inline)]
+    pub use crate::tree::agreement::*;
+    #[doc(no_inline)]
     pub use crate::tree::asr::*;
+    #[cfg(feature = "simple_rooted_tree")]
+    #[doc(no_inline)]
+    pub use crate::tree::bootstrap::*;
+    #[doc(no_inline)]
+    pub use crate::tree::continuous::*;
     #[doc(no_inline)]
     pub use crate::tree::distances::*;
     #[doc(no_inline)]
+    pub use crate::tree::diversity::*;
+    #[doc(no_inline)]
     pub use crate::tree::io::*;
     #[doc(no_inline)]
     pub use crate::tree::likelihood::*;
     #[doc(no_inline)]
+    pub use crate::tree::mast::*;
+    #[doc(no_inline)]
+    pub use crate::tree::matrix::*;
+    #[cfg(feature = "simple_rooted_tree")]
+    #[doc(no_inline)]
+    pub use crate::tree::network::*;
+    #[doc(no_inline)]
     pub use crate::tree::ops::*;
     #[doc(no_inline)]
+    pub use crate::tree::parsimony::*;
+    #[doc(no_inline)]
+    pub use crate::tree::reconciliation::*;
+    #[doc(no_inline)]
+    pub use crate::tree::signal::*;
+    #[doc(no_inline)]
     pub use crate::tree::simple_rtree::*;
     #[doc(no_inline)]
     pub use crate::tree::simulation::*;
+    #[cfg(feature = "simple_rooted_tree")]
+    #[doc(no_inline)]
+    pub use crate::tree::species::*;
+    #[doc(no_inline)]
+    pub use crate::tree::splits::*;
+    #[doc(no_inline)]
+    pub use crate::tree::treeset::*;
+    #[doc(no_inline)]
+    pub use crate::tree::treespace::*;
 
     #[cfg(feature = "simple_rooted_tree")]
     pub use crate::tree::{PhyloTree, SimpleRootedTree};
@@ -97,7 +97,7 @@ fn point_gamma(prob: f64, shape: f64, rate: f64) -> Result<f64, AsrError> {
 }
 
 /// Natural log of the gamma function (Lanczos approximation).
-fn ln_gamma(x: f64) -> f64 {
+pub(crate) fn ln_gamma(x: f64) -> f64 {
     const G: f64 = 7.0;
     const COEFFS: [f64; 9] = [
         0.999_999_999_999_809_9,
@@ -126,7 +126,7 @@ fn ln_gamma(x: f64) -> f64 {
 }
 
 /// Regularized lower incomplete gamma function P(shape, x) = gamma(shape, x) / Gamma(shape).
-fn incomplete_gamma(x: f64, shape: f64) -> f64 {
+pub(crate) fn incomplete_gamma(x: f64, shape: f64) -> f64 {
     if x <= 0.0 {
         return 0.0;
     }
@@ -175,6 +175,54 @@ fn incomplete_gamma(x: f64, shape: f64) -> f64 {
     }
 }
 
+/// Regularized incomplete beta function `I_x(a, b)`, by Lentz's continued
+/// fraction.
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    // The fraction converges fast only below the mean; use the symmetry
+    // `I_x(a, b) = 1 - I_{1-x}(b, a)` above it.
+    if x > (a + 1.0) / (a + b + 2.0) {
+        return 1.0 - incomplete_beta(1.0 - x, b, a);
+    }
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..500 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    (ln_front.exp() * h / a).clamp(0.0, 1.0)
+}
+
 /// Approximate inverse standard normal CDF (Acklam's algorithm), used only to seed
 /// Newton-Raphson in [`point_gamma`].
 fn inverse_normal_cdf(p: f64) -> f64 {
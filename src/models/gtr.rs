@@ -63,6 +63,12 @@ impl<A: Alphabet> GtrModel<A> {
         self.matrix.transition(rate * t)
     }
 
+    /// Returns the instantaneous rate matrix `Q` of the plain (unscaled by
+    /// category) generator. Category `c` evolves under `categories()[c].rate * Q`.
+    pub fn generator(&self) -> DMatrix<f64> {
+        self.matrix.generator()
+    }
+
     /// Returns the equilibrium frequencies.
     pub fn equilibrium(&self) -> &nalgebra::DVector<f64> {
         self.matrix.equilibrium()
@@ -138,6 +138,31 @@ impl RateMatrix {
     pub fn equilibrium(&self) -> &DVector<f64> {
         &self.pi
     }
+
+    /// Returns the instantaneous rate matrix `Q`, rebuilt from the stored
+    /// eigendecomposition as `diag(1/sqrt(pi)) * V * Lambda * V^T * diag(sqrt(pi))`.
+    ///
+    /// Off-diagonal entries are clamped at zero, so round-off never yields a
+    /// negative rate, and each diagonal is reset to minus its row sum.
+    pub fn generator(&self) -> DMatrix<f64> {
+        let n = self.n_states;
+        let s = &self.eigenvectors
+            * DMatrix::from_diagonal(&self.eigenvalues)
+            * self.eigenvectors.transpose();
+
+        let mut q = DMatrix::zeros(n, n);
+        for i in 0..n {
+            let mut row_sum = 0.0;
+            for j in 0..n {
+                if i != j {
+                    q[(i, j)] = (self.inv_sqrt_pi[i] * s[(i, j)] * self.sqrt_pi[j]).max(0.0);
+                    row_sum += q[(i, j)];
+                }
+            }
+            q[(i, i)] = -row_sum;
+        }
+        q
+    }
 }
 
 #[cfg(test)]
@@ -161,4 +186,28 @@ mod tests {
             assert!((sum - 1.0).abs() < 1e-10);
         }
     }
+
+    #[test]
+    fn test_generator_matches_transition() {
+        let pi = vec![0.1, 0.2, 0.3, 0.4];
+        let w = DMatrix::from_row_slice(
+            4,
+            4,
+            &[
+                0.0, 1.0, 2.0, 1.0, 1.0, 0.0, 1.0, 3.0, 2.0, 1.0, 0.0, 1.0, 1.0, 3.0, 1.0, 0.0,
+            ],
+        );
+        let model = RateMatrix::new(4, pi, w, true).unwrap();
+        let q = model.generator();
+
+        for i in 0..4 {
+            let sum: f64 = q.row(i).iter().sum();
+            assert!(sum.abs() < 1e-10);
+        }
+        // P(t) ~ I + Qt for small t.
+        let t = 1e-6;
+        let p_t = model.transition(t);
+        let approx = DMatrix::identity(4, 4) + &q * t;
+        assert!((p_t - approx).abs().max() < 1e-10);
+    }
 }
@@ -2,8 +2,8 @@
 pub mod simple_rnode;
 
 use crate::node::simple_rnode::{
-    EdgeWeight, NodeTaxa, NodeWeight, RootedAnnotatedNode, RootedMetaNode, RootedTreeNode,
-    RootedWeightedNode, RootedZetaNode,
+    EdgeWeight, NodeTaxa, NodeWeight, RootedAnnotatedNode, RootedMetaNode, RootedSupportNode,
+    RootedTreeNode, RootedWeightedNode, RootedZetaNode,
 };
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
@@ -75,6 +75,9 @@ where
     /// parse it however they like; `Arc<str>` so cloning a node — or the whole
     /// tree — shares the string rather than reallocating it.
     annotation: Option<Arc<str>>,
+    /// Support values of edge ending in node (e.g. bootstrap percentages read
+    /// from internal Newick labels). Shared on clone, like `annotation`.
+    support: Option<Arc<[f32]>>,
 }
 
 impl<T, W, Z> RootedTreeNode for Node<T, W, Z>
@@ -94,6 +97,7 @@ where
             weight: None,
             zeta: None,
             annotation: None,
+            support: None,
         }
     }
 
@@ -235,6 +239,21 @@ where
     }
 }
 
+impl<T, W, Z> RootedSupportNode for Node<T, W, Z>
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    fn get_support(&self) -> Option<&[f32]> {
+        self.support.as_deref()
+    }
+
+    fn set_support(&mut self, support: Option<Arc<[f32]>>) {
+        self.support = support;
+    }
+}
+
 impl<T, W, Z> Debug for Node<T, W, Z>
 where
     T: NodeTaxa,
@@ -212,3 +212,25 @@ pub trait RootedAnnotatedNode: RootedTreeNode {
         self.set_annotation(None);
     }
 }
+
+/// A trait describing the behaviour of a Node whose incoming edge carries one
+/// or more support values, such as a bootstrap percentage or the SH-aLRT and
+/// UFBoot pair IQ-TREE writes as `95.3/98`. The values are kept in the order
+/// they were given.
+pub trait RootedSupportNode: RootedTreeNode {
+    /// Returns the support values of the edge leading into the node, if any.
+    fn get_support(&self) -> Option<&[f32]>;
+
+    /// Sets the support values of the edge leading into the node.
+    fn set_support(&mut self, support: Option<Arc<[f32]>>);
+
+    /// Returns true if the node carries support values.
+    fn is_supported(&self) -> bool {
+        self.get_support().is_some()
+    }
+
+    /// Clears the node's support values.
+    fn remove_support(&mut self) {
+        self.set_support(None);
+    }
+}
@@ -1,20 +1,60 @@
+/// Module with agreement forests and SPR/TBR distances
+pub mod agreement;
 /// Module with traits and structs for ancestral sequence reconstruction
 pub mod asr;
+/// Module with bootstrap and jackknife resampling of alignments
+#[cfg(feature = "simple_rooted_tree")]
+pub mod bootstrap;
+/// Module with continuous trait models and independent contrasts
+pub mod continuous;
 /// Module with traits and structs for distance computation
 pub mod distances;
+/// Module with phylogenetic diversity metrics for communities
+pub mod diversity;
 /// Module with traits and structs for tree encoding
 pub mod io;
 /// Module with phylogenetic likelihood under a substitution model
 pub mod likelihood;
+/// Module with maximum agreement subtrees and rogue taxa
+pub mod mast;
+/// Module with labelled distance matrices and their file formats
+pub mod matrix;
+/// Module with rooted phylogenetic networks
+#[cfg(feature = "simple_rooted_tree")]
+pub mod network;
 /// Iterative Newick-format parser
 #[cfg(feature = "simple_rooted_tree")]
 pub(crate) mod newick;
+/// NeXML reader and writer
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) mod nexml;
 /// Module with traits and structs for tree operations
 pub mod ops;
+/// Module with maximum-parsimony scoring and tree search
+pub mod parsimony;
+/// PhyloXML reader and writer
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) mod phyloxml;
+/// Module with gene tree / species tree reconciliation
+pub mod reconciliation;
+/// Module with phylogenetic signal statistics and PGLS regression
+pub mod signal;
 /// Module with traits and structs for general tree traits
 pub mod simple_rtree;
 /// Module with traits and structs for tree simulation
 pub mod simulation;
+/// Module with quartet-based species tree inference from gene trees
+#[cfg(feature = "simple_rooted_tree")]
+pub mod species;
+/// Module with split-based tree distances
+pub mod splits;
+/// Module with tree collections and their all-pairs distance matrices
+pub mod treeset;
+/// Module with embedding and clustering of tree distance matrices
+pub mod treespace;
+/// Minimal XML pull reader for the PhyloXML and NeXML parsers
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) mod xml;
 
 #[cfg(feature = "simple_rooted_tree")]
 pub use simple_rooted_tree::*;
@@ -39,7 +79,17 @@ mod simple_rooted_tree {
     use std::collections::{HashMap, HashSet};
 
     use crate::tree::asr::{JointAsr, MarginalAsr};
-    use crate::tree::likelihood::TreeLikelihood;
+    use crate::tree::continuous::{
+        AncestralStates, ContinuousTraits, Contrast, OuFit, PagelTransforms,
+    };
+    use crate::tree::diversity::{
+        Community, DiversityIndex, EffectSize, Metric, NullModel, PhylogeneticDiversity, RootRule,
+        Weighting,
+    };
+    use crate::tree::likelihood::{StochasticMap, StochasticMapping, TreeLikelihood};
+    use crate::tree::parsimony::TreeParsimony;
+    use crate::tree::reconciliation::{EventCosts, Reconcile, Reconciliation};
+    use crate::tree::signal::{LambdaFit, Pgls, PglsFit, PhylogeneticSignal, SignalTest};
 
     /// Type alias for Phylogenetic tree.
     pub type PhyloTree = SimpleRootedTree<String, f32, f32>;
@@ -75,6 +125,180 @@ mod simple_rooted_tree {
         }
     }
 
+    impl StochasticMapping for PhyloTree {
+        fn stochastic_maps<A: Alphabet>(
+            &self,
+            model: &GtrModel<A>,
+            aln: &Alignment,
+            n_samples: usize,
+            seed: u64,
+        ) -> Result<Vec<StochasticMap<A>>, AsrError> {
+            crate::tree::likelihood::simmap::compute_stochastic_maps(
+                self, model, aln, n_samples, seed,
+            )
+        }
+    }
+
+    impl ContinuousTraits for PhyloTree {
+        fn independent_contrasts(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+        ) -> Result<Vec<Contrast>, ComparativeError> {
+            crate::tree::continuous::compute_independent_contrasts(self, traits)
+        }
+
+        fn bm_ancestral_states(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+        ) -> Result<AncestralStates, ComparativeError> {
+            crate::tree::continuous::compute_bm_ancestral_states(self, traits)
+        }
+
+        fn ou_log_likelihood(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+            alpha: f64,
+            theta: f64,
+            sigma2: f64,
+        ) -> Result<f64, ComparativeError> {
+            crate::tree::continuous::compute_ou_log_likelihood(self, traits, alpha, theta, sigma2)
+        }
+
+        fn fit_ou(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+        ) -> Result<OuFit, ComparativeError> {
+            crate::tree::continuous::compute_ou_fit(self, traits)
+        }
+    }
+
+    impl Reconcile for PhyloTree {
+        fn reconcile(
+            &self,
+            species: &Self,
+            map: &std::collections::HashMap<String, String>,
+        ) -> Result<Reconciliation, ReconciliationError> {
+            crate::tree::reconciliation::compute_reconciliation(self, species, map)
+        }
+
+        fn best_rooting(
+            &self,
+            species: &Self,
+            map: &std::collections::HashMap<String, String>,
+            costs: &EventCosts,
+        ) -> Result<(Self, Reconciliation), ReconciliationError> {
+            crate::tree::reconciliation::compute_best_rooting(self, species, map, costs)
+        }
+
+        fn reconcile_dtl(
+            &self,
+            species: &Self,
+            map: &std::collections::HashMap<String, String>,
+            costs: &EventCosts,
+        ) -> Result<Reconciliation, ReconciliationError> {
+            crate::tree::reconciliation::compute_dtl_reconciliation(self, species, map, costs)
+        }
+    }
+
+    impl PagelTransforms for PhyloTree {
+        fn lambda_transform(&self, lambda: f64) -> Result<Self, ComparativeError> {
+            crate::tree::continuous::compute_lambda_transform(self, lambda)
+        }
+
+        fn kappa_transform(&self, kappa: f64) -> Result<Self, ComparativeError> {
+            crate::tree::continuous::compute_kappa_transform(self, kappa)
+        }
+
+        fn delta_transform(&self, delta: f64) -> Result<Self, ComparativeError> {
+            crate::tree::continuous::compute_delta_transform(self, delta)
+        }
+    }
+
+    impl PhylogeneticDiversity for PhyloTree {
+        fn faith_pd(&self, community: &Community, rule: RootRule) -> Result<f64, ComparativeError> {
+            DiversityIndex::new(self)?.faith_pd(community, rule)
+        }
+
+        fn mpd(
+            &self,
+            community: &Community,
+            weighting: Weighting,
+        ) -> Result<f64, ComparativeError> {
+            DiversityIndex::new(self)?.mpd(community, weighting)
+        }
+
+        fn mntd(
+            &self,
+            community: &Community,
+            weighting: Weighting,
+        ) -> Result<f64, ComparativeError> {
+            DiversityIndex::new(self)?.mntd(community, weighting)
+        }
+
+        fn phylogenetic_endemism(
+            &self,
+            communities: &[Community],
+        ) -> Result<Vec<f64>, ComparativeError> {
+            DiversityIndex::new(self)?.phylogenetic_endemism(communities)
+        }
+
+        fn effect_size(
+            &self,
+            community: &Community,
+            metric: Metric,
+            null: &NullModel,
+            runs: usize,
+            seed: u64,
+        ) -> Result<EffectSize, ComparativeError> {
+            DiversityIndex::new(self)?.effect_size(community, metric, null, runs, seed)
+        }
+    }
+
+    impl PhylogeneticSignal for PhyloTree {
+        fn vcv(&self) -> Result<(Vec<String>, nalgebra::DMatrix<f64>), ComparativeError> {
+            crate::tree::signal::compute_vcv(self)
+        }
+
+        fn blomberg_k(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+            permutations: usize,
+            seed: u64,
+        ) -> Result<SignalTest, ComparativeError> {
+            crate::tree::signal::compute_blomberg_k(self, traits, permutations, seed)
+        }
+
+        fn pagel_lambda(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+        ) -> Result<LambdaFit, ComparativeError> {
+            crate::tree::signal::compute_pagel_lambda(self, traits)
+        }
+
+        fn morans_i(
+            &self,
+            traits: &std::collections::HashMap<String, f64>,
+            permutations: usize,
+            seed: u64,
+        ) -> Result<SignalTest, ComparativeError> {
+            crate::tree::signal::compute_morans_i(self, traits, permutations, seed)
+        }
+
+        fn pgls(
+            &self,
+            response: &std::collections::HashMap<String, f64>,
+            model: &Pgls,
+        ) -> Result<PglsFit, ComparativeError> {
+            crate::tree::signal::compute_pgls(self, response, model)
+        }
+    }
+
+    impl TreeParsimony for PhyloTree {
+        fn parsimony_score<A: Alphabet>(&self, aln: &Alignment) -> Result<usize, AsrError> {
+            crate::tree::parsimony::compute_parsimony_score::<A>(self, aln)
+        }
+    }
+
     /// Pointer-based wrapper around `Arc<T>` for use as HashMap key.
     /// Hashes and compares by Arc pointer identity, avoiding content hashing.
     #[derive(Clone, Debug)]
@@ -631,6 +855,14 @@ mod simple_rooted_tree {
         }
     }
 
+    impl<T, W, Z> RootedSupportTree for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+    }
+
     impl<T, W, Z> PathFunction for SimpleRootedTree<T, W, Z>
     where
         T: NodeTaxa,
@@ -687,6 +919,22 @@ mod simple_rooted_tree {
     {
     }
 
+    impl<T, W, Z> SplitDistances for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+    }
+
+    impl<T, W, Z> LeafDistances for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+    }
+
     impl<T, W, Z> DistanceMatrix for SimpleRootedTree<T, W, Z>
     where
         T: NodeTaxa,
@@ -694,23 +942,20 @@ mod simple_rooted_tree {
         Z: NodeWeight,
     {
         fn matrix(&self) -> Vec<Vec<TreeNodeWeight<Self>>> {
-            // One euler-tour index, shared across every pair, rather than the
-            // naive per-call rebuild the old signature forced.
-            let oracle = self.lca();
+            // One index, shared across every pair: root distances are summed
+            // once rather than walked to the root per query.
+            let oracle = self.path_oracle();
             let mut out_mat = vec![vec![W::infinity(); self.nodes.len()]; self.nodes.len()];
-            for node_ids in self
+            let node_ids = self
                 .postord_ids(self.get_root_id())
                 .expect("invariant: the root id always names a node")
-                .combinations(2)
-            {
-                let n1 = node_ids[0];
-                let n2 = node_ids[1];
+                .collect_vec();
+            for (i, &n1) in node_ids.iter().enumerate() {
                 out_mat[n1][n1] = W::zero();
-                out_mat[n2][n2] = W::zero();
-                out_mat[n1][n2] = self
-                    .pairwise_distance(&oracle, n1, n2)
-                    .expect("invariant: both ids came from a traversal of this tree");
-                out_mat[n2][n1] = out_mat[n1][n2];
+                for &n2 in &node_ids[..i] {
+                    out_mat[n1][n2] = oracle.path_length(n1, n2);
+                    out_mat[n2][n1] = out_mat[n1][n2];
+                }
             }
             out_mat
         }
@@ -1078,17 +1323,34 @@ mod simple_rooted_tree {
         fn from_newick_with<H: AnnotationHandler>(
             newick_str: &[u8],
             annotations: H,
+        ) -> std::io::Result<Self> {
+            Self::from_newick_with_labels(newick_str, annotations, InternalLabels::Taxa)
+        }
+
+        fn subtree_to_newick_with<H: AnnotationWriter>(
+            &self,
+            node_id: TreeNodeID<Self>,
+            annotations: H,
+        ) -> impl std::fmt::Display {
+            self.subtree_to_newick_formatted(node_id, annotations, NewickFormat::default())
+        }
+
+        fn from_newick_with_labels<H: AnnotationHandler>(
+            newick_str: &[u8],
+            annotations: H,
+            labels: InternalLabels,
         ) -> std::io::Result<Self> {
             let input = std::str::from_utf8(newick_str)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-            crate::tree::newick::parse_newick(input, &annotations)
+            crate::tree::newick::parse_newick(input, &annotations, labels)
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
         }
 
-        fn subtree_to_newick_with<H: AnnotationWriter>(
+        fn subtree_to_newick_formatted<H: AnnotationWriter>(
             &self,
             node_id: TreeNodeID<Self>,
             annotations: H,
+            format: NewickFormat,
         ) -> impl std::fmt::Display {
             // Iterative Euler-tour walk writing into a single buffer: no
             // recursion (so tree height cannot overflow the stack) and no
@@ -1124,7 +1386,16 @@ mod simple_rooted_tree {
                         out.push(')');
                     }
                     if let Some(taxa_str) = &node.get_taxa() {
-                        out.push_str(&taxa_str.to_string());
+                        crate::tree::newick::push_label(
+                            &mut out,
+                            &taxa_str.to_string(),
+                            format.quoting(),
+                        );
+                    } else if let Some(support) = node
+                        .get_support()
+                        .filter(|_| format.supports() && children.len() > 1)
+                    {
+                        out.push_str(&support.iter().join("/"));
                     }
                     // The writer decides what (if anything) a stored annotation
                     // contributes; the default emits it verbatim after the label.
@@ -1135,7 +1406,10 @@ mod simple_rooted_tree {
                     }
                     if let Some(w) = node.get_weight() {
                         out.push(':');
-                        out.push_str(&w.to_string());
+                        match format.precision() {
+                            Some(digits) => out.push_str(&format!("{w:.digits$}")),
+                            None => out.push_str(&w.to_string()),
+                        }
                     }
                     stack.pop();
                 }
@@ -1152,6 +1426,42 @@ mod simple_rooted_tree {
     {
     }
 
+    impl<T, W, Z> PhyloXml for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+        fn from_phyloxml(xml: &[u8]) -> std::io::Result<Vec<Self>> {
+            let input = crate::tree::xml::document_text(xml)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            crate::tree::phyloxml::parse_phyloxml(&input)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        }
+
+        fn to_phyloxml(trees: &[Self]) -> String {
+            crate::tree::phyloxml::write_phyloxml(trees)
+        }
+    }
+
+    impl<T, W, Z> NeXml for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+        fn from_nexml(xml: &[u8]) -> std::io::Result<Vec<Self>> {
+            let input = crate::tree::xml::document_text(xml)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            crate::tree::nexml::parse_nexml(&input)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        }
+
+        fn to_nexml(trees: &[Self]) -> String {
+            crate::tree::nexml::write_nexml(trees)
+        }
+    }
+
     impl<T, W, Z> SPR for SimpleRootedTree<T, W, Z>
     where
         T: NodeTaxa,
@@ -1312,6 +1622,30 @@ mod simple_rooted_tree {
     {
     }
 
+    impl<T, W, Z> BhvDistance for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+    }
+
+    impl<T, W, Z> AgreementForests for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+    }
+
+    impl<T, W, Z> AgreementSubtrees for SimpleRootedTree<T, W, Z>
+    where
+        T: NodeTaxa,
+        W: EdgeWeight,
+        Z: NodeWeight,
+    {
+    }
+
     #[cfg(feature = "serde")]
     impl<T, W, Z> serde::Serialize for SimpleRootedTree<T, W, Z>
     where
@@ -0,0 +1,867 @@
+//! Agreement forests, and the SPR and TBR distances they measure.
+//!
+//! An agreement forest of two trees on one taxon set cuts both into the same
+//! pieces: its components partition the taxa, each induces the same topology
+//! in both trees, and the subtrees spanning different components are disjoint
+//! in each tree. The fewest cuts, one less than the size of a maximum
+//! agreement forest, is the rooted subprune-and-regraft distance of rooted
+//! trees once a root marker `ρ` hangs above both roots (Bordewich & Semple
+//! 2005), and the tree bisection-and-reconnection distance of unrooted ones
+//! (Allen & Steel 2001). Both are NP-hard to compute.
+//!
+//! [`AgreementForests`](crate::tree::agreement::AgreementForests) finds both
+//! with one engine. The cherries `(a, c)` of the first tree, pairs of labels
+//! sharing a neighbour other than `ρ`, are resolved one at a time against the
+//! second tree, which is cut as it goes:
+//!
+//! * if `a` and `c` form a cherry there too, both collapse into one label;
+//! * if they lie in different components, one of them is cut off;
+//! * otherwise subtrees hang off the path between them. A lone one, for SPR,
+//!   is cut off; else either `a` or `c` is, or the pendant subtrees are: for
+//!   SPR every one but the one holding the root, for TBR the one next to `a`
+//!   or the one next to `c`.
+//!
+//! The exact solvers branch on these cases under a budget of cuts, in the
+//! bounded-search style of Whidden, Beiko & Zeh (2013), raising the budget
+//! from the lower bound the approximation gives and dropping branches the
+//! approximation shows cannot meet it. A search visits at most `3^k` states
+//! for SPR distance `k` and `4^k` for TBR, so suits distances up to the low
+//! tens; the `_within` variants give up past a given distance. The
+//! approximations cut every option at once, in `O(n²)` time, and are within a
+//! factor 3 of the SPR distance and 4 of the TBR distance.
+//!
+//! Trees must be binary, apart from a three-way root in TBR; unary nodes are
+//! suppressed.
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
+
+use crate::prelude::*;
+
+/// Label of an internal node.
+const INTERNAL: usize = usize::MAX;
+/// Label of a leaf standing in for the rest of a rooted tree above a cut-off
+/// subtree, so that the subtree keeps its root.
+const MARKER: usize = usize::MAX - 1;
+/// Entry of [`Forest::node_of`] for a label merged into another.
+const MERGED: usize = usize::MAX;
+
+/// An agreement forest of two trees, as found by [`AgreementForests`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgreementForest {
+    /// Taxa of each component, sorted; components ordered by first taxon.
+    components: Vec<Vec<String>>,
+    /// Component holding the roots, for rooted forests.
+    root: Option<usize>,
+    distance: usize,
+}
+
+impl AgreementForest {
+    /// Taxa of each component, sorted, with components ordered by their first
+    /// taxon. Each component induces the same subtree in both trees.
+    pub fn components(&self) -> &[Vec<String>] {
+        &self.components
+    }
+
+    /// The component still attached to the roots of both trees, for an SPR
+    /// forest; every other component is a clade that moved. `None` for TBR
+    /// forests, and when every taxon was cut away from the root.
+    pub fn root_component(&self) -> Option<&[String]> {
+        self.root.map(|i| self.components[i].as_slice())
+    }
+
+    /// Number of cuts, the SPR or TBR distance the forest witnesses.
+    pub fn distance(&self) -> usize {
+        self.distance
+    }
+}
+
+/// A trait describing agreement forests of two trees and the rooted SPR and
+/// TBR distances they give
+pub trait AgreementForests: RootedTree + RootedMetaTree
+where
+    <Self as RootedTree>::Node: RootedMetaNode,
+{
+    /// Returns a maximum agreement forest of self and tree as rooted trees; its
+    /// distance is the rooted SPR distance.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different leaf taxa;
+    /// [`TreeError::NotBinary`] if a node has more than two children.
+    fn spr_forest(&self, tree: &Self) -> Result<AgreementForest, TreeError> {
+        Ok(self
+            .spr_forest_within(tree, usize::MAX)?
+            .expect("invariant: an unbounded search finds a forest"))
+    }
+
+    /// Returns a maximum agreement forest of self and tree as rooted trees, or
+    /// `None` if the rooted SPR distance exceeds `max_distance`.
+    ///
+    /// # Errors
+    ///
+    /// As for [`spr_forest`](Self::spr_forest).
+    fn spr_forest_within(
+        &self,
+        tree: &Self,
+        max_distance: usize,
+    ) -> Result<Option<AgreementForest>, TreeError> {
+        let (search, taxa) = Search::new(self, tree, true)?;
+        Ok(search.solve(max_distance).map(|s| s.forest(&taxa)))
+    }
+
+    /// Returns an agreement forest of self and tree as rooted trees with at
+    /// most three times the fewest cuts.
+    ///
+    /// # Errors
+    ///
+    /// As for [`spr_forest`](Self::spr_forest).
+    fn spr_forest_approx(&self, tree: &Self) -> Result<AgreementForest, TreeError> {
+        let (search, taxa) = Search::new(self, tree, true)?;
+        Ok(search.approximate().forest(&taxa))
+    }
+
+    /// Returns a maximum agreement forest of self and tree as unrooted trees;
+    /// its distance is the TBR distance.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different leaf taxa;
+    /// [`TreeError::NotBinary`] if a node other than the root has more than
+    /// two children, or the root more than three.
+    fn tbr_forest(&self, tree: &Self) -> Result<AgreementForest, TreeError> {
+        Ok(self
+            .tbr_forest_within(tree, usize::MAX)?
+            .expect("invariant: an unbounded search finds a forest"))
+    }
+
+    /// Returns a maximum agreement forest of self and tree as unrooted trees,
+    /// or `None` if the TBR distance exceeds `max_distance`.
+    ///
+    /// # Errors
+    ///
+    /// As for [`tbr_forest`](Self::tbr_forest).
+    fn tbr_forest_within(
+        &self,
+        tree: &Self,
+        max_distance: usize,
+    ) -> Result<Option<AgreementForest>, TreeError> {
+        let (search, taxa) = Search::new(self, tree, false)?;
+        Ok(search.solve(max_distance).map(|s| s.forest(&taxa)))
+    }
+
+    /// Returns an agreement forest of self and tree as unrooted trees with at
+    /// most four times the fewest cuts.
+    ///
+    /// # Errors
+    ///
+    /// As for [`tbr_forest`](Self::tbr_forest).
+    fn tbr_forest_approx(&self, tree: &Self) -> Result<AgreementForest, TreeError> {
+        let (search, taxa) = Search::new(self, tree, false)?;
+        Ok(search.approximate().forest(&taxa))
+    }
+}
+
+/// A binary forest as an unrooted graph with labelled leaves.
+#[derive(Debug, Clone)]
+struct Forest {
+    /// Neighbours of each node, in the first `degree` slots.
+    adj: Vec<[usize; 3]>,
+    /// Number of neighbours of each node.
+    degree: Vec<u8>,
+    /// Label of each node: a label id, [`INTERNAL`] or [`MARKER`].
+    label: Vec<usize>,
+    /// Node carrying each label id.
+    node_of: Vec<usize>,
+    /// Nodes whose neighbourhood changed since they were last checked for a
+    /// cherry, if the forest is searched for them.
+    dirty: Option<Vec<usize>>,
+}
+
+impl Forest {
+    /// The forest of `tree`, leaves labelled by `taxon`, with a leaf labelled
+    /// `rho` above the root if given. Unary nodes, and a root left with two
+    /// neighbours, are suppressed.
+    fn from_tree<T>(
+        tree: &T,
+        labels: usize,
+        rho: Option<usize>,
+        cherries: bool,
+        taxon: impl Fn(TreeNodeID<T>) -> usize,
+    ) -> Result<Self, TreeError>
+    where
+        T: RootedTree + RootedMetaTree,
+        <T as RootedTree>::Node: RootedMetaNode,
+    {
+        let mut forest = Forest {
+            adj: vec![],
+            degree: vec![],
+            label: vec![],
+            node_of: vec![MERGED; labels],
+            dirty: cherries.then(Vec::new),
+        };
+        let root = tree.get_root_id();
+        let mut dense: HashMap<TreeNodeID<T>, usize> = HashMap::default();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            let children = tree.get_node_children_ids(id).count();
+            let limit = match (id == root, rho) {
+                (true, None) => 3,
+                _ => 2,
+            };
+            if children > limit {
+                return Err(TreeError::NotBinary(id.into()));
+            }
+            let node = match tree.is_leaf(id) {
+                true => forest.add_node(taxon(id)),
+                false => forest.add_node(INTERNAL),
+            };
+            if let Some(parent) = tree.get_node_parent_id(id) {
+                forest.link(dense[&parent], node);
+            }
+            dense.insert(id, node);
+            stack.extend(tree.get_node_children_ids(id));
+        }
+        if let Some(rho) = rho {
+            let marker = forest.add_node(rho);
+            forest.link(dense[&root], marker);
+        }
+        for v in 0..forest.adj.len() {
+            forest.tidy(v);
+        }
+        Ok(forest)
+    }
+
+    fn add_node(&mut self, label: usize) -> usize {
+        let node = self.adj.len();
+        self.adj.push([INTERNAL; 3]);
+        self.degree.push(0);
+        self.label.push(label);
+        if label < self.node_of.len() {
+            self.node_of[label] = node;
+        }
+        node
+    }
+
+    fn adj(&self, u: usize) -> &[usize] {
+        &self.adj[u][..self.degree[u] as usize]
+    }
+
+    fn link(&mut self, u: usize, v: usize) {
+        for (x, y) in [(u, v), (v, u)] {
+            self.adj[x][self.degree[x] as usize] = y;
+            self.degree[x] += 1;
+        }
+        if let Some(dirty) = &mut self.dirty {
+            dirty.extend([u, v]);
+        }
+    }
+
+    fn unlink(&mut self, u: usize, v: usize) {
+        for (x, y) in [(u, v), (v, u)] {
+            let i = self.adj(x).iter().position(|&z| z == y);
+            let i = i.expect("invariant: unlinked nodes are neighbours");
+            self.degree[x] -= 1;
+            self.adj[x].swap(i, self.degree[x] as usize);
+        }
+    }
+
+    /// Removes `v` if it is an unlabelled leaf, or splices it out if it is an
+    /// unlabelled node of degree two, then tidies whatever that exposes.
+    fn tidy(&mut self, mut v: usize) {
+        while self.label[v] == INTERNAL {
+            match *self.adj(v) {
+                [w] => {
+                    self.unlink(v, w);
+                    v = w;
+                }
+                [x, y] => {
+                    self.unlink(v, x);
+                    self.unlink(v, y);
+                    self.link(x, y);
+                    return;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Whether the label is alone in its component, but for a marker.
+    fn is_isolated(&self, label: usize) -> bool {
+        match *self.adj(self.node_of[label]) {
+            [] => true,
+            [v] => self.label[v] == MARKER,
+            _ => false,
+        }
+    }
+
+    /// Cuts the edge above the leaf labelled `label`. Returns false if it was
+    /// already isolated.
+    fn cut_leaf(&mut self, label: usize) -> bool {
+        if self.is_isolated(label) {
+            return false;
+        }
+        let u = self.node_of[label];
+        let w = self.adj(u)[0];
+        self.unlink(u, w);
+        self.tidy(w);
+        true
+    }
+
+    /// Cuts edge `(v, w)`. With `rooted`, the side of `w` keeps its root by
+    /// taking a marker leaf where the edge was.
+    fn cut_pendant(&mut self, v: usize, w: usize, rooted: bool) {
+        self.unlink(v, w);
+        match rooted && self.label[w] == INTERNAL && self.degree[w] == 2 {
+            true => {
+                let marker = self.add_node(MARKER);
+                self.link(w, marker);
+            }
+            false => self.tidy(w),
+        }
+        self.tidy(v);
+    }
+
+    /// Nodes on the path between the leaves labelled `a` and `c`, both
+    /// included, if they are connected.
+    fn path(&self, a: usize, c: usize) -> Option<Vec<usize>> {
+        let (start, end) = (self.node_of[a], self.node_of[c]);
+        let mut from = vec![start; self.adj.len()];
+        let mut stack = vec![(start, start)];
+        while let Some((u, parent)) = stack.pop() {
+            from[u] = parent;
+            if u == end {
+                let mut path = vec![end];
+                while let Some(&u) = path.last().filter(|&&u| u != start) {
+                    path.push(from[u]);
+                }
+                return Some(path);
+            }
+            stack.extend(
+                self.adj(u)
+                    .iter()
+                    .filter(|&&v| v != parent)
+                    .map(|&v| (v, u)),
+            );
+        }
+        None
+    }
+
+    /// Whether the side of edge `(v, w)` holding `w` holds a marker or `rho`.
+    fn holds_root(&self, v: usize, w: usize, rho: Option<usize>) -> bool {
+        let mut stack = vec![(w, v)];
+        while let Some((u, parent)) = stack.pop() {
+            if self.label[u] == MARKER || Some(self.label[u]) == rho {
+                return true;
+            }
+            stack.extend(
+                self.adj(u)
+                    .iter()
+                    .filter(|&&x| x != parent)
+                    .map(|&x| (x, u)),
+            );
+        }
+        false
+    }
+
+    /// Replaces the cherry of leaves `a` and `c` by one leaf labelled `merged`.
+    fn contract(&mut self, a: usize, c: usize, merged: usize) {
+        let (u, x) = (self.node_of[a], self.node_of[c]);
+        self.node_of[a] = MERGED;
+        self.node_of[c] = MERGED;
+        self.label[u] = INTERNAL;
+        self.label[x] = INTERNAL;
+        let node = match *self.adj(u) {
+            [v] if v == x => {
+                self.unlink(u, x);
+                self.add_node(INTERNAL)
+            }
+            [v] => {
+                self.unlink(u, v);
+                self.unlink(x, v);
+                v
+            }
+            _ => unreachable!("a cherry leaf has one neighbour"),
+        };
+        self.label[node] = merged;
+        self.node_of.push(node);
+        if let Some(dirty) = &mut self.dirty {
+            dirty.extend(&self.adj[node][..self.degree[node] as usize]);
+        }
+    }
+
+    /// Two labels other than `rho` sharing a neighbour. With `rho` above the
+    /// root, these are a cherry of the rooted tree.
+    ///
+    /// Only nodes that changed since they were last checked are looked at, as
+    /// only a change next to a node makes it a cherry.
+    fn cherry(&mut self, rho: Option<usize>) -> Option<(usize, usize)> {
+        let dirty = self
+            .dirty
+            .as_mut()
+            .expect("invariant: only the first tree is searched for cherries");
+        while let Some(&u) = dirty.last() {
+            if self.label[u] == INTERNAL {
+                let mut leaves = self.adj[u][..self.degree[u] as usize]
+                    .iter()
+                    .map(|&x| self.label[x])
+                    .filter(|&l| l != INTERNAL && Some(l) != rho);
+                if let (Some(a), Some(c)) = (leaves.next(), leaves.next()) {
+                    return Some((a, c));
+                }
+            }
+            dirty.pop();
+        }
+        None
+    }
+}
+
+/// One edge to cut.
+#[derive(Debug, Clone, Copy)]
+enum Cut {
+    /// The edge above a label, in both trees.
+    Leaf(usize),
+    /// An edge of the second forest, as `(path node, pendant subtree root)`.
+    Pendant(usize, usize),
+}
+
+/// A cherry of the first tree that the second forest does not agree with.
+enum Conflict {
+    /// The two labels are in different components.
+    Apart(usize, usize),
+    /// The two labels are joined by a path with these pendant subtrees, as
+    /// `(path node, subtree root)`, in path order, at least two of them. For
+    /// rooted forests the one holding the root is left out.
+    Path(usize, usize, Vec<(usize, usize)>),
+}
+
+impl Conflict {
+    /// The sets of cuts, one of which some maximum agreement forest makes.
+    fn branches(&self, rooted: bool) -> Vec<Vec<Cut>> {
+        match self {
+            Conflict::Apart(a, c) => vec![vec![Cut::Leaf(*a)], vec![Cut::Leaf(*c)]],
+            Conflict::Path(a, c, pendants) => {
+                let mut branches = vec![vec![Cut::Leaf(*a)], vec![Cut::Leaf(*c)]];
+                let cut = |&(v, w): &(usize, usize)| Cut::Pendant(v, w);
+                match rooted {
+                    true => branches.push(pendants.iter().map(cut).collect()),
+                    false => {
+                        branches.push(vec![cut(&pendants[0])]);
+                        branches.push(vec![cut(&pendants[pendants.len() - 1])]);
+                    }
+                }
+                branches
+            }
+        }
+    }
+
+    /// A cut from every branch, pendant edges first.
+    fn all(&self, rooted: bool) -> Vec<Cut> {
+        match self {
+            Conflict::Apart(a, c) => vec![Cut::Leaf(*a), Cut::Leaf(*c)],
+            Conflict::Path(a, c, pendants) => {
+                let mut cuts = vec![Cut::Pendant(pendants[0].0, pendants[0].1)];
+                let (v, w) = pendants[pendants.len() - 1];
+                if !rooted {
+                    cuts.push(Cut::Pendant(v, w));
+                }
+                cuts.extend([Cut::Leaf(*a), Cut::Leaf(*c)]);
+                cuts
+            }
+        }
+    }
+}
+
+/// The state of a search for an agreement forest.
+#[derive(Debug, Clone)]
+struct Search {
+    /// The first tree, less the labels already cut off.
+    first: Forest,
+    /// The second tree, cut so far.
+    second: Forest,
+    /// The two labels each contracted label replaced, from id `base` on.
+    merged: Vec<(usize, usize)>,
+    /// Number of taxa; the ids below are taxa.
+    taxa: usize,
+    /// Number of initial labels: the taxa, and `ρ` if rooted.
+    base: usize,
+    /// The label above both roots, for rooted forests.
+    rho: Option<usize>,
+    rooted: bool,
+    cuts: usize,
+}
+
+impl Search {
+    fn new<T>(t1: &T, t2: &T, rooted: bool) -> Result<(Self, Vec<String>), TreeError>
+    where
+        T: RootedTree + RootedMetaTree,
+        <T as RootedTree>::Node: RootedMetaNode,
+    {
+        let leaf_taxa = |tree: &T| {
+            let mut taxa = tree
+                .get_leaf_ids()
+                .map(|leaf| {
+                    tree.get_node_taxa(leaf)
+                        .expect("invariant: leaves carry a taxon")
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            taxa.sort_unstable();
+            taxa
+        };
+        let taxa = leaf_taxa(t1);
+        if taxa != leaf_taxa(t2) {
+            return Err(TreeError::TaxaSetMismatch);
+        }
+        let index: HashMap<&str, usize> = taxa
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.as_str(), i))
+            .collect();
+        let n = taxa.len();
+        let (base, rho) = match rooted {
+            true => (n + 1, Some(n)),
+            false => (n, None),
+        };
+        let forest = |tree: &T, cherries| {
+            let taxon = |leaf| {
+                index[tree
+                    .get_node_taxa(leaf)
+                    .expect("invariant: leaves carry a taxon")
+                    .to_string()
+                    .as_str()]
+            };
+            Forest::from_tree(tree, base, rho, cherries, taxon)
+        };
+        let search = Search {
+            first: forest(t1, true)?,
+            second: forest(t2, false)?,
+            merged: vec![],
+            taxa: n,
+            base,
+            rho,
+            rooted,
+            cuts: 0,
+        };
+        Ok((search, taxa))
+    }
+
+    /// Makes the moves every maximum agreement forest agrees on, until the
+    /// first tree is resolved or a choice is needed.
+    fn advance(&mut self) -> Option<Conflict> {
+        if self.taxa == 0 {
+            return None;
+        }
+        loop {
+            let (a, c) = self.first.cherry(self.rho)?;
+            if let Some(&x) = [a, c].iter().find(|&&x| self.second.is_isolated(x)) {
+                self.first.cut_leaf(x);
+                continue;
+            }
+            let Some(path) = self.second.path(a, c) else {
+                return Some(Conflict::Apart(a, c));
+            };
+            if path.len() <= 3 {
+                let merged = self.base + self.merged.len();
+                self.merged.push((a, c));
+                self.first.contract(a, c, merged);
+                self.second.contract(a, c, merged);
+                continue;
+            }
+            let pendants = path
+                .windows(3)
+                .map(|w| {
+                    let off = self
+                        .second
+                        .adj(w[1])
+                        .iter()
+                        .find(|&&x| x != w[0] && x != w[2])
+                        .expect("invariant: path nodes are binary");
+                    (w[1], *off)
+                })
+                .filter(|&(v, w)| !self.rooted || !self.second.holds_root(v, w, self.rho))
+                .collect::<Vec<_>>();
+            if let &[(v, w)] = pendants.as_slice() {
+                self.apply(&[Cut::Pendant(v, w)]);
+                continue;
+            }
+            return Some(Conflict::Path(a, c, pendants));
+        }
+    }
+
+    fn apply(&mut self, cuts: &[Cut]) {
+        for &cut in cuts {
+            match cut {
+                Cut::Leaf(x) => {
+                    self.first.cut_leaf(x);
+                    if self.second.cut_leaf(x) {
+                        self.cuts += 1;
+                    }
+                }
+                Cut::Pendant(v, w) => {
+                    self.second.cut_pendant(v, w, self.rooted);
+                    self.cuts += 1;
+                }
+            }
+        }
+    }
+
+    /// A maximum agreement forest with at most `limit` cuts in all.
+    fn exact(mut self, limit: usize) -> Option<Search> {
+        let conflict = self.advance();
+        if self.cuts > limit {
+            return None;
+        }
+        let Some(conflict) = conflict else {
+            return Some(self);
+        };
+        let more = self.clone().approximate().cuts - self.cuts;
+        if self.cuts + more.div_ceil(self.factor()) > limit {
+            return None;
+        }
+        conflict
+            .branches(self.rooted)
+            .into_iter()
+            .filter(|cuts| self.cuts + cuts.len() <= limit)
+            .find_map(|cuts| {
+                let mut next = self.clone();
+                next.apply(&cuts);
+                next.exact(limit)
+            })
+    }
+
+    fn approximate(mut self) -> Search {
+        while let Some(conflict) = self.advance() {
+            self.apply(&conflict.all(self.rooted));
+        }
+        self
+    }
+
+    /// The approximation factor of [`Self::approximate`].
+    fn factor(&self) -> usize {
+        match self.rooted {
+            true => 3,
+            false => 4,
+        }
+    }
+
+    /// A maximum agreement forest, raising the budget from the lower bound the
+    /// approximation gives; `None` if it needs more than `max_distance` cuts.
+    fn solve(self, max_distance: usize) -> Option<Search> {
+        let approximate = self.clone().approximate();
+        let upper = approximate.cuts;
+        (upper.div_ceil(self.factor())..upper.min(max_distance.saturating_add(1)))
+            .find_map(|budget| self.clone().exact(budget))
+            .or((upper <= max_distance).then_some(approximate))
+    }
+
+    /// The components of the second forest, in taxon names.
+    fn forest(&self, names: &[String]) -> AgreementForest {
+        let nodes = self.second.adj.len();
+        let mut seen = vec![false; nodes];
+        let mut components = vec![];
+        let mut root = None;
+        for start in 0..nodes {
+            if seen[start] || self.second.label[start] == INTERNAL {
+                continue;
+            }
+            let (mut taxa, mut holds_rho) = (vec![], false);
+            let mut stack = vec![start];
+            seen[start] = true;
+            while let Some(u) = stack.pop() {
+                let mut labels = vec![self.second.label[u]];
+                while let Some(label) = labels.pop() {
+                    match label {
+                        INTERNAL | MARKER => {}
+                        l if Some(l) == self.rho => holds_rho = true,
+                        l if l < self.taxa => taxa.push(l),
+                        l => {
+                            let (a, c) = self.merged[l - self.base];
+                            labels.extend([a, c]);
+                        }
+                    }
+                }
+                for &v in self.second.adj(u) {
+                    if !seen[v] {
+                        seen[v] = true;
+                        stack.push(v);
+                    }
+                }
+            }
+            if !taxa.is_empty() {
+                taxa.sort_unstable();
+                components.push((taxa, holds_rho));
+            }
+        }
+        components.sort();
+        let components = components
+            .into_iter()
+            .enumerate()
+            .map(|(i, (taxa, holds_rho))| {
+                if holds_rho {
+                    root = Some(i);
+                }
+                taxa.into_iter().map(|t| names[t].clone()).collect()
+            })
+            .collect();
+        AgreementForest {
+            components,
+            root,
+            distance: self.cuts,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::tree::PhyloTree;
+    use itertools::Itertools;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+    use std::collections::BTreeSet;
+
+    fn taxon(tree: &PhyloTree, id: usize) -> String {
+        tree.get_node_taxa(id).unwrap().to_string()
+    }
+
+    /// Clusters of `tree` restricted to `taxa`, or the splits they give when
+    /// unrooted.
+    fn induced(tree: &PhyloTree, taxa: &[String], rooted: bool) -> BTreeSet<Vec<String>> {
+        tree.get_node_ids()
+            .filter_map(|id| {
+                let below = tree
+                    .get_cluster_ids(id)
+                    .unwrap()
+                    .map(|leaf| taxon(tree, leaf))
+                    .filter(|t| taxa.contains(t))
+                    .sorted()
+                    .collect_vec();
+                if rooted {
+                    return (!below.is_empty()).then_some(below);
+                }
+                let other = taxa.iter().filter(|t| !below.contains(t)).cloned();
+                let side = below.clone().min(other.sorted().collect());
+                (side.len() > 1 && side.len() + 1 < taxa.len()).then_some(side)
+            })
+            .collect()
+    }
+
+    /// Nodes of the subtree of `tree` spanning `taxa`, up to the root if
+    /// `to_root`.
+    fn span(tree: &PhyloTree, taxa: &[String], to_root: bool) -> BTreeSet<usize> {
+        let leaves = tree
+            .get_leaf_ids()
+            .filter(|&id| taxa.contains(&taxon(tree, id)))
+            .collect_vec();
+        let top = match to_root {
+            true => tree.get_root_id(),
+            false => tree.lca().get_lca_id(&leaves),
+        };
+        leaves
+            .iter()
+            .flat_map(|&leaf| {
+                let path = tree.node_to_root_ids(leaf).unwrap().collect_vec();
+                let end = path.iter().position(|&id| id == top).unwrap();
+                path[..=end].to_vec()
+            })
+            .collect()
+    }
+
+    fn assert_agrees(t1: &PhyloTree, t2: &PhyloTree, forest: &AgreementForest, rooted: bool) {
+        let taxa = forest.components().concat();
+        assert_eq!(taxa.len(), t1.get_leaf_ids().count());
+        assert_eq!(taxa.iter().unique().count(), taxa.len());
+        // Every cut adds a component, but one holding only the root is dropped.
+        let dropped = rooted && forest.root_component().is_none();
+        assert_eq!(
+            forest.components().len() + dropped as usize,
+            forest.distance() + 1
+        );
+        for tree in [t1, t2] {
+            let mut used = BTreeSet::new();
+            for (i, component) in forest.components().iter().enumerate() {
+                assert_eq!(
+                    induced(t1, component, rooted),
+                    induced(t2, component, rooted)
+                );
+                let to_root = rooted && forest.root == Some(i);
+                for node in span(tree, component, to_root) {
+                    assert!(used.insert(node), "components overlap at {node}");
+                }
+            }
+        }
+    }
+
+    fn random_spr(tree: &mut PhyloTree, rng: &mut StdRng) {
+        let root = tree.get_root_id();
+        let nodes = tree.get_node_ids().filter(|&id| id != root).collect_vec();
+        let moved = *nodes.choose(rng).unwrap();
+        let below = tree.postord_ids(moved).unwrap().collect_vec();
+        let targets = nodes.iter().filter(|id| !below.contains(id));
+        let target = *targets.collect_vec().choose(rng).copied().unwrap();
+        let from = (tree.get_node_parent_id(moved).unwrap(), moved);
+        let to = (tree.get_node_parent_id(target).unwrap(), target);
+        tree.spr(from, to).unwrap();
+        // Drop the unary and unreachable nodes the move leaves behind.
+        let newick = tree.to_newick().to_string();
+        *tree = PhyloTree::from_newick(newick.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_single_move() {
+        let t1 = PhyloTree::from_newick("(((A,B),C),(D,E));".as_bytes()).unwrap();
+        let t2 = PhyloTree::from_newick("(((A,C),B),(D,E));".as_bytes()).unwrap();
+        let forest = t1.spr_forest(&t2).unwrap();
+        assert_eq!(forest.distance(), 1);
+        assert_agrees(&t1, &t2, &forest, true);
+        let root = forest.root_component().unwrap();
+        assert_eq!(root.len(), 4);
+        // Regrafting B above the old root is a rooted SPR move too.
+        let t3 = PhyloTree::from_newick("(B,((A,C),(D,E)));".as_bytes()).unwrap();
+        assert_eq!(t1.spr_forest(&t3).unwrap().distance(), 1);
+        assert_eq!(t1.tbr_forest(&t1).unwrap().distance(), 0);
+        assert_eq!(t1.spr_forest_within(&t2, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_random_moves_bound_distances() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for moves in 1..=4 {
+            let t1 = PhyloTree::yule(14);
+            let mut t2 = t1.clone();
+            for _ in 0..moves {
+                random_spr(&mut t2, &mut rng);
+            }
+            let spr = t1.spr_forest(&t2).unwrap();
+            let tbr = t1.tbr_forest(&t2).unwrap();
+            assert!(spr.distance() <= moves, "{moves} moves");
+            assert!(tbr.distance() <= spr.distance());
+            assert_agrees(&t1, &t2, &spr, true);
+            assert_agrees(&t1, &t2, &tbr, false);
+            let spr_approx = t1.spr_forest_approx(&t2).unwrap();
+            let tbr_approx = t1.tbr_forest_approx(&t2).unwrap();
+            assert_agrees(&t1, &t2, &spr_approx, true);
+            assert_agrees(&t1, &t2, &tbr_approx, false);
+            assert!((spr.distance()..=3 * spr.distance()).contains(&spr_approx.distance()));
+            assert!((tbr.distance()..=4 * tbr.distance()).contains(&tbr_approx.distance()));
+            assert_eq!(spr.distance(), t2.spr_forest(&t1).unwrap().distance());
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let t1 = PhyloTree::from_newick("((A,B,C),D);".as_bytes()).unwrap();
+        let t2 = PhyloTree::from_newick("((A,B),(C,D));".as_bytes()).unwrap();
+        assert!(matches!(t1.spr_forest(&t2), Err(TreeError::NotBinary(_))));
+        let t3 = PhyloTree::from_newick("((A,B),(C,E));".as_bytes()).unwrap();
+        assert!(matches!(
+            t2.tbr_forest(&t3),
+            Err(TreeError::TaxaSetMismatch)
+        ));
+        // An unrooted tree may keep a three-way root.
+        let t4 = PhyloTree::from_newick("(A,B,(C,D));".as_bytes()).unwrap();
+        assert_eq!(t4.tbr_forest(&t2).unwrap().distance(), 0);
+    }
+}
@@ -0,0 +1,331 @@
+//! Nonparametric bootstrap and jackknife over alignment sites.
+//!
+//! A replicate is drawn as new multiplicities for the patterns of
+//! [`Alignment::compress_columns`](crate::alignment::Alignment::compress_columns),
+//! never by copying columns. The bootstrap (Felsenstein 1985) draws as many
+//! sites as the alignment has, with replacement; the delete-half jackknife
+//! draws half of them, rounded down, without. Each draw picks a site and
+//! counts its pattern, so a replicate costs one pass over the sites and one
+//! vector of counts.
+//!
+//! [`Resampler::run`](crate::tree::bootstrap::Resampler::run) hands each
+//! replicate to a tree-building closure and collects the trees. Every
+//! replicate draws from its own generator, seeded from the resampler's seed,
+//! so the same seed gives the same trees whether the replicates run one after
+//! another or, under the `parallel` feature, with `run_par`.
+//!
+//! The replicate trees give the support of a reference tree's splits through
+//! [`TreeSet::split_support`](crate::tree::treeset::TreeSet::split_support),
+//! which [`annotate_support`](crate::tree::bootstrap::annotate_support)
+//! writes onto the tree as percentages for Newick output.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::alignment::{Alignment, CompressedColumns};
+use crate::prelude::*;
+use crate::tree::PhyloTree;
+
+/// How a replicate draws its sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Resampling {
+    /// As many sites as the alignment has, with replacement.
+    #[default]
+    Bootstrap,
+    /// Half the sites, rounded down, without replacement.
+    Jackknife,
+}
+
+impl Resampling {
+    /// Multiplicities of the patterns of `columns` in one replicate drawn
+    /// with `rng`, in pattern order.
+    pub fn draw<R: Rng>(&self, columns: &CompressedColumns, rng: &mut R) -> Vec<usize> {
+        let sites = columns.site_to_pattern.len();
+        let mut multiplicity = vec![0; columns.patterns.len()];
+        match self {
+            Resampling::Bootstrap => {
+                for _ in 0..sites {
+                    multiplicity[columns.site_to_pattern[rng.gen_range(0..sites)]] += 1;
+                }
+            }
+            Resampling::Jackknife => {
+                for site in rand::seq::index::sample(rng, sites, sites / 2) {
+                    multiplicity[columns.site_to_pattern[site]] += 1;
+                }
+            }
+        }
+        multiplicity
+    }
+}
+
+/// One resampled alignment, as pattern multiplicities over the patterns of
+/// the original.
+#[derive(Debug, Clone)]
+pub struct Replicate<'a> {
+    /// Position of the replicate, from 0.
+    pub index: usize,
+    /// Patterns of the original alignment.
+    pub columns: &'a CompressedColumns,
+    /// Times each pattern was drawn; patterns not drawn have 0.
+    pub multiplicity: Vec<usize>,
+}
+
+impl Replicate<'_> {
+    /// Number of sites drawn.
+    pub fn width(&self) -> usize {
+        self.multiplicity.iter().sum()
+    }
+
+    /// The replicate as an alignment, for builders that need one. Rows are
+    /// in [`leaf_order`](CompressedColumns::leaf_order), and the sites of a
+    /// pattern are adjacent, in pattern order.
+    pub fn to_alignment(&self) -> Alignment {
+        let names = self.columns.leaf_order.clone();
+        let index: HashMap<String, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect();
+        let width = self.width();
+        let mut residues = Vec::with_capacity(names.len() * width);
+        for row in 0..names.len() {
+            for (pattern, &count) in self.columns.patterns.iter().zip(&self.multiplicity) {
+                residues.extend(std::iter::repeat_n(pattern[row], count));
+            }
+        }
+        Alignment::from_parts(names, index, residues, width)
+    }
+}
+
+/// Settings for resampling an alignment and building a tree from each
+/// replicate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resampler {
+    resampling: Resampling,
+    replicates: usize,
+    seed: u64,
+}
+
+impl Resampler {
+    /// `replicates` bootstrap replicates, seeded with 0.
+    pub fn new(replicates: usize) -> Self {
+        Resampler {
+            resampling: Resampling::Bootstrap,
+            replicates,
+            seed: 0,
+        }
+    }
+
+    /// Sets how replicates draw their sites.
+    pub fn with_resampling(mut self, resampling: Resampling) -> Self {
+        self.resampling = resampling;
+        self
+    }
+
+    /// Sets the seed the replicates' generators are seeded from.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// How replicates draw their sites.
+    pub fn resampling(&self) -> Resampling {
+        self.resampling
+    }
+
+    /// Number of replicates.
+    pub fn replicates(&self) -> usize {
+        self.replicates
+    }
+
+    /// The seed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Seed of each replicate's generator, in replicate order.
+    fn replicate_seeds(&self) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        (0..self.replicates).map(|_| rng.gen()).collect()
+    }
+
+    /// Every replicate of `columns`, drawn as the iterator advances.
+    pub fn draws<'a>(
+        &self,
+        columns: &'a CompressedColumns,
+    ) -> impl ExactSizeIterator<Item = Replicate<'a>> + 'a {
+        let resampling = self.resampling;
+        self.replicate_seeds()
+            .into_iter()
+            .enumerate()
+            .map(move |(index, seed)| replicate(resampling, columns, index, seed))
+    }
+
+    /// Builds a tree from every replicate of `columns` with `build`, and
+    /// returns the trees in replicate order.
+    ///
+    /// # Errors
+    ///
+    /// The first error `build` returns, after which no more replicates are
+    /// built.
+    pub fn run<F, E>(&self, columns: &CompressedColumns, mut build: F) -> Result<Vec<PhyloTree>, E>
+    where
+        F: FnMut(&Replicate<'_>) -> Result<PhyloTree, E>,
+    {
+        self.draws(columns)
+            .map(|replicate| build(&replicate))
+            .collect()
+    }
+
+    #[cfg(feature = "parallel")]
+    /// Builds the trees of [`run`](Self::run), replicates in parallel. The
+    /// trees are the same, in the same order.
+    ///
+    /// # Errors
+    ///
+    /// An error `build` returns, if any does.
+    pub fn run_par<F, E>(&self, columns: &CompressedColumns, build: F) -> Result<Vec<PhyloTree>, E>
+    where
+        F: Fn(&Replicate<'_>) -> Result<PhyloTree, E> + Sync,
+        E: Send,
+    {
+        self.replicate_seeds()
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, seed)| build(&replicate(self.resampling, columns, index, seed)))
+            .collect()
+    }
+}
+
+/// Replicate `index` of `columns`, drawn with a generator seeded with `seed`.
+fn replicate(
+    resampling: Resampling,
+    columns: &CompressedColumns,
+    index: usize,
+    seed: u64,
+) -> Replicate<'_> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Replicate {
+        index,
+        columns,
+        multiplicity: resampling.draw(columns, &mut rng),
+    }
+}
+
+/// Sets the support of every internal node of `tree` whose split is not
+/// trivial to the percentage of `replicates` holding that split, rounded to
+/// a whole number. Other nodes keep their support.
+///
+/// # Errors
+///
+/// [`TreeError::UnlabelledLeaf`] if a leaf of `tree` or of a replicate has
+/// no taxon, and [`TreeError::TaxaSetMismatch`] if a replicate's leaf taxa
+/// differ from those of the others or of `tree`, or `replicates` is empty.
+pub fn annotate_support(tree: &mut PhyloTree, replicates: &[PhyloTree]) -> Result<(), TreeError> {
+    let set = TreeSet::from_trees(replicates)?;
+    for (id, support) in set.split_support(tree)? {
+        let percent = (support * 100.0).round() as f32;
+        tree.set_node_support(id, Some(Arc::from([percent])));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Nucleotide;
+    use crate::tree::parsimony::{parsimony_search, ParsimonySearch};
+
+    fn alignment() -> Alignment {
+        Alignment::from_fasta(concat!(
+            ">a\nAAAAAAAAAACCCCCGT\n",
+            ">b\nAAAAAAAAAACCCCCTG\n",
+            ">c\nGGGGGGGGGGCCCCCGT\n",
+            ">d\nGGGGGGGGGGAAAAATG\n",
+            ">e\nGGGGGGGGGGAAAAAGT\n",
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn draws_are_seeded_and_sized() {
+        let columns = alignment().compress_columns();
+        let resampler = Resampler::new(20).with_seed(5);
+        let first: Vec<_> = resampler.draws(&columns).map(|r| r.multiplicity).collect();
+        let again: Vec<_> = resampler.draws(&columns).map(|r| r.multiplicity).collect();
+        assert_eq!(first, again);
+        assert!(first.iter().all(|m| m.iter().sum::<usize>() == 17));
+        assert_ne!(first[0], first[1]);
+
+        let jackknife = resampler.with_resampling(Resampling::Jackknife);
+        for replicate in jackknife.draws(&columns) {
+            assert_eq!(replicate.width(), 8);
+            // Without replacement, no pattern is drawn more often than it occurs.
+            assert!(replicate
+                .multiplicity
+                .iter()
+                .zip(&columns.multiplicity)
+                .all(|(drawn, had)| drawn <= had));
+            let aln = replicate.to_alignment();
+            assert_eq!(aln.names(), ["a", "b", "c", "d", "e"]);
+            assert_eq!(aln.width(), 8);
+        }
+    }
+
+    #[test]
+    fn bootstrap_support_of_a_parsimony_tree() {
+        let aln = alignment();
+        let search = ParsimonySearch::new();
+        let build = |replicate: &Replicate<'_>| {
+            parsimony_search::<Nucleotide>(&replicate.to_alignment(), &search)
+                .map(|result| result.trees[0].clone())
+        };
+        let columns = aln.compress_columns();
+        let trees = Resampler::new(50)
+            .with_seed(11)
+            .run(&columns, build)
+            .unwrap();
+        assert_eq!(trees.len(), 50);
+        #[cfg(feature = "parallel")]
+        {
+            let par = Resampler::new(50)
+                .with_seed(11)
+                .run_par(&columns, build)
+                .unwrap();
+            let newick = |trees: &[PhyloTree]| {
+                trees
+                    .iter()
+                    .map(|t| t.to_newick().to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(newick(&par), newick(&trees));
+        }
+
+        let mut tree = parsimony_search::<Nucleotide>(&aln, &search).unwrap().trees[0].clone();
+        annotate_support(&mut tree, &trees).unwrap();
+        let supports: Vec<f32> = tree
+            .get_node_ids()
+            .filter_map(|id| tree.get_node_support(id).map(|s| s[0]))
+            .collect();
+        // Ten sites back {a, b} against {c, d, e}, so every replicate has it.
+        assert!(supports.contains(&100.0));
+        assert!(supports.iter().all(|s| (0.0..=100.0).contains(s)));
+
+        let mut other = PhyloTree::from_newick(b"((a,x),(c,d,e));").unwrap();
+        assert_eq!(
+            annotate_support(&mut other, &trees),
+            Err(TreeError::TaxaSetMismatch)
+        );
+        let mut unlabelled = PhyloTree::from_newick(b"((a,b),(c,d,));").unwrap();
+        assert!(matches!(
+            annotate_support(&mut unlabelled, &trees),
+            Err(TreeError::UnlabelledLeaf(_))
+        ));
+    }
+}
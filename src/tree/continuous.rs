@@ -0,0 +1,849 @@
+//! Continuous trait evolution: Brownian motion, Ornstein–Uhlenbeck and
+//! phylogenetic independent contrasts.
+//!
+//! Trait values come in as a map from taxon name to value, one per leaf.
+//!
+//! Brownian motion (BM) needs no matrices. A post-order pass merges sister
+//! lineages pairwise, as in Felsenstein's (1985) independent contrasts. Each
+//! merge yields one contrast and a precision-weighted estimate for the parent.
+//! The contrasts give the ML rate `sigma2` and the exact log-likelihood.
+//! A pre-order pass then brings in the information from outside each subtree,
+//! so every internal node gets its ML state and a confidence interval in
+//! `O(n)`. Polytomies are merged one child at a time. That matches resolving
+//! them with zero-length branches, so they are handled exactly.
+//!
+//! The Ornstein–Uhlenbeck (OU) model pulls the trait toward an optimum `theta`
+//! at rate `alpha`. Its likelihood is a GLS computation over the tip covariance
+//! matrix, with the root fixed at `theta`. `theta` and `sigma2` have closed
+//! forms for a given `alpha`, so fitting is a one-dimensional search over
+//! `alpha`.
+//!
+//! Pagel's lambda, kappa and delta return a rescaled copy of the tree. Any
+//! method here can then be run on the copy.
+
+use std::collections::HashMap;
+
+use crate::error::ComparativeError;
+use crate::node::NodeID;
+
+#[cfg(feature = "simple_rooted_tree")]
+use {crate::prelude::*, crate::tree::PhyloTree, nalgebra::DMatrix, nalgebra::DVector};
+
+/// The 97.5% quantile of the standard normal, for 95% confidence intervals.
+#[cfg(feature = "simple_rooted_tree")]
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Continuous trait models on a tree.
+///
+/// Feature-free (like [`crate::tree::likelihood::TreeLikelihood`]) so a caller
+/// bringing its own tree type can implement it without `simple_rooted_tree`.
+pub trait ContinuousTraits {
+    /// Felsenstein's phylogenetic independent contrasts, one per merge of
+    /// sister lineages in post-order.
+    fn independent_contrasts(
+        &self,
+        traits: &HashMap<String, f64>,
+    ) -> Result<Vec<Contrast>, ComparativeError>;
+
+    /// Fits Brownian motion and returns ML states, with 95% confidence
+    /// intervals, for every internal node.
+    fn bm_ancestral_states(
+        &self,
+        traits: &HashMap<String, f64>,
+    ) -> Result<AncestralStates, ComparativeError>;
+
+    /// Log-likelihood of the tip values under an OU process with the given
+    /// parameters, starting from `theta` at the root.
+    fn ou_log_likelihood(
+        &self,
+        traits: &HashMap<String, f64>,
+        alpha: f64,
+        theta: f64,
+        sigma2: f64,
+    ) -> Result<f64, ComparativeError>;
+
+    /// Fits an OU process by maximum likelihood.
+    fn fit_ou(&self, traits: &HashMap<String, f64>) -> Result<OuFit, ComparativeError>;
+}
+
+/// Pagel's branch-length transforms.
+pub trait PagelTransforms: Sized {
+    /// Multiplies internal branches by `lambda` and stretches terminal ones so
+    /// every tip keeps its distance from the root. `lambda = 0` is a star tree
+    /// and `lambda = 1` leaves the tree unchanged.
+    fn lambda_transform(&self, lambda: f64) -> Result<Self, ComparativeError>;
+
+    /// Raises every branch length to the power `kappa`. `kappa = 0` sets every
+    /// branch to length one (punctuational change at speciation).
+    fn kappa_transform(&self, kappa: f64) -> Result<Self, ComparativeError>;
+
+    /// Maps each node's distance from the root `h` to `T * (h / T)^delta`,
+    /// where `T` is the tree height. `delta < 1` moves change toward the root,
+    /// and `delta > 1` moves it toward the tips.
+    fn delta_transform(&self, delta: f64) -> Result<Self, ComparativeError>;
+}
+
+/// One phylogenetic independent contrast.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contrast {
+    /// The node at which the two lineages were merged.
+    pub node: NodeID,
+    /// Difference between the two lineages' estimated values.
+    pub raw: f64,
+    /// Expected variance of `raw` per unit of `sigma2`: the sum of the two
+    /// (extended) branch lengths.
+    pub variance: f64,
+    /// `raw / sqrt(variance)`, i.i.d. normal under Brownian motion.
+    pub standardized: f64,
+}
+
+/// A Brownian motion model fitted by maximum likelihood.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BrownianFit {
+    /// Rate of evolution (variance per unit branch length).
+    pub sigma2: f64,
+    /// State at the root.
+    pub root_state: f64,
+    /// Log-likelihood of the tip values at the fitted parameters.
+    pub log_likelihood: f64,
+}
+
+/// An estimated ancestral state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeEstimate {
+    /// The ML state.
+    pub estimate: f64,
+    /// Standard error of `estimate`.
+    pub std_error: f64,
+    /// Lower end of the 95% confidence interval.
+    pub ci_lower: f64,
+    /// Upper end of the 95% confidence interval.
+    pub ci_upper: f64,
+}
+
+/// Brownian motion ancestral states for every internal node.
+#[derive(Clone, Debug)]
+pub struct AncestralStates {
+    /// The fitted model the estimates are conditioned on.
+    pub fit: BrownianFit,
+    /// Estimates keyed by internal node.
+    pub estimates: HashMap<NodeID, NodeEstimate>,
+}
+
+/// An Ornstein–Uhlenbeck model fitted by maximum likelihood.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OuFit {
+    /// Strength of the pull toward `theta`.
+    pub alpha: f64,
+    /// The optimum, also the root state.
+    pub theta: f64,
+    /// Rate of the stochastic component.
+    pub sigma2: f64,
+    /// Log-likelihood of the tip values at the fitted parameters.
+    pub log_likelihood: f64,
+}
+
+impl OuFit {
+    /// Time for the expected distance to `theta` to halve, `ln 2 / alpha`.
+    pub fn half_life(&self) -> f64 {
+        std::f64::consts::LN_2 / self.alpha
+    }
+}
+
+/// A value estimated at a node and its variance in units of `sigma2`.
+#[cfg(feature = "simple_rooted_tree")]
+#[derive(Clone, Copy, Debug)]
+struct Message {
+    value: f64,
+    variance: f64,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+impl Message {
+    /// Precision-weighted combination of two independent estimates of one
+    /// value. Written without reciprocals so that one zero variance is exact.
+    fn merge(self, other: Message, node: NodeID) -> Result<Message, ComparativeError> {
+        let total = self.variance + other.variance;
+        if total <= 0.0 {
+            return Err(ComparativeError::ZeroLengthContrast(node));
+        }
+        Ok(Message {
+            value: (self.value * other.variance + other.value * self.variance) / total,
+            variance: self.variance * other.variance / total,
+        })
+    }
+
+    /// The message after travelling along a branch of length `length`.
+    fn extend(self, length: f64) -> Message {
+        Message {
+            value: self.value,
+            variance: self.variance + length,
+        }
+    }
+}
+
+/// Branch lengths and tip values of a tree, checked and indexed by node id.
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) struct TraitData {
+    /// Length of the branch above each node; zero for the root.
+    pub(crate) lengths: Vec<f64>,
+    /// Leaves paired with their trait values.
+    pub(crate) tips: Vec<(NodeID, f64)>,
+    pub(crate) postord: Vec<NodeID>,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+impl TraitData {
+    pub(crate) fn new(
+        tree: &PhyloTree,
+        traits: &HashMap<String, f64>,
+        min_taxa: usize,
+    ) -> Result<Self, ComparativeError> {
+        let root = tree.get_root_id();
+        let n_slots = tree.get_node_ids().max().map_or(0, |id| id + 1);
+        let mut lengths = vec![0.0; n_slots];
+        for node_id in tree.get_node_ids().filter(|&id| id != root) {
+            let length = tree
+                .get_edge_weight(0, node_id)
+                .map(f64::from)
+                .filter(|w| w.is_finite() && *w >= 0.0)
+                .ok_or(ComparativeError::MissingBranchLength(node_id))?;
+            lengths[node_id] = length;
+        }
+
+        let mut tips = Vec::new();
+        for leaf in tree.get_leaf_ids() {
+            let name = tree.get_node_taxa(leaf).cloned().unwrap_or_default();
+            let value = *traits
+                .get(&name)
+                .ok_or_else(|| ComparativeError::MissingTrait(name.clone()))?;
+            if !value.is_finite() {
+                return Err(ComparativeError::NonFiniteTrait(name));
+            }
+            tips.push((leaf, value));
+        }
+        if let Some(name) = traits.keys().find(|name| {
+            tree.get_taxa_node_id(name)
+                .is_none_or(|id| !tree.is_leaf(id))
+        }) {
+            return Err(ComparativeError::UnknownTaxon(name.clone()));
+        }
+        if tips.len() < min_taxa {
+            return Err(ComparativeError::TooFewTaxa {
+                expected: min_taxa,
+                actual: tips.len(),
+            });
+        }
+
+        let postord = tree
+            .postord_ids(root)
+            .expect("invariant: the root id always names a node")
+            .collect();
+        Ok(TraitData {
+            lengths,
+            tips,
+            postord,
+        })
+    }
+
+    /// Post-order pass: each node's estimate from its own subtree, and the
+    /// contrasts made along the way.
+    fn up_pass(
+        &self,
+        tree: &PhyloTree,
+    ) -> Result<(Vec<Option<Message>>, Vec<Contrast>), ComparativeError> {
+        let mut below: Vec<Option<Message>> = vec![None; self.lengths.len()];
+        for &(leaf, value) in &self.tips {
+            below[leaf] = Some(Message {
+                value,
+                variance: 0.0,
+            });
+        }
+        let mut contrasts = Vec::with_capacity(self.tips.len().saturating_sub(1));
+        for &node in &self.postord {
+            if tree.is_leaf(node) {
+                continue;
+            }
+            let mut acc: Option<Message> = None;
+            for child in tree.get_node_children_ids(node) {
+                let msg = below[child]
+                    .expect("invariant: children are visited before their parent")
+                    .extend(self.lengths[child]);
+                acc = Some(match acc {
+                    None => msg,
+                    Some(prev) => {
+                        let variance = prev.variance + msg.variance;
+                        let merged = prev.merge(msg, node)?;
+                        let raw = prev.value - msg.value;
+                        contrasts.push(Contrast {
+                            node,
+                            raw,
+                            variance,
+                            standardized: raw / variance.sqrt(),
+                        });
+                        merged
+                    }
+                });
+            }
+            below[node] = acc;
+        }
+        Ok((below, contrasts))
+    }
+
+    /// ML Brownian motion fit from the up pass.
+    fn fit(&self, root_msg: Message, contrasts: &[Contrast]) -> BrownianFit {
+        let n = self.tips.len() as f64;
+        let sigma2 = contrasts
+            .iter()
+            .map(|c| c.standardized * c.standardized)
+            .sum::<f64>()
+            / n;
+        let ln_2pi = (2.0 * std::f64::consts::PI).ln();
+        // The contrasts and the root estimate are independent, and the map from
+        // tip values to them has unit Jacobian, so their densities multiply out
+        // to the full likelihood. The root estimate sits exactly at its mean.
+        let contrast_ll: f64 = contrasts
+            .iter()
+            .map(|c| {
+                -0.5 * (ln_2pi + (sigma2 * c.variance).ln())
+                    - 0.5 * c.raw.powi(2) / (sigma2 * c.variance)
+            })
+            .sum();
+        let root_ll = -0.5 * (ln_2pi + (sigma2 * root_msg.variance).ln());
+        BrownianFit {
+            sigma2,
+            root_state: root_msg.value,
+            log_likelihood: contrast_ll + root_ll,
+        }
+    }
+}
+
+/// Felsenstein's phylogenetic independent contrasts for `traits` on `tree`.
+///
+/// # Errors
+///
+/// Returns a [`ComparativeError`] if a leaf has no value or a value names no
+/// leaf, a branch has no length, fewer than two taxa are given, or two sister
+/// lineages are separated by no branch length at all.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_independent_contrasts(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+) -> Result<Vec<Contrast>, ComparativeError> {
+    let data = TraitData::new(tree, traits, 2)?;
+    Ok(data.up_pass(tree)?.1)
+}
+
+/// Brownian motion ML ancestral states for every internal node of `tree`.
+///
+/// A node's estimate combines its subtree's estimate with the one from the
+/// rest of the tree, and its standard error is `sqrt(sigma2 / precision)`.
+/// `sigma2` is the ML rate (sum of squared standardized contrasts over the
+/// number of tips).
+///
+/// # Errors
+///
+/// See [`compute_independent_contrasts`].
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_bm_ancestral_states(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+) -> Result<AncestralStates, ComparativeError> {
+    let data = TraitData::new(tree, traits, 2)?;
+    let (below, contrasts) = data.up_pass(tree)?;
+    let root = tree.get_root_id();
+    let root_msg = below[root].expect("invariant: the up pass reaches the root");
+    let fit = data.fit(root_msg, &contrasts);
+
+    // Pre-order pass: `above[v]` is the estimate of v's state from everything
+    // outside v's subtree.
+    let mut above: Vec<Option<Message>> = vec![None; data.lengths.len()];
+    let mut estimates = HashMap::new();
+    for &node in data.postord.iter().rev() {
+        if tree.is_leaf(node) {
+            continue;
+        }
+        let own = below[node].expect("invariant: the up pass covers every node");
+        let full = match above[node] {
+            Some(outside) => own.merge(outside, node)?,
+            None => own,
+        };
+        let std_error = (fit.sigma2 * full.variance).sqrt();
+        estimates.insert(
+            node,
+            NodeEstimate {
+                estimate: full.value,
+                std_error,
+                ci_lower: full.value - Z_95 * std_error,
+                ci_upper: full.value + Z_95 * std_error,
+            },
+        );
+
+        let children = tree.get_node_children_ids(node).collect::<Vec<_>>();
+        for &child in &children {
+            let mut acc = above[node];
+            for &sibling in children.iter().filter(|&&s| s != child) {
+                let msg = below[sibling]
+                    .expect("invariant: the up pass covers every node")
+                    .extend(data.lengths[sibling]);
+                acc = Some(match acc {
+                    None => msg,
+                    Some(prev) => prev.merge(msg, node)?,
+                });
+            }
+            // A unary node has nothing outside its only child but its own
+            // outside, which the root does not have.
+            above[child] = acc.map(|m| m.extend(data.lengths[child]));
+        }
+    }
+
+    Ok(AncestralStates { fit, estimates })
+}
+
+/// Shared root-path lengths between tips, and each tip's distance from the
+/// root, in the order of `data.tips`.
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) fn shared_paths(tree: &PhyloTree, data: &TraitData) -> (DMatrix<f64>, Vec<f64>) {
+    let mut depth = vec![0.0; data.lengths.len()];
+    for &node in data.postord.iter().rev() {
+        if let Some(parent) = tree.get_node_parent_id(node) {
+            depth[node] = depth[parent] + data.lengths[node];
+        }
+    }
+    let lca = tree.lca();
+    let n = data.tips.len();
+    let mut shared = DMatrix::zeros(n, n);
+    for i in 0..n {
+        shared[(i, i)] = depth[data.tips[i].0];
+        for j in 0..i {
+            let s = depth[lca.get_lca_id(&[data.tips[i].0, data.tips[j].0])];
+            shared[(i, j)] = s;
+            shared[(j, i)] = s;
+        }
+    }
+    let heights = data.tips.iter().map(|&(tip, _)| depth[tip]).collect();
+    (shared, heights)
+}
+
+/// OU tip covariance per unit `sigma2`, with the root fixed:
+/// `exp(-alpha (T_i + T_j - 2 s_ij)) * (1 - exp(-2 alpha s_ij)) / (2 alpha)`,
+/// which tends to the Brownian `s_ij` as `alpha -> 0`.
+#[cfg(feature = "simple_rooted_tree")]
+fn ou_covariance(shared: &DMatrix<f64>, heights: &[f64], alpha: f64) -> DMatrix<f64> {
+    let n = heights.len();
+    DMatrix::from_fn(n, n, |i, j| {
+        let s = shared[(i, j)];
+        let accumulated = if alpha == 0.0 {
+            s
+        } else {
+            -(-2.0 * alpha * s).exp_m1() / (2.0 * alpha)
+        };
+        (-alpha * (heights[i] + heights[j] - 2.0 * s)).exp() * accumulated
+    })
+}
+
+/// Cholesky factor of `c` and its log-determinant.
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) fn factor(
+    c: DMatrix<f64>,
+) -> Result<(nalgebra::linalg::Cholesky<f64, nalgebra::Dyn>, f64), ComparativeError> {
+    let chol = c.cholesky().ok_or(ComparativeError::SingularCovariance)?;
+    let log_det = 2.0
+        * chol
+            .l_dirty()
+            .diagonal()
+            .iter()
+            .map(|d| d.ln())
+            .sum::<f64>();
+    Ok((chol, log_det))
+}
+
+/// Log-likelihood of the tips under an OU process with parameters `alpha`,
+/// `theta` and `sigma2`, the root fixed at `theta`.
+///
+/// # Errors
+///
+/// Returns [`ComparativeError::InvalidParameter`] for a negative `alpha` or a
+/// non-positive `sigma2`, [`ComparativeError::SingularCovariance`] if tips are
+/// not distinguishable (e.g. zero-length sisters), and the input errors of
+/// [`compute_independent_contrasts`].
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_ou_log_likelihood(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+    alpha: f64,
+    theta: f64,
+    sigma2: f64,
+) -> Result<f64, ComparativeError> {
+    if !(alpha >= 0.0 && alpha.is_finite()) {
+        return Err(ComparativeError::InvalidParameter(format!(
+            "alpha must be finite and non-negative, got {alpha}"
+        )));
+    }
+    if !(sigma2 > 0.0 && sigma2.is_finite()) {
+        return Err(ComparativeError::InvalidParameter(format!(
+            "sigma2 must be finite and positive, got {sigma2}"
+        )));
+    }
+    let data = TraitData::new(tree, traits, 1)?;
+    let (shared, heights) = shared_paths(tree, &data);
+    let (chol, log_det) = factor(ou_covariance(&shared, &heights, alpha))?;
+    let n = data.tips.len() as f64;
+    let residuals = DVector::from_iterator(data.tips.len(), data.tips.iter().map(|t| t.1 - theta));
+    let quad = residuals.dot(&chol.solve(&residuals));
+    Ok(-0.5 * (n * (2.0 * std::f64::consts::PI * sigma2).ln() + log_det + quad / sigma2))
+}
+
+/// Profile log-likelihood of `alpha`, with `theta` and `sigma2` at their GLS
+/// optima for that `alpha`.
+#[cfg(feature = "simple_rooted_tree")]
+fn ou_profile(
+    shared: &DMatrix<f64>,
+    heights: &[f64],
+    values: &DVector<f64>,
+    alpha: f64,
+) -> Result<OuFit, ComparativeError> {
+    let n = values.len();
+    let (chol, log_det) = factor(ou_covariance(shared, heights, alpha))?;
+    let ones = DVector::from_element(n, 1.0);
+    let c_inv_ones = chol.solve(&ones);
+    let theta = values.dot(&c_inv_ones) / ones.dot(&c_inv_ones);
+    let residuals = values.add_scalar(-theta);
+    let sigma2 = residuals.dot(&chol.solve(&residuals)) / n as f64;
+    if sigma2 <= 0.0 {
+        // Every tip has the same value; there is nothing to fit.
+        return Err(ComparativeError::SingularCovariance);
+    }
+    let log_likelihood =
+        -0.5 * (n as f64 * ((2.0 * std::f64::consts::PI * sigma2).ln() + 1.0) + log_det);
+    Ok(OuFit {
+        alpha,
+        theta,
+        sigma2,
+        log_likelihood,
+    })
+}
+
+/// Fits an OU process to the tips by maximum likelihood.
+///
+/// `alpha` is searched on a log grid from `1e-4 / T` to `1e3 / T`, where `T`
+/// is the tree height, and then refined by golden-section search around the
+/// best grid point. `theta` and `sigma2` are profiled out in closed form at
+/// each `alpha`. A fit at the lower end of the grid means the data look
+/// Brownian.
+///
+/// # Errors
+///
+/// Returns the input errors of [`compute_independent_contrasts`],
+/// [`ComparativeError::TooFewTaxa`] for fewer than three taxa, and
+/// [`ComparativeError::SingularCovariance`] if all tips share one value or
+/// cannot be told apart.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_ou_fit(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+) -> Result<OuFit, ComparativeError> {
+    const GRID: usize = 36;
+    let data = TraitData::new(tree, traits, 3)?;
+    let (shared, heights) = shared_paths(tree, &data);
+    let height = heights.iter().copied().fold(0.0, f64::max);
+    if height <= 0.0 {
+        return Err(ComparativeError::SingularCovariance);
+    }
+    let values = DVector::from_iterator(data.tips.len(), data.tips.iter().map(|t| t.1));
+    let profile = |log_alpha: f64| ou_profile(&shared, &heights, &values, log_alpha.exp());
+
+    let (lo, hi) = ((1e-4 / height).ln(), (1e3 / height).ln());
+    let step = (hi - lo) / (GRID - 1) as f64;
+    let mut best: Option<(usize, OuFit)> = None;
+    for k in 0..GRID {
+        // Very large alphas can make the covariance numerically singular;
+        // those points simply drop out of the search.
+        if let Ok(fit) = profile(lo + step * k as f64) {
+            if best.is_none_or(|(_, b)| fit.log_likelihood > b.log_likelihood) {
+                best = Some((k, fit));
+            }
+        }
+    }
+    let (k, mut best_fit) = best.ok_or(ComparativeError::SingularCovariance)?;
+
+    // Golden-section refinement on the bracket around the best grid point.
+    let inv_phi = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut a, mut b) = (
+        lo + step * k.saturating_sub(1) as f64,
+        lo + step * (k + 1).min(GRID - 1) as f64,
+    );
+    let neg_ll = |x: f64| profile(x).map_or(f64::INFINITY, |f| -f.log_likelihood);
+    let mut c = b - inv_phi * (b - a);
+    let mut d = a + inv_phi * (b - a);
+    let (mut fc, mut fd) = (neg_ll(c), neg_ll(d));
+    for _ in 0..60 {
+        if fc < fd {
+            b = d;
+            d = c;
+            fd = fc;
+            c = b - inv_phi * (b - a);
+            fc = neg_ll(c);
+        } else {
+            a = c;
+            c = d;
+            fc = fd;
+            d = a + inv_phi * (b - a);
+            fd = neg_ll(d);
+        }
+    }
+    if let Ok(fit) = profile((a + b) / 2.0) {
+        if fit.log_likelihood > best_fit.log_likelihood {
+            best_fit = fit;
+        }
+    }
+    Ok(best_fit)
+}
+
+/// Copies `tree` with each non-root branch set to `length(node, old_length)`.
+#[cfg(feature = "simple_rooted_tree")]
+fn rescale<F>(tree: &PhyloTree, mut length: F) -> Result<PhyloTree, ComparativeError>
+where
+    F: FnMut(NodeID, f64) -> Result<f64, ComparativeError>,
+{
+    let root = tree.get_root_id();
+    let mut out = tree.clone();
+    for node_id in tree.get_node_ids().filter(|&id| id != root) {
+        let old = tree
+            .get_edge_weight(0, node_id)
+            .map(f64::from)
+            .filter(|w| w.is_finite() && *w >= 0.0)
+            .ok_or(ComparativeError::MissingBranchLength(node_id))?;
+        let new = length(node_id, old)?;
+        out.set_edge_weight((0, node_id), Some(new as f32));
+    }
+    Ok(out)
+}
+
+/// Distance from the root to every node, indexed by node id.
+#[cfg(feature = "simple_rooted_tree")]
+fn root_distances(tree: &PhyloTree) -> Result<Vec<f64>, ComparativeError> {
+    let root = tree.get_root_id();
+    let n_slots = tree.get_node_ids().max().map_or(0, |id| id + 1);
+    let mut depth = vec![0.0; n_slots];
+    for node in tree
+        .preord_ids(root)
+        .expect("invariant: the root id always names a node")
+    {
+        if let Some(parent) = tree.get_node_parent_id(node) {
+            let length = tree
+                .get_edge_weight(parent, node)
+                .map(f64::from)
+                .filter(|w| w.is_finite() && *w >= 0.0)
+                .ok_or(ComparativeError::MissingBranchLength(node))?;
+            depth[node] = depth[parent] + length;
+        }
+    }
+    Ok(depth)
+}
+
+/// Pagel's lambda transform of `tree`.
+///
+/// # Errors
+///
+/// Returns [`ComparativeError::InvalidParameter`] for a negative `lambda`, or
+/// one so large that a terminal branch would become negative, and
+/// [`ComparativeError::MissingBranchLength`] if a branch has no length.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_lambda_transform(
+    tree: &PhyloTree,
+    lambda: f64,
+) -> Result<PhyloTree, ComparativeError> {
+    if !(lambda >= 0.0 && lambda.is_finite()) {
+        return Err(ComparativeError::InvalidParameter(format!(
+            "lambda must be finite and non-negative, got {lambda}"
+        )));
+    }
+    let depth = root_distances(tree)?;
+    rescale(tree, |node, old| {
+        if !tree.is_leaf(node) {
+            return Ok(old * lambda);
+        }
+        let parent = tree
+            .get_node_parent_id(node)
+            .expect("invariant: only the root has no parent");
+        let stretched = depth[node] - lambda * depth[parent];
+        if stretched < 0.0 {
+            return Err(ComparativeError::InvalidParameter(format!(
+                "lambda {lambda} makes the branch above node {node} negative"
+            )));
+        }
+        Ok(stretched)
+    })
+}
+
+/// Pagel's kappa transform of `tree`.
+///
+/// # Errors
+///
+/// Returns [`ComparativeError::InvalidParameter`] for a negative `kappa` and
+/// [`ComparativeError::MissingBranchLength`] if a branch has no length.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_kappa_transform(
+    tree: &PhyloTree,
+    kappa: f64,
+) -> Result<PhyloTree, ComparativeError> {
+    if !(kappa >= 0.0 && kappa.is_finite()) {
+        return Err(ComparativeError::InvalidParameter(format!(
+            "kappa must be finite and non-negative, got {kappa}"
+        )));
+    }
+    rescale(tree, |_, old| Ok(old.powf(kappa)))
+}
+
+/// Pagel's delta transform of `tree`.
+///
+/// # Errors
+///
+/// Returns [`ComparativeError::InvalidParameter`] for a non-positive `delta`
+/// and [`ComparativeError::MissingBranchLength`] if a branch has no length.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_delta_transform(
+    tree: &PhyloTree,
+    delta: f64,
+) -> Result<PhyloTree, ComparativeError> {
+    if !(delta > 0.0 && delta.is_finite()) {
+        return Err(ComparativeError::InvalidParameter(format!(
+            "delta must be finite and positive, got {delta}"
+        )));
+    }
+    let depth = root_distances(tree)?;
+    let height = depth.iter().copied().fold(0.0, f64::max);
+    if height <= 0.0 {
+        return Ok(tree.clone());
+    }
+    let scaled = |h: f64| height * (h / height).powf(delta);
+    rescale(tree, |node, _| {
+        let parent = tree
+            .get_node_parent_id(node)
+            .expect("invariant: only the root has no parent");
+        Ok(scaled(depth[node]) - scaled(depth[parent]))
+    })
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+
+    fn traits(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v)).collect()
+    }
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick("((A:1,B:1):1,(C:0.5,D:1.5):0.5,E:2);".as_bytes()).unwrap()
+    }
+
+    fn values() -> HashMap<String, f64> {
+        traits(&[("A", 1.0), ("B", 2.0), ("C", 4.0), ("D", 3.0), ("E", 0.5)])
+    }
+
+    #[test]
+    fn test_contrasts_on_cherry() {
+        let tree = PhyloTree::from_newick("((A:1,B:3):1,C:2);".as_bytes()).unwrap();
+        let contrasts = tree
+            .independent_contrasts(&traits(&[("A", 1.0), ("B", 5.0), ("C", 2.0)]))
+            .unwrap();
+        assert_eq!(contrasts.len(), 2);
+        // A vs B: raw -4, variance 4. Their parent sits at (1*3 + 5*1)/4 = 2 with
+        // extra length 3/4, so the next contrast is 2 - 2 over 1.75 + 2.
+        assert!((contrasts[0].raw + 4.0).abs() < 1e-12);
+        assert!((contrasts[0].standardized + 2.0).abs() < 1e-12);
+        assert!(contrasts[1].raw.abs() < 1e-12);
+        assert!((contrasts[1].variance - 3.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bm_matches_gls_likelihood() {
+        let tree = tree();
+        let states = tree.bm_ancestral_states(&values()).unwrap();
+        // With alpha = 0 the OU covariance is the Brownian one, so the OU
+        // likelihood at the BM estimates must agree exactly.
+        let ou = tree
+            .ou_log_likelihood(&values(), 0.0, states.fit.root_state, states.fit.sigma2)
+            .unwrap();
+        assert!((ou - states.fit.log_likelihood).abs() < 1e-9);
+        assert_eq!(states.estimates.len(), 3);
+        let root = states.estimates[&tree.get_root_id()];
+        assert!((root.estimate - states.fit.root_state).abs() < 1e-12);
+        assert!(root.ci_lower < root.estimate && root.estimate < root.ci_upper);
+    }
+
+    #[test]
+    fn test_ancestral_states_match_rerooted_contrasts() {
+        // The estimate at the (A,B) ancestor equals the root estimate of the
+        // same tree rerooted there.
+        let tree = tree();
+        let rerooted =
+            PhyloTree::from_newick("(A:1,B:1,((C:0.5,D:1.5):0.5,E:2):1);".as_bytes()).unwrap();
+        let states = tree.bm_ancestral_states(&values()).unwrap();
+        let a = tree.get_taxa_node_id(&"A".to_string()).unwrap();
+        let ab = tree.get_node_parent_id(a).unwrap();
+        let expected = rerooted.bm_ancestral_states(&values()).unwrap().fit;
+        assert!((states.estimates[&ab].estimate - expected.root_state).abs() < 1e-12);
+        assert!((states.fit.sigma2 - expected.sigma2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_ou_fit_beats_bm() {
+        let tree = tree();
+        let fit = tree.fit_ou(&values()).unwrap();
+        let bm = tree.bm_ancestral_states(&values()).unwrap().fit;
+        assert!(fit.alpha > 0.0);
+        assert!(fit.log_likelihood >= bm.log_likelihood - 1e-6);
+        let at_fit = tree
+            .ou_log_likelihood(&values(), fit.alpha, fit.theta, fit.sigma2)
+            .unwrap();
+        assert!((at_fit - fit.log_likelihood).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_input_errors() {
+        let tree = tree();
+        let mut missing = values();
+        missing.remove("E");
+        assert_eq!(
+            tree.independent_contrasts(&missing),
+            Err(ComparativeError::MissingTrait("E".to_string()))
+        );
+        let mut extra = values();
+        extra.insert("Z".to_string(), 1.0);
+        assert_eq!(
+            tree.independent_contrasts(&extra),
+            Err(ComparativeError::UnknownTaxon("Z".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_pagel_transforms() {
+        let tree = tree();
+        let height = |t: &PhyloTree, taxon: &str| {
+            let depth = root_distances(t).unwrap();
+            depth[t.get_taxa_node_id(&taxon.to_string()).unwrap()]
+        };
+
+        let star = tree.lambda_transform(0.0).unwrap();
+        for taxon in ["A", "B", "C", "D", "E"] {
+            assert!((height(&star, taxon) - height(&tree, taxon)).abs() < 1e-6);
+            let parent = star
+                .get_node_parent_id(star.get_taxa_node_id(&taxon.to_string()).unwrap())
+                .unwrap();
+            assert_eq!(root_distances(&star).unwrap()[parent], 0.0);
+        }
+
+        let unit = tree.kappa_transform(0.0).unwrap();
+        assert!(unit
+            .get_node_ids()
+            .filter(|&id| id != unit.get_root_id())
+            .all(|id| unit.get_edge_weight(0, id) == Some(1.0)));
+
+        let delta = tree.delta_transform(2.0).unwrap();
+        assert!((height(&delta, "E") - 2.0).abs() < 1e-6);
+        assert!((height(&delta, "A") - 2.0).abs() < 1e-6);
+        assert!(tree.delta_transform(0.0).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use itertools::Itertools;
-use num::{Float, NumCast, One, Zero};
+use num::{Float, NumCast, One, ToPrimitive, Zero};
 use std::fmt::Debug;
 use vers_vecs::BitVec;
 
@@ -12,6 +12,8 @@ use std::collections::{HashMap, HashSet};
 use rayon::prelude::*;
 
 use crate::prelude::*;
+#[cfg(feature = "simple_rooted_tree")]
+use crate::{node::Node, tree::PhyloTree};
 
 /// A trait describing the path functions in a tree.
 pub trait PathFunction: RootedTree
@@ -76,6 +78,16 @@ where
     /// Return the symmetrical pairwise distance matrix.
     fn matrix(&self) -> Vec<Vec<TreeNodeWeight<Self>>>;
 
+    /// Builds a constant-time path-length oracle borrowing this tree
+    /// immutably.
+    ///
+    /// Root distances are computed once, so every later query is O(1). Build
+    /// one, run every query against it, then drop it before mutating the tree
+    /// again.
+    fn path_oracle(&self) -> PathOracle<'_, Self> {
+        PathOracle::build(self)
+    }
+
     /// Distance between two nodes, using a prebuilt LCA oracle.
     ///
     /// The oracle is passed in rather than rebuilt per call so a full matrix
@@ -90,6 +102,28 @@ where
         node_id_1: TreeNodeID<Self>,
         node_id_2: TreeNodeID<Self>,
     ) -> Result<TreeNodeWeight<Self>, TreeError>;
+
+    /// Distance between two nodes, using a prebuilt path oracle, in O(1).
+    ///
+    /// Like [`Self::pairwise_distance`], but root distances come from the
+    /// oracle rather than a walk to the root per call. Build it once with
+    /// [`Self::path_oracle`] and borrow it here.
+    /// # Errors
+    ///
+    /// [`TreeError::UnknownNode`] if either id is not a node of this tree.
+    fn path_distance(
+        &self,
+        oracle: &PathOracle<'_, Self>,
+        node_id_1: TreeNodeID<Self>,
+        node_id_2: TreeNodeID<Self>,
+    ) -> Result<TreeNodeWeight<Self>, TreeError> {
+        for id in [node_id_1, node_id_2] {
+            if !self.contains_node(id) {
+                return Err(TreeError::UnknownNode(id.into()));
+            }
+        }
+        Ok(oracle.path_length(node_id_1, node_id_2))
+    }
 }
 
 /// A trait describing naive computation of Robinson Foulds distance
@@ -574,3 +608,651 @@ where
         Ok(Self::compute_norm(cophen_vec.into_iter(), norm))
     }
 }
+
+/// Taxa below an edge, as a bitset over the sorted taxa of a [`BhvTree`].
+type Cluster = Vec<u64>;
+
+/// Edges of one tree that shrink to zero together along a geodesic, and the
+/// edges of the other tree that then grow together.
+type SupportPair = (Vec<(Cluster, f64)>, Vec<(Cluster, f64)>);
+
+fn cluster_size(c: &Cluster) -> usize {
+    c.iter().map(|w| w.count_ones() as usize).sum()
+}
+
+fn cluster_meet(a: &Cluster, b: &Cluster) -> usize {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x & y).count_ones() as usize)
+        .sum()
+}
+
+/// Two clusters label edges of one rooted tree iff they are nested or
+/// disjoint.
+fn compatible(a: &Cluster, b: &Cluster) -> bool {
+    let meet = cluster_meet(a, b);
+    meet == 0 || meet == cluster_size(a) || meet == cluster_size(b)
+}
+
+fn norm(edges: &[(Cluster, f64)]) -> f64 {
+    edges.iter().map(|(_, l)| l * l).sum::<f64>().sqrt()
+}
+
+/// A rooted tree with edge lengths, as a point of Billera-Holmes-Vogtmann
+/// tree space.
+///
+/// Internal edges are keyed by the cluster of taxa below them and pendant
+/// edges by their taxon. Edges of zero length are dropped: they only place
+/// the tree on the boundary of an orthant. Unary nodes are suppressed, their
+/// edges joined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BhvTree {
+    /// Leaf taxa, sorted.
+    taxa: Vec<String>,
+    /// Internal edges, ascending by cluster, with positive lengths.
+    edges: Vec<(Cluster, f64)>,
+    /// Pendant edge length of each taxon.
+    leaves: Vec<f64>,
+}
+
+impl BhvTree {
+    /// Reads the edges of `tree`. Taxa are compared by their `Display` form.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::MissingWeight`] if an edge has no weight.
+    pub fn from_tree<T>(tree: &T) -> Result<Self, TreeError>
+    where
+        T: RootedWeightedTree + RootedMetaTree,
+        <T as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+    {
+        let label = |leaf| {
+            tree.get_node_taxa(leaf)
+                .expect("invariant: leaves carry a taxon")
+                .to_string()
+        };
+        let mut taxa = tree.get_leaf_ids().map(label).collect::<Vec<_>>();
+        taxa.sort_unstable();
+        let index: HashMap<&str, usize> = taxa
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.as_str(), i))
+            .collect();
+
+        // Reversed preorder finishes children before their parent.
+        let mut order = vec![];
+        let mut stack = vec![tree.get_root_id()];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(tree.get_node_children_ids(id));
+        }
+        let mut clusters: HashMap<TreeNodeID<T>, Cluster> = HashMap::default();
+        let mut lengths: HashMap<Cluster, f64> = HashMap::default();
+        let mut leaves = vec![0.0; taxa.len()];
+        for &id in order.iter().rev() {
+            let mut cluster = vec![0u64; taxa.len().div_ceil(64)];
+            match tree.is_leaf(id) {
+                true => {
+                    let t = index[label(id).as_str()];
+                    cluster[t / 64] |= 1 << (t % 64);
+                }
+                false => {
+                    for child in tree.get_node_children_ids(id) {
+                        for (word, w) in cluster.iter_mut().zip(&clusters[&child]) {
+                            *word |= w;
+                        }
+                    }
+                }
+            }
+            if let Some(parent) = tree.get_node_parent_id(id) {
+                let length = tree
+                    .get_edge_weight(parent, id)
+                    .ok_or(TreeError::MissingWeight(id.into()))?
+                    .to_f64()
+                    .expect("invariant: float edge weights convert to f64");
+                match cluster_size(&cluster) {
+                    0 => {}
+                    1 => {
+                        let (word, bits) = cluster
+                            .iter()
+                            .enumerate()
+                            .find(|&(_, &w)| w != 0)
+                            .expect("invariant: a singleton cluster has a set bit");
+                        leaves[word * 64 + bits.trailing_zeros() as usize] += length;
+                    }
+                    _ => *lengths.entry(cluster.clone()).or_insert(0.0) += length,
+                }
+            }
+            clusters.insert(id, cluster);
+        }
+        let mut edges = lengths
+            .into_iter()
+            .filter(|&(_, l)| l > 0.0)
+            .collect::<Vec<_>>();
+        edges.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(BhvTree {
+            taxa,
+            edges,
+            leaves,
+        })
+    }
+
+    /// Leaf taxa, sorted.
+    pub fn taxa(&self) -> &[String] {
+        &self.taxa
+    }
+
+    /// Internal edges, as the taxa below each with its length.
+    pub fn edges(&self) -> impl Iterator<Item = (Vec<&str>, f64)> {
+        self.edges.iter().map(|(cluster, length)| {
+            let taxa = (0..self.taxa.len())
+                .filter(|&t| cluster[t / 64] >> (t % 64) & 1 == 1)
+                .map(|t| self.taxa[t].as_str())
+                .collect();
+            (taxa, *length)
+        })
+    }
+
+    /// Pendant edge length of each taxon, in the order of
+    /// [`taxa`](Self::taxa).
+    pub fn leaf_lengths(&self) -> &[f64] {
+        &self.leaves
+    }
+
+    /// The geodesic from this tree to `other`, by the GTP algorithm of Owen &
+    /// Provan (2011).
+    ///
+    /// Edges common to both trees split the problem into independent parts,
+    /// one below each common edge. In each part the geodesic is described by
+    /// a sequence of pairs `(A_i, B_i)` of edge sets of the two trees: the
+    /// edges of `A_i` shrink to zero together while those of `B_i` grow.
+    /// Starting from one pair, a pair is split in two whenever a minimum
+    /// weight vertex cover of its incompatibility graph, found by max flow,
+    /// weighs less than one. The path length is then
+    /// `sqrt(Σ (‖A_i‖ + ‖B_i‖)²)`, plus the squared differences of common and
+    /// pendant edges under the root.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    pub fn geodesic(&self, other: &BhvTree) -> Result<Geodesic, TreeError> {
+        if self.taxa != other.taxa {
+            return Err(TreeError::TaxaSetMismatch);
+        }
+        let mut common = vec![];
+        let (mut only_a, mut only_b) = (vec![], vec![]);
+        let (mut x, mut y) = (self.edges.iter().peekable(), other.edges.iter().peekable());
+        loop {
+            match (x.peek(), y.peek()) {
+                (Some(a), Some(b)) => match a.0.cmp(&b.0) {
+                    std::cmp::Ordering::Equal => {
+                        common.push((a.0.clone(), a.1, b.1));
+                        x.next();
+                        y.next();
+                    }
+                    std::cmp::Ordering::Less => only_a.extend(x.next().cloned()),
+                    std::cmp::Ordering::Greater => only_b.extend(y.next().cloned()),
+                },
+                (Some(_), None) => only_a.extend(x.next().cloned()),
+                (None, Some(_)) => only_b.extend(y.next().cloned()),
+                (None, None) => break,
+            }
+        }
+
+        // An edge belongs to the part of the smallest common edge above it;
+        // edges in different parts are always compatible.
+        let part = |c: &Cluster| {
+            common
+                .iter()
+                .enumerate()
+                .filter(|(_, (d, ..))| d != c && cluster_meet(d, c) == cluster_size(c))
+                .min_by_key(|(_, (d, ..))| cluster_size(d))
+                .map_or(common.len(), |(i, _)| i)
+        };
+        let mut parts = vec![(vec![], vec![]); common.len() + 1];
+        for edge in only_a {
+            parts[part(&edge.0)].0.push(edge);
+        }
+        for edge in only_b {
+            parts[part(&edge.0)].1.push(edge);
+        }
+        let support = parts
+            .into_iter()
+            .flat_map(|(a, b)| geodesic_support(a, b))
+            .collect::<Vec<_>>();
+
+        let squared = common.iter().map(|(_, a, b)| (a - b).powi(2)).sum::<f64>()
+            + support
+                .iter()
+                .map(|(a, b)| (norm(a) + norm(b)).powi(2))
+                .sum::<f64>()
+            + self
+                .leaves
+                .iter()
+                .zip(&other.leaves)
+                .map(|(a, b)| (a - b).powi(2))
+                .sum::<f64>();
+        Ok(Geodesic {
+            taxa: self.taxa.clone(),
+            common,
+            support,
+            leaves: self
+                .leaves
+                .iter()
+                .copied()
+                .zip(other.leaves.iter().copied())
+                .collect(),
+            length: squared.sqrt(),
+        })
+    }
+
+    /// Length of the geodesic to `other`.
+    ///
+    /// # Errors
+    ///
+    /// As for [`geodesic`](Self::geodesic).
+    pub fn distance(&self, other: &BhvTree) -> Result<f64, TreeError> {
+        Ok(self.geodesic(other)?.length())
+    }
+
+    #[cfg(feature = "simple_rooted_tree")]
+    /// Builds the tree this point stands for.
+    pub fn to_tree(&self) -> PhyloTree {
+        let mut tree = PhyloTree::new(0);
+        let root = tree.get_root_id();
+        let attach = |tree: &mut PhyloTree, parent: usize, length: f64| {
+            let node = Node::new(tree.next_id());
+            let id = node.get_id();
+            tree.add_child(parent, node);
+            tree.set_edge_weight((parent, id), Some(length as f32));
+            id
+        };
+        // Larger clusters first, so each cluster's parent is the last one
+        // before it that contains it.
+        let mut edges = self.edges.iter().collect::<Vec<_>>();
+        edges.sort_by_key(|(c, _)| std::cmp::Reverse(cluster_size(c)));
+        let mut nodes: Vec<usize> = Vec::with_capacity(edges.len());
+        for (p, (cluster, length)) in edges.iter().enumerate() {
+            let parent = (0..p)
+                .rev()
+                .find(|&q| cluster_meet(&edges[q].0, cluster) == cluster_size(cluster))
+                .map_or(root, |q| nodes[q]);
+            nodes.push(attach(&mut tree, parent, *length));
+        }
+        for (t, name) in self.taxa.iter().enumerate() {
+            let parent = (0..edges.len())
+                .rev()
+                .find(|&q| edges[q].0[t / 64] >> (t % 64) & 1 == 1)
+                .map_or(root, |q| nodes[q]);
+            let id = attach(&mut tree, parent, self.leaves[t]);
+            tree.set_node_taxa(id, Some(name.clone()));
+        }
+        tree
+    }
+}
+
+/// Splits `(a, b)` into the support of the geodesic between the parts of two
+/// trees sharing no edge.
+fn geodesic_support(a: Vec<(Cluster, f64)>, b: Vec<(Cluster, f64)>) -> Vec<SupportPair> {
+    let conflicts = a
+        .iter()
+        .map(|(e, _)| {
+            (0..b.len())
+                .filter(|&j| !compatible(e, &b[j].0))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut pairs = vec![((0..a.len()).collect::<Vec<_>>(), (0..b.len()).collect())];
+    let mut i = 0;
+    while i < pairs.len() {
+        match split_pair(&a, &b, &conflicts, &pairs[i]) {
+            Some((first, second)) => {
+                pairs[i] = first;
+                pairs.insert(i + 1, second);
+            }
+            None => i += 1,
+        }
+    }
+    pairs
+        .into_iter()
+        .filter(|(x, y)| !x.is_empty() || !y.is_empty())
+        .map(|(x, y)| {
+            (
+                x.into_iter().map(|i| a[i].clone()).collect(),
+                y.into_iter().map(|j| b[j].clone()).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Splits the pair of edge sets `(A, B)`, given by position in `a` and `b`,
+/// into `(C1, D1), (C2, D2)` with `C2` compatible with `D1` and
+/// `‖C1‖/‖D1‖ < ‖C2‖/‖D2‖`, if such a split exists. `C1 ∪ D2` is a minimum
+/// weight vertex cover of the incompatibility graph, each edge weighing its
+/// squared length over the squared norm of its side.
+#[allow(clippy::type_complexity)]
+fn split_pair(
+    a: &[(Cluster, f64)],
+    b: &[(Cluster, f64)],
+    conflicts: &[Vec<usize>],
+    (x, y): &(Vec<usize>, Vec<usize>),
+) -> Option<((Vec<usize>, Vec<usize>), (Vec<usize>, Vec<usize>))> {
+    if x.is_empty() || y.is_empty() {
+        return None;
+    }
+    let squared = |edges: &[(Cluster, f64)], ids: &[usize]| {
+        ids.iter().map(|&i| edges[i].1 * edges[i].1).sum::<f64>()
+    };
+    let (na, nb) = (squared(a, x), squared(b, y));
+    // Source 0, A at 1..=p, B at p+1..=p+q, sink p+q+1.
+    let (p, q) = (x.len(), y.len());
+    let sink = p + q + 1;
+    let mut position = vec![usize::MAX; b.len()];
+    for (k, &j) in y.iter().enumerate() {
+        position[j] = k;
+    }
+    let mut network = FlowNetwork::new(sink + 1);
+    for (k, &i) in x.iter().enumerate() {
+        network.add_arc(0, 1 + k, a[i].1 * a[i].1 / na);
+        for &j in &conflicts[i] {
+            if position[j] != usize::MAX {
+                network.add_arc(1 + k, 1 + p + position[j], f64::INFINITY);
+            }
+        }
+    }
+    for (k, &j) in y.iter().enumerate() {
+        network.add_arc(1 + p + k, sink, b[j].1 * b[j].1 / nb);
+    }
+    let reachable = network.min_cut(0, sink);
+
+    let (mut c1, mut c2, mut d1, mut d2) = (vec![], vec![], vec![], vec![]);
+    let mut cover = 0.0;
+    for (k, &i) in x.iter().enumerate() {
+        match reachable[1 + k] {
+            true => c2.push(i),
+            false => {
+                cover += a[i].1 * a[i].1 / na;
+                c1.push(i);
+            }
+        }
+    }
+    for (k, &j) in y.iter().enumerate() {
+        match reachable[1 + p + k] {
+            true => {
+                cover += b[j].1 * b[j].1 / nb;
+                d2.push(j);
+            }
+            false => d1.push(j),
+        }
+    }
+    match cover < 1.0 - 1e-10 {
+        true => Some(((c1, d1), (c2, d2))),
+        false => None,
+    }
+}
+
+/// Residual capacity below which an arc counts as saturated.
+const FLOW_EPSILON: f64 = 1e-12;
+
+/// A flow network for minimum cuts, by Dinic's algorithm.
+struct FlowNetwork {
+    /// Head and residual capacity of each arc; arc `e ^ 1` reverses arc `e`.
+    arcs: Vec<(usize, f64)>,
+    /// Arcs leaving each vertex.
+    out: Vec<Vec<usize>>,
+}
+
+impl FlowNetwork {
+    fn new(vertices: usize) -> Self {
+        FlowNetwork {
+            arcs: vec![],
+            out: vec![vec![]; vertices],
+        }
+    }
+
+    fn add_arc(&mut self, from: usize, to: usize, capacity: f64) {
+        self.out[from].push(self.arcs.len());
+        self.arcs.push((to, capacity));
+        self.out[to].push(self.arcs.len());
+        self.arcs.push((from, 0.0));
+    }
+
+    /// Saturates a maximum flow from `source` to `sink`, then returns which
+    /// vertices the residual network still reaches from `source`: the source
+    /// side of a minimum cut.
+    fn min_cut(mut self, source: usize, sink: usize) -> Vec<bool> {
+        loop {
+            let level = self.levels(source);
+            if level[sink] == usize::MAX {
+                return level.iter().map(|&l| l != usize::MAX).collect();
+            }
+            let mut next = vec![0; self.out.len()];
+            while self.augment(source, sink, f64::INFINITY, &level, &mut next) > FLOW_EPSILON {}
+        }
+    }
+
+    /// Breadth-first distance of each vertex from `source` over unsaturated
+    /// arcs, `usize::MAX` if unreached.
+    fn levels(&self, source: usize) -> Vec<usize> {
+        let mut level = vec![usize::MAX; self.out.len()];
+        level[source] = 0;
+        let mut queue = std::collections::VecDeque::from([source]);
+        while let Some(u) = queue.pop_front() {
+            for &e in &self.out[u] {
+                let (v, capacity) = self.arcs[e];
+                if capacity > FLOW_EPSILON && level[v] == usize::MAX {
+                    level[v] = level[u] + 1;
+                    queue.push_back(v);
+                }
+            }
+        }
+        level
+    }
+
+    /// Pushes flow along one path of increasing level from `u` to `sink`,
+    /// returning the amount.
+    fn augment(
+        &mut self,
+        u: usize,
+        sink: usize,
+        limit: f64,
+        level: &[usize],
+        next: &mut [usize],
+    ) -> f64 {
+        if u == sink {
+            return limit;
+        }
+        while next[u] < self.out[u].len() {
+            let e = self.out[u][next[u]];
+            let (v, capacity) = self.arcs[e];
+            if capacity > FLOW_EPSILON && level[v] == level[u] + 1 {
+                let pushed = self.augment(v, sink, limit.min(capacity), level, next);
+                if pushed > FLOW_EPSILON {
+                    self.arcs[e].1 -= pushed;
+                    self.arcs[e ^ 1].1 += pushed;
+                    return pushed;
+                }
+            }
+            next[u] += 1;
+        }
+        0.0
+    }
+}
+
+/// The shortest path between two points of BHV tree space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geodesic {
+    taxa: Vec<String>,
+    /// Edges of both trees, with their length in each.
+    common: Vec<(Cluster, f64, f64)>,
+    /// Pairs of edge sets of the two trees, each shrinking and growing
+    /// together.
+    support: Vec<SupportPair>,
+    /// Pendant edge lengths in each tree.
+    leaves: Vec<(f64, f64)>,
+    length: f64,
+}
+
+impl Geodesic {
+    /// Length of the path.
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    /// The tree a `t` fraction of the way along the path, `t` clamped to
+    /// `[0, 1]`.
+    pub fn point_at(&self, t: f64) -> BhvTree {
+        let t = t.clamp(0.0, 1.0);
+        let mut edges = self
+            .common
+            .iter()
+            .map(|(c, a, b)| (c.clone(), (1.0 - t) * a + t * b))
+            .collect::<Vec<_>>();
+        for (a, b) in &self.support {
+            let (na, nb) = (norm(a), norm(b));
+            // Positive while the edges of `a` remain, negative once those of
+            // `b` have appeared.
+            let balance = (1.0 - t) * na - t * nb;
+            match balance > 0.0 {
+                true => edges.extend(a.iter().map(|(c, l)| (c.clone(), l * balance / na))),
+                false => edges.extend(b.iter().map(|(c, l)| (c.clone(), -l * balance / nb))),
+            }
+        }
+        edges.retain(|&(_, l)| l > 0.0);
+        edges.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        BhvTree {
+            taxa: self.taxa.clone(),
+            edges,
+            leaves: self
+                .leaves
+                .iter()
+                .map(|(a, b)| (1.0 - t) * a + t * b)
+                .collect(),
+        }
+    }
+}
+
+/// Fréchet mean and median of a sample of trees in BHV tree space.
+///
+/// BHV space has non-positive curvature, so both exist and the mean is
+/// unique. The median need not be: the sum of distances is convex but not
+/// strictly so, and two trees, for example, have every point on the geodesic
+/// between them as a median. Neither has a closed form. They are approximated
+/// by cyclic proximal point iterations (Bacák 2014), which walk from the
+/// current estimate along the geodesic to each tree in turn:
+///
+/// * the **mean**, minimizing the sum of squared distances, steps `1/(k+1)`
+///   of the way at the `k`-th step, which on a flat orthant is exactly the
+///   running average;
+/// * the **median**, minimizing the sum of distances, steps a distance of
+///   `λ/s` on pass `s`, where `λ` is the mean distance from the first tree to
+///   the sample.
+///
+/// Both are deterministic. Each step computes one geodesic.
+#[derive(Debug, Clone)]
+pub struct Frechet {
+    iterations: usize,
+}
+
+impl Default for Frechet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Frechet {
+    /// Defaults to 100 passes over the sample.
+    pub fn new() -> Self {
+        Frechet { iterations: 100 }
+    }
+
+    /// Sets the number of passes over the sample.
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Approximate Fréchet mean of `trees`.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `trees` is empty.
+    pub fn mean(&self, trees: &[BhvTree]) -> Result<BhvTree, TreeError> {
+        assert!(!trees.is_empty(), "a Fréchet mean needs at least one tree");
+        let mut estimate = trees[0].clone();
+        // The start counts as the first step, so whole passes end on a full
+        // cycle of the sample.
+        let steps = (self.iterations * trees.len()).saturating_sub(1);
+        let mut k = 1;
+        for tree in trees.iter().cycle().skip(1).take(steps) {
+            k += 1;
+            estimate = estimate.geodesic(tree)?.point_at(1.0 / k as f64);
+        }
+        Ok(estimate)
+    }
+
+    /// Approximate Fréchet median of `trees`.
+    ///
+    /// When the median is not unique, this approaches one of the medians, and
+    /// which one can depend on the order of `trees`.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `trees` is empty.
+    pub fn median(&self, trees: &[BhvTree]) -> Result<BhvTree, TreeError> {
+        assert!(
+            !trees.is_empty(),
+            "a Fréchet median needs at least one tree"
+        );
+        let mut estimate = trees[0].clone();
+        let scale = trees
+            .iter()
+            .map(|tree| estimate.distance(tree))
+            .sum::<Result<f64, _>>()?
+            / trees.len() as f64;
+        for pass in 1..=self.iterations {
+            let step = scale / pass as f64;
+            for tree in trees {
+                let path = estimate.geodesic(tree)?;
+                if path.length() > 0.0 {
+                    estimate = path.point_at(step / path.length());
+                }
+            }
+        }
+        Ok(estimate)
+    }
+}
+
+/// A trait describing geodesic distance in Billera-Holmes-Vogtmann tree space
+pub trait BhvDistance: RootedWeightedTree + RootedMetaTree
+where
+    <Self as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+{
+    /// Returns the tree as a point of BHV tree space.
+    ///
+    /// # Errors
+    ///
+    /// As for [`BhvTree::from_tree`].
+    fn to_bhv(&self) -> Result<BhvTree, TreeError> {
+        BhvTree::from_tree(self)
+    }
+
+    /// Returns the BHV geodesic distance between tree and self, pendant edges
+    /// included.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::MissingWeight`] if an edge of either tree has no weight;
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different leaf taxa.
+    fn bhv_dist(&self, tree: &Self) -> Result<f64, TreeError> {
+        self.to_bhv()?.distance(&tree.to_bhv()?)
+    }
+}
@@ -0,0 +1,700 @@
+//! Phylogenetic diversity of ecological communities.
+//!
+//! A community is a set of taxa (the leaves of a tree), optionally with
+//! abundances. The metrics follow the usual definitions from the community
+//! phylogenetics literature:
+//!
+//! * **Faith's PD** is the total branch length spanned by the community. With
+//!   [`RootRule::Include`](crate::tree::diversity::RootRule::Include) the span
+//!   runs up to the root of the tree, which is Faith's original definition and
+//!   what `picante::pd` reports. With
+//!   [`RootRule::Exclude`](crate::tree::diversity::RootRule::Exclude) only the
+//!   minimal subtree connecting the taxa is counted.
+//! * **MPD** is the mean patristic distance between pairs of taxa, and **MNTD**
+//!   the mean distance from each taxon to its nearest relative in the
+//!   community. With
+//!   [`Weighting::Abundance`](crate::tree::diversity::Weighting::Abundance),
+//!   pairs are weighted by the product of abundances and taxa by their
+//!   abundance.
+//! * **Phylogenetic endemism** (Rosauer et al. 2009) credits each branch in a
+//!   community's span with its length divided by the number of communities
+//!   the branch occurs in.
+//! * **Standardized effect sizes** compare any of the above with draws from a
+//!   [`NullModel`](crate::tree::diversity::NullModel). The permutations use a
+//!   seeded generator, so results are reproducible.
+//!
+//! Queries go through a
+//! [`DiversityIndex`](crate::tree::diversity::DiversityIndex), which is built
+//! once per tree and caches root distances, branch lengths and cluster sizes
+//! next to an [`LcaOracle`](crate::iter::lca::LcaOracle). A patristic distance
+//! is then `O(1)`, and Faith's PD of `k` taxa costs `O(k log k)`: visiting the
+//! taxa in euler-tour order walks every spanned branch exactly twice. Cluster
+//! sizes give the expected PD of a random community in closed form
+//! ([`DiversityIndex::expected_faith_pd`](crate::tree::diversity::DiversityIndex::expected_faith_pd)).
+
+use std::collections::HashMap;
+
+use crate::error::ComparativeError;
+
+#[cfg(feature = "simple_rooted_tree")]
+use {
+    crate::iter::lca::LcaOracle,
+    crate::models::gamma::ln_gamma,
+    crate::node::NodeID,
+    crate::prelude::*,
+    crate::tree::PhyloTree,
+    rand::{rngs::StdRng, seq::SliceRandom, SeedableRng},
+};
+
+/// Community phylogenetic diversity metrics.
+///
+/// Feature-free (like [`crate::tree::continuous::ContinuousTraits`]) so a
+/// caller bringing its own tree type can implement it without
+/// `simple_rooted_tree`. Each call on a [`PhyloTree`]
+/// builds a fresh [`DiversityIndex`]; build one directly to query many
+/// communities against the same tree.
+pub trait PhylogeneticDiversity {
+    /// Faith's phylogenetic diversity of `community`.
+    fn faith_pd(&self, community: &Community, rule: RootRule) -> Result<f64, ComparativeError>;
+
+    /// Mean pairwise distance between the taxa of `community`.
+    fn mpd(&self, community: &Community, weighting: Weighting) -> Result<f64, ComparativeError>;
+
+    /// Mean nearest-taxon distance within `community`.
+    fn mntd(&self, community: &Community, weighting: Weighting) -> Result<f64, ComparativeError>;
+
+    /// Phylogenetic endemism of each community, with branch ranges counted
+    /// over all of `communities`.
+    fn phylogenetic_endemism(
+        &self,
+        communities: &[Community],
+    ) -> Result<Vec<f64>, ComparativeError>;
+
+    /// Standardized effect size of `metric` for `community` against `runs`
+    /// draws from `null`.
+    fn effect_size(
+        &self,
+        community: &Community,
+        metric: Metric,
+        null: &NullModel,
+        runs: usize,
+        seed: u64,
+    ) -> Result<EffectSize, ComparativeError>;
+}
+
+/// A set of taxa with abundances.
+///
+/// Taxa with zero abundance are treated as absent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Community {
+    abundances: HashMap<String, f64>,
+}
+
+impl Community {
+    /// A presence-only community: every taxon has abundance 1.
+    pub fn from_taxa<I, S>(taxa: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Community {
+            abundances: taxa.into_iter().map(|t| (t.into(), 1.0)).collect(),
+        }
+    }
+
+    /// A community with the given abundance of each taxon.
+    pub fn from_abundances(abundances: HashMap<String, f64>) -> Self {
+        Community { abundances }
+    }
+
+    /// Abundance of each taxon, as given.
+    pub fn abundances(&self) -> &HashMap<String, f64> {
+        &self.abundances
+    }
+
+    /// Number of taxa with non-zero abundance.
+    pub fn richness(&self) -> usize {
+        self.abundances.values().filter(|&&a| a != 0.0).count()
+    }
+}
+
+/// Whether Faith's PD counts the path from the community up to the root.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RootRule {
+    /// Count every branch between the taxa and the root of the tree.
+    #[default]
+    Include,
+    /// Count only the minimal subtree connecting the taxa. A single taxon has
+    /// a PD of zero.
+    Exclude,
+}
+
+/// How MPD and MNTD weight taxa.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Weighting {
+    /// Every present taxon counts once.
+    #[default]
+    Presence,
+    /// Taxa count in proportion to their abundance.
+    Abundance,
+}
+
+/// A diversity metric, for [`PhylogeneticDiversity::effect_size`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Faith's PD.
+    FaithPd(RootRule),
+    /// Mean pairwise distance.
+    Mpd(Weighting),
+    /// Mean nearest-taxon distance.
+    Mntd(Weighting),
+}
+
+/// How null communities are drawn for standardized effect sizes.
+///
+/// Both models keep the community's richness and its abundances, and only
+/// change which taxa carry them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NullModel {
+    /// Shuffle taxon labels across all leaves of the tree.
+    TaxaLabels,
+    /// Draw taxa from this pool, such as the union of all sampled
+    /// communities.
+    SamplePool(Vec<String>),
+}
+
+/// A metric compared with its null distribution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectSize {
+    /// The metric on the observed community.
+    pub observed: f64,
+    /// Mean of the metric over the null communities.
+    pub null_mean: f64,
+    /// Standard deviation of the metric over the null communities.
+    pub null_sd: f64,
+    /// `(observed - null_mean) / null_sd`. Not finite when the null
+    /// distribution has no spread.
+    pub z: f64,
+    /// Lower-tail rank p-value: the share of null communities, counting the
+    /// observed one, with a value no larger than `observed`. Small values mean
+    /// the community is phylogenetically clustered.
+    pub p_value: f64,
+    /// Number of null communities drawn.
+    pub runs: usize,
+}
+
+/// Precomputed tree data for fast diversity queries over many communities.
+#[cfg(feature = "simple_rooted_tree")]
+pub struct DiversityIndex<'t> {
+    lca: LcaOracle<'t, PhyloTree>,
+    /// Distance from the root, indexed by node id.
+    depth: Vec<f64>,
+    /// Length of the branch above each node; zero for the root.
+    lengths: Vec<f64>,
+    parents: Vec<Option<NodeID>>,
+    /// Number of leaves below each node.
+    cluster_sizes: Vec<usize>,
+    /// Leaf ids in euler-tour order.
+    leaves: Vec<NodeID>,
+    taxa: HashMap<String, NodeID>,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+impl<'t> DiversityIndex<'t> {
+    /// Indexes `tree`. Fails with [`ComparativeError::MissingBranchLength`] if
+    /// a non-root branch has no length or a negative one.
+    pub fn new(tree: &'t PhyloTree) -> Result<Self, ComparativeError> {
+        let root = tree.get_root_id();
+        let n_slots = tree.get_node_ids().max().map_or(0, |id| id + 1);
+        let mut lengths = vec![0.0; n_slots];
+        let mut parents = vec![None; n_slots];
+        for node_id in tree.get_node_ids().filter(|&id| id != root) {
+            lengths[node_id] = tree
+                .get_edge_weight(0, node_id)
+                .map(f64::from)
+                .filter(|w| w.is_finite() && *w >= 0.0)
+                .ok_or(ComparativeError::MissingBranchLength(node_id))?;
+            parents[node_id] = tree.get_node_parent_id(node_id);
+        }
+
+        let postord: Vec<NodeID> = tree
+            .postord_ids(root)
+            .expect("invariant: the root id always names a node")
+            .collect();
+        let mut depth = vec![0.0; n_slots];
+        for &node in postord.iter().rev() {
+            if let Some(parent) = parents[node] {
+                depth[node] = depth[parent] + lengths[node];
+            }
+        }
+        let mut cluster_sizes = vec![0; n_slots];
+        for &node in &postord {
+            if tree.is_leaf(node) {
+                cluster_sizes[node] = 1;
+            }
+            if let Some(parent) = parents[node] {
+                cluster_sizes[parent] += cluster_sizes[node];
+            }
+        }
+
+        let lca = tree.lca();
+        let mut leaves: Vec<NodeID> = tree.get_leaf_ids().collect();
+        leaves.sort_by_key(|&leaf| lca.get_fa_index(leaf));
+        let taxa = leaves
+            .iter()
+            .filter_map(|&leaf| tree.get_node_taxa(leaf).map(|t| (t.clone(), leaf)))
+            .collect();
+        Ok(DiversityIndex {
+            lca,
+            depth,
+            lengths,
+            parents,
+            cluster_sizes,
+            leaves,
+            taxa,
+        })
+    }
+
+    /// Number of leaves of the tree.
+    pub fn num_taxa(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Patristic distance between two nodes.
+    fn distance(&self, a: NodeID, b: NodeID) -> f64 {
+        let ancestor = self.lca.get_lca_id(&[a, b]);
+        self.depth[a] + self.depth[b] - 2.0 * self.depth[ancestor]
+    }
+
+    /// The present taxa of `community` as leaf ids with their abundances, in
+    /// euler-tour order.
+    fn resolve(&self, community: &Community) -> Result<Vec<(NodeID, f64)>, ComparativeError> {
+        let mut tips = Vec::with_capacity(community.abundances.len());
+        for (taxon, &abundance) in &community.abundances {
+            if !abundance.is_finite() || abundance < 0.0 {
+                return Err(ComparativeError::InvalidParameter(format!(
+                    "abundance of {taxon:?} must be finite and non-negative"
+                )));
+            }
+            let leaf = *self
+                .taxa
+                .get(taxon)
+                .ok_or_else(|| ComparativeError::UnknownTaxon(taxon.clone()))?;
+            if abundance > 0.0 {
+                tips.push((leaf, abundance));
+            }
+        }
+        tips.sort_by_key(|&(leaf, _)| self.lca.get_fa_index(leaf));
+        Ok(tips)
+    }
+
+    /// Faith's PD of `community`.
+    pub fn faith_pd(&self, community: &Community, rule: RootRule) -> Result<f64, ComparativeError> {
+        Ok(self.pd_of(&self.resolve(community)?, rule))
+    }
+
+    /// Mean pairwise distance. Needs at least two present taxa.
+    pub fn mpd(
+        &self,
+        community: &Community,
+        weighting: Weighting,
+    ) -> Result<f64, ComparativeError> {
+        self.mpd_of(&self.resolve(community)?, weighting)
+    }
+
+    /// Mean nearest-taxon distance. Needs at least two present taxa.
+    pub fn mntd(
+        &self,
+        community: &Community,
+        weighting: Weighting,
+    ) -> Result<f64, ComparativeError> {
+        self.mntd_of(&self.resolve(community)?, weighting)
+    }
+
+    /// Expects `tips` in euler-tour order.
+    fn pd_of(&self, tips: &[(NodeID, f64)], rule: RootRule) -> f64 {
+        let (Some(&(first, _)), Some(&(last, _))) = (tips.first(), tips.last()) else {
+            return 0.0;
+        };
+        // A closed tour through the taxa in euler order covers each branch of
+        // their spanning subtree twice.
+        let tour: f64 = tips
+            .windows(2)
+            .map(|w| self.distance(w[0].0, w[1].0))
+            .sum::<f64>()
+            + self.distance(last, first);
+        let span = tour / 2.0;
+        match rule {
+            RootRule::Exclude => span,
+            RootRule::Include => span + self.depth[self.lca.get_lca_id(&[first, last])],
+        }
+    }
+
+    fn mpd_of(
+        &self,
+        tips: &[(NodeID, f64)],
+        weighting: Weighting,
+    ) -> Result<f64, ComparativeError> {
+        check_pairs(tips)?;
+        let (mut total, mut weights) = (0.0, 0.0);
+        for (i, &(a, wa)) in tips.iter().enumerate() {
+            for &(b, wb) in &tips[..i] {
+                let w = match weighting {
+                    Weighting::Presence => 1.0,
+                    Weighting::Abundance => wa * wb,
+                };
+                total += w * self.distance(a, b);
+                weights += w;
+            }
+        }
+        Ok(total / weights)
+    }
+
+    fn mntd_of(
+        &self,
+        tips: &[(NodeID, f64)],
+        weighting: Weighting,
+    ) -> Result<f64, ComparativeError> {
+        check_pairs(tips)?;
+        let (mut total, mut weights) = (0.0, 0.0);
+        for (i, &(a, wa)) in tips.iter().enumerate() {
+            let nearest = tips
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, &(b, _))| self.distance(a, b))
+                .fold(f64::INFINITY, f64::min);
+            let w = match weighting {
+                Weighting::Presence => 1.0,
+                Weighting::Abundance => wa,
+            };
+            total += w * nearest;
+            weights += w;
+        }
+        Ok(total / weights)
+    }
+
+    fn metric_of(&self, tips: &[(NodeID, f64)], metric: Metric) -> Result<f64, ComparativeError> {
+        match metric {
+            Metric::FaithPd(rule) => Ok(self.pd_of(tips, rule)),
+            Metric::Mpd(weighting) => self.mpd_of(tips, weighting),
+            Metric::Mntd(weighting) => self.mntd_of(tips, weighting),
+        }
+    }
+
+    /// Phylogenetic endemism of each community. A branch's range is the
+    /// number of communities whose root-inclusive span contains it.
+    pub fn phylogenetic_endemism(
+        &self,
+        communities: &[Community],
+    ) -> Result<Vec<f64>, ComparativeError> {
+        let mut range = vec![0usize; self.lengths.len()];
+        let mut stamp = vec![usize::MAX; self.lengths.len()];
+        let mut spans = Vec::with_capacity(communities.len());
+        for (c, community) in communities.iter().enumerate() {
+            let mut span = Vec::new();
+            for (leaf, _) in self.resolve(community)? {
+                // Climb until reaching a branch this community already owns.
+                let mut node = Some(leaf);
+                while let Some(n) = node.filter(|&n| stamp[n] != c) {
+                    stamp[n] = c;
+                    range[n] += 1;
+                    span.push(n);
+                    node = self.parents[n];
+                }
+            }
+            spans.push(span);
+        }
+        Ok(spans
+            .iter()
+            .map(|span| {
+                span.iter()
+                    .map(|&n| self.lengths[n] / range[n] as f64)
+                    .sum()
+            })
+            .collect())
+    }
+
+    /// Expected Faith's PD of `richness` taxa drawn uniformly at random from
+    /// the leaves, in closed form.
+    ///
+    /// A branch above a cluster of `s` of the `n` leaves is missed by every
+    /// taxon with probability `C(n - s, k) / C(n, k)`; with
+    /// [`RootRule::Exclude`] it is also left out when every taxon falls inside
+    /// the cluster.
+    pub fn expected_faith_pd(
+        &self,
+        richness: usize,
+        rule: RootRule,
+    ) -> Result<f64, ComparativeError> {
+        let n = self.num_taxa();
+        if richness > n {
+            return Err(ComparativeError::InvalidParameter(format!(
+                "richness {richness} exceeds the {n} taxa of the tree"
+            )));
+        }
+        let all = ln_choose(n, richness);
+        let prob = |m: usize| {
+            if m < richness {
+                0.0
+            } else {
+                (ln_choose(m, richness) - all).exp()
+            }
+        };
+        Ok(self
+            .cluster_sizes
+            .iter()
+            .zip(&self.lengths)
+            .filter(|&(_, &length)| length > 0.0)
+            .map(|(&s, &length)| {
+                let inside = match rule {
+                    RootRule::Include => 0.0,
+                    RootRule::Exclude => prob(s),
+                };
+                length * (1.0 - prob(n - s) - inside).max(0.0)
+            })
+            .sum())
+    }
+
+    /// Standardized effect size of `metric` for `community` against `runs`
+    /// null communities drawn from `null`.
+    pub fn effect_size(
+        &self,
+        community: &Community,
+        metric: Metric,
+        null: &NullModel,
+        runs: usize,
+        seed: u64,
+    ) -> Result<EffectSize, ComparativeError> {
+        if runs == 0 {
+            return Err(ComparativeError::InvalidParameter(
+                "at least one null community is needed".to_string(),
+            ));
+        }
+        let tips = self.resolve(community)?;
+        let observed = self.metric_of(&tips, metric)?;
+
+        let pool: Vec<NodeID> = match null {
+            NullModel::TaxaLabels => self.leaves.clone(),
+            NullModel::SamplePool(taxa) => {
+                let mut pool = taxa
+                    .iter()
+                    .map(|taxon| {
+                        self.taxa
+                            .get(taxon)
+                            .copied()
+                            .ok_or_else(|| ComparativeError::UnknownTaxon(taxon.clone()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                pool.sort_unstable();
+                pool.dedup();
+                pool
+            }
+        };
+        if pool.len() < tips.len() {
+            return Err(ComparativeError::InvalidParameter(format!(
+                "null pool has {} taxa, community has {}",
+                pool.len(),
+                tips.len()
+            )));
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut values = Vec::with_capacity(runs);
+        for _ in 0..runs {
+            let mut draw: Vec<(NodeID, f64)> = pool
+                .choose_multiple(&mut rng, tips.len())
+                .zip(&tips)
+                .map(|(&leaf, &(_, abundance))| (leaf, abundance))
+                .collect();
+            draw.sort_by_key(|&(leaf, _)| self.lca.get_fa_index(leaf));
+            values.push(self.metric_of(&draw, metric)?);
+        }
+
+        let null_mean = values.iter().sum::<f64>() / runs as f64;
+        let null_sd = if runs > 1 {
+            (values.iter().map(|v| (v - null_mean).powi(2)).sum::<f64>() / (runs - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let at_most = values.iter().filter(|&&v| v <= observed).count();
+        Ok(EffectSize {
+            observed,
+            null_mean,
+            null_sd,
+            z: (observed - null_mean) / null_sd,
+            p_value: (at_most + 1) as f64 / (runs + 1) as f64,
+            runs,
+        })
+    }
+}
+
+/// MPD and MNTD are undefined for fewer than two taxa.
+#[cfg(feature = "simple_rooted_tree")]
+fn check_pairs(tips: &[(NodeID, f64)]) -> Result<(), ComparativeError> {
+    if tips.len() < 2 {
+        return Err(ComparativeError::TooFewTaxa {
+            expected: 2,
+            actual: tips.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Natural log of the binomial coefficient `C(n, k)`, for `k <= n`.
+#[cfg(feature = "simple_rooted_tree")]
+fn ln_choose(n: usize, k: usize) -> f64 {
+    ln_gamma(n as f64 + 1.0) - ln_gamma(k as f64 + 1.0) - ln_gamma((n - k) as f64 + 1.0)
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+
+    const TREE: &str = "(((A:1,B:2):1,C:3):1,(D:2,E:2):3);";
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick(TREE.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_faith_pd_root_rules() {
+        let tree = tree();
+        let index = DiversityIndex::new(&tree).unwrap();
+        let ab = Community::from_taxa(["A", "B"]);
+        assert!((index.faith_pd(&ab, RootRule::Include).unwrap() - 5.0).abs() < 1e-9);
+        assert!((index.faith_pd(&ab, RootRule::Exclude).unwrap() - 3.0).abs() < 1e-9);
+
+        let ad = Community::from_taxa(["A", "D"]);
+        assert!((index.faith_pd(&ad, RootRule::Include).unwrap() - 8.0).abs() < 1e-9);
+        assert!((index.faith_pd(&ad, RootRule::Exclude).unwrap() - 8.0).abs() < 1e-9);
+
+        let single = Community::from_taxa(["C"]);
+        assert!((index.faith_pd(&single, RootRule::Include).unwrap() - 4.0).abs() < 1e-9);
+        assert_eq!(index.faith_pd(&single, RootRule::Exclude).unwrap(), 0.0);
+
+        let all = Community::from_taxa(["A", "B", "C", "D", "E"]);
+        assert!((tree.faith_pd(&all, RootRule::Include).unwrap() - 15.0).abs() < 1e-9);
+        assert_eq!(
+            index
+                .faith_pd(&Community::default(), RootRule::Include)
+                .unwrap(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_mpd_and_mntd() {
+        let tree = tree();
+        let index = DiversityIndex::new(&tree).unwrap();
+        let community = Community::from_taxa(["A", "B", "D"]);
+        // d(A,B) = 3, d(A,D) = 8, d(B,D) = 9
+        let mpd = index.mpd(&community, Weighting::Presence).unwrap();
+        assert!((mpd - 20.0 / 3.0).abs() < 1e-9);
+        let mntd = index.mntd(&community, Weighting::Presence).unwrap();
+        assert!((mntd - (3.0 + 3.0 + 8.0) / 3.0).abs() < 1e-9);
+
+        let weighted = Community::from_abundances(HashMap::from([
+            ("A".to_string(), 2.0),
+            ("B".to_string(), 1.0),
+            ("D".to_string(), 1.0),
+            ("E".to_string(), 0.0),
+        ]));
+        let mpd = index.mpd(&weighted, Weighting::Abundance).unwrap();
+        assert!((mpd - (2.0 * 3.0 + 2.0 * 8.0 + 9.0) / 5.0).abs() < 1e-9);
+        let mntd = index.mntd(&weighted, Weighting::Abundance).unwrap();
+        assert!((mntd - (2.0 * 3.0 + 3.0 + 8.0) / 4.0).abs() < 1e-9);
+
+        assert_eq!(
+            index.mpd(&Community::from_taxa(["A"]), Weighting::Presence),
+            Err(ComparativeError::TooFewTaxa {
+                expected: 2,
+                actual: 1
+            })
+        );
+        assert_eq!(
+            index.mpd(&Community::from_taxa(["A", "Z"]), Weighting::Presence),
+            Err(ComparativeError::UnknownTaxon("Z".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_phylogenetic_endemism() {
+        let tree = tree();
+        let communities = [
+            Community::from_taxa(["A", "B"]),
+            Community::from_taxa(["A", "D"]),
+        ];
+        let pe = tree.phylogenetic_endemism(&communities).unwrap();
+        // A's branch and the two above it are shared; B, D and D's parent
+        // branch are endemic.
+        assert!((pe[0] - (0.5 + 0.5 + 0.5 + 2.0)).abs() < 1e-9);
+        assert!((pe[1] - (0.5 + 0.5 + 0.5 + 2.0 + 3.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_expected_pd_matches_enumeration() {
+        let tree = tree();
+        let index = DiversityIndex::new(&tree).unwrap();
+        let names = ["A", "B", "C", "D", "E"];
+        for rule in [RootRule::Include, RootRule::Exclude] {
+            for k in 0..=5 {
+                let mut total = 0.0;
+                let mut count = 0;
+                for mask in 0u32..32 {
+                    if mask.count_ones() as usize != k {
+                        continue;
+                    }
+                    let taxa = (0..5).filter(|i| mask & (1 << i) != 0).map(|i| names[i]);
+                    total += index.faith_pd(&Community::from_taxa(taxa), rule).unwrap();
+                    count += 1;
+                }
+                let expected = index.expected_faith_pd(k, rule).unwrap();
+                assert!(
+                    (expected - total / count as f64).abs() < 1e-9,
+                    "k={k}, {rule:?}"
+                );
+            }
+        }
+        assert!(index.expected_faith_pd(6, RootRule::Include).is_err());
+    }
+
+    #[test]
+    fn test_effect_size() {
+        let tree = tree();
+        let index = DiversityIndex::new(&tree).unwrap();
+        let clustered = Community::from_taxa(["A", "B"]);
+        let ses = index
+            .effect_size(
+                &clustered,
+                Metric::FaithPd(RootRule::Include),
+                &NullModel::TaxaLabels,
+                999,
+                7,
+            )
+            .unwrap();
+        assert!((ses.observed - 5.0).abs() < 1e-9);
+        assert!(ses.z < 0.0);
+        assert!(
+            (ses.null_mean - index.expected_faith_pd(2, RootRule::Include).unwrap()).abs() < 0.2
+        );
+        let again = index
+            .effect_size(
+                &clustered,
+                Metric::FaithPd(RootRule::Include),
+                &NullModel::TaxaLabels,
+                999,
+                7,
+            )
+            .unwrap();
+        assert_eq!(ses, again);
+
+        // A pool of exactly the community's taxa reproduces it every time.
+        let pool = NullModel::SamplePool(vec!["A".to_string(), "B".to_string()]);
+        let ses = index
+            .effect_size(&clustered, Metric::Mpd(Weighting::Presence), &pool, 10, 1)
+            .unwrap();
+        assert_eq!(ses.null_sd, 0.0);
+        assert_eq!(ses.p_value, 1.0);
+    }
+}
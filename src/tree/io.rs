@@ -97,6 +97,94 @@ where
     }
 }
 
+/// How the Newick parser reads the label of an internal node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InternalLabels {
+    /// As a taxon, like a leaf label. This is the default used by
+    /// [`Newick::from_newick`].
+    #[default]
+    Taxa,
+    /// As one or more `/`-separated support values, such as RAxML's `95` or
+    /// IQ-TREE's `95.3/98`, stored on the node rather than as a taxon. Quoted
+    /// labels, and labels that are not all numbers, stay taxa.
+    Support,
+}
+
+/// When the Newick writer quotes a label.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quoting {
+    /// Never; labels are written verbatim.
+    #[default]
+    Never,
+    /// When the label holds whitespace or one of `( ) [ ] , : ; '`, so that
+    /// it reads back the same.
+    AsNeeded,
+    /// Always.
+    Always,
+}
+
+/// Formatting options for the Newick writer.
+///
+/// The default writes support values where a node has no taxon, branch
+/// lengths at full precision, and labels verbatim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NewickFormat {
+    supports: bool,
+    precision: Option<usize>,
+    quoting: Quoting,
+}
+
+impl Default for NewickFormat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NewickFormat {
+    /// Creates the default format.
+    pub fn new() -> Self {
+        NewickFormat {
+            supports: true,
+            precision: None,
+            quoting: Quoting::Never,
+        }
+    }
+
+    /// Whether to write the support values of internal nodes without a
+    /// taxon as their label, joined by `/`.
+    pub fn with_supports(mut self, supports: bool) -> Self {
+        self.supports = supports;
+        self
+    }
+
+    /// Writes branch lengths with `digits` digits after the decimal point.
+    pub fn with_precision(mut self, digits: usize) -> Self {
+        self.precision = Some(digits);
+        self
+    }
+
+    /// Sets when labels are quoted.
+    pub fn with_quoting(mut self, quoting: Quoting) -> Self {
+        self.quoting = quoting;
+        self
+    }
+
+    /// Whether support values are written.
+    pub fn supports(&self) -> bool {
+        self.supports
+    }
+
+    /// Digits after the decimal point of branch lengths, if fixed.
+    pub fn precision(&self) -> Option<usize> {
+        self.precision
+    }
+
+    /// When labels are quoted.
+    pub fn quoting(&self) -> Quoting {
+        self.quoting
+    }
+}
+
 /// A trait descibing Newick encoding of a tree.
 pub trait Newick: RootedTree {
     /// Creates a new tree from a Newick string, retaining node `[...]`
@@ -110,6 +198,22 @@ pub trait Newick: RootedTree {
         annotations: H,
     ) -> std::io::Result<Self>;
 
+    /// Creates a new tree from a Newick string, reading internal node labels
+    /// as `labels` says and retaining node `[...]` annotations according to
+    /// `annotations`.
+    ///
+    /// The default reads internal labels as taxa whatever `labels` says, as
+    /// [`Newick::from_newick_with`] does; trees that store support values
+    /// override it.
+    fn from_newick_with_labels<H: AnnotationHandler>(
+        newick_str: &[u8],
+        annotations: H,
+        labels: InternalLabels,
+    ) -> std::io::Result<Self> {
+        let _ = labels;
+        Self::from_newick_with(newick_str, annotations)
+    }
+
     /// Creates a new tree using a Newick string, keeping node annotations
     /// verbatim. Equivalent to [`Newick::from_newick_with`] with
     /// [`KeepRawAnnotations`].
@@ -129,6 +233,22 @@ pub trait Newick: RootedTree {
         annotations: H,
     ) -> impl Display;
 
+    /// Encodes a subtree starting from a node as a Newick string, emitting node
+    /// annotations as decided by `annotations` and laid out as `format` says.
+    ///
+    /// The default ignores `format` and writes what
+    /// [`Newick::subtree_to_newick_with`] writes; trees that support the
+    /// formatting options override it.
+    fn subtree_to_newick_formatted<H: AnnotationWriter>(
+        &self,
+        node_id: TreeNodeID<Self>,
+        annotations: H,
+        format: NewickFormat,
+    ) -> impl Display {
+        let _ = format;
+        self.subtree_to_newick_with(node_id, annotations)
+    }
+
     /// Encodes a subtree starting from a node as a Newick string, writing node
     /// annotations verbatim.
     fn subtree_to_newick(&self, node_id: TreeNodeID<Self>) -> impl Display {
@@ -144,6 +264,19 @@ pub trait Newick: RootedTree {
         )
     }
 
+    /// Encodes a tree as a Newick string, emitting node annotations as decided
+    /// by `annotations` and laid out as `format` says.
+    fn to_newick_formatted<H: AnnotationWriter>(
+        &self,
+        annotations: H,
+        format: NewickFormat,
+    ) -> impl Display {
+        format!(
+            "{};",
+            self.subtree_to_newick_formatted(self.get_root_id(), annotations, format)
+        )
+    }
+
     /// Encodes a tree as a Newick string, writing node annotations verbatim.
     fn to_newick(&self) -> impl Display {
         format!("{};", self.subtree_to_newick(self.get_root_id()))
@@ -247,3 +380,51 @@ pub trait Nexus: Newick {
         fs::write(p, self.to_nexus()?.as_bytes())
     }
 }
+
+/// A trait for reading and writing PhyloXML documents.
+///
+/// Clade names, branch lengths and confidences map to node taxa, edge weights
+/// and support values; other clade elements, such as taxonomy, colors and
+/// events, are kept verbatim as node annotations and written back unchanged.
+pub trait PhyloXml: RootedTree + Sized {
+    /// Reads every phylogeny of a PhyloXML document, which is UTF-8 unless
+    /// its XML declaration names ISO-8859-1.
+    fn from_phyloxml(xml: &[u8]) -> io::Result<Vec<Self>>;
+
+    /// Reads every phylogeny of a PhyloXML file.
+    fn from_phyloxml_file(p: &Path) -> io::Result<Vec<Self>> {
+        Self::from_phyloxml(&fs::read(p)?)
+    }
+
+    /// Writes `trees` as one PhyloXML document.
+    fn to_phyloxml(trees: &[Self]) -> String;
+
+    /// Writes `trees` to a PhyloXML file.
+    fn to_phyloxml_file(trees: &[Self], p: &Path) -> io::Result<()> {
+        fs::write(p, Self::to_phyloxml(trees))
+    }
+}
+
+/// A trait for reading and writing NeXML documents.
+///
+/// OTU labels, edge lengths and support metas map to node taxa, edge weights
+/// and support values; other metas of a node or of the edge above it are kept
+/// verbatim as node annotations and written back unchanged.
+pub trait NeXml: RootedTree + Sized {
+    /// Reads every tree of a NeXML document, which is UTF-8 unless its XML
+    /// declaration names ISO-8859-1.
+    fn from_nexml(xml: &[u8]) -> io::Result<Vec<Self>>;
+
+    /// Reads every tree of a NeXML file.
+    fn from_nexml_file(p: &Path) -> io::Result<Vec<Self>> {
+        Self::from_nexml(&fs::read(p)?)
+    }
+
+    /// Writes `trees` as one NeXML document.
+    fn to_nexml(trees: &[Self]) -> String;
+
+    /// Writes `trees` to a NeXML file.
+    fn to_nexml_file(trees: &[Self], p: &Path) -> io::Result<()> {
+        fs::write(p, Self::to_nexml(trees))
+    }
+}
@@ -6,11 +6,16 @@
 //!
 //! [`compute_log_likelihood`](crate::tree::likelihood::compute_log_likelihood)
 //! returns just the tree's log-likelihood — the Felsenstein up pass, no ancestral
-//! states. [`compute_marginal_asr`](crate::tree::likelihood::compute_marginal_asr)
-//! reuses the same per-pattern pruning core (`prune_pattern_category`) and adds a
-//! pre-order down pass to reconstruct states and posteriors, so the two never
-//! drift. The [`TreeLikelihood`](crate::tree::likelihood::TreeLikelihood) trait
-//! exposes the likelihood-only path.
+//! states — from a kernel that prunes blocks of patterns through contiguous
+//! buffers, on several threads with `compute_log_likelihood_par` under the
+//! `parallel` feature.
+//! [`compute_marginal_asr`](crate::tree::likelihood::compute_marginal_asr)
+//! prunes one pattern at a time (`prune_pattern_category`), keeping every node's
+//! profile to seed a pre-order down pass that reconstructs states and posteriors.
+//! The kernel repeats that core's arithmetic in the same order, so the two give
+//! the same log-likelihood to the bit. The
+//! [`TreeLikelihood`](crate::tree::likelihood::TreeLikelihood) trait exposes the
+//! likelihood-only path.
 //!
 //! The joint (Viterbi) engine keeps its own recursion: it maximizes rather than
 //! sums over states (a different semiring), so it cannot share the marginal core.
@@ -18,16 +23,36 @@
 //! Making pruning generic over `RootedTree` (rather than concrete in `PhyloTree`)
 //! is a deliberate non-goal here.
 
+/// Cached partial likelihoods for repeated evaluation of one tree.
+#[cfg(feature = "simple_rooted_tree")]
+pub mod engine;
+
+/// Blocked Felsenstein pruning over whole alignments.
+#[cfg(feature = "simple_rooted_tree")]
+mod pruning;
+
 /// Likelihood profiles and scaling for numerical stability.
 pub mod profile;
 
 /// Result type carrying reconstructed states and the tree log-likelihood.
 pub mod reconstruction;
 
+/// Stochastic character mapping: sampled substitution histories along branches.
+pub mod simmap;
+
+/// SH-like aLRT and aBayes branch support from nearest-neighbour interchanges.
+#[cfg(feature = "simple_rooted_tree")]
+pub mod support;
+
 #[cfg(test)]
 mod integration_test;
 
+#[cfg(feature = "simple_rooted_tree")]
+pub use self::engine::LikelihoodEngine;
 pub use self::reconstruction::Reconstruction;
+pub use self::simmap::{Segment, SimmapSummary, StochasticMap, StochasticMapping};
+#[cfg(feature = "simple_rooted_tree")]
+pub use self::support::{AlrtSupport, BranchTest};
 
 /// Log-likelihood of an alignment given a tree and a substitution model.
 ///
@@ -48,9 +73,18 @@ pub trait TreeLikelihood {
 // is trait-level and depends on none of this.
 #[cfg(feature = "simple_rooted_tree")]
 use {
-    self::profile::Profile, crate::alignment::Alignment, crate::alphabet::Alphabet,
-    crate::error::AsrError, crate::models::GtrModel, crate::node::NodeID, crate::prelude::*,
-    crate::tree::PhyloTree, nalgebra::DVector, num_traits::NumCast, std::collections::HashMap,
+    self::profile::Profile,
+    self::pruning::Pruning,
+    crate::alignment::{Alignment, CompressedColumns},
+    crate::alphabet::Alphabet,
+    crate::error::AsrError,
+    crate::models::GtrModel,
+    crate::node::NodeID,
+    crate::prelude::*,
+    crate::tree::PhyloTree,
+    nalgebra::DVector,
+    num_traits::NumCast,
+    std::collections::HashMap,
 };
 
 /// Numerically stable log-sum-exp: `ln(sum_i exp(xs[i]))`.
@@ -75,9 +109,9 @@ fn log_sum_exp(xs: &[f64]) -> f64 {
 /// the profile map together with this category's log-likelihood contribution
 /// `ln(weight) + ln(root_mass) + root_log_scale`.
 ///
-/// This is the shared core: [`compute_log_likelihood`] keeps only the returned
-/// `cat_ll` (dropping the profiles), while [`compute_marginal_asr`] retains the
-/// profiles to seed its down pass. There is exactly one pruning recursion.
+/// [`compute_marginal_asr`] retains the profiles to seed its down pass.
+/// [`compute_log_likelihood`] prunes through the blocked kernel instead, which
+/// repeats this arithmetic in the same order and is tested against it.
 #[cfg(feature = "simple_rooted_tree")]
 #[allow(clippy::too_many_arguments)]
 fn prune_pattern_category<A: Alphabet>(
@@ -144,11 +178,11 @@ fn prune_pattern_category<A: Alphabet>(
 
 /// Log-likelihood of an alignment given a tree and a substitution model.
 ///
-/// Felsenstein's pruning up pass only: for each compressed alignment pattern the
-/// per-category log-likelihoods (`prune_pattern_category`) are mixed with
-/// `log_sum_exp` and accumulated by pattern multiplicity. No down pass, and no
-/// per-node sequence/posterior allocation — unlike [`compute_marginal_asr`], whose
-/// `log_likelihood` field this reproduces exactly.
+/// Felsenstein's pruning up pass only: the per-category log-likelihoods of each
+/// compressed alignment pattern are mixed with `log_sum_exp` and accumulated by
+/// pattern multiplicity. No down pass, and no per-node sequence/posterior
+/// allocation — unlike [`compute_marginal_asr`], whose `log_likelihood` field this
+/// reproduces exactly.
 ///
 /// Concrete in `PhyloTree`, so it is gated on the feature that defines it. The
 /// [`TreeLikelihood`] trait itself stays available without that feature.
@@ -162,50 +196,49 @@ where
     A: Alphabet,
 {
     let comp = aln.compress_columns();
-    let root = tree.get_root_id();
-    let n_states = A::N_STATES;
-    let pi = model.equilibrium();
-    let categories = model.categories();
-    let n_categories = categories.len();
+    let pattern_lls = pattern_log_likelihoods(tree, model, &comp)?;
+    Ok(weighted_total(&pattern_lls, &comp.multiplicity))
+}
 
-    // Map alignment leaf order to NodeIDs.
-    let mut leaf_id_map = Vec::with_capacity(comp.leaf_order.len());
-    for name in &comp.leaf_order {
-        let node_id = tree.get_taxa_node_id(name).ok_or_else(|| {
-            AsrError::AlphabetMismatch(format!("Taxon {} in alignment not found in tree", name))
-        })?;
-        leaf_id_map.push(node_id);
-    }
+/// [`compute_log_likelihood`], with blocks of patterns pruned in parallel. The
+/// result is the same to the bit.
+#[cfg(all(feature = "simple_rooted_tree", feature = "parallel"))]
+pub fn compute_log_likelihood_par<A>(
+    tree: &PhyloTree,
+    model: &GtrModel<A>,
+    aln: &Alignment,
+) -> Result<f64, AsrError>
+where
+    A: Alphabet,
+{
+    let comp = aln.compress_columns();
+    let pattern_lls = Pruning::new(tree, model, &comp)?.pattern_log_likelihoods_par();
+    Ok(weighted_total(&pattern_lls, &comp.multiplicity))
+}
 
-    let postord = tree
-        .postord_ids(root)
-        .expect("invariant: the root id always names a node")
-        .collect::<Vec<_>>();
+/// Log-likelihood of each pattern of `comp`, its categories mixed with
+/// `log_sum_exp`, in pattern order. [`compute_log_likelihood`] weights these by
+/// multiplicity; resampling tests such as RELL weight them by resampled ones.
+#[cfg(feature = "simple_rooted_tree")]
+pub(crate) fn pattern_log_likelihoods<A>(
+    tree: &PhyloTree,
+    model: &GtrModel<A>,
+    comp: &CompressedColumns,
+) -> Result<Vec<f64>, AsrError>
+where
+    A: Alphabet,
+{
+    Ok(Pruning::new(tree, model, comp)?.pattern_log_likelihoods())
+}
 
+/// Pattern log-likelihoods summed by multiplicity, in pattern order.
+#[cfg(feature = "simple_rooted_tree")]
+fn weighted_total(pattern_lls: &[f64], multiplicity: &[usize]) -> f64 {
     let mut total_log_likelihood = 0.0;
-    for (p_idx, pattern) in comp.patterns.iter().enumerate() {
-        let multiplicity = comp.multiplicity[p_idx] as f64;
-
-        let mut cat_log_likelihoods = Vec::with_capacity(n_categories);
-        for (cat_idx, category) in categories.iter().enumerate() {
-            let (_profiles, cat_ll) = prune_pattern_category(
-                tree,
-                model,
-                cat_idx,
-                category.weight,
-                pattern,
-                &leaf_id_map,
-                &postord,
-                pi,
-                n_states,
-            )?;
-            cat_log_likelihoods.push(cat_ll);
-        }
-
-        total_log_likelihood += multiplicity * log_sum_exp(&cat_log_likelihoods);
+    for (ll, &multiplicity) in pattern_lls.iter().zip(multiplicity) {
+        total_log_likelihood += multiplicity as f64 * ll;
     }
-
-    Ok(total_log_likelihood)
+    total_log_likelihood
 }
 
 /// Internal implementation of Marginal ASR logic.
@@ -251,7 +284,7 @@ where
     let mut final_posteriors = if want_posteriors {
         let mut map = HashMap::new();
         for node_id in tree.get_node_ids() {
-            map.insert(node_id, vec![vec![0.0; n_states]; aln.width]);
+            map.insert(node_id, vec![vec![0.0; n_states]; aln.width()]);
         }
         Some(map)
     } else {
@@ -260,7 +293,7 @@ where
 
     // Initialize sequences
     for node_id in tree.get_node_ids() {
-        final_sequences.insert(node_id, vec![0; aln.width]);
+        final_sequences.insert(node_id, vec![0; aln.width()]);
     }
 
     let postord = tree
@@ -388,7 +421,7 @@ where
                 .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
                 .unwrap()
                 .0;
-            for site in 0..aln.width {
+            for site in 0..aln.width() {
                 if comp.site_to_pattern[site] == p_idx {
                     final_sequences.get_mut(&v).unwrap()[site] = best_state;
                     if let Some(ref mut map) = final_posteriors {
@@ -443,7 +476,7 @@ where
     let mut total_log_likelihood = 0.0;
     let mut final_sequences = HashMap::new();
     for node_id in tree.get_node_ids() {
-        final_sequences.insert(node_id, vec![0; aln.width]);
+        final_sequences.insert(node_id, vec![0; aln.width()]);
     }
 
     let postord = tree
@@ -543,7 +576,7 @@ where
         total_log_likelihood += multiplicity * best_overall_ll;
 
         for (v, s_v) in best_overall_states {
-            for site in 0..aln.width {
+            for site in 0..aln.width() {
                 if comp.site_to_pattern[site] == p_idx {
                     final_sequences.get_mut(&v).unwrap()[site] = s_v;
                 }
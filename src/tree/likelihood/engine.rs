@@ -0,0 +1,707 @@
+//! Cached conditional likelihoods for evaluating one tree many times.
+//!
+//! [`compute_log_likelihood`](crate::tree::likelihood::compute_log_likelihood)
+//! compresses the alignment and prunes the whole tree on every call. A
+//! [`LikelihoodEngine`](crate::tree::likelihood::engine::LikelihoodEngine)
+//! owns the tree, the compressed patterns and the row of each leaf, and keeps
+//! for every node its scaled conditional likelihoods (the *partials*) per
+//! rate category and pattern, and for every edge its transition matrices per
+//! category. Changing a branch length, or moving a subtree by NNI or SPR
+//! through the engine, marks only the nodes between the change and the root
+//! stale, and the next evaluation recomputes just those.
+//!
+//! Besides the partials below each node, the engine keeps *upper* partials:
+//! the likelihood of everything outside a node's subtree, as seen from the
+//! top of the edge above it, with the equilibrium frequencies folded in. Each
+//! is the tree's likelihood rerooted on that edge, so the log-likelihood as a
+//! function of one branch length costs one pass over the patterns whatever
+//! the size of the tree, which is what branch-length optimization needs.
+//! Upper partials are rebuilt, all together, the first time they are needed
+//! after a change.
+//!
+//! Scaling follows [`Profile::scale`](crate::tree::likelihood::profile::Profile::scale),
+//! so results match `compute_log_likelihood` to rounding.
+
+use std::collections::HashMap;
+
+use nalgebra::DMatrix;
+use num_traits::NumCast;
+
+use super::log_sum_exp;
+use crate::alignment::{Alignment, CompressedColumns};
+use crate::alphabet::Alphabet;
+use crate::error::{AsrError, TreeError};
+use crate::models::GtrModel;
+use crate::node::NodeID;
+use crate::prelude::*;
+use crate::tree::PhyloTree;
+
+/// Scaled conditional likelihoods of one node, category-major, then pattern,
+/// then state, with one log scale per category and pattern.
+#[derive(Debug, Clone, Default)]
+struct Partials {
+    values: Vec<f64>,
+    log_scales: Vec<f64>,
+}
+
+/// Row-major transition matrices of one edge, one per category, and the
+/// length they were computed for.
+#[derive(Debug, Clone)]
+struct Transitions {
+    length: f64,
+    values: Vec<f64>,
+}
+
+/// A tree, an alignment and a model, with the conditional likelihoods of the
+/// tree's nodes cached between evaluations.
+pub struct LikelihoodEngine<'a, A: Alphabet> {
+    model: &'a GtrModel<A>,
+    tree: PhyloTree,
+    columns: CompressedColumns,
+    /// Alignment row of each leaf, by node id.
+    rows: HashMap<NodeID, usize>,
+    /// Partials below each node, by node id.
+    lower: Vec<Partials>,
+    /// Whether `lower` is current, by node id. A stale node's ancestors are
+    /// stale too.
+    fresh: Vec<bool>,
+    /// Partials outside each node's subtree, at the top of its edge.
+    upper: Vec<Partials>,
+    upper_fresh: bool,
+    /// Transition matrices of the edge above each node, by node id.
+    transitions: Vec<Transitions>,
+}
+
+impl<'a, A: Alphabet> LikelihoodEngine<'a, A> {
+    /// An engine for `tree` and `aln` under `model`. Every partial is
+    /// computed on the first evaluation.
+    ///
+    /// # Errors
+    ///
+    /// As for [`from_columns`](Self::from_columns).
+    pub fn new(tree: PhyloTree, model: &'a GtrModel<A>, aln: &Alignment) -> Result<Self, AsrError> {
+        Self::from_columns(tree, model, aln.compress_columns())
+    }
+
+    /// An engine for `tree` and the compressed patterns of an alignment.
+    ///
+    /// # Errors
+    ///
+    /// [`AsrError::AlphabetMismatch`] if a taxon of the alignment is not in
+    /// the tree or a residue is not in `A`, and [`AsrError::InvalidAlignment`]
+    /// if a leaf of the tree has no row.
+    pub fn from_columns(
+        tree: PhyloTree,
+        model: &'a GtrModel<A>,
+        columns: CompressedColumns,
+    ) -> Result<Self, AsrError> {
+        let mut rows = HashMap::new();
+        for (row, name) in columns.leaf_order.iter().enumerate() {
+            let id = tree.get_taxa_node_id(name).ok_or_else(|| {
+                AsrError::AlphabetMismatch(format!("Taxon {} in alignment not found in tree", name))
+            })?;
+            rows.insert(id, row);
+        }
+
+        let slots = tree.get_node_ids().max().map_or(0, |id| id + 1);
+        let mut engine = LikelihoodEngine {
+            model,
+            tree,
+            columns,
+            rows,
+            lower: vec![Partials::default(); slots],
+            fresh: vec![false; slots],
+            upper: vec![Partials::default(); slots],
+            upper_fresh: false,
+            transitions: vec![
+                Transitions {
+                    length: f64::NAN,
+                    values: vec![],
+                };
+                slots
+            ],
+        };
+        let leaves: Vec<NodeID> = engine.tree.get_leaf_ids().collect();
+        for leaf in leaves {
+            engine.lower[leaf] = engine.tip(leaf)?;
+            engine.fresh[leaf] = true;
+        }
+        Ok(engine)
+    }
+
+    /// The tree, with the branch lengths and topology of every change made
+    /// through the engine.
+    pub fn tree(&self) -> &PhyloTree {
+        &self.tree
+    }
+
+    /// Gives the tree back.
+    pub fn into_tree(self) -> PhyloTree {
+        self.tree
+    }
+
+    /// The compressed patterns the tree is scored on.
+    pub fn columns(&self) -> &CompressedColumns {
+        &self.columns
+    }
+
+    /// The model.
+    pub fn model(&self) -> &GtrModel<A> {
+        self.model
+    }
+
+    /// Log-likelihood of the alignment, recomputing the stale partials first.
+    pub fn log_likelihood(&mut self) -> f64 {
+        let lls = self.pattern_log_likelihoods();
+        self.total(&lls)
+    }
+
+    /// Log-likelihood of each pattern, its categories mixed, in pattern order.
+    pub fn pattern_log_likelihoods(&mut self) -> Vec<f64> {
+        self.update_lower();
+        let root = &self.lower[self.tree.get_root_id()];
+        let pi = self.model.equilibrium();
+        let n = A::N_STATES;
+        let patterns = self.columns.patterns.len();
+        (0..patterns)
+            .map(|p| {
+                let cats: Vec<f64> = self
+                    .model
+                    .categories()
+                    .iter()
+                    .enumerate()
+                    .map(|(c, category)| {
+                        let block = c * patterns + p;
+                        let values = &root.values[block * n..(block + 1) * n];
+                        let mass: f64 = values.iter().zip(pi.iter()).map(|(x, pi)| x * pi).sum();
+                        category.weight.ln() + mass.ln() + root.log_scales[block]
+                    })
+                    .collect();
+                log_sum_exp(&cats)
+            })
+            .collect()
+    }
+
+    /// Log-likelihood of the alignment with the branch above `node` set to
+    /// `length` and every other branch as it is, from the partials on either
+    /// side of the branch. The tree is left unchanged.
+    ///
+    /// # Errors
+    ///
+    /// As for [`branch_pattern_log_likelihoods`](Self::branch_pattern_log_likelihoods).
+    pub fn branch_log_likelihood(&mut self, node: NodeID, length: f64) -> Result<f64, TreeError> {
+        let lls = self.branch_pattern_log_likelihoods(node, length)?;
+        Ok(self.total(&lls))
+    }
+
+    /// Log-likelihood of each pattern with the branch above `node` set to
+    /// `length`, as [`branch_log_likelihood`](Self::branch_log_likelihood)
+    /// computes it.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::UnknownNode`] if `node` is not in the tree, and
+    /// [`TreeError::NoParent`] if it is the root.
+    pub fn branch_pattern_log_likelihoods(
+        &mut self,
+        node: NodeID,
+        length: f64,
+    ) -> Result<Vec<f64>, TreeError> {
+        self.parent(node)?;
+        self.update_upper();
+        let matrices = self.matrices(length);
+        let (lower, upper) = (&self.lower[node], &self.upper[node]);
+        let n = A::N_STATES;
+        let patterns = self.columns.patterns.len();
+        let mut message = vec![0.0; n];
+        Ok((0..patterns)
+            .map(|p| {
+                let cats: Vec<f64> = self
+                    .model
+                    .categories()
+                    .iter()
+                    .enumerate()
+                    .map(|(c, category)| {
+                        let block = c * patterns + p;
+                        let range = block * n..(block + 1) * n;
+                        propagate(
+                            &matrices[c * n * n..(c + 1) * n * n],
+                            &lower.values[range.clone()],
+                            &mut message,
+                        );
+                        let mass: f64 = upper.values[range]
+                            .iter()
+                            .zip(&message)
+                            .map(|(x, y)| x * y)
+                            .sum();
+                        category.weight.ln()
+                            + mass.ln()
+                            + lower.log_scales[block]
+                            + upper.log_scales[block]
+                    })
+                    .collect();
+                log_sum_exp(&cats)
+            })
+            .collect())
+    }
+
+    /// Sets the length of the branch above `node`. The nodes above it become
+    /// stale.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::UnknownNode`] if `node` is not in the tree, and
+    /// [`TreeError::NoParent`] if it is the root.
+    pub fn set_branch_length(&mut self, node: NodeID, length: f64) -> Result<(), TreeError> {
+        let parent = self.parent(node)?;
+        self.set_length(node, length);
+        self.invalidate(parent);
+        Ok(())
+    }
+
+    /// Applies [`NNI::nni`] at `node`. The node and those above it become
+    /// stale.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`NNI::nni`].
+    pub fn nni(&mut self, node: NodeID, left_ch: bool) -> Result<(), TreeError> {
+        self.tree.nni(node, left_ch)?;
+        self.invalidate(node);
+        Ok(())
+    }
+
+    /// Prunes the subtree below `node` and regrafts it onto the middle of the
+    /// branch above `target`. The parent of `node` is suppressed, its branch
+    /// joined to its other child's, and reused as the node splitting the
+    /// target branch, so node ids do not change. The nodes above the old and
+    /// the new position become stale.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::UnknownNode`] if either node is not in the tree,
+    /// [`TreeError::NoParent`] if `node`, its parent or `target` is the
+    /// root, [`TreeError::NotBinary`] if the parent of `node` has other than
+    /// two children, and [`TreeError::UnknownEdge`] if the branch above
+    /// `target` is not in the tree once the subtree is pruned.
+    pub fn spr(&mut self, node: NodeID, target: NodeID) -> Result<(), TreeError> {
+        let parent = self.parent(node)?;
+        let grandparent = self.parent(parent)?;
+        let target_parent = self.parent(target)?;
+        let children: Vec<NodeID> = self.tree.get_node_children_ids(parent).collect();
+        if children.len() != 2 {
+            return Err(TreeError::NotBinary(parent));
+        }
+        let mut inside = Some(target);
+        while let Some(id) = inside {
+            if id == node || target == parent {
+                return Err(TreeError::UnknownEdge(target_parent, target));
+            }
+            inside = self.tree.get_node_parent_id(id);
+        }
+        let sibling = children[(children[0] == node) as usize];
+
+        // Suppress the parent, joining its branch to the sibling's.
+        let joined = self.length(parent) + self.length(sibling);
+        self.tree.delete_edge(parent, sibling);
+        self.tree.delete_edge(grandparent, parent);
+        self.tree.set_child(grandparent, sibling);
+        self.set_length(sibling, joined);
+        self.invalidate(grandparent);
+
+        // Split the target branch with it.
+        let target_parent = self
+            .tree
+            .get_node_parent_id(target)
+            .expect("invariant: target is not the root");
+        let half = self.length(target) / 2.0;
+        self.tree.delete_edge(target_parent, target);
+        self.tree.set_child(target_parent, parent);
+        self.tree.set_child(parent, target);
+        self.set_length(parent, half);
+        self.set_length(target, half);
+        // The parent may be stale from an earlier edit, with its new
+        // ancestors still fresh, so they are marked from the top of its new
+        // branch too.
+        self.invalidate(parent);
+        self.invalidate(target_parent);
+        Ok(())
+    }
+
+    /// Number of nodes whose partials the next evaluation recomputes.
+    pub fn stale(&self) -> usize {
+        self.tree
+            .get_node_ids()
+            .filter(|&id| !self.fresh[id])
+            .count()
+    }
+
+    /// Parent of `node`, with the errors of the edits.
+    fn parent(&self, node: NodeID) -> Result<NodeID, TreeError> {
+        if !self.tree.contains_node(node) {
+            return Err(TreeError::UnknownNode(node));
+        }
+        self.tree
+            .get_node_parent_id(node)
+            .ok_or(TreeError::NoParent(node))
+    }
+
+    /// Length of the branch above `node`, 0 if unset.
+    fn length(&self, node: NodeID) -> f64 {
+        self.tree
+            .get_node(node)
+            .and_then(|n| n.get_weight())
+            .and_then(NumCast::from)
+            .unwrap_or(0.0)
+    }
+
+    fn set_length(&mut self, node: NodeID, length: f64) {
+        self.tree
+            .get_node_mut(node)
+            .expect("invariant: callers check the node exists")
+            .set_weight(Some(length as f32));
+    }
+
+    /// Marks `node` and its ancestors stale, stopping at the first ancestor
+    /// already stale. That ancestor's own ancestors are stale too unless a
+    /// node on the path has just moved, which callers moving nodes handle.
+    fn invalidate(&mut self, node: NodeID) {
+        self.upper_fresh = false;
+        let mut current = Some(node);
+        while let Some(id) = current {
+            if !self.fresh[id] {
+                break;
+            }
+            self.fresh[id] = false;
+            current = self.tree.get_node_parent_id(id);
+        }
+    }
+
+    /// Partials of a leaf: its residue's profile in every category, scaled.
+    fn tip(&self, leaf: NodeID) -> Result<Partials, AsrError> {
+        let row = *self.rows.get(&leaf).ok_or_else(|| {
+            AsrError::InvalidAlignment("Leaf in tree not found in alignment leaf order".to_string())
+        })?;
+        let mut values = vec![];
+        let mut log_scales = vec![];
+        for pattern in &self.columns.patterns {
+            let mut profile = A::profile(pattern[row]).ok_or_else(|| {
+                AsrError::AlphabetMismatch("Invalid char in alignment".to_string())
+            })?;
+            log_scales.push(rescale(&mut profile));
+            values.extend(profile);
+        }
+        let categories = self.model.n_categories();
+        Ok(Partials {
+            values: values.repeat(categories),
+            log_scales: log_scales.repeat(categories),
+        })
+    }
+
+    /// Transition matrices for a branch of `length`, row-major, one per
+    /// category.
+    fn matrices(&self, length: f64) -> Vec<f64> {
+        let n = A::N_STATES;
+        let mut values = Vec::with_capacity(self.model.n_categories() * n * n);
+        for c in 0..self.model.n_categories() {
+            let p: DMatrix<f64> = self.model.category_transition(c, length);
+            for i in 0..n {
+                values.extend((0..n).map(|j| p[(i, j)]));
+            }
+        }
+        values
+    }
+
+    /// Recomputes the transition matrices of the branch above `node` if its
+    /// length has changed.
+    fn refresh_transitions(&mut self, node: NodeID) {
+        let length = self.length(node);
+        if self.transitions[node].length != length {
+            self.transitions[node] = Transitions {
+                length,
+                values: self.matrices(length),
+            };
+        }
+    }
+
+    /// Recomputes the stale partials below each node, children first.
+    fn update_lower(&mut self) {
+        let root = self.tree.get_root_id();
+        // Stale nodes form subtrees hanging from the root, so a preorder of
+        // them alone, reversed, visits children first.
+        let mut order = vec![];
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            if self.fresh[id] {
+                continue;
+            }
+            order.push(id);
+            stack.extend(self.tree.get_node_children_ids(id));
+        }
+        let n = A::N_STATES;
+        let blocks = self.model.n_categories() * self.columns.patterns.len();
+        let mut message = vec![0.0; n];
+        for &id in order.iter().rev() {
+            let children: Vec<NodeID> = self.tree.get_node_children_ids(id).collect();
+            for &child in &children {
+                self.refresh_transitions(child);
+            }
+            let mut out = std::mem::take(&mut self.lower[id]);
+            out.values.clear();
+            out.values.resize(blocks * n, 1.0);
+            out.log_scales.clear();
+            out.log_scales.resize(blocks, 0.0);
+            for block in 0..blocks {
+                let acc = &mut out.values[block * n..(block + 1) * n];
+                for &child in &children {
+                    let c = block / self.columns.patterns.len();
+                    let lower = &self.lower[child];
+                    propagate(
+                        &self.transitions[child].values[c * n * n..(c + 1) * n * n],
+                        &lower.values[block * n..(block + 1) * n],
+                        &mut message,
+                    );
+                    for (x, m) in acc.iter_mut().zip(&message) {
+                        *x *= m;
+                    }
+                    out.log_scales[block] += lower.log_scales[block];
+                }
+                out.log_scales[block] += rescale(acc);
+            }
+            self.lower[id] = out;
+            self.fresh[id] = true;
+        }
+    }
+
+    /// Recomputes every upper partial if any branch or the topology has
+    /// changed since they were computed, parents first.
+    fn update_upper(&mut self) {
+        self.update_lower();
+        if self.upper_fresh {
+            return;
+        }
+        let root = self.tree.get_root_id();
+        let order: Vec<NodeID> = self
+            .tree
+            .preord_ids(root)
+            .expect("invariant: the root id always names a node")
+            .collect();
+        for &id in &order {
+            if id != root {
+                self.refresh_transitions(id);
+            }
+        }
+        let n = A::N_STATES;
+        let patterns = self.columns.patterns.len();
+        let blocks = self.model.n_categories() * patterns;
+        let pi = self.model.equilibrium();
+        let mut message = vec![0.0; n];
+        for &id in &order {
+            let parent = self.tree.get_node_parent_id(id);
+            for child in self.tree.get_node_children_ids(id) {
+                let mut out = std::mem::take(&mut self.upper[child]);
+                out.values.clear();
+                out.log_scales.clear();
+                for block in 0..blocks {
+                    let c = block / patterns;
+                    let range = block * n..(block + 1) * n;
+                    let mut log_scale = 0.0;
+                    // What reaches `id` from above, or the root's prior.
+                    let mut acc: Vec<f64> = match parent {
+                        None => pi.iter().copied().collect(),
+                        Some(_) => {
+                            let above = &self.upper[id];
+                            propagate_back(
+                                &self.transitions[id].values[c * n * n..(c + 1) * n * n],
+                                &above.values[range.clone()],
+                                &mut message,
+                            );
+                            log_scale += above.log_scales[block];
+                            message.clone()
+                        }
+                    };
+                    for sibling in self.tree.get_node_children_ids(id) {
+                        if sibling == child {
+                            continue;
+                        }
+                        let lower = &self.lower[sibling];
+                        propagate(
+                            &self.transitions[sibling].values[c * n * n..(c + 1) * n * n],
+                            &lower.values[range.clone()],
+                            &mut message,
+                        );
+                        for (x, m) in acc.iter_mut().zip(&message) {
+                            *x *= m;
+                        }
+                        log_scale += lower.log_scales[block];
+                    }
+                    log_scale += rescale(&mut acc);
+                    out.values.extend(acc);
+                    out.log_scales.push(log_scale);
+                }
+                self.upper[child] = out;
+            }
+        }
+        self.upper_fresh = true;
+    }
+
+    /// Pattern log-likelihoods summed by multiplicity.
+    fn total(&self, lls: &[f64]) -> f64 {
+        lls.iter()
+            .zip(&self.columns.multiplicity)
+            .map(|(ll, &m)| m as f64 * ll)
+            .sum()
+    }
+}
+
+/// `out = P x` for a row-major square `matrix`.
+fn propagate(matrix: &[f64], x: &[f64], out: &mut [f64]) {
+    let n = x.len();
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = matrix[i * n..(i + 1) * n]
+            .iter()
+            .zip(x)
+            .map(|(p, x)| p * x)
+            .sum();
+    }
+}
+
+/// `out = P^T x` for a row-major square `matrix`.
+fn propagate_back(matrix: &[f64], x: &[f64], out: &mut [f64]) {
+    let n = x.len();
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = (0..n).map(|k| x[k] * matrix[k * n + i]).sum();
+    }
+}
+
+/// Divides `values` by their maximum and returns its log, as
+/// [`Profile::scale`](super::profile::Profile::scale) does. Values with no
+/// positive entry are left alone.
+fn rescale(values: &mut [f64]) -> f64 {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max <= 0.0 {
+        return 0.0;
+    }
+    for value in values.iter_mut() {
+        *value /= max;
+    }
+    max.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Nucleotide;
+    use crate::tree::likelihood::compute_log_likelihood;
+
+    fn alignment() -> Alignment {
+        Alignment::from_fasta(concat!(
+            ">a\nAACGTTAGCARTAC\n",
+            ">b\nAACGTTAGCAGTAC\n",
+            ">c\nAGCGTCAGCTGTA-\n",
+            ">d\nTGCATCAGGTGCAC\n",
+            ">e\nTGCATCTGGTGCAA\n",
+            ">f\nTGCAACTGNTGCAA\n",
+        ))
+        .unwrap()
+    }
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick(b"(((a:0.05,b:0.1):0.2,c:0.3):0.1,((d:0.1,e:0.15):0.05,f:0.2):0.2);")
+            .unwrap()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn edits_match_full_recomputation() {
+        let model = GtrModel::<Nucleotide>::hky85([0.3, 0.2, 0.2, 0.3], 2.0)
+            .unwrap()
+            .with_gamma(0.5, 4)
+            .unwrap()
+            .with_invariant(0.1)
+            .unwrap();
+        let aln = alignment();
+        let mut engine = LikelihoodEngine::new(tree(), &model, &aln).unwrap();
+        let full = |engine: &LikelihoodEngine<'_, Nucleotide>| {
+            compute_log_likelihood(engine.tree(), &model, &aln).unwrap()
+        };
+        assert!(close(engine.log_likelihood(), full(&engine)));
+        assert_eq!(engine.stale(), 0);
+
+        // A leaf branch stales only the path from its parent to the root.
+        let a = engine.tree().get_taxa_node_id(&"a".to_string()).unwrap();
+        engine.set_branch_length(a, 0.3).unwrap();
+        assert_eq!(engine.stale(), 3);
+        assert!(close(engine.log_likelihood(), full(&engine)));
+
+        let ab = engine.tree().get_node_parent_id(a).unwrap();
+        let abc = engine.tree().get_node_parent_id(ab).unwrap();
+        engine.nni(ab, false).unwrap();
+        assert_eq!(engine.stale(), 3);
+        assert!(close(engine.log_likelihood(), full(&engine)));
+
+        let d = engine.tree().get_taxa_node_id(&"d".to_string()).unwrap();
+        engine.spr(d, abc).unwrap();
+        assert!(close(engine.log_likelihood(), full(&engine)));
+        assert_eq!(engine.tree().get_leaf_ids().count(), 6);
+
+        let above_a = engine.tree().get_node_parent_id(a).unwrap();
+        assert_eq!(engine.spr(a, a), Err(TreeError::UnknownEdge(above_a, a)));
+        let root = engine.tree().get_root_id();
+        assert_eq!(
+            engine.set_branch_length(root, 1.0),
+            Err(TreeError::NoParent(root))
+        );
+    }
+
+    #[test]
+    fn edits_without_evaluation_between_them() {
+        let model = GtrModel::<Nucleotide>::jukes_cantor().unwrap();
+        let aln = alignment();
+        let mut engine = LikelihoodEngine::new(tree(), &model, &aln).unwrap();
+        engine.log_likelihood();
+        let a = engine.tree().get_taxa_node_id(&"a".to_string()).unwrap();
+        let f = engine.tree().get_taxa_node_id(&"f".to_string()).unwrap();
+        // The moved parent is already stale when the subtree is regrafted.
+        engine.set_branch_length(a, 0.4).unwrap();
+        engine.spr(a, f).unwrap();
+        let full = compute_log_likelihood(engine.tree(), &model, &aln).unwrap();
+        assert!(close(engine.log_likelihood(), full));
+    }
+
+    #[test]
+    fn branch_evaluation_reroots_on_the_branch() {
+        let model = GtrModel::<Nucleotide>::k80(3.0)
+            .unwrap()
+            .with_gamma(1.0, 4)
+            .unwrap();
+        let aln = alignment();
+        let mut engine = LikelihoodEngine::new(tree(), &model, &aln).unwrap();
+        let ids: Vec<NodeID> = engine.tree().get_node_ids().collect();
+        for id in ids {
+            if engine.tree().get_node_parent_id(id).is_none() {
+                continue;
+            }
+            for length in [0.0, 0.0625, 0.5, 2.0] {
+                let quick = engine.branch_log_likelihood(id, length).unwrap();
+                let mut tree = engine.tree().clone();
+                tree.get_node_mut(id)
+                    .unwrap()
+                    .set_weight(Some(length as f32));
+                let full = compute_log_likelihood(&tree, &model, &aln).unwrap();
+                assert!(
+                    close(quick, full),
+                    "node {id} at {length}: {quick} vs {full}"
+                );
+            }
+        }
+        // Evaluating a branch leaves the tree alone.
+        assert!(close(
+            engine.log_likelihood(),
+            compute_log_likelihood(engine.tree(), &model, &aln).unwrap()
+        ));
+    }
+}
@@ -244,23 +244,23 @@ fn test_gtr_custom_pi() {
 fn test_alignment_fasta_multiline() {
     let data = b">Seq1\nACGTACGT\nACGTACGT\n>Seq2\nAGGTAGGT\nAGGTAGGT\n";
     let aln = Alignment::from_fasta_bytes(data).unwrap();
-    assert_eq!(aln.width, 16);
-    assert_eq!(aln.seqs["Seq1"], b"ACGTACGTACGTACGT");
+    assert_eq!(aln.width(), 16);
+    assert_eq!(aln.row("Seq1").unwrap(), b"ACGTACGTACGTACGT");
 }
 
 #[test]
 fn test_alignment_fasta_crlf() {
     let data = b">Seq1\r\nACGT\r\n>Seq2\r\nAGGT\r\n";
     let aln = Alignment::from_fasta_bytes(data).unwrap();
-    assert_eq!(aln.width, 4);
+    assert_eq!(aln.width(), 4);
 }
 
 #[test]
 fn test_alignment_fasta_uppercase() {
     let data = b">Seq1\nacgt\n>Seq2\naggt\n";
     let aln = Alignment::from_fasta_bytes(data).unwrap();
-    assert_eq!(aln.seqs["Seq1"], b"ACGT");
-    assert_eq!(aln.seqs["Seq2"], b"AGGT");
+    assert_eq!(aln.row("Seq1").unwrap(), b"ACGT");
+    assert_eq!(aln.row("Seq2").unwrap(), b"AGGT");
 }
 
 #[test]
@@ -295,19 +295,17 @@ fn test_alignment_fasta_duplicate_id_returns_error() {
 fn test_alignment_fasta_whitespace_in_header() {
     let data = b">Seq1 some description here\nACGT\n>Seq2 another desc\nAGGT\n";
     let aln = Alignment::from_fasta_bytes(data).unwrap();
-    assert_eq!(aln.seqs["Seq1"], b"ACGT");
-    assert_eq!(aln.seqs["Seq2"], b"AGGT");
+    assert_eq!(aln.row("Seq1").unwrap(), b"ACGT");
+    assert_eq!(aln.row("Seq2").unwrap(), b"AGGT");
 }
 
 #[test]
 fn test_compression_identical_columns() {
-    let mut seqs = HashMap::new();
-    seqs.insert("S1".to_string(), b"AATT".to_vec());
-    seqs.insert(
-        "S2".to_string(),
-        "AAT T".replace(' ', "-").as_bytes().to_vec(),
-    );
-    let aln = Alignment { seqs, width: 4 };
+    let aln = Alignment::new(
+        vec!["S1".to_string(), "S2".to_string()],
+        vec![b"AATT".to_vec(), b"AAT-".to_vec()],
+    )
+    .unwrap();
 
     let comp = aln.compress_columns();
     // Col 0: AA -> P0
@@ -464,7 +462,7 @@ fn test_marginal_asr_likelihood_normalization() {
     let aln = Alignment::from_fasta_bytes(aln_data).unwrap();
 
     // The likelihood should be finite and positive in linear space
-    assert!(aln.seqs.values().next().unwrap().len() == aln.width);
+    assert!(aln.row_at(0).len() == aln.width());
     let result = tree.marginal_asr::<Nucleotide>(&model, &aln, true);
     assert!(result.is_ok());
     let recon = result.unwrap();
@@ -478,7 +476,7 @@ fn test_marginal_asr_likelihood_normalization() {
     if let Some(ref posters) = recon.posteriors {
         let root_id: NodeID = tree.get_root_id();
         if let Some(site_posters) = posters.get(&root_id) {
-            assert_eq!(site_posters.len(), aln.width);
+            assert_eq!(site_posters.len(), aln.width());
             for (site_idx, post) in site_posters.iter().enumerate() {
                 let sum: f64 = post.iter().sum();
                 assert!(
@@ -564,13 +562,14 @@ fn test_asr_with_zero_branch_length() {
 
 #[test]
 fn test_compression_with_duplicate_patterns() {
-    let mut seqs = HashMap::new();
     // Columns 0-3: AA, CC, TT, GG -> all unique
     // Columns 4-7: AA, CC, TT, GG -> duplicates of 0-3
     // Should give 4 patterns with multiplicity 2 each
-    seqs.insert("S1".to_string(), b"ACGTACGT".to_vec());
-    seqs.insert("S2".to_string(), b"ACGTACGT".to_vec());
-    let aln = Alignment { seqs, width: 8 };
+    let aln = Alignment::new(
+        vec!["S1".to_string(), "S2".to_string()],
+        vec![b"ACGTACGT".to_vec(), b"ACGTACGT".to_vec()],
+    )
+    .unwrap();
 
     let comp = aln.compress_columns();
     assert_eq!(comp.patterns.len(), 4);
@@ -581,20 +580,21 @@ fn test_compression_with_duplicate_patterns() {
 
 #[test]
 fn test_compression_re_expansion() {
-    let mut seqs = HashMap::new();
-    seqs.insert("S1".to_string(), b"AATTGGCC".to_vec());
-    seqs.insert("S2".to_string(), b"ATGCATGC".to_vec());
-    let aln = Alignment { seqs, width: 8 };
+    let aln = Alignment::new(
+        vec!["S1".to_string(), "S2".to_string()],
+        vec![b"AATTGGCC".to_vec(), b"ATGCATGC".to_vec()],
+    )
+    .unwrap();
 
     let comp = aln.compress_columns();
 
     // Verify site-to-pattern mapping is correct by reconstructing the original columns
-    for site in 0..aln.width {
+    for site in 0..aln.width() {
         let p_idx = comp.site_to_pattern[site];
         let expected_col: Vec<u8> = comp
             .leaf_order
             .iter()
-            .map(|name| aln.seqs[name][site])
+            .map(|name| aln.row(name).unwrap()[site])
             .collect();
         assert_eq!(
             comp.patterns[p_idx], expected_col,
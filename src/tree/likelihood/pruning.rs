@@ -0,0 +1,581 @@
+//! Felsenstein pruning over whole alignments, patterns in blocks.
+//!
+//! `prune_pattern_category` prunes one pattern under one category at a time
+//! and allocates as it goes, which suits reconstruction but not scoring
+//! alignments with many patterns. [`Pruning`] lays out the tree once, as a
+//! postorder of internal nodes, and prunes a block of patterns at a time
+//! into contiguous pattern × category × state buffers. Transition matrices
+//! are computed once per branch and category, and for branches ending in a
+//! leaf so is their product with every residue profile in the alignment, so
+//! a node with two leaf children costs one multiplication per state, and one
+//! with a leaf child and an internal child costs one matrix-vector product.
+//! With four states, as for nucleotides, matrix-vector products are written
+//! with explicit SIMD on x86-64: one AVX register per column when the build
+//! enables AVX (say with `-C target-cpu=native`), two SSE2 registers
+//! otherwise. Other targets use four-lane arrays, which the compiler may
+//! vectorize on its own.
+//!
+//! The arithmetic is that of `prune_pattern_category`, operation for
+//! operation and in the same order: products sum over a matrix's columns in
+//! turn as `nalgebra` does, each lane multiplying and then adding with no
+//! fused multiply-add, partials are scaled as
+//! [`Profile::scale`](crate::tree::likelihood::profile::Profile::scale)
+//! scales them, and patterns never mix. Results are therefore the same to the
+//! bit whether blocks run one after another or, under the `parallel`
+//! feature, on several threads.
+
+use std::collections::HashMap;
+
+use num_traits::NumCast;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::log_sum_exp;
+use crate::alignment::CompressedColumns;
+use crate::alphabet::Alphabet;
+use crate::error::AsrError;
+use crate::models::GtrModel;
+use crate::node::NodeID;
+use crate::prelude::*;
+use crate::tree::PhyloTree;
+
+/// Patterns pruned together. Bounds the buffers of a block, which hold every
+/// internal node's partials for these patterns.
+const BLOCK: usize = 256;
+
+/// A child of an internal node, and where its branch's products come from.
+#[derive(Debug, Clone, Copy)]
+enum Child {
+    /// A leaf, with its alignment row and the offset of its branch's table
+    /// of products with residue profiles.
+    Tip { row: usize, table: usize },
+    /// An internal node, with its position in the postorder and the offset
+    /// of its branch's transition matrices.
+    Inner { slot: usize, matrix: usize },
+}
+
+/// A tree and a model laid out for pruning the patterns of one alignment.
+pub(crate) struct Pruning<'a> {
+    columns: &'a CompressedColumns,
+    states: usize,
+    categories: usize,
+    /// `ln` of each category's weight.
+    log_weights: Vec<f64>,
+    equilibrium: Vec<f64>,
+    /// Children of each internal node, in postorder; the root is last.
+    steps: Vec<Vec<Child>>,
+    /// Alignment row of the root, when the tree is a single leaf.
+    root_tip: Option<usize>,
+    /// Column-major transition matrices of branches to internal nodes,
+    /// category-major within a branch.
+    matrices: Vec<f64>,
+    /// Index of each residue code of the alignment among `profiles`.
+    codes: [usize; 256],
+    /// Scaled profile of each distinct residue code, and its log scale.
+    profiles: Vec<f64>,
+    profile_scales: Vec<f64>,
+    /// Products of each leaf branch's matrices with every profile,
+    /// category-major, then code.
+    tables: Vec<f64>,
+}
+
+impl<'a> Pruning<'a> {
+    /// Lays out `tree` under `model` for the patterns of `columns`.
+    ///
+    /// # Errors
+    ///
+    /// [`AsrError::AlphabetMismatch`] if a taxon of the alignment is not in
+    /// the tree or a residue is not in `A`, and [`AsrError::InvalidAlignment`]
+    /// if a leaf of the tree has no row.
+    pub(crate) fn new<A: Alphabet>(
+        tree: &PhyloTree,
+        model: &GtrModel<A>,
+        columns: &'a CompressedColumns,
+    ) -> Result<Self, AsrError> {
+        let states = A::N_STATES;
+        let categories = model.n_categories();
+
+        let mut rows = HashMap::new();
+        for (row, name) in columns.leaf_order.iter().enumerate() {
+            let id = tree.get_taxa_node_id(name).ok_or_else(|| {
+                AsrError::AlphabetMismatch(format!("Taxon {} in alignment not found in tree", name))
+            })?;
+            rows.insert(id, row);
+        }
+        let row_of = |id: NodeID| {
+            rows.get(&id).copied().ok_or_else(|| {
+                AsrError::InvalidAlignment(
+                    "Leaf in tree not found in alignment leaf order".to_string(),
+                )
+            })
+        };
+
+        let mut codes = [0; 256];
+        let mut seen = [false; 256];
+        let mut profiles = vec![];
+        let mut profile_scales = vec![];
+        for &code in columns.patterns.iter().flatten() {
+            if std::mem::replace(&mut seen[code as usize], true) {
+                continue;
+            }
+            let mut profile = A::profile(code).ok_or_else(|| {
+                AsrError::AlphabetMismatch("Invalid char in alignment".to_string())
+            })?;
+            codes[code as usize] = profile_scales.len();
+            profile_scales.push(rescale(&mut profile));
+            profiles.extend(profile);
+        }
+
+        let mut pruning = Pruning {
+            columns,
+            states,
+            categories,
+            log_weights: model.categories().iter().map(|c| c.weight.ln()).collect(),
+            equilibrium: model.equilibrium().iter().copied().collect(),
+            steps: vec![],
+            root_tip: None,
+            matrices: vec![],
+            codes,
+            profiles,
+            profile_scales,
+            tables: vec![],
+        };
+
+        let root = tree.get_root_id();
+        if tree.is_leaf(root) {
+            pruning.root_tip = Some(row_of(root)?);
+            return Ok(pruning);
+        }
+        let mut slots = HashMap::new();
+        for id in tree
+            .postord_ids(root)
+            .expect("invariant: the root id always names a node")
+        {
+            if tree.is_leaf(id) {
+                continue;
+            }
+            let mut children = vec![];
+            for child in tree.get_node_children_ids(id) {
+                let length: f64 = tree
+                    .get_edge_weight(id, child)
+                    .and_then(NumCast::from)
+                    .unwrap_or(0.0);
+                let matrices: Vec<f64> = (0..categories)
+                    .flat_map(|c| model.category_transition(c, length).as_slice().to_vec())
+                    .collect();
+                children.push(match slots.get(&child) {
+                    Some(&slot) => {
+                        let matrix = pruning.matrices.len();
+                        pruning.matrices.extend(matrices);
+                        Child::Inner { slot, matrix }
+                    }
+                    None => {
+                        let table = pruning.tables.len();
+                        let mut product = vec![0.0; states];
+                        for c in 0..categories {
+                            let matrix = &matrices[c * states * states..(c + 1) * states * states];
+                            for profile in pruning.profiles.chunks(states) {
+                                multiply(matrix, profile, &mut product);
+                                pruning.tables.extend_from_slice(&product);
+                            }
+                        }
+                        Child::Tip {
+                            row: row_of(child)?,
+                            table,
+                        }
+                    }
+                });
+            }
+            slots.insert(id, pruning.steps.len());
+            pruning.steps.push(children);
+        }
+        Ok(pruning)
+    }
+
+    /// Log-likelihood of each pattern, its categories mixed, in pattern order.
+    pub(crate) fn pattern_log_likelihoods(&self) -> Vec<f64> {
+        let mut out = vec![0.0; self.columns.patterns.len()];
+        for (block, lls) in out.chunks_mut(BLOCK).enumerate() {
+            self.prune(block * BLOCK, lls);
+        }
+        out
+    }
+
+    #[cfg(feature = "parallel")]
+    /// The log-likelihoods of [`pattern_log_likelihoods`](Self::pattern_log_likelihoods),
+    /// blocks of patterns pruned in parallel.
+    pub(crate) fn pattern_log_likelihoods_par(&self) -> Vec<f64> {
+        let mut out = vec![0.0; self.columns.patterns.len()];
+        out.par_chunks_mut(BLOCK)
+            .enumerate()
+            .for_each(|(block, lls)| self.prune(block * BLOCK, lls));
+        out
+    }
+
+    /// Prunes the patterns from `start` on, one per entry of `lls`, and
+    /// writes their log-likelihoods there.
+    fn prune(&self, start: usize, lls: &mut [f64]) {
+        let (n, cats, len) = (self.states, self.categories, lls.len());
+        let stride = len * cats;
+        let mut values = vec![0.0; self.steps.len() * stride * n];
+        let mut scales = vec![0.0; self.steps.len() * stride];
+        let mut scratch = vec![0.0; n];
+
+        for (slot, children) in self.steps.iter().enumerate() {
+            let (done, rest) = values.split_at_mut(slot * stride * n);
+            let (done_scales, rest_scales) = scales.split_at_mut(slot * stride);
+            // Partials and log scale of an internal child for block entry
+            // `block`.
+            let inner = |slot: usize, block: usize| {
+                let at = slot * stride + block;
+                (&done[at * n..(at + 1) * n], done_scales[at])
+            };
+            for local in 0..len {
+                let pattern = &self.columns.patterns[start + local];
+                for c in 0..cats {
+                    let block = local * cats + c;
+                    let acc = &mut rest[block * n..(block + 1) * n];
+                    let mut log_scale = match children.as_slice() {
+                        [Child::Tip { row: a, table: ta }, Child::Tip { row: b, table: tb }] => {
+                            let (x, sx) = self.tip(*ta, c, pattern[*a]);
+                            let (y, sy) = self.tip(*tb, c, pattern[*b]);
+                            for ((acc, x), y) in acc.iter_mut().zip(x).zip(y) {
+                                *acc = x * y;
+                            }
+                            sx + sy
+                        }
+                        [Child::Tip { row, table }, Child::Inner { slot, matrix }]
+                        | [Child::Inner { slot, matrix }, Child::Tip { row, table }] => {
+                            let (x, sx) = self.tip(*table, c, pattern[*row]);
+                            let (y, sy) = inner(*slot, block);
+                            multiply(self.matrix(*matrix, c), y, acc);
+                            for (acc, x) in acc.iter_mut().zip(x) {
+                                *acc *= x;
+                            }
+                            sx + sy
+                        }
+                        children => {
+                            acc.fill(1.0);
+                            let mut log_scale = 0.0;
+                            for child in children {
+                                let (product, s) = match *child {
+                                    Child::Tip { row, table } => self.tip(table, c, pattern[row]),
+                                    Child::Inner { slot, matrix } => {
+                                        let (y, s) = inner(slot, block);
+                                        multiply(self.matrix(matrix, c), y, &mut scratch);
+                                        (&scratch[..], s)
+                                    }
+                                };
+                                for (acc, p) in acc.iter_mut().zip(product) {
+                                    *acc *= p;
+                                }
+                                log_scale += s;
+                            }
+                            log_scale
+                        }
+                    };
+                    log_scale += rescale(acc);
+                    rest_scales[block] = log_scale;
+                }
+            }
+        }
+
+        let mut cat_lls = vec![0.0; cats];
+        for (local, ll) in lls.iter_mut().enumerate() {
+            for (c, cat_ll) in cat_lls.iter_mut().enumerate() {
+                let block = local * cats + c;
+                let (root, log_scale) = match self.root_tip {
+                    Some(row) => {
+                        let q = self.codes[self.columns.patterns[start + local][row] as usize];
+                        (&self.profiles[q * n..(q + 1) * n], self.profile_scales[q])
+                    }
+                    None => {
+                        let at = (self.steps.len() - 1) * stride + block;
+                        (&values[at * n..(at + 1) * n], scales[at])
+                    }
+                };
+                let mut mass = 0.0;
+                for (pi, x) in self.equilibrium.iter().zip(root) {
+                    mass += pi * x;
+                }
+                *cat_ll = self.log_weights[c] + mass.ln() + log_scale;
+            }
+            *ll = log_sum_exp(&cat_lls);
+        }
+    }
+
+    /// Transition matrix of category `c` of the branch at `offset`.
+    fn matrix(&self, offset: usize, c: usize) -> &[f64] {
+        let size = self.states * self.states;
+        &self.matrices[offset + c * size..offset + (c + 1) * size]
+    }
+
+    /// Product of category `c` of the leaf branch at `table` with the profile
+    /// of residue `code`, and the profile's log scale.
+    fn tip(&self, table: usize, c: usize, code: u8) -> (&[f64], f64) {
+        let n = self.states;
+        let q = self.codes[code as usize];
+        let at = table + (c * self.profile_scales.len() + q) * n;
+        (&self.tables[at..at + n], self.profile_scales[q])
+    }
+}
+
+/// `out = P x` for a column-major square `matrix`, summing over columns in
+/// turn as `nalgebra`'s matrix-vector product does.
+fn multiply(matrix: &[f64], x: &[f64], out: &mut [f64]) {
+    if let (Ok(matrix), Ok(x), Ok(out)) = (
+        <&[f64; 16]>::try_from(matrix),
+        <&[f64; 4]>::try_from(x),
+        <&mut [f64; 4]>::try_from(&mut *out),
+    ) {
+        *out = multiply4(matrix, x);
+        return;
+    }
+    let n = x.len();
+    for (o, p) in out.iter_mut().zip(&matrix[..n]) {
+        *o = p * x[0];
+    }
+    for (j, &xj) in x.iter().enumerate().skip(1) {
+        for (o, p) in out.iter_mut().zip(&matrix[j * n..(j + 1) * n]) {
+            *o += p * xj;
+        }
+    }
+}
+
+/// [`multiply`] for four states, one column at a time in a 256-bit register.
+#[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+fn multiply4(matrix: &[f64; 16], x: &[f64; 4]) -> [f64; 4] {
+    use std::arch::x86_64::{
+        _mm256_add_pd, _mm256_castpd256_pd128, _mm256_extractf128_pd, _mm256_mul_pd,
+        _mm256_set1_pd, _mm256_set_pd, _mm_cvtsd_f64, _mm_unpackhi_pd,
+    };
+
+    #[target_feature(enable = "avx")]
+    fn kernel(matrix: &[f64; 16], x: &[f64; 4]) -> [f64; 4] {
+        let column = |j: usize| {
+            let c = &matrix[j * 4..(j + 1) * 4];
+            _mm256_set_pd(c[3], c[2], c[1], c[0])
+        };
+        let mut out = _mm256_mul_pd(column(0), _mm256_set1_pd(x[0]));
+        for (j, &xj) in x.iter().enumerate().skip(1) {
+            out = _mm256_add_pd(out, _mm256_mul_pd(column(j), _mm256_set1_pd(xj)));
+        }
+        let (low, high) = (_mm256_castpd256_pd128(out), _mm256_extractf128_pd::<1>(out));
+        [
+            _mm_cvtsd_f64(low),
+            _mm_cvtsd_f64(_mm_unpackhi_pd(low, low)),
+            _mm_cvtsd_f64(high),
+            _mm_cvtsd_f64(_mm_unpackhi_pd(high, high)),
+        ]
+    }
+
+    // SAFETY: this function is only compiled when AVX is enabled for the
+    // whole build, so every CPU the binary runs on has it.
+    unsafe { kernel(matrix, x) }
+}
+
+/// [`multiply`] for four states, one column at a time in two 128-bit
+/// registers. SSE2 is part of every x86-64 target.
+#[cfg(all(
+    target_arch = "x86_64",
+    target_feature = "sse2",
+    not(target_feature = "avx")
+))]
+fn multiply4(matrix: &[f64; 16], x: &[f64; 4]) -> [f64; 4] {
+    use std::arch::x86_64::{
+        _mm_add_pd, _mm_cvtsd_f64, _mm_mul_pd, _mm_set1_pd, _mm_set_pd, _mm_unpackhi_pd,
+    };
+
+    #[target_feature(enable = "sse2")]
+    fn kernel(matrix: &[f64; 16], x: &[f64; 4]) -> [f64; 4] {
+        let half = |j: usize, k: usize| _mm_set_pd(matrix[j * 4 + k + 1], matrix[j * 4 + k]);
+        let x0 = _mm_set1_pd(x[0]);
+        let (mut low, mut high) = (_mm_mul_pd(half(0, 0), x0), _mm_mul_pd(half(0, 2), x0));
+        for (j, &xj) in x.iter().enumerate().skip(1) {
+            let xj = _mm_set1_pd(xj);
+            low = _mm_add_pd(low, _mm_mul_pd(half(j, 0), xj));
+            high = _mm_add_pd(high, _mm_mul_pd(half(j, 2), xj));
+        }
+        [
+            _mm_cvtsd_f64(low),
+            _mm_cvtsd_f64(_mm_unpackhi_pd(low, low)),
+            _mm_cvtsd_f64(high),
+            _mm_cvtsd_f64(_mm_unpackhi_pd(high, high)),
+        ]
+    }
+
+    // SAFETY: this function is only compiled when SSE2 is enabled for the
+    // whole build, as it is on every x86-64 target.
+    unsafe { kernel(matrix, x) }
+}
+
+/// [`multiply`] for four states on targets without an explicit SIMD kernel.
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse2")))]
+fn multiply4(matrix: &[f64; 16], x: &[f64; 4]) -> [f64; 4] {
+    multiply4_lanes(matrix, x)
+}
+
+/// [`multiply`] for four states, one column at a time across four-lane
+/// arrays. The fallback for [`multiply4`], and its reference in tests.
+#[cfg(any(test, not(all(target_arch = "x86_64", target_feature = "sse2"))))]
+fn multiply4_lanes(matrix: &[f64; 16], x: &[f64; 4]) -> [f64; 4] {
+    let column = |j: usize| -> [f64; 4] {
+        matrix[j * 4..(j + 1) * 4]
+            .try_into()
+            .expect("invariant: a slice of four entries")
+    };
+    let lanes = |col: [f64; 4], xj: f64| col.map(|p| p * xj);
+    let mut out = lanes(column(0), x[0]);
+    for (j, &xj) in x.iter().enumerate().skip(1) {
+        let term = lanes(column(j), xj);
+        for (o, t) in out.iter_mut().zip(term) {
+            *o += t;
+        }
+    }
+    out
+}
+
+/// Divides `values` by their maximum and returns its log, as
+/// [`Profile::scale`](super::profile::Profile::scale) does. Values with no
+/// positive entry are left alone.
+fn rescale(values: &mut [f64]) -> f64 {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max <= 0.0 {
+        return 0.0;
+    }
+    for value in values.iter_mut() {
+        *value /= max;
+    }
+    max.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment::Alignment;
+    use crate::alphabet::{AminoAcid, Nucleotide};
+    use crate::tree::likelihood::compute_marginal_asr;
+
+    /// Per-pattern log-likelihoods the old way, one pattern and category at
+    /// a time.
+    fn reference<A: Alphabet>(
+        tree: &PhyloTree,
+        model: &GtrModel<A>,
+        comp: &CompressedColumns,
+    ) -> Vec<f64> {
+        let leaf_id_map: Vec<NodeID> = comp
+            .leaf_order
+            .iter()
+            .map(|name| tree.get_taxa_node_id(name).unwrap())
+            .collect();
+        let postord: Vec<NodeID> = tree.postord_ids(tree.get_root_id()).unwrap().collect();
+        comp.patterns
+            .iter()
+            .map(|pattern| {
+                let cats: Vec<f64> = (0..model.n_categories())
+                    .map(|c| {
+                        super::super::prune_pattern_category(
+                            tree,
+                            model,
+                            c,
+                            model.categories()[c].weight,
+                            pattern,
+                            &leaf_id_map,
+                            &postord,
+                            model.equilibrium(),
+                            A::N_STATES,
+                        )
+                        .unwrap()
+                        .1
+                    })
+                    .collect();
+                log_sum_exp(&cats)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn blocks_match_pattern_by_pattern_pruning_exactly() {
+        // Enough distinct patterns for several blocks, with ambiguity codes,
+        // gaps and a polytomy.
+        let taxa = ["a", "b", "c", "d", "e", "f", "g"];
+        let residues = b"ACGTRYN-";
+        let mut fasta = String::new();
+        for (i, taxon) in taxa.iter().enumerate() {
+            fasta.push_str(&format!(">{taxon}\n"));
+            for site in 0..700usize {
+                let code = residues[(site * (i + 3) + site / (i + 1)) % residues.len()];
+                fasta.push(code as char);
+            }
+            fasta.push('\n');
+        }
+        let aln = Alignment::from_fasta(&fasta).unwrap();
+        let comp = aln.compress_columns();
+        assert!(comp.patterns.len() > 2 * BLOCK);
+
+        let tree = PhyloTree::from_newick(
+            b"(((a:0.05,b:0.1):0.2,(c:0.3,d:0.01):0.02):0.1,(e:0.1,f:0.15,g:0.4):0.05);",
+        )
+        .unwrap();
+        let model = GtrModel::<Nucleotide>::hky85([0.3, 0.2, 0.2, 0.3], 2.0)
+            .unwrap()
+            .with_gamma(0.5, 4)
+            .unwrap()
+            .with_invariant(0.1)
+            .unwrap();
+        let pruning = Pruning::new(&tree, &model, &comp).unwrap();
+        let lls = pruning.pattern_log_likelihoods();
+        assert_eq!(lls, reference(&tree, &model, &comp));
+        #[cfg(feature = "parallel")]
+        assert_eq!(pruning.pattern_log_likelihoods_par(), lls);
+
+        let total: f64 = lls
+            .iter()
+            .zip(&comp.multiplicity)
+            .map(|(ll, &m)| m as f64 * ll)
+            .sum();
+        let asr = compute_marginal_asr(&tree, &model, &aln, false).unwrap();
+        assert_eq!(total, asr.log_likelihood);
+    }
+
+    #[test]
+    fn other_alphabets_and_single_leaves() {
+        let aln = Alignment::from_fasta(">a\nMKVLA\n>b\nMKILG\n>c\nMRVWG\n").unwrap();
+        let comp = aln.compress_columns();
+        let tree = PhyloTree::from_newick(b"((a:0.1,b:0.2):0.1,c:0.3);").unwrap();
+        let model = GtrModel::<AminoAcid>::jukes_cantor()
+            .unwrap()
+            .with_gamma(1.0, 2)
+            .unwrap();
+        let lls = Pruning::new(&tree, &model, &comp)
+            .unwrap()
+            .pattern_log_likelihoods();
+        assert_eq!(lls, reference(&tree, &model, &comp));
+
+        let aln = Alignment::from_fasta(">a\nACGR\n").unwrap();
+        let comp = aln.compress_columns();
+        let tree = PhyloTree::from_newick(b"a;").unwrap();
+        let model = GtrModel::<Nucleotide>::jukes_cantor().unwrap();
+        let lls = Pruning::new(&tree, &model, &comp)
+            .unwrap()
+            .pattern_log_likelihoods();
+        assert_eq!(lls, reference(&tree, &model, &comp));
+    }
+
+    #[test]
+    fn four_state_kernel_matches_lanes_to_the_bit() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(50);
+        for _ in 0..1000 {
+            // Magnitudes spread over many binades, so that rounding differs
+            // between any two orders or groupings of the arithmetic.
+            let mut draw = || rng.gen::<f64>() * 2f64.powi(rng.gen_range(-60..4));
+            let matrix: [f64; 16] = std::array::from_fn(|_| draw());
+            let x: [f64; 4] = std::array::from_fn(|_| draw());
+            let expected = multiply4_lanes(&matrix, &x).map(f64::to_bits);
+            assert_eq!(multiply4(&matrix, &x).map(f64::to_bits), expected);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! Stochastic character mapping (simmap).
+//!
+//! Samples complete character histories along every branch, conditioned on the
+//! states observed at the tips (Nielsen 2002; Huelsenbeck et al. 2003). Each
+//! sample is drawn in two stages:
+//!
+//! 1. **Node states.** The Felsenstein up pass (the same pruning core that
+//!    backs [`compute_marginal_asr`](crate::tree::likelihood::compute_marginal_asr))
+//!    gives conditional likelihoods at every node. A rate category is drawn from
+//!    its posterior, the root state from `pi * L_root`, and each child's state
+//!    given its parent's from `P(t)[parent, .] * L_child`.
+//! 2. **Branch paths.** Every branch then gets a substitution path that starts
+//!    and ends in the sampled states, drawn exactly by uniformization (Hobolth &
+//!    Stone 2009) from the model's generator `Q`.
+//!
+//! For a discrete trait, an Mk model is a [`GtrModel`] over an alphabet with one
+//! state per trait value: [`GtrModel::jukes_cantor`] gives equal rates, and
+//! [`GtrModel::new`] arbitrary symmetric ones.
+
+use std::collections::HashMap;
+
+use crate::alignment::Alignment;
+use crate::alphabet::Alphabet;
+use crate::error::AsrError;
+use crate::models::GtrModel;
+use crate::node::NodeID;
+
+#[cfg(feature = "simple_rooted_tree")]
+use {
+    super::{log_sum_exp, prune_pattern_category},
+    crate::prelude::*,
+    crate::tree::PhyloTree,
+    nalgebra::DMatrix,
+    num_traits::NumCast,
+    rand::{rngs::StdRng, Rng, SeedableRng},
+};
+
+/// Sampling of character histories conditioned on an alignment.
+///
+/// Feature-free (like [`crate::tree::likelihood::TreeLikelihood`]) so a caller
+/// bringing its own tree type can implement it without `simple_rooted_tree`.
+pub trait StochasticMapping {
+    /// Draws `n_samples` independent character histories of every site of
+    /// `aln` under `model`. The same `seed` always yields the same samples.
+    fn stochastic_maps<A: Alphabet>(
+        &self,
+        model: &GtrModel<A>,
+        aln: &Alignment,
+        n_samples: usize,
+        seed: u64,
+    ) -> Result<Vec<StochasticMap<A>>, AsrError>;
+}
+
+/// A stretch of a branch spent in one state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    /// The state index occupied.
+    pub state: usize,
+    /// How long (in branch-length units) the state was held.
+    pub duration: f64,
+}
+
+/// One sampled character history.
+///
+/// Branches are keyed by their child node. A branch's segments run from the
+/// parent end to the child end, and consecutive segments always differ in
+/// state, so every segment boundary is one substitution.
+pub struct StochasticMap<A> {
+    /// The sampled state of each node at each site.
+    pub node_states: HashMap<NodeID, Vec<usize>>,
+    /// The sampled path along the branch above each non-root node, per site.
+    pub edge_histories: HashMap<NodeID, Vec<Vec<Segment>>>,
+    /// The alphabet the states index into.
+    pub alphabet: std::marker::PhantomData<A>,
+}
+
+impl<A: Alphabet> StochasticMap<A> {
+    /// Time spent in each state along the branch above `node`, summed over
+    /// sites. `None` for the root or an unknown node.
+    pub fn edge_dwell_times(&self, node: NodeID) -> Option<Vec<f64>> {
+        let mut dwell = vec![0.0; A::N_STATES];
+        for segment in self.edge_histories.get(&node)?.iter().flatten() {
+            dwell[segment.state] += segment.duration;
+        }
+        Some(dwell)
+    }
+
+    /// Number of `i -> j` substitutions along the branch above `node`, summed
+    /// over sites. `None` for the root or an unknown node.
+    pub fn edge_transition_counts(&self, node: NodeID) -> Option<Vec<Vec<usize>>> {
+        let mut counts = vec![vec![0; A::N_STATES]; A::N_STATES];
+        for path in self.edge_histories.get(&node)? {
+            for pair in path.windows(2) {
+                counts[pair[0].state][pair[1].state] += 1;
+            }
+        }
+        Some(counts)
+    }
+
+    /// Time spent in each state across the whole tree, summed over sites.
+    pub fn dwell_times(&self) -> Vec<f64> {
+        let mut dwell = vec![0.0; A::N_STATES];
+        for node in self.edge_histories.keys() {
+            let edge = self
+                .edge_dwell_times(*node)
+                .expect("invariant: iterating over recorded edges");
+            for (total, d) in dwell.iter_mut().zip(edge) {
+                *total += d;
+            }
+        }
+        dwell
+    }
+
+    /// Number of `i -> j` substitutions across the whole tree, summed over
+    /// sites.
+    pub fn transition_counts(&self) -> Vec<Vec<usize>> {
+        let mut counts = vec![vec![0; A::N_STATES]; A::N_STATES];
+        for node in self.edge_histories.keys() {
+            let edge = self
+                .edge_transition_counts(*node)
+                .expect("invariant: iterating over recorded edges");
+            for (row, edge_row) in counts.iter_mut().zip(edge) {
+                for (c, e) in row.iter_mut().zip(edge_row) {
+                    *c += e;
+                }
+            }
+        }
+        counts
+    }
+}
+
+/// Posterior means over a set of [`StochasticMap`]s.
+#[derive(Clone, Debug)]
+pub struct SimmapSummary {
+    /// Number of maps summarized.
+    pub n_samples: usize,
+    /// Mean time spent in each state across the tree.
+    pub dwell_times: Vec<f64>,
+    /// Mean number of `i -> j` substitutions across the tree.
+    pub transitions: Vec<Vec<f64>>,
+    /// Mean time spent in each state on the branch above each node.
+    pub edge_dwell_times: HashMap<NodeID, Vec<f64>>,
+    /// Mean number of `i -> j` substitutions on the branch above each node.
+    pub edge_transitions: HashMap<NodeID, Vec<Vec<f64>>>,
+}
+
+impl SimmapSummary {
+    /// Averages dwell times and transition counts over `maps`, per branch and
+    /// across the tree. An empty slice gives all-zero means.
+    pub fn new<A: Alphabet>(maps: &[StochasticMap<A>]) -> Self {
+        let n = A::N_STATES;
+        let mut summary = SimmapSummary {
+            n_samples: maps.len(),
+            dwell_times: vec![0.0; n],
+            transitions: vec![vec![0.0; n]; n],
+            edge_dwell_times: HashMap::new(),
+            edge_transitions: HashMap::new(),
+        };
+        if maps.is_empty() {
+            return summary;
+        }
+        let scale = 1.0 / maps.len() as f64;
+
+        for map in maps {
+            for &node in map.edge_histories.keys() {
+                let dwell = map
+                    .edge_dwell_times(node)
+                    .expect("invariant: iterating over recorded edges");
+                let counts = map
+                    .edge_transition_counts(node)
+                    .expect("invariant: iterating over recorded edges");
+
+                let mean_dwell = summary
+                    .edge_dwell_times
+                    .entry(node)
+                    .or_insert_with(|| vec![0.0; n]);
+                for (m, d) in mean_dwell.iter_mut().zip(&dwell) {
+                    *m += d * scale;
+                }
+                for (m, d) in summary.dwell_times.iter_mut().zip(&dwell) {
+                    *m += d * scale;
+                }
+
+                let mean_counts = summary
+                    .edge_transitions
+                    .entry(node)
+                    .or_insert_with(|| vec![vec![0.0; n]; n]);
+                for i in 0..n {
+                    for j in 0..n {
+                        let c = counts[i][j] as f64 * scale;
+                        mean_counts[i][j] += c;
+                        summary.transitions[i][j] += c;
+                    }
+                }
+            }
+        }
+        summary
+    }
+}
+
+/// Draws an index with probability proportional to `weights`.
+#[cfg(feature = "simple_rooted_tree")]
+fn sample_index<R: Rng>(weights: &[f64], rng: &mut R) -> Result<usize, AsrError> {
+    let total: f64 = weights.iter().sum();
+    if !(total > 0.0 && total.is_finite()) {
+        return Err(AsrError::NumericalInstability);
+    }
+    let mut u = rng.gen::<f64>() * total;
+    for (i, &w) in weights.iter().enumerate() {
+        if u < w {
+            return Ok(i);
+        }
+        u -= w;
+    }
+    // Round-off can leave `u` just past the last bucket; take the last state
+    // that had any mass.
+    Ok(weights
+        .iter()
+        .rposition(|&w| w > 0.0)
+        .expect("invariant: total is positive"))
+}
+
+/// Hard cap on the number of uniformized jumps drawn for one branch. Only
+/// reachable when `mu * t` is enormous, where a path this long is already a
+/// fine approximation.
+#[cfg(feature = "simple_rooted_tree")]
+const MAX_JUMPS: usize = 10_000;
+
+/// Samples a path from `start` to `end` over time `t` under generator `q`,
+/// given `p_end = P(t)[start, end]`, by uniformization.
+///
+/// With `mu = max_i -Q_ii` and `R = I + Q / mu`, the process is a Markov chain
+/// with transition matrix `R` whose jumps (some of them virtual, `i -> i`)
+/// arrive as a Poisson process of rate `mu`. The jump count is drawn from its
+/// distribution given both endpoints, jump times uniformly, and the states one
+/// at a time given the end state. Virtual jumps are then dropped.
+#[cfg(feature = "simple_rooted_tree")]
+fn sample_path<R: Rng>(
+    q: &DMatrix<f64>,
+    start: usize,
+    end: usize,
+    t: f64,
+    p_end: f64,
+    rng: &mut R,
+) -> Vec<Segment> {
+    let n_states = q.nrows();
+    let mu = (0..n_states).map(|i| -q[(i, i)]).fold(0.0, f64::max);
+    if mu * t <= 0.0 {
+        return vec![Segment {
+            state: start,
+            duration: t,
+        }];
+    }
+    let r = DMatrix::identity(n_states, n_states) + q / mu;
+
+    // Number of jumps, with P(n | start, end) = Pois(n; mu t) R^n[start, end] / p_end.
+    // The Poisson mass is carried in log space so large `mu t` doesn't
+    // underflow its first terms.
+    let target = rng.gen::<f64>() * p_end;
+    let mut powers = vec![DMatrix::identity(n_states, n_states)];
+    let mut log_pois = -mu * t;
+    let mut cumulative = log_pois.exp() * powers[0][(start, end)];
+    while cumulative < target && powers.len() <= MAX_JUMPS {
+        let n = powers.len();
+        log_pois += (mu * t).ln() - (n as f64).ln();
+        let next = &powers[n - 1] * &r;
+        cumulative += log_pois.exp() * next[(start, end)];
+        powers.push(next);
+    }
+    let n_jumps = powers.len() - 1;
+
+    let mut times: Vec<f64> = (0..n_jumps).map(|_| rng.gen::<f64>() * t).collect();
+    times.sort_by(f64::total_cmp);
+
+    let mut segments = Vec::new();
+    let mut state = start;
+    let mut entered = 0.0;
+    for (k, &time) in times.iter().enumerate() {
+        let remaining = &powers[n_jumps - k - 1];
+        let weights: Vec<f64> = (0..n_states)
+            .map(|x| r[(state, x)] * remaining[(x, end)])
+            .collect();
+        // Weights can only all vanish through round-off; stay put if they do.
+        let next = sample_index(&weights, rng).unwrap_or(state);
+        if next != state {
+            segments.push(Segment {
+                state,
+                duration: time - entered,
+            });
+            state = next;
+            entered = time;
+        }
+    }
+    segments.push(Segment {
+        state,
+        duration: t - entered,
+    });
+    segments
+}
+
+/// Draws `n_samples` stochastic character maps of `aln` on `tree` under
+/// `model`, from a generator seeded with `seed`.
+///
+/// Node states are drawn jointly from their posterior given the tip data, and
+/// paths along each branch given the states at both ends. Under `+G`/`+I` a
+/// rate category is drawn per site first, from its posterior; invariant sites
+/// (rate zero) never change state. Branches with no length are treated as
+/// length zero, as in the pruning itself.
+///
+/// # Errors
+///
+/// Returns [`AsrError::AlphabetMismatch`] if an alignment taxon is not in the
+/// tree or a character is not in `A`, and [`AsrError::NumericalInstability`]
+/// if the data have zero likelihood under the model.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_stochastic_maps<A>(
+    tree: &PhyloTree,
+    model: &GtrModel<A>,
+    aln: &Alignment,
+    n_samples: usize,
+    seed: u64,
+) -> Result<Vec<StochasticMap<A>>, AsrError>
+where
+    A: Alphabet,
+{
+    let comp = aln.compress_columns();
+    let root = tree.get_root_id();
+    let n_states = A::N_STATES;
+    let pi = model.equilibrium();
+    let categories = model.categories();
+    let q = model.generator();
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut leaf_id_map = Vec::with_capacity(comp.leaf_order.len());
+    for name in &comp.leaf_order {
+        let node_id = tree.get_taxa_node_id(name).ok_or_else(|| {
+            AsrError::AlphabetMismatch(format!("Taxon {} in alignment not found in tree", name))
+        })?;
+        leaf_id_map.push(node_id);
+    }
+
+    let postord = tree
+        .postord_ids(root)
+        .expect("invariant: the root id always names a node")
+        .collect::<Vec<_>>();
+    let preord = tree
+        .preord_ids(root)
+        .expect("invariant: the root id always names a node")
+        .collect::<Vec<_>>();
+    let edge_length = |parent: NodeID, child: NodeID| -> f64 {
+        tree.get_edge_weight(parent, child)
+            .and_then(NumCast::from)
+            .unwrap_or(0.0)
+    };
+
+    // Everything that depends only on the category and the branch is built
+    // once here rather than per site, sample and branch: each category's
+    // scaled generator, and P(t) for every (category, branch) pair, indexed
+    // by the child's position in `preord` (the root's slot is unused).
+    let q_cats: Vec<DMatrix<f64>> = categories.iter().map(|c| &q * c.rate).collect();
+    let transitions: Vec<Vec<DMatrix<f64>>> = (0..categories.len())
+        .map(|cat_idx| {
+            preord
+                .iter()
+                .map(|&v| match tree.get_node_parent_id(v) {
+                    Some(parent) => model.category_transition(cat_idx, edge_length(parent, v)),
+                    None => DMatrix::identity(n_states, n_states),
+                })
+                .collect()
+        })
+        .collect();
+    // The sites of each pattern, in alignment order.
+    let mut pattern_sites = vec![Vec::new(); comp.patterns.len()];
+    for (site, &p_idx) in comp.site_to_pattern.iter().enumerate() {
+        pattern_sites[p_idx].push(site);
+    }
+
+    let mut maps: Vec<StochasticMap<A>> = (0..n_samples)
+        .map(|_| StochasticMap {
+            node_states: preord.iter().map(|&v| (v, vec![0; aln.width()])).collect(),
+            edge_histories: preord
+                .iter()
+                .filter(|&&v| v != root)
+                .map(|&v| (v, vec![Vec::new(); aln.width()]))
+                .collect(),
+            alphabet: std::marker::PhantomData,
+        })
+        .collect();
+
+    for (p_idx, pattern) in comp.patterns.iter().enumerate() {
+        // The up pass depends only on the pattern, so every site sharing it
+        // reuses the same conditional likelihoods.
+        let mut cat_profiles = Vec::with_capacity(categories.len());
+        let mut cat_log_likelihoods = Vec::with_capacity(categories.len());
+        for (cat_idx, category) in categories.iter().enumerate() {
+            let (profiles, cat_ll) = prune_pattern_category(
+                tree,
+                model,
+                cat_idx,
+                category.weight,
+                pattern,
+                &leaf_id_map,
+                &postord,
+                pi,
+                n_states,
+            )?;
+            cat_profiles.push(profiles);
+            cat_log_likelihoods.push(cat_ll);
+        }
+        let site_ll = log_sum_exp(&cat_log_likelihoods);
+        if !site_ll.is_finite() {
+            return Err(AsrError::NumericalInstability);
+        }
+        let cat_posterior: Vec<f64> = cat_log_likelihoods
+            .iter()
+            .map(|&ll| (ll - site_ll).exp())
+            .collect();
+
+        for &site in &pattern_sites[p_idx] {
+            for map in maps.iter_mut() {
+                let cat_idx = sample_index(&cat_posterior, &mut rng)?;
+                let profiles = &cat_profiles[cat_idx];
+
+                for (pos, &v) in preord.iter().enumerate() {
+                    let prof_v = profiles.get(&v).ok_or(AsrError::NumericalInstability)?;
+                    if v == root {
+                        let weights: Vec<f64> =
+                            (0..n_states).map(|i| pi[i] * prof_v.values[i]).collect();
+                        map.node_states
+                            .get_mut(&v)
+                            .expect("invariant: seeded above")[site] =
+                            sample_index(&weights, &mut rng)?;
+                        continue;
+                    }
+                    let parent = tree
+                        .get_node_parent_id(v)
+                        .expect("invariant: only the root has no parent");
+                    let from = map.node_states[&parent][site];
+                    let t = edge_length(parent, v);
+                    let p_t = &transitions[cat_idx][pos];
+                    let weights: Vec<f64> = (0..n_states)
+                        .map(|j| p_t[(from, j)] * prof_v.values[j])
+                        .collect();
+                    let to = sample_index(&weights, &mut rng)?;
+                    map.node_states
+                        .get_mut(&v)
+                        .expect("invariant: seeded above")[site] = to;
+
+                    let path =
+                        sample_path(&q_cats[cat_idx], from, to, t, p_t[(from, to)], &mut rng);
+                    map.edge_histories
+                        .get_mut(&v)
+                        .expect("invariant: seeded above")[site] = path;
+                }
+            }
+        }
+    }
+
+    Ok(maps)
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::alphabet::Nucleotide;
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick("((A:0.3,B:0.2):0.15,(C:0.4,D:0.1):0.25);".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_paths_match_node_states_and_branch_lengths() {
+        let tree = tree();
+        let aln = Alignment::from_fasta_bytes(b">A\nAC-\n>B\nAGT\n>C\nCGT\n>D\nTGA\n").unwrap();
+        let model = GtrModel::<Nucleotide>::hky85([0.3, 0.2, 0.2, 0.3], 2.0)
+            .unwrap()
+            .with_gamma(0.5, 4)
+            .unwrap();
+        let maps = tree
+            .stochastic_maps::<Nucleotide>(&model, &aln, 25, 11)
+            .unwrap();
+        assert_eq!(maps.len(), 25);
+
+        for map in &maps {
+            for (&child, paths) in &map.edge_histories {
+                let parent = tree.get_node_parent_id(child).unwrap();
+                let t = tree.get_edge_weight(parent, child).unwrap() as f64;
+                for (site, path) in paths.iter().enumerate() {
+                    assert_eq!(path[0].state, map.node_states[&parent][site]);
+                    assert_eq!(path.last().unwrap().state, map.node_states[&child][site]);
+                    let length: f64 = path.iter().map(|s| s.duration).sum();
+                    assert!((length - t).abs() < 1e-9);
+                    assert!(path.windows(2).all(|w| w[0].state != w[1].state));
+                }
+            }
+            // Observed (unambiguous) tips keep their states.
+            let a = tree.get_taxa_node_id(&"A".to_string()).unwrap();
+            assert_eq!(map.node_states[&a][0], 0);
+            assert_eq!(map.node_states[&a][1], 1);
+        }
+    }
+
+    #[test]
+    fn test_summary_requires_changes_between_differing_tips() {
+        let tree = PhyloTree::from_newick("(A:0.5,B:0.5);".as_bytes()).unwrap();
+        let aln = Alignment::from_fasta_bytes(b">A\nA\n>B\nG\n").unwrap();
+        let model = GtrModel::<Nucleotide>::jukes_cantor().unwrap();
+        let maps = tree
+            .stochastic_maps::<Nucleotide>(&model, &aln, 200, 3)
+            .unwrap();
+        assert!(maps
+            .iter()
+            .all(|m| m.transition_counts().iter().flatten().sum::<usize>() >= 1));
+
+        let summary = SimmapSummary::new(&maps);
+        assert_eq!(summary.n_samples, 200);
+        assert!((summary.dwell_times.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        for i in 0..4 {
+            assert_eq!(summary.transitions[i][i], 0.0);
+        }
+        assert!(summary.transitions[0][2] + summary.transitions[2][0] > 0.0);
+    }
+
+    #[test]
+    fn test_same_seed_same_maps() {
+        let tree = tree();
+        let aln = Alignment::from_fasta_bytes(b">A\nAC\n>B\nAG\n>C\nCG\n>D\nTG\n").unwrap();
+        let model = GtrModel::<Nucleotide>::jukes_cantor().unwrap();
+        let first = tree
+            .stochastic_maps::<Nucleotide>(&model, &aln, 5, 42)
+            .unwrap();
+        let second = tree
+            .stochastic_maps::<Nucleotide>(&model, &aln, 5, 42)
+            .unwrap();
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.node_states, b.node_states);
+            assert_eq!(a.edge_histories, b.edge_histories);
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! SH-like aLRT and aBayes support for internal branches.
+//!
+//! An internal branch joins four subtrees, and the two nearest-neighbour
+//! interchanges around it are the only other ways to join them. Each branch
+//! is tested by scoring the tree and both alternatives with the per-pattern
+//! log-likelihoods behind
+//! [`compute_log_likelihood`](crate::tree::likelihood::compute_log_likelihood),
+//! the alternatives made with [`NNI`](crate::tree::ops::NNI). A branch at a
+//! root with two children is the two root edges together; its alternatives
+//! swap a subtree from each side of the root instead, since an interchange
+//! with the sibling would only move the root.
+//!
+//! By default the central branch is re-optimized in all three trees, by
+//! golden-section search on its log length, as PhyML does for the aLRT; the
+//! other branches keep their lengths. Each step scores the branch from the
+//! partials on either side of it, cached in a
+//! [`LikelihoodEngine`](crate::tree::likelihood::engine::LikelihoodEngine),
+//! rather than pruning the whole tree again. With `L0` the tree's log-likelihood and
+//! `L1 >= L2` the alternatives':
+//!
+//! * the **aLRT statistic** is `2 (L0 - L1)` (Anisimova & Gascuel 2006);
+//! * the **SH-like support** (Guindon et al. 2010) is the share of RELL
+//!   replicates, the patterns' log-likelihoods reweighted by bootstrap draws
+//!   of the sites, in which `L0 - L1` beats the gap between the two best
+//!   centred replicate log-likelihoods by more than 0.1, as in PhyML. A
+//!   branch whose alternative scores better gets 0. Every branch is tested
+//!   against the same replicates;
+//! * **aBayes** (Anisimova et al. 2011) is the posterior of the tree among the
+//!   three under equal priors, `e^L0 / (e^L0 + e^L1 + e^L2)`.
+//!
+//! [`BranchTest::annotate`](crate::tree::likelihood::support::BranchTest::annotate)
+//! writes both supports into node annotations as `[&SH-aLRT=..,aBayes=..]`,
+//! which the Newick writer emits after the node's label.
+
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use super::{log_sum_exp, LikelihoodEngine};
+use crate::alignment::{Alignment, CompressedColumns};
+use crate::alphabet::Alphabet;
+use crate::error::AsrError;
+use crate::models::GtrModel;
+use crate::node::NodeID;
+use crate::prelude::*;
+use crate::tree::bootstrap::Resampling;
+use crate::tree::PhyloTree;
+
+/// Margin, in log-likelihood units, by which a RELL replicate's centred gap
+/// must fall short of the observed one to count for the branch.
+const SH_EPSILON: f64 = 0.1;
+
+/// Golden-section steps when optimizing a central branch.
+const OPTIMIZE_STEPS: usize = 30;
+
+/// Bounds of a central branch length during optimization.
+const MIN_LENGTH: f64 = 1e-8;
+const MAX_LENGTH: f64 = 10.0;
+
+/// Support for the branch above an internal node, from the likelihoods of the
+/// tree and its two nearest-neighbour interchanges around the branch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlrtSupport {
+    /// The node below the branch.
+    pub node: NodeID,
+    /// Log-likelihood of the tree, then of each alternative.
+    pub log_likelihoods: [f64; 3],
+    /// `2 (L0 - max(L1, L2))`; negative when an alternative scores better.
+    pub statistic: f64,
+    /// SH-like support, a fraction of the RELL replicates.
+    pub sh_alrt: f64,
+    /// aBayes posterior probability of the branch.
+    pub abayes: f64,
+}
+
+/// Settings for SH-like aLRT and aBayes branch tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchTest {
+    replicates: usize,
+    seed: u64,
+    optimize: bool,
+}
+
+impl Default for BranchTest {
+    fn default() -> Self {
+        BranchTest::new()
+    }
+}
+
+/// The tree and its two alternatives around one branch, with the edges, by
+/// the node below each, that make up the branch.
+struct Configurations {
+    node: NodeID,
+    central: Vec<NodeID>,
+    trees: [PhyloTree; 3],
+}
+
+impl BranchTest {
+    /// 1000 RELL replicates, seeded with 0, with central branches optimized.
+    pub fn new() -> Self {
+        BranchTest {
+            replicates: 1000,
+            seed: 0,
+            optimize: true,
+        }
+    }
+
+    /// Sets the number of RELL replicates of the SH-like test.
+    pub fn with_replicates(mut self, replicates: usize) -> Self {
+        self.replicates = replicates;
+        self
+    }
+
+    /// Sets the seed of the RELL replicates.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Whether to re-optimize the central branch of each tree scored. Without
+    /// it the tree's branch lengths are used as they are.
+    pub fn with_branch_optimization(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Number of RELL replicates.
+    pub fn replicates(&self) -> usize {
+        self.replicates
+    }
+
+    /// The seed of the RELL replicates.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Whether central branches are re-optimized.
+    pub fn branch_optimization(&self) -> bool {
+        self.optimize
+    }
+
+    /// Tests every internal branch of `tree`, in preorder of the node below
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AsrError::NotBinary`] if a node other than the root has other
+    /// than two children, or the root other than two or three, and the errors
+    /// of [`compute_log_likelihood`].
+    pub fn support<A: Alphabet>(
+        &self,
+        tree: &PhyloTree,
+        model: &GtrModel<A>,
+        aln: &Alignment,
+    ) -> Result<Vec<AlrtSupport>, AsrError> {
+        let comp = aln.compress_columns();
+        let mut out = vec![];
+        for node in branches(tree)? {
+            let Some(configurations) = configurations(tree, node)? else {
+                continue;
+            };
+            out.push(self.test(&configurations, model, &comp)?);
+        }
+        Ok(out)
+    }
+
+    /// Tests every internal branch of `tree` as [`support`](Self::support)
+    /// does, and adds `SH-aLRT`, as a percentage, and `aBayes` to the
+    /// annotation of the node below each branch. An annotation already in
+    /// `[&...]` form keeps its other entries, while `SH-aLRT` and `aBayes`
+    /// entries from an earlier call are replaced; any other annotation is
+    /// followed by the new one.
+    ///
+    /// # Errors
+    ///
+    /// As for [`support`](Self::support).
+    pub fn annotate<A: Alphabet>(
+        &self,
+        tree: &mut PhyloTree,
+        model: &GtrModel<A>,
+        aln: &Alignment,
+    ) -> Result<Vec<AlrtSupport>, AsrError> {
+        let supports = self.support(tree, model, aln)?;
+        for support in &supports {
+            let entries = format!(
+                "SH-aLRT={:.1},aBayes={:.3}",
+                support.sh_alrt * 100.0,
+                support.abayes
+            );
+            let node = tree
+                .get_node_mut(support.node)
+                .expect("invariant: supports name nodes of the tree");
+            let annotation = match node.get_annotation() {
+                Some(old) if old.starts_with("[&") && old.ends_with(']') => {
+                    let kept = old[2..old.len() - 1]
+                        .split(',')
+                        .filter(|entry| {
+                            !entry.is_empty()
+                                && !entry.starts_with("SH-aLRT=")
+                                && !entry.starts_with("aBayes=")
+                        })
+                        .chain(std::iter::once(entries.as_str()))
+                        .collect::<Vec<_>>();
+                    format!("[&{}]", kept.join(","))
+                }
+                Some(old) => format!("{old}[&{entries}]"),
+                None => format!("[&{entries}]"),
+            };
+            node.set_annotation(Some(Arc::from(annotation)));
+        }
+        Ok(supports)
+    }
+
+    fn test<A: Alphabet>(
+        &self,
+        configurations: &Configurations,
+        model: &GtrModel<A>,
+        comp: &CompressedColumns,
+    ) -> Result<AlrtSupport, AsrError> {
+        let mut pattern_lls = Vec::with_capacity(3);
+        for tree in &configurations.trees {
+            pattern_lls.push(self.score(tree, &configurations.central, model, comp)?);
+        }
+        let total = |lls: &[f64], multiplicity: &[usize]| -> f64 {
+            lls.iter()
+                .zip(multiplicity)
+                .map(|(ll, &m)| m as f64 * ll)
+                .sum()
+        };
+        let log_likelihoods: [f64; 3] =
+            std::array::from_fn(|i| total(&pattern_lls[i], &comp.multiplicity));
+        let [l0, l1, l2] = log_likelihoods;
+        let delta = l0 - l1.max(l2);
+
+        let mut supported = 0;
+        if delta > 0.0 {
+            let mut rng = StdRng::seed_from_u64(self.seed);
+            for _ in 0..self.replicates {
+                let multiplicity = Resampling::Bootstrap.draw(comp, &mut rng);
+                let mut centred: [f64; 3] = std::array::from_fn(|i| {
+                    total(&pattern_lls[i], &multiplicity) - log_likelihoods[i]
+                });
+                centred.sort_by(|a, b| b.total_cmp(a));
+                if delta > centred[0] - centred[1] + SH_EPSILON {
+                    supported += 1;
+                }
+            }
+        }
+
+        Ok(AlrtSupport {
+            node: configurations.node,
+            log_likelihoods,
+            statistic: 2.0 * delta,
+            sh_alrt: match self.replicates {
+                0 => 0.0,
+                n => supported as f64 / n as f64,
+            },
+            abayes: (l0 - log_sum_exp(&log_likelihoods)).exp(),
+        })
+    }
+
+    /// Pattern log-likelihoods of `tree`, with its central branch optimized if
+    /// the test asks for it.
+    fn score<A: Alphabet>(
+        &self,
+        tree: &PhyloTree,
+        central: &[NodeID],
+        model: &GtrModel<A>,
+        comp: &CompressedColumns,
+    ) -> Result<Vec<f64>, AsrError> {
+        let mut engine = LikelihoodEngine::from_columns(tree.clone(), model, comp.clone())?;
+        if !self.optimize {
+            return Ok(engine.pattern_log_likelihoods());
+        }
+        let initial: f64 = central
+            .iter()
+            .filter_map(|&id| tree.get_node(id).and_then(|node| node.get_weight()))
+            .map(f64::from)
+            .sum();
+        // The whole length goes on the first edge; under a reversible model
+        // only the sum over the root matters.
+        for &id in &central[1..] {
+            engine
+                .set_branch_length(id, 0.0)
+                .expect("invariant: central edges lie below the root");
+        }
+        let mut evaluate = |length: f64| -> (f64, Vec<f64>) {
+            let lls = engine
+                .branch_pattern_log_likelihoods(central[0], length)
+                .expect("invariant: central edges lie below the root");
+            let total = lls
+                .iter()
+                .zip(&comp.multiplicity)
+                .map(|(ll, &m)| m as f64 * ll)
+                .sum();
+            (total, lls)
+        };
+
+        // Golden-section search on the log length, keeping the starting
+        // length if nothing found beats it.
+        let mut best = evaluate(initial.clamp(MIN_LENGTH, MAX_LENGTH));
+        let inv_phi = (5f64.sqrt() - 1.0) / 2.0;
+        let (mut a, mut b) = (MIN_LENGTH.ln(), MAX_LENGTH.ln());
+        let mut c = b - inv_phi * (b - a);
+        let mut d = a + inv_phi * (b - a);
+        let mut fc = evaluate(c.exp());
+        let mut fd = evaluate(d.exp());
+        for _ in 0..OPTIMIZE_STEPS {
+            if fc.0 > fd.0 {
+                b = d;
+                d = c;
+                fd = fc;
+                c = b - inv_phi * (b - a);
+                fc = evaluate(c.exp());
+            } else {
+                a = c;
+                c = d;
+                fc = fd;
+                d = a + inv_phi * (b - a);
+                fd = evaluate(d.exp());
+            }
+        }
+        for candidate in [fc, fd] {
+            if candidate.0 > best.0 {
+                best = candidate;
+            }
+        }
+        Ok(best.1)
+    }
+}
+
+/// Nodes below the internal branches of `tree`, in preorder, after checking
+/// that the tree is binary.
+fn branches(tree: &PhyloTree) -> Result<Vec<NodeID>, AsrError> {
+    let root = tree.get_root_id();
+    let mut out = vec![];
+    for id in tree
+        .preord_ids(root)
+        .expect("invariant: the root id always names a node")
+    {
+        let children = tree.get_node_children_ids(id).count();
+        let binary = match id == root {
+            true => children == 2 || children == 3,
+            false => children == 0 || children == 2,
+        };
+        if !binary {
+            return Err(AsrError::NotBinary(id));
+        }
+        if id != root && children > 0 {
+            out.push(id);
+        }
+    }
+    Ok(out)
+}
+
+/// The tree and its alternatives around the branch above `node`, or `None`
+/// if that branch is not internal once the root is set aside, or is the
+/// root branch already tested from the root's first child.
+fn configurations(tree: &PhyloTree, node: NodeID) -> Result<Option<Configurations>, AsrError> {
+    let root = tree.get_root_id();
+    let parent = tree
+        .get_node_parent_id(node)
+        .expect("invariant: branches excludes the root");
+    let root_children: Vec<NodeID> = tree.get_node_children_ids(root).collect();
+    if parent != root || root_children.len() == 3 {
+        let mut first = tree.clone();
+        let mut second = tree.clone();
+        first
+            .nni(node, false)
+            .map_err(|_| AsrError::NotBinary(node))?;
+        second
+            .nni(node, true)
+            .map_err(|_| AsrError::NotBinary(node))?;
+        return Ok(Some(Configurations {
+            node,
+            central: vec![node],
+            trees: [tree.clone(), first, second],
+        }));
+    }
+
+    let sibling = root_children[(root_children[0] == node) as usize];
+    if node != root_children[0] || tree.is_leaf(sibling) {
+        return Ok(None);
+    }
+    let child = tree
+        .get_node_children_ids(node)
+        .next()
+        .expect("invariant: branches holds internal nodes");
+    let mut trees = [tree.clone(), tree.clone(), tree.clone()];
+    for (alt, other) in trees[1..]
+        .iter_mut()
+        .zip(tree.get_node_children_ids(sibling))
+    {
+        swap_subtrees(alt, child, other);
+    }
+    Ok(Some(Configurations {
+        node,
+        central: vec![node, sibling],
+        trees,
+    }))
+}
+
+/// Exchanges the parents of `a` and `b`, each taking its branch along.
+fn swap_subtrees(tree: &mut PhyloTree, a: NodeID, b: NodeID) {
+    let parent_a = tree
+        .get_node_parent_id(a)
+        .expect("invariant: swapped subtrees hang below the root");
+    let parent_b = tree
+        .get_node_parent_id(b)
+        .expect("invariant: swapped subtrees hang below the root");
+    tree.delete_edge(parent_a, a);
+    tree.delete_edge(parent_b, b);
+    tree.set_child(parent_a, b);
+    tree.set_child(parent_b, a);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alphabet::Nucleotide;
+
+    /// Ten sites group `a` with `b` and `d` with `e`; two group `b` with `c`.
+    fn alignment() -> Alignment {
+        Alignment::from_fasta(concat!(
+            ">a\nAAAAAAAAAACCCCCCCCCCGA\n",
+            ">b\nAAAAAAAAAACCCCCCCCCCTT\n",
+            ">c\nGGGGGGGGGGCCCCCCCCCCTT\n",
+            ">d\nGGGGGGGGGGTTTTTTTTTTGA\n",
+            ">e\nGGGGGGGGGGTTTTTTTTTTGA\n",
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn supported_branches_beat_their_interchanges() {
+        let model = GtrModel::<Nucleotide>::jukes_cantor().unwrap();
+        let aln = alignment();
+        let mut tree =
+            PhyloTree::from_newick(b"((a:0.1,b:0.1):0.2,(c:0.1,(d:0.1,e:0.1):0.2):0.1);").unwrap();
+        let test = BranchTest::new().with_replicates(200).with_seed(3);
+        let supports = test.annotate(&mut tree, &model, &aln).unwrap();
+
+        // The root branch, tested from (a,b), and the branch above (d,e).
+        assert_eq!(supports.len(), 2);
+        for support in &supports {
+            let [l0, l1, l2] = support.log_likelihoods;
+            assert!(l0 > l1 && l0 > l2);
+            assert!(support.statistic > 0.0);
+            assert!(support.sh_alrt > 0.9 && support.sh_alrt <= 1.0);
+            assert!(support.abayes > 0.9 && support.abayes <= 1.0);
+        }
+        // Seeded, so the same test gives the same numbers.
+        assert_eq!(test.support(&tree, &model, &aln).unwrap(), supports);
+
+        let newick = tree.to_newick().to_string();
+        assert!(newick.contains("(d:0.1,e:0.1)[&SH-aLRT="));
+        assert!(newick.contains(",aBayes="));
+
+        // Annotating again replaces the entries and keeps any others.
+        let mut tree = PhyloTree::from_newick(
+            b"((a:0.1,b:0.1):0.2,(c:0.1,(d:0.1,e:0.1)[&rate=1.5]:0.2):0.1);",
+        )
+        .unwrap();
+        test.annotate(&mut tree, &model, &aln).unwrap();
+        test.annotate(&mut tree, &model, &aln).unwrap();
+        let newick = tree.to_newick().to_string();
+        assert_eq!(newick.matches("SH-aLRT=").count(), 2);
+        assert_eq!(newick.matches("aBayes=").count(), 2);
+        assert!(newick.contains("(d:0.1,e:0.1)[&rate=1.5,SH-aLRT="));
+
+        // A wrong tree: the branch above (b,c) loses to an interchange.
+        let wrong =
+            PhyloTree::from_newick(b"(a:0.1,(b:0.1,c:0.1):0.2,(d:0.1,e:0.1):0.2);").unwrap();
+        let supports = BranchTest::new()
+            .with_replicates(50)
+            .with_branch_optimization(false)
+            .support(&wrong, &model, &aln)
+            .unwrap();
+        let bc = &supports[0];
+        assert!(bc.statistic < 0.0);
+        assert_eq!(bc.sh_alrt, 0.0);
+        assert!(bc.abayes < 0.5);
+    }
+
+    #[test]
+    fn polytomies_are_rejected() {
+        let model = GtrModel::<Nucleotide>::jukes_cantor().unwrap();
+        let tree = PhyloTree::from_newick(b"((a,b,c),(d,e));").unwrap();
+        let err = BranchTest::new().support(&tree, &model, &alignment());
+        assert!(matches!(err, Err(AsrError::NotBinary(_))));
+    }
+}
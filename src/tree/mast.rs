@@ -0,0 +1,510 @@
+//! Maximum agreement subtrees, and the rogue taxa that blur a consensus.
+//!
+//! A maximum agreement subtree (MAST), or largest common pruned tree, of
+//! rooted trees on one taxon set is a largest set of taxa on which they all
+//! induce the same subtree. [`AgreementSubtrees::mast`](crate::tree::mast::AgreementSubtrees::mast)
+//! finds one for any number of binary trees with the triplet dynamic program
+//! of Bryant (1997): an agreement subtree whose root separates taxa `a` and
+//! `b` is the best one holding `a` on taxa `c` with `ac|b` in every tree,
+//! joined to the best holding `b` on taxa `d` with `bd|a`. Triplets are read
+//! off per-tree [`LcaOracle`](crate::iter::lca::LcaOracle) depths, the pairs are
+//! visited upwards through the first tree, and the whole takes `O(k n³)` time
+//! and `O(k n²)` space for `k` trees on `n` taxa. The induced tree is
+//! contracted out of the first tree.
+//!
+//! Rogue taxa are those whose unstable placement across a tree set hides
+//! splits its other taxa agree on. [`RogueSearch`](crate::tree::mast::RogueSearch)
+//! looks for them in the manner of RogueNaRok (Aberer, Krompass & Stamatakis
+//! 2013): it scores a consensus by its relative bipartition information, the
+//! summed support of its splits over the `n − 3` a fully resolved tree on all
+//! `n` taxa has, and greedily drops the set of up to a few taxa that raises
+//! the score most, for as long as any does. Pruning a taxon merges the splits
+//! that differed only in it, so a split that wandered with a rogue regains
+//! the support it had lost.
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::prelude::*;
+
+/// A maximum agreement subtree, as found by [`AgreementSubtrees::mast`].
+#[derive(Debug, Clone)]
+pub struct AgreementSubtree<T> {
+    taxa: Vec<String>,
+    tree: T,
+}
+
+impl<T> AgreementSubtree<T> {
+    /// Taxa of the subtree, sorted.
+    pub fn taxa(&self) -> &[String] {
+        &self.taxa
+    }
+
+    /// The subtree, as induced in the first tree.
+    pub fn tree(&self) -> &T {
+        &self.tree
+    }
+
+    /// Consumes the result, returning the induced subtree.
+    pub fn into_tree(self) -> T {
+        self.tree
+    }
+}
+
+/// A trait describing maximum agreement subtrees of rooted trees
+pub trait AgreementSubtrees: ContractTree + RootedMetaTree
+where
+    <Self as RootedTree>::Node: RootedMetaNode,
+{
+    /// Returns a maximum agreement subtree of self and `others`, with the
+    /// subtree self induces on its taxa.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different leaf taxa;
+    /// [`TreeError::NotBinary`] if a node has more than two children.
+    fn mast(&self, others: &[&Self]) -> Result<AgreementSubtree<Self>, TreeError> {
+        let taxon = |tree: &Self, leaf| {
+            tree.get_node_taxa(leaf)
+                .expect("invariant: leaves carry a taxon")
+                .to_string()
+        };
+        for tree in std::iter::once(self).chain(others.iter().copied()) {
+            if let Some(id) = tree
+                .get_node_ids()
+                .find(|&id| tree.get_node_children_ids(id).count() > 2)
+            {
+                return Err(TreeError::NotBinary(id.into()));
+            }
+        }
+
+        // Leaves of self in preorder, so every cluster is a range of them,
+        // and the binary nodes in reverse preorder, children first.
+        let mut leaves = vec![];
+        let mut ranges: HashMap<TreeNodeID<Self>, (usize, usize)> = HashMap::default();
+        let mut order = vec![];
+        let mut stack = vec![(self.get_root_id(), false)];
+        while let Some((id, done)) = stack.pop() {
+            if done {
+                let children = self.get_node_children_ids(id).collect_vec();
+                let range = children.iter().fold((usize::MAX, 0), |acc, c| {
+                    (acc.0.min(ranges[c].0), acc.1.max(ranges[c].1))
+                });
+                ranges.insert(id, range);
+                if children.len() == 2 {
+                    order.push((ranges[&children[0]], ranges[&children[1]]));
+                }
+                continue;
+            }
+            if self.is_leaf(id) {
+                ranges.insert(id, (leaves.len(), leaves.len() + 1));
+                leaves.push(id);
+                continue;
+            }
+            stack.push((id, true));
+            stack.extend(self.get_node_children_ids(id).map(|c| (c, false)));
+        }
+        let n = leaves.len();
+        let names = leaves.iter().map(|&l| taxon(self, l)).collect_vec();
+        let position: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.as_str(), i))
+            .collect();
+
+        // Depth of the LCA of every pair of taxa, in every other tree.
+        let mut depths = Vec::with_capacity(others.len());
+        for tree in others {
+            let mut ids = vec![None; n];
+            for leaf in tree.get_leaf_ids() {
+                let i = *position
+                    .get(taxon(tree, leaf).as_str())
+                    .ok_or(TreeError::TaxaSetMismatch)?;
+                ids[i] = Some(leaf);
+            }
+            let ids = ids
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .ok_or(TreeError::TaxaSetMismatch)?;
+            if tree.get_leaf_ids().count() != n {
+                return Err(TreeError::TaxaSetMismatch);
+            }
+            let oracle = tree.lca();
+            let mut depth = vec![0u32; n * n];
+            for a in 0..n {
+                for b in a + 1..n {
+                    let d = oracle.get_node_depth(oracle.get_lca_id(&[ids[a], ids[b]])) as u32;
+                    depth[a * n + b] = d;
+                    depth[b * n + a] = d;
+                }
+            }
+            depths.push(depth);
+        }
+
+        // `size[a n + b]` is the largest agreement subtree whose root
+        // separates `a` and `b`; `pick[a n + b]` the partner `c` of `a` at the
+        // root of its side, if that side is more than `a`.
+        let mut size = vec![0u32; n * n];
+        let mut pick = vec![u32::MAX; n * n];
+        let side = |size: &[u32], a: usize, b: usize, range: (usize, usize)| {
+            (range.0..range.1)
+                .filter(|&c| c != a)
+                .filter(|&c| depths.iter().all(|d| d[a * n + c] > d[a * n + b]))
+                .map(|c| (size[a * n + c], c))
+                .max()
+                .map_or((1, u32::MAX), |(s, c)| (s, c as u32))
+        };
+        let mut best = (n.min(1) as u32, 0, 0);
+        for &(left, right) in &order {
+            for a in left.0..left.1 {
+                for b in right.0..right.1 {
+                    let (s, c) = side(&size, a, b, left);
+                    let (t, d) = side(&size, b, a, right);
+                    size[a * n + b] = s + t;
+                    size[b * n + a] = s + t;
+                    pick[a * n + b] = c;
+                    pick[b * n + a] = d;
+                    best = best.max((s + t, a, b));
+                }
+            }
+        }
+
+        // Unwind the picks into the taxa.
+        let mut taxa = vec![];
+        let mut pending = match best.0 {
+            0 => vec![],
+            1 => {
+                taxa.push(0);
+                vec![]
+            }
+            _ => vec![(best.1, best.2), (best.2, best.1)],
+        };
+        while let Some((a, b)) = pending.pop() {
+            match pick[a * n + b] {
+                u32::MAX => taxa.push(a),
+                c => pending.extend([(a, c as usize), (c as usize, a)]),
+            }
+        }
+        let ids = taxa.iter().map(|&t| leaves[t]).collect_vec();
+        let tree = self.contract_tree_with_oracle(&ids, &self.lca())?;
+        let taxa = taxa
+            .into_iter()
+            .map(|t| names[t].clone())
+            .sorted()
+            .collect();
+        Ok(AgreementSubtree { taxa, tree })
+    }
+}
+
+/// Taxa dropped by one step of a [`RogueSearch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RogueDrop {
+    taxa: Vec<String>,
+    score: f64,
+}
+
+impl RogueDrop {
+    /// The taxa dropped together, sorted.
+    pub fn taxa(&self) -> &[String] {
+        &self.taxa
+    }
+
+    /// Relative bipartition information of the consensus once they are gone.
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// The rogue taxa a [`RogueSearch`] found, in the order it dropped them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RogueTaxa {
+    initial: f64,
+    drops: Vec<RogueDrop>,
+}
+
+impl RogueTaxa {
+    /// Relative bipartition information of the consensus of all taxa.
+    pub fn initial_score(&self) -> f64 {
+        self.initial
+    }
+
+    /// Relative bipartition information once every rogue is dropped.
+    pub fn score(&self) -> f64 {
+        self.drops.last().map_or(self.initial, |d| d.score)
+    }
+
+    /// Each step of the search; every one raises the score.
+    pub fn drops(&self) -> &[RogueDrop] {
+        &self.drops
+    }
+
+    /// Every rogue taxon, in the order dropped.
+    pub fn taxa(&self) -> impl Iterator<Item = &str> {
+        self.drops
+            .iter()
+            .flat_map(|d| d.taxa.iter().map(String::as_str))
+    }
+}
+
+/// Greedy search for rogue taxa in a [`TreeSet`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RogueSearch {
+    threshold: f64,
+    max_dropset: usize,
+}
+
+impl Default for RogueSearch {
+    fn default() -> Self {
+        RogueSearch::new()
+    }
+}
+
+impl RogueSearch {
+    /// A search against the majority-rule consensus that drops one taxon at a
+    /// time.
+    pub fn new() -> Self {
+        RogueSearch {
+            threshold: 0.5,
+            max_dropset: 1,
+        }
+    }
+
+    /// Counts a split in the consensus when more than this fraction of the
+    /// trees hold it.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `threshold` is in `[0.5, 1)`, where the splits kept are
+    /// always compatible.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        assert!(
+            (0.5..1.0).contains(&threshold),
+            "the consensus threshold must be in [0.5, 1)"
+        );
+        self.threshold = threshold;
+        self
+    }
+
+    /// Tries every set of up to `max_dropset` taxa at each step, rather than
+    /// single taxa, to find rogues that only move together. The cost grows
+    /// as the number of taxa to this power.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_dropset` is zero.
+    pub fn with_max_dropset(mut self, max_dropset: usize) -> Self {
+        assert!(max_dropset > 0, "a dropset holds at least one taxon");
+        self.max_dropset = max_dropset;
+        self
+    }
+
+    /// Runs the search over the trees of `set`.
+    pub fn search(&self, set: &TreeSet) -> RogueTaxa {
+        let n = set.taxa().len();
+        let words = n.div_ceil(64);
+        let splits = set
+            .split_postings()
+            .map(|(side, trees)| (side.words().to_vec(), trees))
+            .collect_vec();
+        let mut kept = vec![0u64; words];
+        for t in 0..n {
+            kept[t / 64] |= 1 << (t % 64);
+        }
+        let score = |kept: &[u64]| self.score(set, &splits, kept);
+        let initial = score(&kept);
+        let (mut current, mut drops) = (initial, vec![]);
+        loop {
+            let remaining = (0..n)
+                .filter(|&t| kept[t / 64] >> (t % 64) & 1 == 1)
+                .collect_vec();
+            let best = (1..=self.max_dropset.min(remaining.len().saturating_sub(4)))
+                .flat_map(|k| remaining.iter().copied().combinations(k))
+                .map(|dropset| {
+                    let mut next = kept.clone();
+                    for &t in &dropset {
+                        next[t / 64] &= !(1 << (t % 64));
+                    }
+                    (score(&next), next, dropset)
+                })
+                .fold(None, |best: Option<(f64, _, _)>, candidate| match best {
+                    Some(b) if b.0 >= candidate.0 => Some(b),
+                    _ => Some(candidate),
+                });
+            match best {
+                Some((score, next, dropset)) if score > current + SCORE_EPSILON => {
+                    current = score;
+                    kept = next;
+                    drops.push(RogueDrop {
+                        taxa: dropset.iter().map(|&t| set.taxa()[t].clone()).collect(),
+                        score,
+                    });
+                }
+                _ => break,
+            }
+        }
+        RogueTaxa { initial, drops }
+    }
+
+    /// Relative bipartition information of the consensus of `set` restricted
+    /// to the taxa in `kept`.
+    fn score(&self, set: &TreeSet, splits: &[SplitPostings<'_>], kept: &[u64]) -> f64 {
+        let remaining = kept.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+        let first = kept.iter().position(|&w| w != 0);
+        let (Some(first), true) = (first, set.taxa().len() > 3) else {
+            return 0.0;
+        };
+        let first_bit = kept[first] & kept[first].wrapping_neg();
+        let mut merged: HashMap<Vec<u64>, Vec<usize>> = HashMap::default();
+        for (s, (side, _)) in splits.iter().enumerate() {
+            let mut projected = side.iter().zip(kept).map(|(w, k)| w & k).collect_vec();
+            if projected[first] & first_bit != 0 {
+                for (w, k) in projected.iter_mut().zip(kept) {
+                    *w = !*w & k;
+                }
+            }
+            let len = projected
+                .iter()
+                .map(|w| w.count_ones() as usize)
+                .sum::<usize>();
+            if len > 1 && len + 1 < remaining {
+                merged.entry(projected).or_default().push(s);
+            }
+        }
+        let mut seen = vec![usize::MAX; set.len()];
+        let support = merged
+            .values()
+            .enumerate()
+            .map(|(g, group)| {
+                let holders = match group.as_slice() {
+                    &[s] => splits[s].1.len(),
+                    _ => group
+                        .iter()
+                        .flat_map(|&s| splits[s].1)
+                        .filter(|&&(tree, _)| {
+                            let fresh = seen[tree as usize] != g;
+                            seen[tree as usize] = g;
+                            fresh
+                        })
+                        .count(),
+                };
+                holders as f64 / set.len() as f64
+            })
+            .filter(|&support| support > self.threshold)
+            .sum::<f64>();
+        support / (set.taxa().len() - 3) as f64
+    }
+}
+
+/// A split side as words, with the trees holding it.
+type SplitPostings<'a> = (Vec<u64>, &'a [(u32, f64)]);
+
+/// Smallest score gain a rogue search counts as one.
+const SCORE_EPSILON: f64 = 1e-12;
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::tree::PhyloTree;
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+    use std::collections::BTreeSet;
+
+    fn tree(newick: &str) -> PhyloTree {
+        PhyloTree::from_newick(newick.as_bytes()).unwrap()
+    }
+
+    /// Clusters of `tree` restricted to `taxa`.
+    fn induced(tree: &PhyloTree, taxa: &[String]) -> BTreeSet<Vec<String>> {
+        tree.get_node_ids()
+            .map(|id| {
+                tree.get_cluster_ids(id)
+                    .unwrap()
+                    .map(|leaf| tree.get_node_taxa(leaf).unwrap().to_string())
+                    .filter(|t| taxa.contains(t))
+                    .sorted()
+                    .collect_vec()
+            })
+            .filter(|cluster| !cluster.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn test_small_mast() {
+        let t1 = tree("(((A,B),C),D);");
+        let mast = t1.mast(&[&t1.clone()]).unwrap();
+        assert_eq!(mast.taxa(), ["A", "B", "C", "D"]);
+        assert_eq!(induced(mast.tree(), mast.taxa()), induced(&t1, mast.taxa()));
+
+        let t2 = tree("(((A,C),B),D);");
+        let mast = t1.mast(&[&t2]).unwrap();
+        assert_eq!(mast.taxa().len(), 3);
+        assert!(mast.taxa().contains(&"D".to_string()));
+        assert_eq!(induced(mast.tree(), mast.taxa()), induced(&t2, mast.taxa()));
+
+        let t3 = tree("((A,B),(C,E));");
+        assert!(matches!(t1.mast(&[&t3]), Err(TreeError::TaxaSetMismatch)));
+        let t4 = tree("((A,B,C),D);");
+        assert!(matches!(t1.mast(&[&t4]), Err(TreeError::NotBinary(_))));
+    }
+
+    #[test]
+    fn test_mast_matches_brute_force() {
+        for _ in 0..20 {
+            let trees = (0..3).map(|_| PhyloTree::yule(7)).collect_vec();
+            let mast = trees[0].mast(&[&trees[1], &trees[2]]).unwrap();
+            let names = trees[0]
+                .get_leaf_ids()
+                .map(|id| trees[0].get_node_taxa(id).unwrap().to_string())
+                .collect_vec();
+            let agrees = |taxa: &[String]| {
+                trees
+                    .iter()
+                    .all(|t| induced(t, taxa) == induced(&trees[0], taxa))
+            };
+            let largest = (1..=names.len())
+                .rev()
+                .find(|&k| {
+                    names
+                        .iter()
+                        .cloned()
+                        .combinations(k)
+                        .any(|taxa| agrees(&taxa))
+                })
+                .unwrap();
+            assert_eq!(mast.taxa().len(), largest);
+            assert_eq!(mast.tree().get_leaf_ids().count(), largest);
+            assert!(agrees(mast.taxa()));
+            assert_eq!(
+                induced(mast.tree(), mast.taxa()),
+                induced(&trees[0], mast.taxa())
+            );
+        }
+    }
+
+    #[test]
+    fn test_rogue_search() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let core = "((((A,B),C),D),(((E,F),G),H));";
+        let taxa = ["A", "B", "C", "D", "E", "F", "G", "H"];
+        let trees = (0..40)
+            .map(|_| {
+                let host = taxa.choose(&mut rng).unwrap();
+                tree(&core.replace(host, &format!("({host},X)")))
+            })
+            .collect_vec();
+        let set = TreeSet::from_trees(&trees).unwrap();
+        let rogues = RogueSearch::new().search(&set);
+        assert_eq!(rogues.taxa().collect_vec(), ["X"]);
+        assert!(rogues.score() > rogues.initial_score());
+        // Without X the five splits of the core are in every tree.
+        assert!((rogues.score() - 5.0 / 6.0).abs() < 1e-9);
+
+        let steady = TreeSet::from_trees(&vec![tree(core); 5]).unwrap();
+        let rogues = RogueSearch::default().with_max_dropset(2).search(&steady);
+        assert!(rogues.drops().is_empty());
+        assert_eq!(rogues.score(), rogues.initial_score());
+    }
+}
@@ -0,0 +1,679 @@
+//! Labelled distance matrices and their file formats.
+//!
+//! [`LabelledMatrix`](crate::tree::matrix::LabelledMatrix) is a square matrix
+//! of `f64` whose rows and columns are named, typically by taxon. Unlike
+//! [`DistanceMatrix::matrix`](crate::tree::distances::DistanceMatrix::matrix),
+//! which is indexed by arena node id, it holds only the rows it was built with
+//! and looks them up by label.
+//! [`LeafDistances`](crate::tree::matrix::LeafDistances) builds one over the
+//! leaves of a tree, by branch length or by node count.
+//!
+//! Matrices read and write:
+//!
+//! * **PHYLIP**, square or lower-triangular
+//!   ([`PhylipLayout`](crate::tree::matrix::PhylipLayout)). Labels are padded
+//!   to the classic ten columns and followed by a space, so both strict and
+//!   relaxed readers accept them. Whitespace inside a label is written as `_`.
+//!   The reader takes relaxed names, detects the layout from the first row, and
+//!   allows rows to wrap across lines.
+//! * **CSV/TSV** with a header row of labels and one labelled row per taxon.
+//!   Fields are quoted as in RFC 4180 where needed.
+//! * A compact **binary** form: labels, then `f64`s in little-endian order.
+//!   Symmetric matrices with a zero diagonal store only the strict lower
+//!   triangle.
+
+use std::fmt::Write;
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
+
+use num::ToPrimitive;
+
+use crate::error::MatrixError;
+use crate::prelude::*;
+
+/// Magic bytes opening the binary format.
+const BINARY_MAGIC: &[u8; 4] = b"PDM1";
+/// Binary layout flag: every entry stored.
+const BINARY_FULL: u8 = 0;
+/// Binary layout flag: strict lower triangle stored.
+const BINARY_LOWER: u8 = 1;
+
+/// Row layout of a PHYLIP distance matrix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhylipLayout {
+    /// Every row holds all `n` entries.
+    #[default]
+    Square,
+    /// Row `i` holds the `i` entries left of the diagonal.
+    LowerTriangular,
+}
+
+/// A square matrix with labelled rows and columns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelledMatrix {
+    labels: Vec<String>,
+    index: HashMap<String, usize>,
+    /// Row-major entries.
+    values: Vec<f64>,
+}
+
+impl LabelledMatrix {
+    /// Builds a matrix from labels and rows, one row per label.
+    ///
+    /// # Errors
+    ///
+    /// [`MatrixError::RowCount`] if there is not one row per label,
+    /// [`MatrixError::RowLength`] if a row does not have one entry per label,
+    /// and [`MatrixError::DuplicateLabel`] if two labels are the same.
+    pub fn new(labels: Vec<String>, rows: Vec<Vec<f64>>) -> Result<Self, MatrixError> {
+        let n = labels.len();
+        if rows.len() != n {
+            return Err(MatrixError::RowCount {
+                expected: n,
+                actual: rows.len(),
+            });
+        }
+        let mut values = Vec::with_capacity(n * n);
+        for (row, entries) in rows.into_iter().enumerate() {
+            if entries.len() != n {
+                return Err(MatrixError::RowLength {
+                    row,
+                    expected: n,
+                    actual: entries.len(),
+                });
+            }
+            values.extend(entries);
+        }
+        Self::from_parts(labels, values)
+    }
+
+    /// Builds a matrix with entry `(i, j)` set to `f(i, j)`.
+    pub fn from_fn<F>(labels: Vec<String>, mut f: F) -> Result<Self, MatrixError>
+    where
+        F: FnMut(usize, usize) -> f64,
+    {
+        let n = labels.len();
+        let values = (0..n * n).map(|k| f(k / n, k % n)).collect();
+        Self::from_parts(labels, values)
+    }
+
+    fn from_parts(labels: Vec<String>, values: Vec<f64>) -> Result<Self, MatrixError> {
+        let mut index = HashMap::default();
+        for (i, label) in labels.iter().enumerate() {
+            if index.insert(label.clone(), i).is_some() {
+                return Err(MatrixError::DuplicateLabel(label.clone()));
+            }
+        }
+        Ok(LabelledMatrix {
+            labels,
+            index,
+            values,
+        })
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Returns true if the matrix has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Row labels, in row order.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Row index of `label`.
+    pub fn index_of(&self, label: &str) -> Option<usize> {
+        self.index.get(label).copied()
+    }
+
+    /// Entry `(i, j)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        assert!(i < self.len() && j < self.len(), "index out of bounds");
+        self.values[i * self.len() + j]
+    }
+
+    /// Entry between two labels.
+    pub fn distance(&self, a: &str, b: &str) -> Option<f64> {
+        Some(self.get(self.index_of(a)?, self.index_of(b)?))
+    }
+
+    /// Row of `label`.
+    pub fn row(&self, label: &str) -> Option<&[f64]> {
+        self.index_of(label).map(|i| self.row_at(i))
+    }
+
+    /// Row `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn row_at(&self, i: usize) -> &[f64] {
+        let n = self.len();
+        &self.values[i * n..(i + 1) * n]
+    }
+
+    /// Iterator over rows, in row order.
+    pub fn rows(&self) -> impl Iterator<Item = &[f64]> {
+        (0..self.len()).map(|i| self.row_at(i))
+    }
+
+    /// Returns true if the matrix is symmetric with a zero diagonal.
+    pub fn is_symmetric(&self) -> bool {
+        let n = self.len();
+        (0..n).all(|i| self.get(i, i) == 0.0 && (0..i).all(|j| self.get(i, j) == self.get(j, i)))
+    }
+
+    /// Encodes the matrix in PHYLIP format.
+    ///
+    /// The lower-triangular layout reads only the entries left of the
+    /// diagonal, so it suits symmetric matrices. PHYLIP names end at
+    /// whitespace, so whitespace inside a label is written as `_`, and such a
+    /// label reads back with underscores in its place.
+    pub fn to_phylip(&self, layout: PhylipLayout) -> String {
+        let mut out = format!("{}\n", self.len());
+        for (i, label) in self.labels.iter().enumerate() {
+            let width = match layout {
+                PhylipLayout::Square => self.len(),
+                PhylipLayout::LowerTriangular => i,
+            };
+            push_phylip_row(&mut out, label, &self.row_at(i)[..width]);
+        }
+        out
+    }
+
+    /// Parses a PHYLIP distance matrix, square or lower-triangular.
+    ///
+    /// The layout is read from the first row: a name on its own line starts a
+    /// lower-triangular matrix. A lower-triangular matrix is mirrored into a
+    /// symmetric one with a zero diagonal.
+    pub fn from_phylip(input: &str) -> Result<Self, MatrixError> {
+        let mut lines = input.lines().filter(|l| !l.trim().is_empty());
+        let n: usize = lines
+            .next()
+            .and_then(|l| l.split_whitespace().next())
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| MatrixError::InvalidHeader("expected a taxon count".to_string()))?;
+        let layout = match lines.clone().next().map(|l| l.split_whitespace().count()) {
+            Some(1) if n > 1 => PhylipLayout::LowerTriangular,
+            _ => PhylipLayout::Square,
+        };
+
+        let mut tokens = lines.flat_map(str::split_whitespace);
+        let mut labels = Vec::with_capacity(n);
+        let mut values = vec![0.0; n * n];
+        for row in 0..n {
+            labels.push(
+                tokens
+                    .next()
+                    .ok_or(MatrixError::Truncated(row))?
+                    .to_string(),
+            );
+            let width = match layout {
+                PhylipLayout::Square => n,
+                PhylipLayout::LowerTriangular => row,
+            };
+            for col in 0..width {
+                let text = tokens.next().ok_or(MatrixError::Truncated(row))?;
+                let value = parse_value(row, text)?;
+                values[row * n + col] = value;
+                if layout == PhylipLayout::LowerTriangular {
+                    values[col * n + row] = value;
+                }
+            }
+        }
+        if let Some(extra) = tokens.next() {
+            return Err(MatrixError::InvalidValue {
+                row: n,
+                text: extra.to_string(),
+            });
+        }
+        Self::from_parts(labels, values)
+    }
+
+    /// Encodes the matrix as delimiter-separated text: a header row of
+    /// labels after an empty corner cell, then one labelled row per taxon.
+    pub fn to_delimited(&self, delimiter: char) -> String {
+        let mut out = String::new();
+        for label in &self.labels {
+            out.push(delimiter);
+            out.push_str(&quote_field(label, delimiter));
+        }
+        out.push('\n');
+        for (i, label) in self.labels.iter().enumerate() {
+            out.push_str(&quote_field(label, delimiter));
+            for value in self.row_at(i) {
+                write!(out, "{delimiter}{value}").expect("writing to a String cannot fail");
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Parses delimiter-separated text in the layout of
+    /// [`to_delimited`](Self::to_delimited). Row labels must match the header
+    /// labels in order.
+    pub fn from_delimited(input: &str, delimiter: char) -> Result<Self, MatrixError> {
+        let mut records = split_records(input, delimiter)?.into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| MatrixError::InvalidHeader("expected a header row".to_string()))?;
+        let labels: Vec<String> = header.into_iter().skip(1).collect();
+        let n = labels.len();
+        let mut values = Vec::with_capacity(n * n);
+        for (row, label) in labels.iter().enumerate() {
+            let record = records.next().ok_or(MatrixError::Truncated(row))?;
+            if record.len() != n + 1 {
+                return Err(MatrixError::RowLength {
+                    row,
+                    expected: n,
+                    actual: record.len().saturating_sub(1),
+                });
+            }
+            if record[0] != *label {
+                return Err(MatrixError::InvalidHeader(format!(
+                    "row {row} is labelled {:?} but column {row} is {label:?}",
+                    record[0]
+                )));
+            }
+            for text in &record[1..] {
+                values.push(parse_value(row, text.trim())?);
+            }
+        }
+        if records.next().is_some() {
+            return Err(MatrixError::RowLength {
+                row: n,
+                expected: 0,
+                actual: 1,
+            });
+        }
+        Self::from_parts(labels, values)
+    }
+
+    /// Encodes the matrix as comma-separated values.
+    pub fn to_csv(&self) -> String {
+        self.to_delimited(',')
+    }
+
+    /// Parses comma-separated values.
+    pub fn from_csv(input: &str) -> Result<Self, MatrixError> {
+        Self::from_delimited(input, ',')
+    }
+
+    /// Encodes the matrix as tab-separated values.
+    pub fn to_tsv(&self) -> String {
+        self.to_delimited('\t')
+    }
+
+    /// Parses tab-separated values.
+    pub fn from_tsv(input: &str) -> Result<Self, MatrixError> {
+        Self::from_delimited(input, '\t')
+    }
+
+    /// Encodes the matrix in the compact binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let n = self.len();
+        let lower = self.is_symmetric();
+        let mut out = Vec::with_capacity(9 + n * 8 + self.values.len() * 8);
+        out.extend_from_slice(BINARY_MAGIC);
+        out.push(if lower { BINARY_LOWER } else { BINARY_FULL });
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+        for label in &self.labels {
+            out.extend_from_slice(&(label.len() as u32).to_le_bytes());
+            out.extend_from_slice(label.as_bytes());
+        }
+        for i in 0..n {
+            let width = if lower { i } else { n };
+            for value in &self.row_at(i)[..width] {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// Decodes the compact binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MatrixError> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        if reader.take(4) != Some(BINARY_MAGIC.as_slice()) {
+            return Err(MatrixError::InvalidHeader(
+                "missing binary matrix magic".to_string(),
+            ));
+        }
+        let lower = match reader.take(1).map(|b| b[0]) {
+            Some(BINARY_FULL) => false,
+            Some(BINARY_LOWER) => true,
+            _ => {
+                return Err(MatrixError::InvalidHeader(
+                    "unknown binary matrix layout".to_string(),
+                ))
+            }
+        };
+        let n = reader.u32().ok_or(MatrixError::Truncated(0))? as usize;
+        // Every label takes at least its length prefix and every stored value
+        // eight bytes, so a count the input cannot hold is rejected before
+        // anything is sized from it.
+        let cells = match lower {
+            true => n.checked_mul(n.saturating_sub(1)).map(|c| c / 2),
+            false => n.checked_mul(n),
+        };
+        let needed = cells
+            .and_then(|c| c.checked_mul(8))
+            .and_then(|v| v.checked_add(n.checked_mul(4)?));
+        if needed.is_none_or(|needed| needed > reader.remaining()) {
+            return Err(MatrixError::Truncated(0));
+        }
+        let mut labels = Vec::with_capacity(n);
+        for row in 0..n {
+            let len = reader.u32().ok_or(MatrixError::Truncated(0))? as usize;
+            let label = reader
+                .take(len)
+                .ok_or(MatrixError::Truncated(0))
+                .and_then(|b| {
+                    String::from_utf8(b.to_vec()).map_err(|_| {
+                        MatrixError::InvalidHeader(format!("label {row} is not valid UTF-8"))
+                    })
+                })?;
+            labels.push(label);
+        }
+        let mut values = vec![0.0; n * n];
+        for row in 0..n {
+            let width = if lower { row } else { n };
+            for col in 0..width {
+                let value = reader.f64().ok_or(MatrixError::Truncated(row))?;
+                values[row * n + col] = value;
+                if lower {
+                    values[col * n + row] = value;
+                }
+            }
+        }
+        Self::from_parts(labels, values)
+    }
+}
+
+/// Appends one PHYLIP row: the label, with whitespace written as `_` and
+/// padded to ten columns, then the values.
+pub(crate) fn push_phylip_row(out: &mut String, label: &str, values: &[f64]) {
+    let name: String = label
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect();
+    write!(out, "{name:<10}").expect("writing to a String cannot fail");
+    for value in values {
+        write!(out, " {value}").expect("writing to a String cannot fail");
+    }
+    out.push('\n');
+}
+
+fn parse_value(row: usize, text: &str) -> Result<f64, MatrixError> {
+    text.parse().map_err(|_| MatrixError::InvalidValue {
+        row,
+        text: text.to_string(),
+    })
+}
+
+/// Quotes `field` if it holds the delimiter, a quote or a line break.
+pub(crate) fn quote_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits delimiter-separated text into records, honouring quoted fields.
+/// Blank lines are skipped.
+fn split_records(input: &str, delimiter: char) -> Result<Vec<Vec<String>>, MatrixError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            '\r' => {}
+            '\n' => {
+                if !(record.is_empty() && field.is_empty()) {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            _ if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(MatrixError::Truncated(records.len().saturating_sub(1)));
+    }
+    if !(record.is_empty() && field.is_empty()) {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Cursor over the binary format.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let out = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(out)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes(b.try_into().expect("took four bytes")))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.take(8)
+            .map(|b| f64::from_le_bytes(b.try_into().expect("took eight bytes")))
+    }
+}
+
+/// Labelled leaf-by-leaf distance matrices of a tree.
+///
+/// Rows follow [`PathOracle::leaf_ids`] and are labelled by taxon. Both
+/// builders share one [`PathOracle`], so they run in O(n²) for n leaves.
+pub trait LeafDistances: DistanceMatrix + RootedMetaTree
+where
+    <Self as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+{
+    /// Patristic (branch-length) distances between leaves. A missing edge
+    /// weight counts as one.
+    ///
+    /// # Errors
+    ///
+    /// [`MatrixError::UnlabelledLeaf`] if a leaf has no taxon, and
+    /// [`MatrixError::DuplicateLabel`] if two leaves share one.
+    fn patristic_matrix(&self) -> Result<LabelledMatrix, MatrixError> {
+        let oracle = self.path_oracle();
+        leaf_matrix(self, &oracle, |u, v| {
+            oracle
+                .path_length(u, v)
+                .to_f64()
+                .expect("invariant: float edge weights convert to f64")
+        })
+    }
+
+    /// Number of edges between leaves.
+    ///
+    /// # Errors
+    ///
+    /// As for [`patristic_matrix`](Self::patristic_matrix).
+    fn node_count_matrix(&self) -> Result<LabelledMatrix, MatrixError> {
+        let oracle = self.path_oracle();
+        leaf_matrix(self, &oracle, |u, v| oracle.path_edges(u, v) as f64)
+    }
+}
+
+fn leaf_matrix<T, F>(
+    tree: &T,
+    oracle: &PathOracle<'_, T>,
+    mut distance: F,
+) -> Result<LabelledMatrix, MatrixError>
+where
+    T: DistanceMatrix + RootedMetaTree,
+    <T as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+    F: FnMut(TreeNodeID<T>, TreeNodeID<T>) -> f64,
+{
+    let leaves = oracle.leaf_ids();
+    let labels = leaves
+        .iter()
+        .map(|&leaf| {
+            tree.get_node_taxa(leaf)
+                .map(ToString::to_string)
+                .ok_or_else(|| MatrixError::UnlabelledLeaf(leaf.into()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    LabelledMatrix::from_fn(labels, |i, j| distance(leaves[i], leaves[j]))
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::tree::PhyloTree;
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick("((A:1,B:2):0.5,(C:1,D:1.5):1,E:3);".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_leaf_matrices_by_label() {
+        let tree = tree();
+        let patristic = tree.patristic_matrix().unwrap();
+        assert_eq!(patristic.len(), 5);
+        assert_eq!(patristic.distance("A", "B"), Some(3.0));
+        assert_eq!(patristic.distance("A", "D"), Some(4.0));
+        assert_eq!(patristic.distance("E", "E"), Some(0.0));
+        assert_eq!(patristic.distance("A", "Z"), None);
+        assert!(patristic.is_symmetric());
+
+        let counts = tree.node_count_matrix().unwrap();
+        assert_eq!(counts.distance("A", "B"), Some(2.0));
+        assert_eq!(counts.distance("A", "E"), Some(3.0));
+        assert_eq!(counts.row("C").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_text_round_trips() {
+        let matrix = tree().patristic_matrix().unwrap();
+        for layout in [PhylipLayout::Square, PhylipLayout::LowerTriangular] {
+            let text = matrix.to_phylip(layout);
+            assert_eq!(LabelledMatrix::from_phylip(&text).unwrap(), matrix);
+        }
+        assert_eq!(LabelledMatrix::from_csv(&matrix.to_csv()).unwrap(), matrix);
+        assert_eq!(LabelledMatrix::from_tsv(&matrix.to_tsv()).unwrap(), matrix);
+
+        let awkward = LabelledMatrix::new(
+            vec!["a,b".to_string(), "say \"hi\"".to_string()],
+            vec![vec![0.0, 1.5], vec![2.5, 0.0]],
+        )
+        .unwrap();
+        assert_eq!(
+            LabelledMatrix::from_csv(&awkward.to_csv()).unwrap(),
+            awkward
+        );
+        // PHYLIP names end at whitespace, so spaces come back as underscores.
+        let spaced = LabelledMatrix::from_fn(
+            vec!["Homo sapiens".to_string(), "Pan".to_string()],
+            |i, j| (i != j) as u8 as f64,
+        )
+        .unwrap();
+        let read = LabelledMatrix::from_phylip(&spaced.to_phylip(PhylipLayout::Square)).unwrap();
+        assert_eq!(read.labels(), ["Homo_sapiens", "Pan"]);
+    }
+
+    #[test]
+    fn test_phylip_wrapped_rows() {
+        let text = "3\nalpha 0 1\n 2\nbeta 1 0 3\ngamma 2 3\n0\n";
+        let matrix = LabelledMatrix::from_phylip(text).unwrap();
+        assert_eq!(matrix.distance("alpha", "gamma"), Some(2.0));
+        assert_eq!(matrix.row_at(2), &[2.0, 3.0, 0.0]);
+
+        assert_eq!(
+            LabelledMatrix::from_phylip("3\nalpha\nbeta 1\n"),
+            Err(MatrixError::Truncated(2))
+        );
+        assert_eq!(
+            LabelledMatrix::from_phylip("2\nalpha\nbeta x\n"),
+            Err(MatrixError::InvalidValue {
+                row: 1,
+                text: "x".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let symmetric = tree().patristic_matrix().unwrap();
+        let bytes = symmetric.to_bytes();
+        assert_eq!(LabelledMatrix::from_bytes(&bytes).unwrap(), symmetric);
+
+        let asymmetric = LabelledMatrix::new(
+            vec!["x".to_string(), "y".to_string()],
+            vec![vec![0.0, 1.0], vec![2.0, 0.0]],
+        )
+        .unwrap();
+        // Magic, layout flag, count, five one-byte labels, strict lower
+        // triangle.
+        assert_eq!(bytes.len(), 4 + 1 + 4 + 5 * (4 + 1) + 10 * 8);
+        let full = asymmetric.to_bytes();
+        assert_eq!(LabelledMatrix::from_bytes(&full).unwrap(), asymmetric);
+
+        assert!(LabelledMatrix::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(LabelledMatrix::from_bytes(b"nope").is_err());
+        // A row count the input cannot hold fails before any allocation.
+        let mut huge = BINARY_MAGIC.to_vec();
+        huge.push(BINARY_FULL);
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            LabelledMatrix::from_bytes(&huge),
+            Err(MatrixError::Truncated(0))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_labels_are_rejected() {
+        assert_eq!(
+            LabelledMatrix::from_fn(vec!["a".to_string(), "a".to_string()], |_, _| 0.0),
+            Err(MatrixError::DuplicateLabel("a".to_string()))
+        );
+        assert_eq!(
+            LabelledMatrix::new(vec!["a".to_string(), "b".to_string()], vec![vec![0.0, 1.0]]),
+            Err(MatrixError::RowCount {
+                expected: 2,
+                actual: 1
+            })
+        );
+    }
+}
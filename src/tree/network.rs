@@ -0,0 +1,818 @@
+//! Rooted phylogenetic networks.
+//!
+//! A [`PhyloNetwork`](crate::tree::network::PhyloNetwork) is a rooted
+//! directed acyclic graph. Its reticulation nodes, such as recombinant or
+//! hybrid lineages, have more than one parent, and each edge into a
+//! reticulation may carry the probability that the lineage inherits from that
+//! parent. Networks are read and written in extended Newick (Cardona et al.
+//! 2008), where a reticulation appears once per parent under a shared
+//! `#H1`-style tag, with the Rich Newick `:length:support:probability` edge
+//! fields.
+//!
+//! A network displays a tree for each way of keeping one parent edge at every
+//! reticulation, after unlabelled dead ends and nodes left with one child are
+//! removed. [`PhyloNetwork::displayed_trees`](crate::tree::network::PhyloNetwork::displayed_trees)
+//! enumerates them as [`PhyloTree`]s, and the softwired clusters of the
+//! network are the clusters of those trees. There are `∏ k_r` of them for
+//! reticulations of in-degree `k_r`, so both walks are meant for networks
+//! with a handful of reticulations.
+//!
+//! The level of a network is the largest number of reticulations in one of
+//! its blobs, the biconnected components of its undirected graph, and is
+//! found in linear time. A network is tree-child when every internal node has
+//! a child that is not a reticulation.
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashSet as HashSet;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashSet;
+
+use {
+    crate::error::NetworkError,
+    crate::node::{Node, NodeID},
+    crate::prelude::*,
+    crate::tree::PhyloTree,
+    itertools::Itertools,
+    std::fmt::Display,
+};
+
+/// A directed edge of a [`PhyloNetwork`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkEdge {
+    /// Node the edge leaves.
+    pub parent: NodeID,
+    /// Node the edge enters.
+    pub child: NodeID,
+    /// Branch length, if any.
+    pub length: Option<f32>,
+    /// Probability that `child` inherits from `parent`, if given. Only
+    /// meaningful on edges into reticulations.
+    pub inheritance: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct NetworkNode {
+    taxa: Option<String>,
+    /// Indices of the edges into the node.
+    parents: Vec<usize>,
+    /// Indices of the edges out of the node.
+    children: Vec<usize>,
+}
+
+/// A rooted phylogenetic network with `String` taxa and `f32` branch lengths.
+///
+/// Node ids are dense, and the root is always node `0`.
+#[derive(Debug, Clone)]
+pub struct PhyloNetwork {
+    nodes: Vec<NetworkNode>,
+    edges: Vec<NetworkEdge>,
+}
+
+/// A tree displayed by a [`PhyloNetwork`].
+#[derive(Debug, Clone)]
+pub struct DisplayedTree {
+    /// The tree, with the lengths of suppressed nodes' edges summed.
+    pub tree: PhyloTree,
+    /// The product of the inheritance probabilities of the kept edges.
+    pub probability: f64,
+    /// The kept parent of each reticulation, in the order of
+    /// [`PhyloNetwork::reticulation_ids`].
+    pub parents: Vec<NodeID>,
+}
+
+impl Default for PhyloNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PhyloNetwork {
+    /// Creates a network with only an unlabelled root.
+    pub fn new() -> Self {
+        PhyloNetwork {
+            nodes: vec![NetworkNode::default()],
+            edges: vec![],
+        }
+    }
+
+    /// Builds a network from the taxa of its nodes and its edges, with node
+    /// `0` as the root. Fails with the nodes on or below a directed cycle.
+    pub(crate) fn from_parts(
+        taxa: Vec<Option<String>>,
+        edges: Vec<NetworkEdge>,
+    ) -> Result<Self, Vec<NodeID>> {
+        let mut network = PhyloNetwork {
+            nodes: taxa
+                .into_iter()
+                .map(|taxa| NetworkNode {
+                    taxa,
+                    ..Default::default()
+                })
+                .collect(),
+            edges: vec![],
+        };
+        edges.into_iter().for_each(|edge| network.push_edge(edge));
+        let order = network.topological_order();
+        if order.first() != Some(&0) || order.len() < network.nodes.len() {
+            let mut placed = vec![false; network.nodes.len()];
+            order.iter().for_each(|&v| placed[v] = true);
+            return Err((0..placed.len()).filter(|&v| !placed[v]).collect());
+        }
+        Ok(network)
+    }
+
+    /// Reads the first network of an extended Newick string, such as
+    /// `((A,(B)#H1:1::0.4),(#H1:2::0.6,C));`.
+    pub fn from_extended_newick(newick_str: &[u8]) -> std::io::Result<Self> {
+        let input = std::str::from_utf8(newick_str)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        crate::tree::newick::parse_extended_newick(input)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Encodes the network as extended Newick. Each reticulation's subtree is
+    /// written at its first occurrence, and its tags are numbered `#H1`,
+    /// `#H2`, ... in that order. Labels are quoted where needed.
+    pub fn to_extended_newick(&self) -> impl Display {
+        let mut tags = vec![0usize; self.nodes.len()];
+        let mut next_tag = 0;
+        let mut out = String::new();
+        // Frames are (node, edge into it, index of the next child to emit),
+        // with `usize::MAX` for the root's missing edge.
+        let mut stack: Vec<(NodeID, usize, usize)> = vec![(0, usize::MAX, 0)];
+        while let Some(&(v, edge, child_idx)) = stack.last() {
+            let node = &self.nodes[v];
+            let seen = tags[v] > 0 && child_idx == 0;
+            let reticulate = node.parents.len() > 1;
+            if child_idx == 0 && reticulate && !seen {
+                next_tag += 1;
+                tags[v] = next_tag;
+            }
+            if !seen && child_idx < node.children.len() {
+                out.push(if child_idx == 0 { '(' } else { ',' });
+                stack
+                    .last_mut()
+                    .expect("invariant: guarded by while let Some(..) = stack.last()")
+                    .2 += 1;
+                let e = node.children[child_idx];
+                stack.push((self.edges[e].child, e, 0));
+                continue;
+            }
+            if !seen {
+                if !node.children.is_empty() {
+                    out.push(')');
+                }
+                if let Some(taxa) = &node.taxa {
+                    push_label(&mut out, taxa);
+                }
+            }
+            if reticulate {
+                out.push_str(&format!("#H{}", tags[v]));
+            }
+            if let Some(edge) = self.edges.get(edge) {
+                match (edge.length, edge.inheritance) {
+                    (Some(l), Some(p)) => out.push_str(&format!(":{l}::{p}")),
+                    (None, Some(p)) => out.push_str(&format!(":::{p}")),
+                    (Some(l), None) => out.push_str(&format!(":{l}")),
+                    (None, None) => {}
+                }
+            }
+            stack.pop();
+        }
+        out.push(';');
+        out
+    }
+
+    /// Id of the root, which is always `0`.
+    pub fn root(&self) -> NodeID {
+        0
+    }
+
+    /// Number of nodes.
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// All edges, in the order they were added.
+    pub fn edges(&self) -> &[NetworkEdge] {
+        &self.edges
+    }
+
+    /// Taxon of `node`, if it has one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not a node of the network.
+    pub fn taxa(&self, node: NodeID) -> Option<&str> {
+        self.nodes[node].taxa.as_deref()
+    }
+
+    /// Edges into `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not a node of the network.
+    pub fn in_edges(&self, node: NodeID) -> impl Iterator<Item = &NetworkEdge> {
+        self.nodes[node].parents.iter().map(|&e| &self.edges[e])
+    }
+
+    /// Edges out of `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not a node of the network.
+    pub fn out_edges(&self, node: NodeID) -> impl Iterator<Item = &NetworkEdge> {
+        self.nodes[node].children.iter().map(|&e| &self.edges[e])
+    }
+
+    /// Parents of `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not a node of the network.
+    pub fn parents(&self, node: NodeID) -> impl Iterator<Item = NodeID> + '_ {
+        self.in_edges(node).map(|e| e.parent)
+    }
+
+    /// Children of `node`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not a node of the network.
+    pub fn children(&self, node: NodeID) -> impl Iterator<Item = NodeID> + '_ {
+        self.out_edges(node).map(|e| e.child)
+    }
+
+    /// Whether `node` has more than one parent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is not a node of the network.
+    pub fn is_reticulation(&self, node: NodeID) -> bool {
+        self.nodes[node].parents.len() > 1
+    }
+
+    /// Ids of the nodes without children.
+    pub fn leaf_ids(&self) -> impl Iterator<Item = NodeID> + '_ {
+        (0..self.nodes.len()).filter(|&v| self.nodes[v].children.is_empty())
+    }
+
+    /// Ids of the reticulations, in increasing order.
+    pub fn reticulation_ids(&self) -> impl Iterator<Item = NodeID> + '_ {
+        (0..self.nodes.len()).filter(|&v| self.is_reticulation(v))
+    }
+
+    /// Number of reticulations.
+    pub fn num_reticulations(&self) -> usize {
+        self.reticulation_ids().count()
+    }
+
+    /// Adds a new node below `parent` and returns its id.
+    ///
+    /// # Errors
+    ///
+    /// [`NetworkError::UnknownNode`] if `parent` is not a node of the network.
+    pub fn add_child(
+        &mut self,
+        parent: NodeID,
+        taxa: Option<String>,
+        length: Option<f32>,
+    ) -> Result<NodeID, NetworkError> {
+        if parent >= self.nodes.len() {
+            return Err(NetworkError::UnknownNode(parent));
+        }
+        let child = self.nodes.len();
+        self.nodes.push(NetworkNode {
+            taxa,
+            ..Default::default()
+        });
+        self.push_edge(NetworkEdge {
+            parent,
+            child,
+            length,
+            inheritance: None,
+        });
+        Ok(child)
+    }
+
+    /// Adds an edge from `parent` to the existing node `child`, making
+    /// `child` a reticulation.
+    ///
+    /// # Errors
+    ///
+    /// [`NetworkError::UnknownNode`] for an id not in the network,
+    /// [`NetworkError::RootParent`] if `child` is the root,
+    /// [`NetworkError::Cycle`] if `child` is `parent` or one of its
+    /// ancestors, and [`NetworkError::InvalidInheritance`] for a probability
+    /// outside `[0, 1]`.
+    pub fn add_edge(
+        &mut self,
+        parent: NodeID,
+        child: NodeID,
+        length: Option<f32>,
+        inheritance: Option<f32>,
+    ) -> Result<(), NetworkError> {
+        for node in [parent, child] {
+            if node >= self.nodes.len() {
+                return Err(NetworkError::UnknownNode(node));
+            }
+        }
+        if child == 0 {
+            return Err(NetworkError::RootParent);
+        }
+        check_inheritance(inheritance)?;
+        // Walk up from `parent`, looking for `child`.
+        let mut seen = vec![false; self.nodes.len()];
+        let mut pending = vec![parent];
+        while let Some(v) = pending.pop() {
+            if v == child {
+                return Err(NetworkError::Cycle { parent, child });
+            }
+            if !std::mem::replace(&mut seen[v], true) {
+                pending.extend(self.parents(v));
+            }
+        }
+        self.push_edge(NetworkEdge {
+            parent,
+            child,
+            length,
+            inheritance,
+        });
+        Ok(())
+    }
+
+    /// Sets the inheritance probability of the first edge from `parent` to
+    /// `child`.
+    ///
+    /// # Errors
+    ///
+    /// [`NetworkError::UnknownEdge`] if there is no such edge, and
+    /// [`NetworkError::InvalidInheritance`] for a probability outside
+    /// `[0, 1]`.
+    pub fn set_inheritance(
+        &mut self,
+        parent: NodeID,
+        child: NodeID,
+        inheritance: Option<f32>,
+    ) -> Result<(), NetworkError> {
+        check_inheritance(inheritance)?;
+        let edge = self
+            .edges
+            .iter_mut()
+            .find(|e| e.parent == parent && e.child == child)
+            .ok_or(NetworkError::UnknownEdge(parent, child))?;
+        edge.inheritance = inheritance;
+        Ok(())
+    }
+
+    fn push_edge(&mut self, edge: NetworkEdge) {
+        self.nodes[edge.parent].children.push(self.edges.len());
+        self.nodes[edge.child].parents.push(self.edges.len());
+        self.edges.push(edge);
+    }
+
+    /// Nodes with every parent before its children; shorter than the network
+    /// if it has a directed cycle.
+    fn topological_order(&self) -> Vec<NodeID> {
+        let mut waiting = self.nodes.iter().map(|n| n.parents.len()).collect_vec();
+        let mut order = (0..self.nodes.len())
+            .filter(|&v| waiting[v] == 0)
+            .collect_vec();
+        let mut at = 0;
+        while let Some(&v) = order.get(at) {
+            at += 1;
+            for c in self.children(v) {
+                waiting[c] -= 1;
+                if waiting[c] == 0 {
+                    order.push(c);
+                }
+            }
+        }
+        order
+    }
+
+    /// Inheritance probability of edge `e`. An edge into a reticulation
+    /// without one shares what the node's other edges leave equally with
+    /// the node's other such edges.
+    fn inheritance(&self, e: usize) -> f64 {
+        let edge = &self.edges[e];
+        if let Some(p) = edge.inheritance {
+            return f64::from(p);
+        }
+        let (given, missing) =
+            self.in_edges(edge.child)
+                .fold((0.0, 0), |(g, m), e| match e.inheritance {
+                    Some(p) => (g + f64::from(p), m),
+                    None => (g, m + 1),
+                });
+        (1.0 - given).max(0.0) / missing as f64
+    }
+
+    /// Whether every node with children has a child that is not a
+    /// reticulation.
+    pub fn is_tree_child(&self) -> bool {
+        (0..self.nodes.len()).all(|v| {
+            self.nodes[v].children.is_empty() || self.children(v).any(|c| !self.is_reticulation(c))
+        })
+    }
+
+    /// Level of the network: the most reticulations in one of its blobs, or
+    /// `0` for a tree.
+    pub fn level(&self) -> usize {
+        let n = self.nodes.len();
+        let mut adjacent = vec![vec![]; n];
+        for (e, edge) in self.edges.iter().enumerate() {
+            adjacent[edge.parent].push((edge.child, e));
+            adjacent[edge.child].push((edge.parent, e));
+        }
+        // Tarjan's biconnected components, labelling every edge with its
+        // blob. Frames are (node, edge into it, index of the next neighbour).
+        let mut blob = vec![usize::MAX; self.edges.len()];
+        let mut blobs = 0;
+        let mut discovered = vec![usize::MAX; n];
+        let mut low = vec![0; n];
+        let mut pending_edges = vec![];
+        let mut stack = vec![(0, usize::MAX, 0)];
+        discovered[0] = 0;
+        let mut time = 1;
+        while let Some(&(v, into, next)) = stack.last() {
+            if let Some(&(w, e)) = adjacent[v].get(next) {
+                stack
+                    .last_mut()
+                    .expect("invariant: guarded by while let Some(..) = stack.last()")
+                    .2 += 1;
+                if e == into {
+                    continue;
+                }
+                if discovered[w] == usize::MAX {
+                    pending_edges.push(e);
+                    discovered[w] = time;
+                    low[w] = time;
+                    time += 1;
+                    stack.push((w, e, 0));
+                } else if discovered[w] < discovered[v] {
+                    pending_edges.push(e);
+                    low[v] = low[v].min(discovered[w]);
+                }
+                continue;
+            }
+            stack.pop();
+            if let Some(&(u, _, _)) = stack.last() {
+                low[u] = low[u].min(low[v]);
+                if low[v] >= discovered[u] {
+                    while let Some(e) = pending_edges.pop() {
+                        blob[e] = blobs;
+                        if e == into {
+                            break;
+                        }
+                    }
+                    blobs += 1;
+                }
+            }
+        }
+        // The edges into a reticulation close cycles, so share its blob.
+        let mut reticulations = vec![0; blobs];
+        for v in self.reticulation_ids() {
+            let b = blob[self.nodes[v].parents[0]];
+            if b != usize::MAX {
+                reticulations[b] += 1;
+            }
+        }
+        reticulations.into_iter().max().unwrap_or(0)
+    }
+
+    /// Every tree the network displays, one per choice of a parent at each
+    /// reticulation, so the same tree may be listed more than once.
+    /// Unlabelled leaves left below no kept edge are dropped and nodes left
+    /// with one child suppressed, the root included.
+    pub fn displayed_trees(&self) -> impl Iterator<Item = DisplayedTree> + '_ {
+        let order = self.topological_order();
+        self.switchings().map(move |kept| {
+            let alive = self.alive(&order, &kept);
+            let mut tree = PhyloTree::new(0);
+            // Frames are (network node, tree parent, length so far).
+            let mut pending = vec![(0, tree.get_root_id(), None::<f32>)];
+            let mut top = true;
+            while let Some((v, parent, length)) = pending.pop() {
+                let below = self.kept_children(v, &kept, &alive).collect_vec();
+                if below.len() == 1 {
+                    let e = &self.edges[below[0]];
+                    let length = match top {
+                        true => None,
+                        false => add_lengths(length, e.length),
+                    };
+                    pending.push((e.child, parent, length));
+                    continue;
+                }
+                let id = match top {
+                    true => parent,
+                    false => {
+                        let node = Node::new(tree.next_id());
+                        let id = node.get_id();
+                        tree.add_child(parent, node);
+                        tree.set_edge_weight((parent, id), length);
+                        id
+                    }
+                };
+                top = false;
+                if below.is_empty() {
+                    tree.set_node_taxa(id, self.nodes[v].taxa.clone());
+                }
+                for &e in below.iter().rev() {
+                    pending.push((self.edges[e].child, id, self.edges[e].length));
+                }
+            }
+            DisplayedTree {
+                tree,
+                probability: kept
+                    .iter()
+                    .filter(|&&k| k != usize::MAX)
+                    .map(|&e| self.inheritance(e))
+                    .product(),
+                parents: kept
+                    .iter()
+                    .filter(|&&k| k != usize::MAX)
+                    .map(|&e| self.edges[e].parent)
+                    .collect(),
+            }
+        })
+    }
+
+    /// The softwired clusters of the network: the taxa below every node of
+    /// every displayed tree, each sorted, without repeats, in increasing
+    /// order. Unlabelled leaves are left out.
+    pub fn softwired_clusters(&self) -> Vec<Vec<String>> {
+        let order = self.topological_order();
+        let taxa = self
+            .leaf_ids()
+            .filter_map(|v| self.nodes[v].taxa.as_deref().map(|t| (t, v)))
+            .sorted()
+            .collect_vec();
+        let words = taxa.len().div_ceil(64);
+        let mut index = vec![usize::MAX; self.nodes.len()];
+        for (t, &(_, leaf)) in taxa.iter().enumerate() {
+            index[leaf] = t;
+        }
+        let mut clusters: HashSet<Vec<u64>> = HashSet::default();
+        let mut below = vec![vec![0u64; words]; self.nodes.len()];
+        for kept in self.switchings() {
+            let alive = self.alive(&order, &kept);
+            for &v in order.iter().rev() {
+                if !alive[v] {
+                    continue;
+                }
+                let mut cluster = vec![0u64; words];
+                if let Some(&t) = index.get(v).filter(|&&t| t != usize::MAX) {
+                    cluster[t / 64] |= 1 << (t % 64);
+                }
+                for e in self.kept_children(v, &kept, &alive) {
+                    let child = &below[self.edges[e].child];
+                    cluster.iter_mut().zip(child).for_each(|(w, c)| *w |= c);
+                }
+                if cluster.iter().any(|&w| w != 0) {
+                    clusters.insert(cluster.clone());
+                }
+                below[v] = cluster;
+            }
+        }
+        clusters
+            .into_iter()
+            .map(|cluster| {
+                (0..taxa.len())
+                    .filter(|&t| cluster[t / 64] >> (t % 64) & 1 == 1)
+                    .map(|t| taxa[t].0.to_string())
+                    .collect_vec()
+            })
+            .sorted()
+            .collect()
+    }
+
+    /// Every choice of a kept edge into each node, as that edge for each
+    /// reticulation and `usize::MAX` for the other nodes.
+    fn switchings(&self) -> impl Iterator<Item = Vec<usize>> + '_ {
+        let mut next = Some(vec![0usize; self.nodes.len()]);
+        std::iter::from_fn(move || {
+            let choice = next.take()?;
+            let kept = (0..self.nodes.len())
+                .map(|v| match self.is_reticulation(v) {
+                    true => self.nodes[v].parents[choice[v]],
+                    false => usize::MAX,
+                })
+                .collect();
+            // Advance the mixed-radix counter over the reticulations.
+            let mut choice = choice;
+            for v in self.reticulation_ids() {
+                choice[v] += 1;
+                if choice[v] < self.nodes[v].parents.len() {
+                    next = Some(choice);
+                    break;
+                }
+                choice[v] = 0;
+            }
+            Some(kept)
+        })
+    }
+
+    /// Whether each node keeps a leaf below it under the switching `kept`.
+    fn alive(&self, order: &[NodeID], kept: &[usize]) -> Vec<bool> {
+        let mut alive = vec![false; self.nodes.len()];
+        for &v in order.iter().rev() {
+            alive[v] = self.nodes[v].children.is_empty()
+                || self.nodes[v]
+                    .children
+                    .iter()
+                    .any(|&e| is_kept(&self.edges[e], e, kept) && alive[self.edges[e].child]);
+        }
+        alive
+    }
+
+    /// Kept edges out of `v` to children with a leaf below them.
+    fn kept_children<'a>(
+        &'a self,
+        v: NodeID,
+        kept: &'a [usize],
+        alive: &'a [bool],
+    ) -> impl Iterator<Item = usize> + 'a {
+        self.nodes[v]
+            .children
+            .iter()
+            .copied()
+            .filter(move |&e| is_kept(&self.edges[e], e, kept) && alive[self.edges[e].child])
+    }
+}
+
+/// Whether edge `e` is kept under the switching `kept`.
+fn is_kept(edge: &NetworkEdge, e: usize, kept: &[usize]) -> bool {
+    kept[edge.child] == usize::MAX || kept[edge.child] == e
+}
+
+/// Writes `label`, quoted if it holds a Newick delimiter or a `#` that would
+/// read as a hybrid tag.
+fn push_label(out: &mut String, label: &str) {
+    let plain = |c: char| {
+        !c.is_whitespace() && !matches!(c, '(' | ')' | '[' | ']' | ',' | ':' | ';' | '\'' | '#')
+    };
+    match label.chars().all(plain) {
+        true => out.push_str(label),
+        false => {
+            out.push('\'');
+            out.push_str(&label.replace('\'', "''"));
+            out.push('\'');
+        }
+    }
+}
+
+/// Length of two edges joined by suppressing the node between them.
+fn add_lengths(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+    match (a, b) {
+        (None, None) => None,
+        _ => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+    }
+}
+
+/// Rejects an inheritance probability outside `[0, 1]`, or NaN.
+pub(crate) fn check_inheritance(inheritance: Option<f32>) -> Result<(), NetworkError> {
+    match inheritance {
+        Some(p) if !(0.0..=1.0).contains(&p) => Err(NetworkError::InvalidInheritance(p)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(newick: &str) -> PhyloNetwork {
+        PhyloNetwork::from_extended_newick(newick.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_extended_newick_round_trip() {
+        let net = network("((A:1,(B)'x y'#H1:1:95:0.4)a,(#H1:2::0.6,C)[&c]b)r;");
+        assert_eq!(net.num_nodes(), 7);
+        assert_eq!(net.num_reticulations(), 1);
+        let h = net.reticulation_ids().next().unwrap();
+        assert_eq!(net.taxa(h), Some("x y"));
+        let inheritance = net.in_edges(h).map(|e| e.inheritance).collect_vec();
+        assert_eq!(inheritance, [Some(0.4), Some(0.6)]);
+        assert_eq!(
+            net.in_edges(h).map(|e| e.length).collect_vec(),
+            [Some(1.0), Some(2.0)]
+        );
+        assert_eq!(net.level(), 1);
+        assert!(net.is_tree_child());
+
+        let written = net.to_extended_newick().to_string();
+        assert_eq!(written, "((A:1,(B)'x y'#H1:1::0.4)a,(#H1:2::0.6,C)b)r;");
+        let again = network(&written);
+        assert_eq!(again.to_extended_newick().to_string(), written);
+        assert_eq!(again.edges(), net.edges());
+
+        // A tree parses as a network without reticulations.
+        let tree = network("((A,B),(C,D));");
+        assert_eq!(tree.num_reticulations(), 0);
+        assert_eq!(tree.level(), 0);
+        assert_eq!(tree.displayed_trees().count(), 1);
+    }
+
+    #[test]
+    fn test_displayed_trees_and_softwired_clusters() {
+        let net = network("((A,(B)#H1:1::0.4):1,(#H1:2::0.6,C):3);");
+        let displayed = net.displayed_trees().collect_vec();
+        assert_eq!(displayed.len(), 2);
+        let newick = displayed
+            .iter()
+            .map(|d| d.tree.to_newick().to_string())
+            .collect_vec();
+        assert_eq!(newick, ["((A,B:1):1,C:3);", "(A:1,(B:2,C):3);"]);
+        let probability = displayed.iter().map(|d| d.probability).collect_vec();
+        assert!((probability[0] - 0.4).abs() < 1e-6);
+        assert!((probability[1] - 0.6).abs() < 1e-6);
+        let h = net.reticulation_ids().next().unwrap();
+        assert_eq!(displayed[1].parents, [net.parents(h).nth(1).unwrap()]);
+
+        let clusters = net.softwired_clusters();
+        let expected: [&[&str]; 6] = [
+            &["A"],
+            &["A", "B"],
+            &["A", "B", "C"],
+            &["B"],
+            &["B", "C"],
+            &["C"],
+        ];
+        assert_eq!(
+            clusters,
+            expected.map(|c| c.iter().map(|t| t.to_string()).collect_vec())
+        );
+
+        // Missing probabilities share what the others leave.
+        let even = network("((A,(B)#H1),((#H1,C),#H1));");
+        assert_eq!(even.in_edges(3).count(), 3);
+        for d in even.displayed_trees() {
+            assert!((d.probability - 1.0 / 3.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_level_tree_child_and_errors() {
+        let net = network("((#H1,#H2)p,((A)#H1,(B)#H2)q,C);");
+        assert_eq!(net.num_reticulations(), 2);
+        assert_eq!(net.level(), 2);
+        assert!(!net.is_tree_child());
+        assert_eq!(net.displayed_trees().count(), 4);
+
+        // Two separate blobs of one reticulation each.
+        let net = network("(((A)#H1,(#H1,B)),((C)#H2,(#H2,D)));");
+        assert_eq!(net.level(), 1);
+        assert!(net.is_tree_child());
+
+        for bad in ["((A,#H1)#H1);", "((A,#H2)#H1,(#H1)#H2);"] {
+            let err = PhyloNetwork::from_extended_newick(bad.as_bytes()).unwrap_err();
+            assert!(err.to_string().contains("own ancestor"), "{bad}: {err}");
+        }
+        let err = PhyloNetwork::from_extended_newick(b"((A)#H1,(B)#H1);").unwrap_err();
+        assert!(err.to_string().contains("children a second time"));
+        for (bad, at) in [
+            ("((a,(b)#H1:1::1.5)x,(#H1:1::0.5,c)y)r;", 14),
+            ("((a,(b)#H1:1::0.5)x,(#H1:1::-0.5,c)y)r;", 28),
+        ] {
+            let err = crate::tree::newick::parse_extended_newick(bad).unwrap_err();
+            assert!(
+                matches!(err, NewickError::InvalidInheritance { idx, .. } if idx == at),
+                "{bad}: {err}"
+            );
+        }
+
+        let mut net = PhyloNetwork::new();
+        let a = net.add_child(0, None, Some(1.0)).unwrap();
+        let b = net.add_child(0, None, None).unwrap();
+        let h = net.add_child(a, Some("H".into()), None).unwrap();
+        let x = net.add_child(h, Some("X".into()), None).unwrap();
+        net.add_child(b, Some("Y".into()), None).unwrap();
+        assert_eq!(net.add_edge(b, h, None, Some(0.3)), Ok(()));
+        assert_eq!(net.set_inheritance(a, h, Some(0.7)), Ok(()));
+        assert_eq!(
+            net.add_edge(x, a, None, None),
+            Err(NetworkError::Cycle {
+                parent: x,
+                child: a
+            })
+        );
+        assert_eq!(
+            net.add_edge(b, 0, None, None),
+            Err(NetworkError::RootParent)
+        );
+        assert_eq!(
+            net.add_edge(b, 9, None, None),
+            Err(NetworkError::UnknownNode(9))
+        );
+        assert_eq!(
+            net.add_edge(b, x, None, Some(1.5)),
+            Err(NetworkError::InvalidInheritance(1.5))
+        );
+        assert_eq!(
+            net.to_extended_newick().to_string(),
+            "(((X)H#H1:::0.7):1,(Y,#H1:::0.3));"
+        );
+    }
+}
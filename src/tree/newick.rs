@@ -10,8 +10,9 @@
 //! # Accepted grammar
 //!
 //! The parser aims for the common ground of Newick as emitted by popular
-//! phylogenetics software (RAxML, IQ-TREE, RevBayes, BEAST, ...), restricted to
-//! *trees* (network / extended-Newick `#H` hybrid nodes are out of scope):
+//! phylogenetics software (RAxML, IQ-TREE, RevBayes, BEAST, ...). Trees are
+//! read by [`parse_newick`]; the extended Newick networks read by
+//! [`parse_extended_newick`] are covered [below](#extended-newick).
 //!
 //! * **Topology** via `(`, `)`, `,`, terminated by `;` (a missing terminator at
 //!   end of input is tolerated; anything after the first `;` is ignored, so a
@@ -22,7 +23,8 @@
 //!   one of `( ) [ ] , : ; '`. Underscores are kept **literal** (not converted
 //!   to spaces), matching modern tools and keeping identifiers such as
 //!   `GB_GCA_015163815.1` intact. Support values such as IQ-TREE's `95.3/98`
-//!   are ordinary unquoted labels.
+//!   are ordinary unquoted labels, unless internal labels are read as
+//!   [`InternalLabels::Support`], which stores them on the node instead.
 //! * **Branch lengths** `:<number>` in decimal or scientific notation. A token
 //!   that does not parse as a weight is stored as no weight rather than being
 //!   rejected.
@@ -32,12 +34,32 @@
 //!
 //! Malformed input yields a [`NewickError`] carrying a byte offset; the parser
 //! never panics.
+//!
+//! # Extended Newick
+//!
+//! [`parse_extended_newick`] reads rooted networks in the same grammar, with
+//! two additions:
+//!
+//! * **Hybrid tags**: a label may end in `#` followed by an optional type and a
+//!   number, as in `#H1`, `X#LGT2` or `'x y'#R3`. Every occurrence of a tag is
+//!   the same node, one occurrence per parent. At most one of them may list
+//!   children, and the node must not lie below itself.
+//! * **Edge fields**: a branch may carry `:length:support:probability`, any of
+//!   them empty, where the probability is the one of inheriting along it and
+//!   must lie in `[0, 1]`. The support field is read and dropped.
+//!
+//! Comments are skipped rather than kept.
 
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::error::NewickError;
 use crate::node::Node;
 use crate::prelude::*;
+use crate::tree::network::{check_inheritance, NetworkEdge, PhyloNetwork};
 
 /// Returns true if `c` terminates an unquoted label or a branch-length token.
 /// This is exactly the set of characters the Newick standard forbids in an
@@ -160,14 +182,43 @@ fn commit_annotation<T, W, Z, H>(
     raw.clear();
 }
 
+/// Reads an unquoted internal label as `/`-separated support values, if every
+/// part is a number.
+fn parse_support(label: &str) -> Option<Arc<[f32]>> {
+    label
+        .split('/')
+        .map(|part| part.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()
+        .map(Arc::from)
+}
+
+/// Writes `label`, quoted as `quoting` says.
+pub(crate) fn push_label(out: &mut String, label: &str, quoting: Quoting) {
+    let quote = match quoting {
+        Quoting::Never => false,
+        Quoting::AsNeeded => label.is_empty() || label.chars().any(is_delimiter),
+        Quoting::Always => true,
+    };
+    match quote {
+        true => {
+            out.push('\'');
+            out.push_str(&label.replace('\'', "''"));
+            out.push('\'');
+        }
+        false => out.push_str(label),
+    }
+}
+
 /// Parses a Newick string into a [`SimpleRootedTree`], retaining node `[...]`
-/// annotations as decided by `handler`.
+/// annotations as decided by `handler` and reading internal labels as
+/// `labels` says.
 ///
 /// Only the first `;`-terminated tree is read. See the module documentation for
 /// the accepted grammar.
 pub(crate) fn parse_newick<T, W, Z, H>(
     src: &str,
     handler: &H,
+    labels: InternalLabels,
 ) -> Result<SimpleRootedTree<T, W, Z>, NewickError>
 where
     T: NodeTaxa,
@@ -262,12 +313,22 @@ where
                     // A delimiter with no meaning here (e.g. a stray `]`).
                     return Err(NewickError::InvalidCharacter { idx: sc.pos });
                 }
+                saw_content = true;
+                // Labels come after a node's children, so an internal node
+                // already has them here.
+                if labels == InternalLabels::Support && !tree.is_leaf(current) {
+                    if let Some(support) = parse_support(label) {
+                        if let Some(node) = tree.get_node_mut(current) {
+                            node.set_support(Some(support));
+                        }
+                        continue;
+                    }
+                }
                 let taxa = T::from_str(label).map_err(|_| NewickError::InvalidLabel {
                     idx: sc.pos,
                     text: label.to_string(),
                 })?;
                 tree.set_node_taxa(current, Some(taxa));
-                saw_content = true;
             }
         }
     }
@@ -282,3 +343,193 @@ where
     }
     Ok(tree)
 }
+
+/// One appearance of a node in extended Newick; a hybrid node appears once
+/// per parent.
+#[derive(Default)]
+struct Occurrence {
+    /// Byte offset at which the occurrence started.
+    idx: usize,
+    parent: Option<usize>,
+    label: Option<String>,
+    tag: Option<String>,
+    length: Option<f32>,
+    inheritance: Option<f32>,
+    has_children: bool,
+}
+
+/// Splits an unquoted label into its name and its hybrid tag, if it ends in
+/// one.
+fn split_hybrid(label: &str) -> (&str, Option<&str>) {
+    match label.rfind('#') {
+        Some(at) => {
+            let tag = &label[at + 1..];
+            let number = tag.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+            match !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) {
+                true => (&label[..at], Some(tag)),
+                false => (label, None),
+            }
+        }
+        None => (label, None),
+    }
+}
+
+/// Parses an extended Newick string into a [`PhyloNetwork`].
+///
+/// Only the first `;`-terminated network is read. See the module
+/// documentation for the accepted grammar.
+pub(crate) fn parse_extended_newick(src: &str) -> Result<PhyloNetwork, NewickError> {
+    let mut sc = Scanner::new(src);
+    let mut occurrences = vec![Occurrence::default()];
+    let mut current = 0;
+    let mut stack: Vec<usize> = Vec::new();
+    let mut saw_content = false;
+    let mut comments = String::new();
+
+    loop {
+        sc.skip_trivia(&mut comments)?;
+        comments.clear();
+        let c = match sc.peek() {
+            None | Some(';') => break,
+            Some(c) => c,
+        };
+        match c {
+            '(' | ',' => {
+                sc.bump();
+                let parent = match c {
+                    '(' => {
+                        stack.push(current);
+                        occurrences[current].has_children = true;
+                        current
+                    }
+                    _ => *stack
+                        .last()
+                        .ok_or(NewickError::UnbalancedParens { idx: sc.pos })?,
+                };
+                current = occurrences.len();
+                occurrences.push(Occurrence {
+                    idx: sc.pos,
+                    parent: Some(parent),
+                    ..Default::default()
+                });
+                saw_content = true;
+            }
+            ')' => {
+                sc.bump();
+                current = stack
+                    .pop()
+                    .ok_or(NewickError::UnbalancedParens { idx: sc.pos })?;
+            }
+            ':' => {
+                // Up to three fields: length, support and probability.
+                for field in 0..3 {
+                    sc.bump();
+                    sc.skip_trivia(&mut comments)?;
+                    let idx = sc.pos;
+                    let token = sc.read_token();
+                    if !token.is_empty() && field != 1 {
+                        let value =
+                            token
+                                .parse::<f32>()
+                                .map_err(|_| NewickError::InvalidWeight {
+                                    idx,
+                                    text: token.to_string(),
+                                })?;
+                        match field {
+                            0 => occurrences[current].length = Some(value),
+                            _ => {
+                                check_inheritance(Some(value)).map_err(|_| {
+                                    NewickError::InvalidInheritance {
+                                        idx,
+                                        text: token.to_string(),
+                                    }
+                                })?;
+                                occurrences[current].inheritance = Some(value);
+                            }
+                        }
+                    }
+                    sc.skip_trivia(&mut comments)?;
+                    if sc.peek() != Some(':') {
+                        break;
+                    }
+                }
+            }
+            '\'' => {
+                occurrences[current].label = Some(sc.read_quoted()?);
+                saw_content = true;
+            }
+            _ => {
+                let label = sc.read_token();
+                if label.is_empty() {
+                    return Err(NewickError::InvalidCharacter { idx: sc.pos });
+                }
+                let (name, tag) = split_hybrid(label);
+                if !name.is_empty() {
+                    occurrences[current].label = Some(name.to_string());
+                }
+                if let Some(tag) = tag {
+                    occurrences[current].tag = Some(tag.to_string());
+                }
+                saw_content = true;
+            }
+        }
+    }
+    if !stack.is_empty() {
+        return Err(NewickError::UnbalancedParens { idx: sc.pos });
+    }
+    if !saw_content {
+        return Err(NewickError::Empty);
+    }
+
+    // Merge the occurrences of each tag into one node.
+    let mut taxa: Vec<Option<String>> = vec![];
+    let mut tags: HashMap<String, usize> = HashMap::default();
+    let mut node_tags: Vec<Option<String>> = vec![];
+    let mut has_children: Vec<bool> = vec![];
+    let mut nodes = Vec::with_capacity(occurrences.len());
+    for occurrence in &mut occurrences {
+        let node = match &occurrence.tag {
+            Some(tag) if tags.contains_key(tag) => tags[tag],
+            tag => {
+                if let Some(tag) = tag {
+                    tags.insert(tag.clone(), taxa.len());
+                }
+                taxa.push(None);
+                node_tags.push(tag.clone());
+                has_children.push(false);
+                taxa.len() - 1
+            }
+        };
+        if occurrence.has_children {
+            if has_children[node] {
+                return Err(NewickError::DuplicateHybrid {
+                    idx: occurrence.idx,
+                    tag: occurrence.tag.clone().unwrap_or_default(),
+                });
+            }
+            has_children[node] = true;
+        }
+        if taxa[node].is_none() {
+            taxa[node] = occurrence.label.take();
+        }
+        nodes.push(node);
+    }
+    let edges = occurrences
+        .iter()
+        .zip(&nodes)
+        .filter_map(|(occurrence, &child)| {
+            occurrence.parent.map(|parent| NetworkEdge {
+                parent: nodes[parent],
+                child,
+                length: occurrence.length,
+                inheritance: occurrence.inheritance,
+            })
+        })
+        .collect();
+    PhyloNetwork::from_parts(taxa, edges).map_err(|cyclic| NewickError::HybridCycle {
+        tag: cyclic
+            .into_iter()
+            .find_map(|v| node_tags[v].clone())
+            .expect("invariant: a cycle passes through a hybrid node"),
+    })
+}
@@ -0,0 +1,441 @@
+//! NeXML reader and writer.
+//!
+//! Reads the `FloatTree` and `IntTree` elements of every `trees` block. A
+//! node's taxon is the label of its OTU, or the OTU's id if it has no label,
+//! and otherwise the node's own label. Edge lengths become the child's edge
+//! weight, and a `rootedge` length the root's. A literal `meta` whose property
+//! is `support`, `confidence` or `bootstrap`, under any prefix, and whose
+//! content is a number becomes a support value. Every other `meta` of a node
+//! or of the edge above it is kept verbatim, in order, as the node's
+//! annotation. The nodes and edges of a tree must form one rooted tree: the
+//! node marked `root="true"`, or else the only node without a parent, is the
+//! root.
+//!
+//! The writer emits one `otus` block over the taxa of all trees and one
+//! `FloatTree` per tree, with support values as `phylo:support` metas. Kept
+//! metas are written back as read, so prefixes they use beyond `nex`, `xsi`,
+//! `xsd` and `phylo` are not declared in the output.
+
+use std::sync::Arc;
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
+
+use crate::error::XmlError;
+use crate::node::Node;
+use crate::prelude::*;
+use crate::tree::xml::{attribute, escape, local, Event, XmlReader};
+
+/// Properties read as support values.
+const SUPPORT_PROPERTIES: [&str; 3] = ["support", "confidence", "bootstrap"];
+
+/// Support values and other metadata of a node or edge.
+#[derive(Default)]
+struct Metas {
+    support: Vec<f32>,
+    annotation: String,
+}
+
+struct NodeRecord {
+    id: String,
+    /// Byte offset of the node's start tag.
+    idx: usize,
+    label: Option<String>,
+    root: bool,
+    metas: Metas,
+}
+
+struct EdgeRecord {
+    /// `None` for a `rootedge`.
+    source: Option<String>,
+    target: String,
+    length: Option<(usize, String)>,
+    metas: Metas,
+}
+
+/// Parses every tree of a NeXML document.
+pub(crate) fn parse_nexml<T, W, Z>(src: &str) -> Result<Vec<SimpleRootedTree<T, W, Z>>, XmlError>
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let mut reader = XmlReader::new(src);
+    let mut otus = HashMap::default();
+    let mut trees = vec![];
+    while let Some(event) = reader.next()? {
+        let Event::Start {
+            name, attributes, ..
+        } = event
+        else {
+            continue;
+        };
+        match local(name) {
+            "otu" => {
+                let id = attribute(&attributes, "id").unwrap_or_default().to_string();
+                let label = attribute(&attributes, "label").unwrap_or(&id).to_string();
+                otus.insert(id, label);
+                reader.skip_element()?;
+            }
+            "tree" => {
+                let (nodes, edges) = read_tree(&mut reader, src, &otus)?;
+                trees.push(build_tree(nodes, edges)?);
+            }
+            // Characters blocks and the like hold no tree.
+            "characters" => {
+                reader.skip_element()?;
+            }
+            _ => {}
+        }
+    }
+    match trees.is_empty() {
+        true => Err(XmlError::NoTree),
+        false => Ok(trees),
+    }
+}
+
+/// Reads the nodes and edges of the tree whose start tag was just read.
+/// Node labels are resolved against `otus` here.
+fn read_tree(
+    reader: &mut XmlReader<'_>,
+    src: &str,
+    otus: &HashMap<String, String>,
+) -> Result<(Vec<NodeRecord>, Vec<EdgeRecord>), XmlError> {
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    loop {
+        let (name, attributes, idx) = match reader.next_inside()? {
+            Event::Start {
+                name,
+                attributes,
+                idx,
+            } => (name, attributes, idx),
+            Event::End => return Ok((nodes, edges)),
+            Event::Text(_) => continue,
+        };
+        match local(name) {
+            "node" => {
+                let id = attribute(&attributes, "id")
+                    .ok_or_else(|| XmlError::Malformed {
+                        idx,
+                        reason: "node without an id".to_string(),
+                    })?
+                    .to_string();
+                let label = match attribute(&attributes, "otu") {
+                    Some(otu) => Some(
+                        otus.get(otu)
+                            .ok_or_else(|| XmlError::UnknownId(otu.to_string()))?
+                            .clone(),
+                    ),
+                    None => attribute(&attributes, "label").map(str::to_string),
+                };
+                nodes.push(NodeRecord {
+                    id,
+                    idx,
+                    label,
+                    root: attribute(&attributes, "root").is_some_and(|r| r.trim() == "true"),
+                    metas: read_metas(reader, src)?,
+                });
+            }
+            tag @ ("edge" | "rootedge") => {
+                let source = match tag {
+                    "edge" => Some(
+                        attribute(&attributes, "source")
+                            .ok_or_else(|| XmlError::Malformed {
+                                idx,
+                                reason: "edge without a source".to_string(),
+                            })?
+                            .to_string(),
+                    ),
+                    _ => None,
+                };
+                let target = attribute(&attributes, "target")
+                    .ok_or_else(|| XmlError::Malformed {
+                        idx,
+                        reason: format!("{tag} without a target"),
+                    })?
+                    .to_string();
+                edges.push(EdgeRecord {
+                    source,
+                    target,
+                    length: attribute(&attributes, "length").map(|l| (idx, l.to_string())),
+                    metas: read_metas(reader, src)?,
+                });
+            }
+            _ => {
+                reader.skip_element()?;
+            }
+        }
+    }
+}
+
+/// Reads the `meta` children of the element whose start tag was just read.
+fn read_metas(reader: &mut XmlReader<'_>, src: &str) -> Result<Metas, XmlError> {
+    let mut metas = Metas::default();
+    loop {
+        let (name, attributes, idx) = match reader.next_inside()? {
+            Event::Start {
+                name,
+                attributes,
+                idx,
+            } => (name, attributes, idx),
+            Event::End => return Ok(metas),
+            Event::Text(_) => continue,
+        };
+        let end = reader.skip_element()?;
+        if local(name) != "meta" {
+            continue;
+        }
+        let support = attribute(&attributes, "property")
+            .filter(|p| SUPPORT_PROPERTIES.contains(&local(p).to_ascii_lowercase().as_str()))
+            .and_then(|_| attribute(&attributes, "content"))
+            .and_then(|c| c.trim().parse::<f32>().ok());
+        match support {
+            Some(value) => metas.support.push(value),
+            None => metas.annotation.push_str(&src[idx..end]),
+        }
+    }
+}
+
+/// Links the nodes and edges of one NeXML tree into a rooted tree.
+fn build_tree<T, W, Z>(
+    nodes: Vec<NodeRecord>,
+    edges: Vec<EdgeRecord>,
+) -> Result<SimpleRootedTree<T, W, Z>, XmlError>
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let index: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+    let find = |id: &str| {
+        index
+            .get(id)
+            .copied()
+            .ok_or_else(|| XmlError::UnknownId(id.to_string()))
+    };
+    let mut parent: Vec<Option<usize>> = vec![None; nodes.len()];
+    let mut children: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+    let mut edge_above: Vec<Option<&EdgeRecord>> = vec![None; nodes.len()];
+    for edge in &edges {
+        let target = find(&edge.target)?;
+        if edge_above[target].is_some() {
+            return Err(XmlError::NotATree(format!(
+                "node {:?} has more than one parent",
+                edge.target
+            )));
+        }
+        edge_above[target] = Some(edge);
+        if let Some(source) = &edge.source {
+            let source = find(source)?;
+            parent[target] = Some(source);
+            children[source].push(target);
+        }
+    }
+    let root = match nodes.iter().position(|node| node.root) {
+        Some(root) => root,
+        None => {
+            let mut orphans = (0..nodes.len()).filter(|&i| parent[i].is_none());
+            match (orphans.next(), orphans.next()) {
+                (Some(root), None) => root,
+                (None, _) => return Err(XmlError::NotATree("no root".to_string())),
+                (Some(_), Some(_)) => {
+                    return Err(XmlError::NotATree("more than one root".to_string()))
+                }
+            }
+        }
+    };
+    if let Some(p) = parent[root] {
+        return Err(XmlError::NotATree(format!(
+            "root {:?} has parent {:?}",
+            nodes[root].id, nodes[p].id
+        )));
+    }
+
+    let mut tree = SimpleRootedTree::new(0);
+    let mut ids = vec![None; nodes.len()];
+    ids[root] = Some(tree.get_root_id());
+    let mut stack = vec![root];
+    let mut seen = 0;
+    while let Some(i) = stack.pop() {
+        seen += 1;
+        let id = ids[i].expect("invariant: ids are assigned before nodes are pushed");
+        for &child in children[i].iter() {
+            let child_id = tree.next_id();
+            tree.set_node(Node::new(child_id));
+            tree.set_child(id, child_id);
+            ids[child] = Some(child_id);
+        }
+        // Reversed so that children are visited in document order.
+        stack.extend(children[i].iter().rev());
+
+        let record = &nodes[i];
+        if let Some(label) = &record.label {
+            let taxa = T::from_str(label).map_err(|_| XmlError::InvalidValue {
+                idx: record.idx,
+                text: label.clone(),
+            })?;
+            tree.set_node_taxa(id, Some(taxa));
+        }
+        let mut support = record.metas.support.clone();
+        let mut annotation = record.metas.annotation.clone();
+        let mut weight = None;
+        if let Some(edge) = edge_above[i] {
+            support.extend_from_slice(&edge.metas.support);
+            annotation.push_str(&edge.metas.annotation);
+            if let Some((idx, length)) = &edge.length {
+                weight = Some(
+                    length
+                        .trim()
+                        .parse::<W>()
+                        .map_err(|_| XmlError::InvalidValue {
+                            idx: *idx,
+                            text: length.clone(),
+                        })?,
+                );
+            }
+        }
+        let node = tree
+            .get_node_mut(id)
+            .expect("invariant: node was added when its parent was visited");
+        node.set_weight(weight);
+        if !support.is_empty() {
+            node.set_support(Some(Arc::from(support)));
+        }
+        if !annotation.is_empty() {
+            node.set_annotation(Some(Arc::from(annotation)));
+        }
+    }
+    // A cycle among the nodes below the root leaves them unreachable.
+    if seen != nodes.len() {
+        return Err(XmlError::NotATree(format!(
+            "{} nodes are not reachable from the root",
+            nodes.len() - seen
+        )));
+    }
+    Ok(tree)
+}
+
+/// Writes `trees` as one NeXML document.
+pub(crate) fn write_nexml<T, W, Z>(trees: &[SimpleRootedTree<T, W, Z>]) -> String
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<nex:nexml version=\"0.9\" xmlns=\"http://www.nexml.org/2009\" ",
+        "xmlns:nex=\"http://www.nexml.org/2009\" ",
+        "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" ",
+        "xmlns:xsd=\"http://www.w3.org/2001/XMLSchema#\" ",
+        "xmlns:phylo=\"http://purl.org/phylo/\">\n",
+    ));
+    // Nodes are numbered in preorder, which also orders the edges and the
+    // OTUs, so output is deterministic.
+    let orders: Vec<Vec<_>> = trees
+        .iter()
+        .map(|tree| {
+            tree.dfs(tree.get_root_id())
+                .expect("invariant: the root is a node of the tree")
+                .map(|node| node.get_id())
+                .collect()
+        })
+        .collect();
+    let mut otus: Vec<String> = vec![];
+    let mut otu_ids: HashMap<String, usize> = HashMap::default();
+    for (tree, order) in trees.iter().zip(&orders) {
+        for &id in order {
+            if let Some(taxa) = tree.get_node_taxa(id) {
+                let taxa = taxa.to_string();
+                if !otu_ids.contains_key(&taxa) {
+                    otu_ids.insert(taxa.clone(), otus.len());
+                    otus.push(taxa);
+                }
+            }
+        }
+    }
+    out.push_str("  <otus id=\"otus1\">\n");
+    for (i, taxa) in otus.iter().enumerate() {
+        out.push_str(&format!(
+            "    <otu id=\"otu{}\" label=\"{}\"/>\n",
+            i + 1,
+            escape(taxa)
+        ));
+    }
+    out.push_str("  </otus>\n");
+    out.push_str("  <trees id=\"trees1\" otus=\"otus1\">\n");
+    for (t, (tree, order)) in trees.iter().zip(&orders).enumerate() {
+        let t = t + 1;
+        out.push_str(&format!(
+            "    <tree id=\"tree{t}\" xsi:type=\"nex:FloatTree\">\n"
+        ));
+        let number: HashMap<_, _> = order
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| (id, i + 1))
+            .collect();
+        let nodes = order.iter().map(|&id| {
+            tree.get_node(id)
+                .expect("invariant: id came from the traversal")
+        });
+        for (i, node) in nodes.clone().enumerate() {
+            out.push_str(&format!("      <node id=\"t{t}n{}\"", i + 1));
+            if let Some(taxa) = node.get_taxa() {
+                out.push_str(&format!(" otu=\"otu{}\"", otu_ids[&taxa.to_string()] + 1));
+            }
+            if i == 0 {
+                out.push_str(" root=\"true\"");
+            }
+            let support = node.get_support().unwrap_or_default();
+            let annotation = node.get_annotation().filter(|a| a.starts_with('<'));
+            if support.is_empty() && annotation.is_none() {
+                out.push_str("/>\n");
+                continue;
+            }
+            out.push_str(">\n");
+            for value in support {
+                out.push_str(&format!(
+                    "        <meta xsi:type=\"nex:LiteralMeta\" property=\"phylo:support\" datatype=\"xsd:double\" content=\"{value}\"/>\n"
+                ));
+            }
+            if let Some(annotation) = annotation {
+                out.push_str(&format!("        {annotation}\n"));
+            }
+            out.push_str("      </node>\n");
+        }
+        let mut edge = 0;
+        for node in nodes {
+            let target = number[&node.get_id()];
+            let length = node
+                .get_weight()
+                .map(|w| format!(" length=\"{w}\""))
+                .unwrap_or_default();
+            match node.get_parent() {
+                Some(p) => {
+                    edge += 1;
+                    out.push_str(&format!(
+                        "      <edge id=\"t{t}e{edge}\" source=\"t{t}n{}\" target=\"t{t}n{target}\"{length}/>\n",
+                        number[&p]
+                    ));
+                }
+                None if !length.is_empty() => {
+                    out.push_str(&format!(
+                        "      <rootedge id=\"t{t}e0\" target=\"t{t}n{target}\"{length}/>\n"
+                    ));
+                }
+                None => {}
+            }
+        }
+        out.push_str("    </tree>\n");
+    }
+    out.push_str("  </trees>\n");
+    out.push_str("</nex:nexml>\n");
+    out
+}
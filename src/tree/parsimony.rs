@@ -0,0 +1,771 @@
+//! Maximum parsimony: Fitch scoring and heuristic tree search.
+//!
+//! [`compute_parsimony_score`](crate::tree::parsimony::compute_parsimony_score)
+//! scores a tree against an [`Alignment`](crate::alignment::Alignment) with
+//! Fitch's algorithm over the unique patterns of
+//! [`Alignment::compress_columns`](crate::alignment::Alignment::compress_columns).
+//! The scoring is bit-parallel:
+//! [`FitchPatterns`](crate::tree::parsimony::FitchPatterns) stores a node's
+//! state sets as one bit-plane per state, with pattern `p` at bit `p % 64` of
+//! word `p / 64`, so a single pass of word-wide ANDs and ORs merges 64 patterns
+//! at a time. Each pattern's cost is weighted by its multiplicity, so the score
+//! equals the per-site Fitch score summed over the uncompressed alignment.
+//!
+//! [`parsimony_search`](crate::tree::parsimony::parsimony_search) builds
+//! starting trees by stepwise addition and then hill-climbs with NNI, SPR or
+//! TBR rearrangements, keeping every distinct tree that ties the best score
+//! (up to a cap). The search works on unrooted binary topologies internally,
+//! since the Fitch score does not depend on the root.
+//!
+//! Polytomies are scored as the left-to-right resolution of their children,
+//! which is exact for binary trees and an upper bound otherwise.
+
+#[cfg(feature = "simple_rooted_tree")]
+use {
+    crate::node::Node, crate::prelude::*, crate::tree::PhyloTree, rand::rngs::StdRng,
+    rand::seq::SliceRandom, rand::SeedableRng,
+};
+
+#[cfg(all(feature = "simple_rooted_tree", feature = "non_crypto_hash"))]
+use fxhash::FxHashSet as HashSet;
+#[cfg(all(feature = "simple_rooted_tree", not(feature = "non_crypto_hash")))]
+use std::collections::HashSet;
+
+use crate::alignment::{Alignment, CompressedColumns};
+use crate::alphabet::Alphabet;
+use crate::error::AsrError;
+
+/// Fitch parsimony score of an alignment given a tree.
+///
+/// Feature-free (like [`crate::tree::likelihood::TreeLikelihood`]) so a caller
+/// bringing its own tree type can implement it without `simple_rooted_tree`.
+pub trait TreeParsimony {
+    /// Minimum number of state changes needed to explain `aln` on this tree,
+    /// summed over all sites.
+    fn parsimony_score<A: Alphabet>(&self, aln: &Alignment) -> Result<usize, AsrError>;
+}
+
+/// Compressed alignment patterns packed into per-state bit-planes for Fitch
+/// scoring.
+///
+/// A state set occupies [`FitchPatterns::set_len`] words: state `s` of pattern
+/// `p` is bit `p % 64` of word `s * n_words + p / 64`.
+#[derive(Debug, Clone)]
+pub struct FitchPatterns {
+    n_states: usize,
+    n_words: usize,
+    /// Leaf state sets, in `leaf_order`.
+    leaf_sets: Vec<Vec<u64>>,
+    /// Pattern multiplicities, padded with zeros to a whole number of words so
+    /// the padding bits never add to a score.
+    multiplicity: Vec<usize>,
+    leaf_order: Vec<String>,
+}
+
+impl FitchPatterns {
+    /// Packs the unique patterns of `comp` for alphabet `A`.
+    ///
+    /// A character's state set is every state its [`Alphabet::profile`] gives
+    /// non-zero mass, so ambiguity codes and gaps become multi-state sets and
+    /// never force a change on their own.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AsrError::AlphabetMismatch`] if a character is not recognized
+    /// by `A`.
+    pub fn new<A: Alphabet>(comp: &CompressedColumns) -> Result<Self, AsrError> {
+        let n_states = A::N_STATES;
+        let n_patterns = comp.patterns.len();
+        let n_words = n_patterns.div_ceil(64).max(1);
+
+        let mut leaf_sets = vec![vec![0u64; n_states * n_words]; comp.leaf_order.len()];
+        for (p, pattern) in comp.patterns.iter().enumerate() {
+            let (word, bit) = (p / 64, 1u64 << (p % 64));
+            for (leaf, &c) in pattern.iter().enumerate() {
+                let profile = A::profile(c).ok_or_else(|| {
+                    AsrError::AlphabetMismatch(format!(
+                        "Invalid char {} in alignment",
+                        char::from(c)
+                    ))
+                })?;
+                for (s, mass) in profile.iter().enumerate() {
+                    if *mass > 0.0 {
+                        leaf_sets[leaf][s * n_words + word] |= bit;
+                    }
+                }
+            }
+        }
+
+        let mut multiplicity = comp.multiplicity.clone();
+        multiplicity.resize(n_words * 64, 0);
+
+        Ok(FitchPatterns {
+            n_states,
+            n_words,
+            leaf_sets,
+            multiplicity,
+            leaf_order: comp.leaf_order.clone(),
+        })
+    }
+
+    /// Compresses `aln` and packs its patterns for alphabet `A`.
+    ///
+    /// # Errors
+    ///
+    /// See [`FitchPatterns::new`].
+    pub fn from_alignment<A: Alphabet>(aln: &Alignment) -> Result<Self, AsrError> {
+        Self::new::<A>(&aln.compress_columns())
+    }
+
+    /// Taxa in the order their state sets are stored.
+    pub fn leaf_order(&self) -> &[String] {
+        &self.leaf_order
+    }
+
+    /// Number of words in one state set.
+    pub fn set_len(&self) -> usize {
+        self.n_states * self.n_words
+    }
+
+    /// State set of the `idx`-th leaf in [`FitchPatterns::leaf_order`].
+    pub fn leaf_set(&self, idx: usize) -> &[u64] {
+        &self.leaf_sets[idx]
+    }
+
+    /// Fitch merge of two child state sets into `out`, returning the
+    /// multiplicity-weighted number of patterns that needed a change.
+    ///
+    /// Per pattern the parent set is the intersection of the children if that
+    /// is non-empty, and their union (at a cost of one change) otherwise.
+    pub fn merge(&self, left: &[u64], right: &[u64], out: &mut [u64]) -> usize {
+        let n = self.n_words;
+        let mut cost = 0;
+        for w in 0..n {
+            let mut any_shared = 0u64;
+            for s in 0..self.n_states {
+                any_shared |= left[s * n + w] & right[s * n + w];
+            }
+            for s in 0..self.n_states {
+                let (l, r) = (left[s * n + w], right[s * n + w]);
+                out[s * n + w] = (l & r) | (!any_shared & (l | r));
+            }
+            // Padding bits carry zero multiplicity, so they need no mask.
+            let mut changed = !any_shared;
+            while changed != 0 {
+                cost += self.multiplicity[w * 64 + changed.trailing_zeros() as usize];
+                changed &= changed - 1;
+            }
+        }
+        cost
+    }
+}
+
+/// Fitch parsimony score of `aln` on `tree`.
+///
+/// # Errors
+///
+/// Returns [`AsrError::AlphabetMismatch`] if an alignment taxon is not a leaf
+/// of the tree, a tree leaf has no sequence, or a character is not in `A`.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_parsimony_score<A>(tree: &PhyloTree, aln: &Alignment) -> Result<usize, AsrError>
+where
+    A: Alphabet,
+{
+    let patterns = FitchPatterns::from_alignment::<A>(aln)?;
+    let len = patterns.set_len();
+
+    // `next_id` is the lowest vacant slot, so ids can run past it; size by the
+    // largest id instead.
+    let n_slots = tree.get_node_ids().max().map_or(0, |id| id + 1);
+    let mut sets: Vec<Option<Vec<u64>>> = vec![None; n_slots];
+    for (idx, name) in patterns.leaf_order().iter().enumerate() {
+        let node_id = tree.get_taxa_node_id(name).ok_or_else(|| {
+            AsrError::AlphabetMismatch(format!("Taxon {} in alignment not found in tree", name))
+        })?;
+        sets[node_id] = Some(patterns.leaf_set(idx).to_vec());
+    }
+
+    let root = tree.get_root_id();
+    let mut score = 0;
+    let mut scratch = vec![0u64; len];
+    for node_id in tree
+        .postord_ids(root)
+        .expect("invariant: the root id always names a node")
+    {
+        if tree.is_leaf(node_id) {
+            if sets[node_id].is_none() {
+                return Err(AsrError::AlphabetMismatch(format!(
+                    "Leaf {} in tree has no sequence in alignment",
+                    tree.get_node_taxa(node_id)
+                        .map(String::as_str)
+                        .unwrap_or("<unlabelled>")
+                )));
+            }
+            continue;
+        }
+        let mut children = tree.get_node_children_ids(node_id);
+        let first = children
+            .next()
+            .expect("invariant: a non-leaf node has a child");
+        let mut acc = sets[first]
+            .take()
+            .expect("invariant: children are visited before their parent");
+        for child in children {
+            let child_set = sets[child]
+                .take()
+                .expect("invariant: children are visited before their parent");
+            score += patterns.merge(&acc, &child_set, &mut scratch);
+            std::mem::swap(&mut acc, &mut scratch);
+        }
+        sets[node_id] = Some(acc);
+    }
+    Ok(score)
+}
+
+/// Branch-swapping neighbourhood explored by [`parsimony_search`], from the
+/// smallest to the largest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rearrangement {
+    /// Nearest-neighbour interchange across every internal edge.
+    Nni,
+    /// Subtree prune and regraft onto every edge of the remaining tree.
+    Spr,
+    /// Tree bisection and reconnection: like SPR, but the pruned subtree may
+    /// also be rerooted on any of its edges before regrafting.
+    Tbr,
+}
+
+/// Settings for [`parsimony_search`].
+#[derive(Debug, Clone)]
+pub struct ParsimonySearch {
+    moves: Rearrangement,
+    replicates: usize,
+    max_trees: usize,
+    seed: Option<u64>,
+}
+
+impl Default for ParsimonySearch {
+    fn default() -> Self {
+        ParsimonySearch {
+            moves: Rearrangement::Tbr,
+            replicates: 1,
+            max_trees: 100,
+            seed: None,
+        }
+    }
+}
+
+impl ParsimonySearch {
+    /// TBR search from a single stepwise-addition tree built in alignment
+    /// order, keeping at most 100 equally parsimonious trees.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the rearrangement used for hill-climbing.
+    pub fn with_moves(mut self, moves: Rearrangement) -> Self {
+        self.moves = moves;
+        self
+    }
+
+    /// Sets the number of stepwise-addition starting trees. With a seed every
+    /// replicate adds taxa in a random order; without one only the first
+    /// replicate is meaningful, since the order is fixed.
+    pub fn with_replicates(mut self, replicates: usize) -> Self {
+        self.replicates = replicates.max(1);
+        self
+    }
+
+    /// Caps how many equally parsimonious trees are kept.
+    pub fn with_max_trees(mut self, max_trees: usize) -> Self {
+        self.max_trees = max_trees.max(1);
+        self
+    }
+
+    /// Randomizes the taxon addition order with a seeded generator, so runs
+    /// are reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+/// The outcome of a [`parsimony_search`].
+#[cfg(feature = "simple_rooted_tree")]
+#[derive(Debug, Clone)]
+pub struct ParsimonyResult {
+    /// The best score found.
+    pub score: usize,
+    /// Distinct unrooted topologies achieving `score`, each written as a tree
+    /// with a basal trifurcation and no branch lengths.
+    pub trees: Vec<PhyloTree>,
+}
+
+/// An unrooted binary topology over the taxa of a [`FitchPatterns`].
+///
+/// Nodes `0..n_taxa` are the leaves, in [`FitchPatterns::leaf_order`]; the rest
+/// are internal nodes of degree three.
+#[cfg(feature = "simple_rooted_tree")]
+#[derive(Debug, Clone)]
+struct Topology {
+    adj: Vec<Vec<usize>>,
+    n_taxa: usize,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+impl Topology {
+    /// Three taxa joined at one internal node.
+    fn triplet(n_taxa: usize, a: usize, b: usize, c: usize) -> Self {
+        let mut adj = vec![Vec::with_capacity(3); n_taxa];
+        adj.push(vec![a, b, c]);
+        for leaf in [a, b, c] {
+            adj[leaf].push(n_taxa);
+        }
+        Topology { adj, n_taxa }
+    }
+
+    fn unlink(&mut self, u: usize, v: usize) {
+        self.adj[u].retain(|&x| x != v);
+        self.adj[v].retain(|&x| x != u);
+    }
+
+    fn link(&mut self, u: usize, v: usize) {
+        self.adj[u].push(v);
+        self.adj[v].push(u);
+    }
+
+    /// Splits edge `(x, y)` with `mid`.
+    fn split(&mut self, x: usize, y: usize, mid: usize) {
+        self.unlink(x, y);
+        self.link(x, mid);
+        self.link(mid, y);
+    }
+
+    /// Undirected edges reachable from `start` without crossing `blocked`.
+    fn edges_from(&self, start: usize, blocked: usize) -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        let mut stack = vec![(start, blocked)];
+        while let Some((node, parent)) = stack.pop() {
+            for &next in &self.adj[node] {
+                if next != parent && next != blocked {
+                    edges.push((node, next));
+                    stack.push((next, node));
+                }
+            }
+        }
+        edges
+    }
+
+    /// Nodes in post-order when rooted on the edge between leaf `anchor` and
+    /// its neighbour, paired with their parent. Nodes not connected to
+    /// `anchor` are skipped.
+    fn postorder(&self, anchor: usize) -> Vec<(usize, usize)> {
+        let mut order = Vec::with_capacity(self.adj.len());
+        let mut stack = vec![(self.adj[anchor][0], anchor)];
+        while let Some((node, parent)) = stack.pop() {
+            order.push((node, parent));
+            for &next in &self.adj[node] {
+                if next != parent {
+                    stack.push((next, node));
+                }
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Fitch score, hung off leaf `anchor`. Any leaf gives the same score, but
+    /// the anchor must be placed when scoring a partial tree.
+    fn score(&self, patterns: &FitchPatterns, anchor: usize) -> usize {
+        let len = patterns.set_len();
+        let mut sets = vec![0u64; self.adj.len() * len];
+        let mut scratch = vec![0u64; len];
+        let mut score = 0;
+        for (node, parent) in self.postorder(anchor) {
+            if node < self.n_taxa {
+                sets[node * len..(node + 1) * len].copy_from_slice(patterns.leaf_set(node));
+                continue;
+            }
+            let mut children = self.adj[node].iter().filter(|&&c| c != parent);
+            let (a, b) = (
+                *children
+                    .next()
+                    .expect("invariant: internal nodes have degree 3"),
+                *children
+                    .next()
+                    .expect("invariant: internal nodes have degree 3"),
+            );
+            score += patterns.merge(
+                &sets[a * len..(a + 1) * len],
+                &sets[b * len..(b + 1) * len],
+                &mut scratch,
+            );
+            sets[node * len..(node + 1) * len].copy_from_slice(&scratch);
+        }
+        let root = self.adj[anchor][0];
+        score
+            + patterns.merge(
+                patterns.leaf_set(anchor),
+                &sets[root * len..(root + 1) * len],
+                &mut scratch,
+            )
+    }
+
+    /// Canonical form: the sorted non-trivial splits, each as the bitmask of
+    /// taxa on the side away from leaf 0.
+    fn splits(&self) -> Vec<Vec<u64>> {
+        let words = self.n_taxa.div_ceil(64);
+        let mut below = vec![vec![0u64; words]; self.adj.len()];
+        let mut splits = Vec::with_capacity(self.n_taxa.saturating_sub(3));
+        for (node, parent) in self.postorder(0) {
+            if node < self.n_taxa {
+                below[node][node / 64] |= 1 << (node % 64);
+                continue;
+            }
+            let mut acc = vec![0u64; words];
+            for &c in self.adj[node].iter().filter(|&&c| c != parent) {
+                for (a, b) in acc.iter_mut().zip(&below[c]) {
+                    *a |= b;
+                }
+            }
+            if parent >= self.n_taxa {
+                splits.push(acc.clone());
+            }
+            below[node] = acc;
+        }
+        splits.sort_unstable();
+        splits
+    }
+
+    /// Every topology one NNI away.
+    fn nni_neighbours(&self) -> Vec<Topology> {
+        let mut out = Vec::new();
+        for u in self.n_taxa..self.adj.len() {
+            for &v in self.adj[u].iter().filter(|&&v| v > u) {
+                let a = *self.adj[u]
+                    .iter()
+                    .find(|&&x| x != v)
+                    .expect("invariant: internal nodes have degree 3");
+                for &b in self.adj[v].iter().filter(|&&x| x != u) {
+                    let mut t = self.clone();
+                    t.unlink(u, a);
+                    t.unlink(v, b);
+                    t.link(u, b);
+                    t.link(v, a);
+                    out.push(t);
+                }
+            }
+        }
+        out
+    }
+
+    /// Every topology reached by pruning at an internal node and regrafting,
+    /// optionally rerooting the pruned part first (TBR).
+    fn prune_regraft_neighbours(&self, tbr: bool) -> Vec<Topology> {
+        let mut out = Vec::new();
+        for u in self.n_taxa..self.adj.len() {
+            for &v in &self.adj[u] {
+                // Detach the subtree behind `v`, leaving `u` hanging off it, and
+                // close the gap between `u`'s other two neighbours.
+                let mut base = self.clone();
+                let rest: Vec<usize> = self.adj[u].iter().copied().filter(|&x| x != v).collect();
+                let (a, b) = (rest[0], rest[1]);
+                base.unlink(u, a);
+                base.unlink(u, b);
+                base.link(a, b);
+                let targets = base.edges_from(a, u);
+
+                // Candidate attachments of the pruned part: at `v` itself, or
+                // (TBR) on any edge of the pruned part after suppressing `v`.
+                let mut pruned = vec![base.clone()];
+                if tbr && v >= self.n_taxa {
+                    let mut rerooted = base.clone();
+                    rerooted.unlink(u, v);
+                    let ends: Vec<usize> = rerooted.adj[v].clone();
+                    rerooted.unlink(v, ends[0]);
+                    rerooted.unlink(v, ends[1]);
+                    rerooted.link(ends[0], ends[1]);
+                    for (p, q) in rerooted.edges_from(ends[0], v) {
+                        if (p, q) == (ends[0], ends[1]) || (p, q) == (ends[1], ends[0]) {
+                            continue;
+                        }
+                        let mut t = rerooted.clone();
+                        t.split(p, q, v);
+                        t.link(u, v);
+                        pruned.push(t);
+                    }
+                }
+
+                for t in pruned {
+                    for &(x, y) in &targets {
+                        if (x, y) == (a, b) || (x, y) == (b, a) {
+                            continue;
+                        }
+                        let mut n = t.clone();
+                        n.split(x, y, u);
+                        out.push(n);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn neighbours(&self, moves: Rearrangement) -> Vec<Topology> {
+        match moves {
+            Rearrangement::Nni => self.nni_neighbours(),
+            Rearrangement::Spr => self.prune_regraft_neighbours(false),
+            Rearrangement::Tbr => self.prune_regraft_neighbours(true),
+        }
+    }
+
+    /// Writes the topology as a tree rooted at leaf 0's neighbour.
+    fn to_tree(&self, names: &[String]) -> PhyloTree {
+        let mut tree = PhyloTree::new(0);
+        let root = self.adj[0][0];
+        let mut stack = vec![(root, usize::MAX, tree.get_root_id())];
+        while let Some((node, parent, tree_id)) = stack.pop() {
+            if node < self.n_taxa {
+                tree.set_node_taxa(tree_id, Some(names[node].clone()));
+            }
+            for &next in self.adj[node].iter().filter(|&&x| x != parent) {
+                let child = Node::new(tree.next_id());
+                let child_id = child.get_id();
+                tree.add_child(tree_id, child);
+                stack.push((next, node, child_id));
+            }
+        }
+        tree
+    }
+}
+
+/// Builds one tree by stepwise addition: taxa are inserted in `order`, each on
+/// the edge that gives the lowest score so far.
+#[cfg(feature = "simple_rooted_tree")]
+fn stepwise_addition(patterns: &FitchPatterns, order: &[usize]) -> Topology {
+    let n_taxa = order.len();
+    let mut topo = Topology::triplet(n_taxa, order[0], order[1], order[2]);
+    for &taxon in &order[3..] {
+        let mid = topo.adj.len();
+        topo.adj.push(Vec::with_capacity(3));
+        let mut best: Option<(usize, (usize, usize))> = None;
+        for (x, y) in topo.edges_from(order[0], usize::MAX) {
+            topo.split(x, y, mid);
+            topo.link(mid, taxon);
+            // Unplaced taxa are still isolated, so anchor on a placed leaf.
+            let score = topo.score(patterns, order[0]);
+            topo.unlink(mid, taxon);
+            topo.unlink(x, mid);
+            topo.unlink(mid, y);
+            topo.link(x, y);
+            if best.is_none_or(|(s, _)| score < s) {
+                best = Some((score, (x, y)));
+            }
+        }
+        let (_, (x, y)) = best.expect("invariant: a tree with three taxa has edges");
+        topo.split(x, y, mid);
+        topo.link(mid, taxon);
+    }
+    topo
+}
+
+/// Searches for maximum-parsimony trees for `aln`.
+///
+/// Each replicate builds a stepwise-addition tree, and the pool of best trees
+/// is then improved by `settings`' rearrangement until no neighbour of any
+/// pooled tree scores better. Neighbours that tie the best score join the pool
+/// (up to the cap), so the result holds a set of equally parsimonious trees
+/// rather than just the first one found.
+///
+/// # Errors
+///
+/// Returns [`AsrError::InvalidAlignment`] if the alignment has fewer than three
+/// taxa, and [`AsrError::AlphabetMismatch`] if a character is not in `A`.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn parsimony_search<A>(
+    aln: &Alignment,
+    settings: &ParsimonySearch,
+) -> Result<ParsimonyResult, AsrError>
+where
+    A: Alphabet,
+{
+    let patterns = FitchPatterns::from_alignment::<A>(aln)?;
+    let n_taxa = patterns.leaf_order().len();
+    if n_taxa < 3 {
+        return Err(AsrError::InvalidAlignment(format!(
+            "Parsimony search needs at least 3 taxa, found {}",
+            n_taxa
+        )));
+    }
+
+    // Compression keeps the alignment's row order, so without a seed taxa are
+    // added in that order and the search is deterministic.
+    let base_order: Vec<usize> = (0..n_taxa).collect();
+    let mut rng = settings.seed.map(StdRng::seed_from_u64);
+
+    let mut best_score = usize::MAX;
+    let mut pool: Vec<Topology> = Vec::new();
+    let mut seen: HashSet<Vec<Vec<u64>>> = HashSet::default();
+    let offer = |topo: Topology,
+                 score: usize,
+                 best_score: &mut usize,
+                 pool: &mut Vec<Topology>,
+                 seen: &mut HashSet<Vec<Vec<u64>>>|
+     -> bool {
+        if score < *best_score {
+            *best_score = score;
+            pool.clear();
+            seen.clear();
+            seen.insert(topo.splits());
+            pool.push(topo);
+            true
+        } else {
+            if score == *best_score && pool.len() < settings.max_trees && seen.insert(topo.splits())
+            {
+                pool.push(topo);
+            }
+            false
+        }
+    };
+
+    for _ in 0..settings.replicates {
+        let mut order = base_order.clone();
+        if let Some(rng) = rng.as_mut() {
+            order.shuffle(rng);
+        }
+        let start = stepwise_addition(&patterns, &order);
+        let score = start.score(&patterns, 0);
+        offer(start, score, &mut best_score, &mut pool, &mut seen);
+    }
+
+    let mut idx = 0;
+    while idx < pool.len() {
+        let current = pool[idx].clone();
+        idx += 1;
+        for candidate in current.neighbours(settings.moves) {
+            let score = candidate.score(&patterns, 0);
+            if offer(candidate, score, &mut best_score, &mut pool, &mut seen) {
+                // A strictly better tree replaced the pool; start over from it.
+                idx = 0;
+                break;
+            }
+        }
+    }
+
+    let trees = pool
+        .iter()
+        .map(|topo| topo.to_tree(patterns.leaf_order()))
+        .collect();
+    Ok(ParsimonyResult {
+        score: best_score,
+        trees,
+    })
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::alphabet::Nucleotide;
+
+    fn parse(newick: &str) -> PhyloTree {
+        PhyloTree::from_newick(newick.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_fitch_score_counts_changes() {
+        let aln = Alignment::from_fasta_bytes(b">A\nAAC\n>B\nAAC\n>C\nGGC\n>D\nGGT\n").unwrap();
+        let grouped = parse("((A,B),(C,D));");
+        let split = parse("((A,C),(B,D));");
+        assert_eq!(grouped.parsimony_score::<Nucleotide>(&aln).unwrap(), 3);
+        assert_eq!(split.parsimony_score::<Nucleotide>(&aln).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_fitch_score_is_root_invariant() {
+        let aln = Alignment::from_fasta_bytes(
+            b">A\nACGTTA\n>B\nACGATA\n>C\nTCGAAA\n>D\nTTGACA\n>E\nGTCACA\n",
+        )
+        .unwrap();
+        let a = parse("(((A,B),C),(D,E));");
+        let b = parse("(A,(B,(C,(D,E))));");
+        assert_eq!(
+            a.parsimony_score::<Nucleotide>(&aln).unwrap(),
+            b.parsimony_score::<Nucleotide>(&aln).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fitch_score_treats_gaps_as_missing() {
+        let aln = Alignment::from_fasta_bytes(b">A\nA-\n>B\nAR\n>C\nG-\n>D\nGG\n").unwrap();
+        assert_eq!(
+            parse("((A,B),(C,D));")
+                .parsimony_score::<Nucleotide>(&aln)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_fitch_score_missing_taxon_errors() {
+        let aln = Alignment::from_fasta_bytes(b">A\nA\n>B\nA\n>X\nA\n").unwrap();
+        assert!(parse("((A,B),C);")
+            .parsimony_score::<Nucleotide>(&aln)
+            .is_err());
+    }
+
+    #[test]
+    fn test_search_finds_optimal_tree() {
+        let aln = Alignment::from_fasta_bytes(
+            b">A\nAAAAAAAAAA\n>B\nAAAAACCCCC\n>C\nCCCAACCCCC\n>D\nCCCCCGGGGG\n>E\nCCCCCGGTTT\n>F\nCCCCCGGTTA\n",
+        )
+        .unwrap();
+        for moves in [Rearrangement::Nni, Rearrangement::Spr, Rearrangement::Tbr] {
+            let result =
+                parsimony_search::<Nucleotide>(&aln, &ParsimonySearch::new().with_moves(moves))
+                    .unwrap();
+            assert!(!result.trees.is_empty());
+            for tree in &result.trees {
+                assert_eq!(
+                    tree.parsimony_score::<Nucleotide>(&aln).unwrap(),
+                    result.score
+                );
+                assert_eq!(tree.num_taxa(), 6);
+            }
+            let reference = parse("(A,(B,(C,(D,(E,F)))));");
+            assert!(result.score <= reference.parsimony_score::<Nucleotide>(&aln).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_search_returns_all_tied_trees() {
+        // Every column is constant, so all 15 unrooted five-taxon trees tie.
+        let aln = Alignment::from_fasta_bytes(b">A\nAC\n>B\nAC\n>C\nAC\n>D\nAC\n>E\nAC\n").unwrap();
+        let result = parsimony_search::<Nucleotide>(&aln, &ParsimonySearch::new()).unwrap();
+        assert_eq!(result.score, 0);
+        assert_eq!(result.trees.len(), 15);
+
+        let capped =
+            parsimony_search::<Nucleotide>(&aln, &ParsimonySearch::new().with_max_trees(4))
+                .unwrap();
+        assert_eq!(capped.trees.len(), 4);
+    }
+
+    #[test]
+    fn test_search_is_reproducible_with_seed() {
+        let aln = Alignment::from_fasta_bytes(
+            b">A\nACGTTA\n>B\nACGATA\n>C\nTCGAAA\n>D\nTTGACA\n>E\nGTCACA\n",
+        )
+        .unwrap();
+        let settings = ParsimonySearch::new().with_seed(7).with_replicates(3);
+        let first = parsimony_search::<Nucleotide>(&aln, &settings).unwrap();
+        let second = parsimony_search::<Nucleotide>(&aln, &settings).unwrap();
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.trees.len(), second.trees.len());
+    }
+
+    #[test]
+    fn test_search_needs_three_taxa() {
+        let aln = Alignment::from_fasta_bytes(b">A\nA\n>B\nC\n").unwrap();
+        assert!(parsimony_search::<Nucleotide>(&aln, &ParsimonySearch::new()).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! PhyloXML reader and writer.
+//!
+//! Each `<phylogeny>` of a document is one tree, rooted at its first
+//! `<clade>`. A clade's `name`, its `branch_length`, given as an attribute or
+//! an element, and its `confidence` values become the node's taxon, edge
+//! weight and support values. A clade without a name takes the
+//! `scientific_name` of its `taxonomy` as its taxon. Every other element of a
+//! clade, such as `taxonomy`, `color`, `events` or `property`, is kept
+//! verbatim, in order, as the node's annotation, and written back the same
+//! way. Confidence types other than `unknown` are kept at the head of the
+//! annotation, as `confidence` elements, and the writer gives the node's
+//! support values those types in order; values without one are `unknown`.
+
+use std::sync::Arc;
+
+use crate::error::XmlError;
+use crate::node::Node;
+use crate::prelude::*;
+use crate::tree::xml::{attribute, escape, first_text, local, Event, XmlReader};
+
+/// What a clade holds besides its children, gathered until its end tag.
+struct Clade<I> {
+    id: I,
+    /// Byte offset of the clade's start tag.
+    idx: usize,
+    name: Option<String>,
+    scientific_name: Option<String>,
+    support: Vec<f32>,
+    /// The `type` of each confidence in `support`.
+    types: Vec<String>,
+    annotation: String,
+}
+
+impl<I> Clade<I> {
+    fn new(id: I, idx: usize) -> Self {
+        Clade {
+            id,
+            idx,
+            name: None,
+            scientific_name: None,
+            support: vec![],
+            types: vec![],
+            annotation: String::new(),
+        }
+    }
+}
+
+/// Parses every phylogeny of a PhyloXML document that has a clade.
+pub(crate) fn parse_phyloxml<T, W, Z>(src: &str) -> Result<Vec<SimpleRootedTree<T, W, Z>>, XmlError>
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let mut reader = XmlReader::new(src);
+    let mut trees = vec![];
+    while let Some(event) = reader.next()? {
+        let Event::Start { name, .. } = event else {
+            continue;
+        };
+        if local(name) != "phylogeny" {
+            continue;
+        }
+        // The first clade is the tree; the phylogeny's name, description and
+        // other elements are not kept.
+        let mut tree = None;
+        loop {
+            match reader.next_inside()? {
+                Event::Start {
+                    name,
+                    attributes,
+                    idx,
+                } if local(name) == "clade" && tree.is_none() => {
+                    tree = Some(read_clades(&mut reader, src, &attributes, idx)?);
+                }
+                Event::Start { .. } => {
+                    reader.skip_element()?;
+                }
+                Event::End => break,
+                Event::Text(_) => {}
+            }
+        }
+        trees.extend(tree);
+    }
+    match trees.is_empty() {
+        true => Err(XmlError::NoTree),
+        false => Ok(trees),
+    }
+}
+
+/// Reads the clade whose start tag was just read, with `attributes` at byte
+/// `idx`, and everything below it.
+fn read_clades<T, W, Z>(
+    reader: &mut XmlReader<'_>,
+    src: &str,
+    attributes: &[(&str, String)],
+    idx: usize,
+) -> Result<SimpleRootedTree<T, W, Z>, XmlError>
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let mut tree = SimpleRootedTree::new(0);
+    let root = tree.get_root_id();
+    set_weight(&mut tree, root, attribute(attributes, "branch_length"), idx)?;
+    let mut stack = vec![Clade::new(root, idx)];
+    while let Some(clade) = stack.last_mut() {
+        match reader.next_inside()? {
+            Event::Start {
+                name,
+                attributes,
+                idx,
+            } => match local(name) {
+                "clade" => {
+                    let child = tree.next_id();
+                    tree.set_node(Node::new(child));
+                    tree.set_child(clade.id, child);
+                    set_weight(
+                        &mut tree,
+                        child,
+                        attribute(&attributes, "branch_length"),
+                        idx,
+                    )?;
+                    stack.push(Clade::new(child, idx));
+                }
+                "name" => {
+                    let text = reader.read_text()?;
+                    if !text.trim().is_empty() {
+                        clade.name = Some(text.trim().to_string());
+                    }
+                }
+                "branch_length" => {
+                    let text = reader.read_text()?;
+                    let id = clade.id;
+                    set_weight(&mut tree, id, Some(&text), idx)?;
+                }
+                "confidence" => {
+                    let kind = attribute(&attributes, "type").unwrap_or("unknown");
+                    clade.types.push(kind.to_string());
+                    let text = reader.read_text()?;
+                    let value = text
+                        .trim()
+                        .parse::<f32>()
+                        .map_err(|_| XmlError::InvalidValue {
+                            idx,
+                            text: text.clone(),
+                        })?;
+                    clade.support.push(value);
+                }
+                other => {
+                    let end = reader.skip_element()?;
+                    let fragment = &src[idx..end];
+                    if other == "taxonomy" && clade.scientific_name.is_none() {
+                        clade.scientific_name = first_text(fragment, "scientific_name")
+                            .map(|name| name.trim().to_string())
+                            .filter(|name| !name.is_empty());
+                    }
+                    clade.annotation.push_str(fragment);
+                }
+            },
+            Event::End => {
+                let clade = stack.pop().expect("invariant: guarded by while let");
+                if let Some(label) = clade.name.or(clade.scientific_name) {
+                    let taxa = T::from_str(&label).map_err(|_| XmlError::InvalidValue {
+                        idx: clade.idx,
+                        text: label.clone(),
+                    })?;
+                    tree.set_node_taxa(clade.id, Some(taxa));
+                }
+                let node = tree
+                    .get_node_mut(clade.id)
+                    .expect("invariant: clades are added to the tree as they open");
+                let mut annotation = String::new();
+                if clade.types.iter().any(|kind| kind != "unknown") {
+                    for (kind, value) in clade.types.iter().zip(&clade.support) {
+                        annotation.push_str(&confidence(kind, *value));
+                    }
+                }
+                annotation.push_str(&clade.annotation);
+                if !clade.support.is_empty() {
+                    node.set_support(Some(Arc::from(clade.support)));
+                }
+                if !annotation.is_empty() {
+                    node.set_annotation(Some(Arc::from(annotation)));
+                }
+            }
+            Event::Text(_) => {}
+        }
+    }
+    Ok(tree)
+}
+
+fn set_weight<T, W, Z>(
+    tree: &mut SimpleRootedTree<T, W, Z>,
+    id: TreeNodeID<SimpleRootedTree<T, W, Z>>,
+    text: Option<&str>,
+    idx: usize,
+) -> Result<(), XmlError>
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let Some(text) = text else {
+        return Ok(());
+    };
+    let weight = text
+        .trim()
+        .parse::<W>()
+        .map_err(|_| XmlError::InvalidValue {
+            idx,
+            text: text.to_string(),
+        })?;
+    if let Some(node) = tree.get_node_mut(id) {
+        node.set_weight(Some(weight));
+    }
+    Ok(())
+}
+
+/// A `confidence` element of type `kind`.
+fn confidence(kind: &str, value: f32) -> String {
+    format!("<confidence type=\"{}\">{value}</confidence>", escape(kind))
+}
+
+/// Splits the `confidence` elements off the head of `annotation`, returning
+/// their types and the rest of the annotation.
+fn confidence_types(annotation: &str) -> (Vec<&str>, &str) {
+    let mut types = vec![];
+    let mut rest = annotation.trim_start();
+    while let Some(tail) = rest.strip_prefix("<confidence") {
+        let Some(end) = tail.find("</confidence>") else {
+            break;
+        };
+        let tag = &tail[..tail.find('>').unwrap_or(end)];
+        let kind = tag
+            .split_once("type=\"")
+            .and_then(|(_, value)| value.split_once('"'))
+            .map_or("unknown", |(kind, _)| kind);
+        types.push(kind);
+        rest = tail[end + "</confidence>".len()..].trim_start();
+    }
+    (types, rest)
+}
+
+/// Writes `trees` as one PhyloXML document.
+pub(crate) fn write_phyloxml<T, W, Z>(trees: &[SimpleRootedTree<T, W, Z>]) -> String
+where
+    T: NodeTaxa,
+    W: EdgeWeight,
+    Z: NodeWeight,
+{
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<phyloxml xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" ",
+        "xsi:schemaLocation=\"http://www.phyloxml.org http://www.phyloxml.org/1.10/phyloxml.xsd\" ",
+        "xmlns=\"http://www.phyloxml.org\">\n",
+    ));
+    for tree in trees {
+        out.push_str("  <phylogeny rooted=\"true\">\n");
+        // Iterative, like the Newick writer. Each frame is (node id, index of
+        // the next child to write).
+        let mut stack = vec![(tree.get_root_id(), 0)];
+        while let Some(&(id, child_idx)) = stack.last() {
+            let node = tree
+                .get_node(id)
+                .expect("invariant: id came from the traversal stack");
+            let indent = "  ".repeat(stack.len() + 1);
+            if child_idx == 0 {
+                out.push_str(&indent);
+                out.push_str("<clade");
+                if let Some(w) = node.get_weight() {
+                    out.push_str(&format!(" branch_length=\"{w}\""));
+                }
+                out.push_str(">\n");
+                if let Some(taxa) = node.get_taxa() {
+                    let taxa = taxa.to_string();
+                    out.push_str(&format!("{indent}  <name>{}</name>\n", escape(&taxa)));
+                }
+                // Annotations read from other formats, such as Newick
+                // comments, are not XML and are left out.
+                let annotation = node.get_annotation().filter(|a| a.starts_with('<'));
+                let (types, rest) = annotation.map(confidence_types).unwrap_or_default();
+                for (i, value) in node.get_support().unwrap_or_default().iter().enumerate() {
+                    // Types were kept verbatim, so they are already escaped.
+                    let kind = types.get(i).copied().unwrap_or("unknown");
+                    out.push_str(&format!(
+                        "{indent}  <confidence type=\"{kind}\">{value}</confidence>\n"
+                    ));
+                }
+                if !rest.is_empty() {
+                    out.push_str(&format!("{indent}  {rest}\n"));
+                }
+            }
+            match node.get_children().get(child_idx) {
+                Some(&child) => {
+                    stack
+                        .last_mut()
+                        .expect("invariant: guarded by while let Some(..) = stack.last()")
+                        .1 += 1;
+                    stack.push((child, 0));
+                }
+                None => {
+                    out.push_str(&indent);
+                    out.push_str("</clade>\n");
+                    stack.pop();
+                }
+            }
+        }
+        out.push_str("  </phylogeny>\n");
+    }
+    out.push_str("</phyloxml>\n");
+    out
+}
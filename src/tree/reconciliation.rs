@@ -0,0 +1,741 @@
+//! Gene tree / species tree reconciliation under duplication, transfer and
+//! loss.
+//!
+//! Genes are tied to species by a map from gene leaf name to species leaf
+//! name, so several genes may share a species.
+//!
+//! [`reconcile`](crate::tree::reconciliation::Reconcile::reconcile) is the LCA
+//! reconciliation of Goodman et al. (1979) and Page (1994). Every gene node
+//! maps to the last common ancestor of its children's species, read off an
+//! [`LcaOracle`](crate::iter::lca::LcaOracle) of the species tree in `O(1)`.
+//! A node that maps to the same species as one of its children is a
+//! duplication and the rest are speciations. Each species branch a gene
+//! lineage skips past is a loss. The LCA mapping minimises duplications and
+//! losses together, so it is optimal for any positive costs.
+//!
+//! [`best_rooting`](crate::tree::reconciliation::Reconcile::best_rooting)
+//! scores every rooting of the gene tree in `O(n)` overall. Each rooted
+//! subtree of the unrooted gene tree is reconciled once, from the two subtrees
+//! below it, and a rooting costs the two subtrees either side of its root edge
+//! plus the new root.
+//!
+//! [`reconcile_dtl`](crate::tree::reconciliation::Reconcile::reconcile_dtl)
+//! also allows horizontal transfers, under the undated model of Bansal, Alm &
+//! Kellis (2012). A dynamic program over every pair of gene and species nodes
+//! finds a most parsimonious reconciliation in `O(|G| |S|)` time. A transfer
+//! moves one child of a gene node to any species that is neither an ancestor
+//! nor a descendant of the donor, so the reconciliation need not be consistent
+//! with a timing of the species tree.
+//!
+//! Unary nodes of the species tree are not speciations and are passed over.
+//! Gene trees must be binary, though `best_rooting` also accepts a gene tree
+//! with a three-way root, as written for unrooted trees.
+
+use std::collections::HashMap;
+
+use crate::error::ReconciliationError;
+use crate::node::NodeID;
+
+#[cfg(feature = "simple_rooted_tree")]
+use {
+    crate::iter::lca::LcaOracle, crate::node::Node, crate::prelude::*, crate::tree::PhyloTree,
+    itertools::Itertools,
+};
+
+/// Reconciliation of a gene tree with a species tree.
+///
+/// Feature-free (like [`crate::tree::likelihood::TreeLikelihood`]) so a caller
+/// bringing its own tree type can implement it without `simple_rooted_tree`.
+pub trait Reconcile: Sized {
+    /// LCA reconciliation of this gene tree with `species`, where `map` gives
+    /// the species of every gene leaf.
+    fn reconcile(
+        &self,
+        species: &Self,
+        map: &HashMap<String, String>,
+    ) -> Result<Reconciliation, ReconciliationError>;
+
+    /// The rooting of this gene tree whose LCA reconciliation costs least
+    /// under `costs`, with that reconciliation. Ties keep the current root.
+    fn best_rooting(
+        &self,
+        species: &Self,
+        map: &HashMap<String, String>,
+        costs: &EventCosts,
+    ) -> Result<(Self, Reconciliation), ReconciliationError>;
+
+    /// A most parsimonious reconciliation allowing duplications, transfers
+    /// and losses under `costs`.
+    fn reconcile_dtl(
+        &self,
+        species: &Self,
+        map: &HashMap<String, String>,
+        costs: &EventCosts,
+    ) -> Result<Reconciliation, ReconciliationError>;
+}
+
+/// The event at a gene tree node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GeneEvent {
+    /// An extant gene.
+    Leaf,
+    /// The children split with the species.
+    Speciation,
+    /// Both children stay in the species of the node.
+    Duplication,
+    /// One child stays and the other moves to an unrelated species.
+    Transfer,
+}
+
+/// Costs of the events in a reconciliation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventCosts {
+    /// Cost of a duplication.
+    pub duplication: f64,
+    /// Cost of a transfer.
+    pub transfer: f64,
+    /// Cost of a loss.
+    pub loss: f64,
+}
+
+impl Default for EventCosts {
+    /// The usual DTL costs: duplications 2, transfers 3, losses 1.
+    fn default() -> Self {
+        EventCosts::new(2.0, 3.0, 1.0)
+    }
+}
+
+impl EventCosts {
+    /// Costs for a duplication, a transfer and a loss.
+    pub fn new(duplication: f64, transfer: f64, loss: f64) -> Self {
+        EventCosts {
+            duplication,
+            transfer,
+            loss,
+        }
+    }
+
+    #[cfg(feature = "simple_rooted_tree")]
+    fn check(&self) -> Result<(), ReconciliationError> {
+        for (name, cost) in [
+            ("duplication", self.duplication),
+            ("transfer", self.transfer),
+            ("loss", self.loss),
+        ] {
+            if !(cost >= 0.0 && cost.is_finite()) {
+                return Err(ReconciliationError::InvalidCost(format!(
+                    "{name} cost must be finite and non-negative, got {cost}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reconciled gene tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reconciliation {
+    /// Species node of every gene node.
+    pub mapping: HashMap<NodeID, NodeID>,
+    /// Event at every gene node.
+    pub events: HashMap<NodeID, GeneEvent>,
+    /// Number of duplications.
+    pub duplications: usize,
+    /// Number of transfers.
+    pub transfers: usize,
+    /// Number of losses.
+    pub losses: usize,
+}
+
+impl Reconciliation {
+    /// Total cost of the events under `costs`.
+    pub fn cost(&self, costs: &EventCosts) -> f64 {
+        self.duplications as f64 * costs.duplication
+            + self.transfers as f64 * costs.transfer
+            + self.losses as f64 * costs.loss
+    }
+}
+
+/// The species tree with unary nodes passed over, its nodes numbered in
+/// post-order so each subtree is the range of numbers ending at its root.
+#[cfg(feature = "simple_rooted_tree")]
+struct SpeciesIndex<'a> {
+    /// Tree node of each number.
+    ids: Vec<NodeID>,
+    /// Number of each tree node; a unary node shares its child's.
+    index: Vec<usize>,
+    children: Vec<Option<(usize, usize)>>,
+    /// Nodes in the subtree, itself included.
+    size: Vec<usize>,
+    /// Speciations above the node.
+    depth: Vec<usize>,
+    /// Number of each leaf, by name.
+    leaves: HashMap<String, usize>,
+    oracle: LcaOracle<'a, PhyloTree>,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+impl<'a> SpeciesIndex<'a> {
+    fn new(tree: &'a PhyloTree) -> Result<Self, ReconciliationError> {
+        let n_slots = tree.get_node_ids().max().map_or(0, |id| id + 1);
+        let mut index = vec![usize::MAX; n_slots];
+        let (mut ids, mut children, mut size) = (vec![], vec![], vec![]);
+        let mut leaves = HashMap::new();
+        let postord = tree
+            .postord_ids(tree.get_root_id())
+            .expect("invariant: the root id always names a node");
+        for node in postord {
+            let kids = tree.get_node_children_ids(node).collect_vec();
+            let pair = match kids.as_slice() {
+                [] => None,
+                &[only] => {
+                    index[node] = index[only];
+                    continue;
+                }
+                &[a, b] => Some((index[a], index[b])),
+                _ => return Err(ReconciliationError::NonBinarySpecies(node)),
+            };
+            index[node] = ids.len();
+            if let Some(name) = tree.get_node_taxa(node).filter(|_| pair.is_none()) {
+                leaves.insert(name.clone(), ids.len());
+            }
+            size.push(1 + pair.map_or(0, |(a, b)| size[a] + size[b]));
+            ids.push(node);
+            children.push(pair);
+        }
+        let mut depth = vec![0; ids.len()];
+        for s in (0..ids.len()).rev() {
+            if let Some((a, b)) = children[s] {
+                depth[a] = depth[s] + 1;
+                depth[b] = depth[s] + 1;
+            }
+        }
+        Ok(SpeciesIndex {
+            ids,
+            index,
+            children,
+            size,
+            depth,
+            leaves,
+            oracle: tree.lca(),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn lca(&self, a: usize, b: usize) -> usize {
+        self.index[self.oracle.get_lca_id(&[self.ids[a], self.ids[b]])]
+    }
+
+    /// Whether either node lies in the other's subtree.
+    fn comparable(&self, a: usize, b: usize) -> bool {
+        let below = |x: usize, y: usize| y <= x && y + self.size[x] > x;
+        below(a, b) || below(b, a)
+    }
+
+    /// Species of a gene leaf.
+    fn species_of(
+        &self,
+        gene: &PhyloTree,
+        leaf: NodeID,
+        map: &HashMap<String, String>,
+    ) -> Result<usize, ReconciliationError> {
+        let name = gene
+            .get_node_taxa(leaf)
+            .ok_or(ReconciliationError::UnlabelledGene(leaf))?;
+        let species = map
+            .get(name)
+            .ok_or_else(|| ReconciliationError::UnmappedGene(name.clone()))?;
+        self.leaves
+            .get(species)
+            .copied()
+            .ok_or_else(|| ReconciliationError::UnknownSpecies(species.clone()))
+    }
+
+    /// LCA mapping of a gene node with children mapped to `a` and `b`: its
+    /// species, whether it is a duplication, and the losses on its two child
+    /// branches.
+    fn join(&self, a: usize, b: usize) -> (usize, bool, usize) {
+        let m = self.lca(a, b);
+        let duplication = m == a || m == b;
+        let skipped = self.depth[a] + self.depth[b] - 2 * self.depth[m];
+        (m, duplication, skipped - if duplication { 0 } else { 2 })
+    }
+}
+
+/// The two children of a gene node, or `None` for a leaf.
+#[cfg(feature = "simple_rooted_tree")]
+fn gene_children(
+    gene: &PhyloTree,
+    node: NodeID,
+) -> Result<Option<(NodeID, NodeID)>, ReconciliationError> {
+    match gene.get_node_children_ids(node).collect_vec().as_slice() {
+        [] => Ok(None),
+        &[a, b] => Ok(Some((a, b))),
+        _ => Err(ReconciliationError::NonBinaryGene(node)),
+    }
+}
+
+/// LCA reconciliation of `gene` with `species`.
+///
+/// # Errors
+///
+/// Returns [`ReconciliationError::UnmappedGene`] or
+/// [`ReconciliationError::UnknownSpecies`] if a gene leaf has no species leaf,
+/// [`ReconciliationError::NonBinaryGene`] if a gene node does not have two
+/// children and [`ReconciliationError::NonBinarySpecies`] if a species node
+/// has more.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_reconciliation(
+    gene: &PhyloTree,
+    species: &PhyloTree,
+    map: &HashMap<String, String>,
+) -> Result<Reconciliation, ReconciliationError> {
+    let index = SpeciesIndex::new(species)?;
+    lca_reconciliation(gene, &index, map)
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+fn lca_reconciliation(
+    gene: &PhyloTree,
+    index: &SpeciesIndex<'_>,
+    map: &HashMap<String, String>,
+) -> Result<Reconciliation, ReconciliationError> {
+    let mut placed: HashMap<NodeID, usize> = HashMap::new();
+    let mut events = HashMap::new();
+    let (mut duplications, mut losses) = (0, 0);
+    let postord = gene
+        .postord_ids(gene.get_root_id())
+        .expect("invariant: the root id always names a node");
+    for node in postord {
+        let (s, event) = match gene_children(gene, node)? {
+            None => (index.species_of(gene, node, map)?, GeneEvent::Leaf),
+            Some((a, b)) => {
+                let (m, duplication, lost) = index.join(placed[&a], placed[&b]);
+                duplications += usize::from(duplication);
+                losses += lost;
+                match duplication {
+                    true => (m, GeneEvent::Duplication),
+                    false => (m, GeneEvent::Speciation),
+                }
+            }
+        };
+        placed.insert(node, s);
+        events.insert(node, event);
+    }
+    Ok(Reconciliation {
+        mapping: placed
+            .into_iter()
+            .map(|(node, s)| (node, index.ids[s]))
+            .collect(),
+        events,
+        duplications,
+        transfers: 0,
+        losses,
+    })
+}
+
+/// LCA reconciliation of one side of an unrooted gene tree edge.
+#[cfg(feature = "simple_rooted_tree")]
+#[derive(Clone, Copy, Debug)]
+struct Side {
+    species: usize,
+    duplications: usize,
+    losses: usize,
+}
+
+/// The rooting of `gene` whose LCA reconciliation with `species` costs least.
+///
+/// The new root splits the length of its edge in half. Internal node labels
+/// are kept; other node annotations are not.
+///
+/// # Errors
+///
+/// As for [`compute_reconciliation`], and
+/// [`ReconciliationError::InvalidCost`] for a negative or non-finite cost.
+/// The root of `gene` may have three children.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_best_rooting(
+    gene: &PhyloTree,
+    species: &PhyloTree,
+    map: &HashMap<String, String>,
+    costs: &EventCosts,
+) -> Result<(PhyloTree, Reconciliation), ReconciliationError> {
+    costs.check()?;
+    let index = SpeciesIndex::new(species)?;
+    let root = gene.get_root_id();
+    let root_children = gene.get_node_children_ids(root).collect_vec();
+    if gene.get_leaf_ids().count() < 3 {
+        return Ok((gene.clone(), lca_reconciliation(gene, &index, map)?));
+    }
+    if !(2..=3).contains(&root_children.len()) {
+        return Err(ReconciliationError::NonBinaryGene(root));
+    }
+
+    // The unrooted tree, with a two-child root merged into the edge between
+    // its children, which is listed first.
+    let n_slots = gene.get_node_ids().max().map_or(0, |id| id + 1);
+    let mut adj: Vec<Vec<(NodeID, Option<f32>)>> = vec![vec![]; n_slots];
+    let mut edges: Vec<(NodeID, NodeID, Option<f32>)> = vec![];
+    if let &[a, b] = root_children.as_slice() {
+        let length = match (gene.get_edge_weight(root, a), gene.get_edge_weight(root, b)) {
+            (None, None) => None,
+            (x, y) => Some(x.unwrap_or(0.0) + y.unwrap_or(0.0)),
+        };
+        edges.push((a, b, length));
+    }
+    for node in gene.get_node_ids() {
+        match gene.get_node_parent_id(node) {
+            Some(parent) if parent != root || root_children.len() != 2 => {
+                edges.push((parent, node, gene.get_edge_weight(parent, node)));
+            }
+            _ => {}
+        }
+    }
+    for &(u, v, length) in &edges {
+        adj[u].push((v, length));
+        adj[v].push((u, length));
+    }
+    let mut leaf_species = vec![usize::MAX; n_slots];
+    for node in gene.get_node_ids() {
+        match adj[node].len() {
+            0 => {}
+            1 => leaf_species[node] = index.species_of(gene, node, map)?,
+            3 => {}
+            _ => return Err(ReconciliationError::NonBinaryGene(node)),
+        }
+    }
+
+    // `sides[(p, v)]` reconciles the subtree at `v` away from `p`.
+    let mut sides: HashMap<(NodeID, NodeID), Side> = HashMap::new();
+    for &(u, v, _) in &edges {
+        for start in [(u, v), (v, u)] {
+            let mut stack = vec![start];
+            while let Some(&(p, v)) = stack.last() {
+                if sides.contains_key(&(p, v)) {
+                    stack.pop();
+                    continue;
+                }
+                if adj[v].len() == 1 {
+                    let leaf = Side {
+                        species: leaf_species[v],
+                        duplications: 0,
+                        losses: 0,
+                    };
+                    sides.insert((p, v), leaf);
+                    stack.pop();
+                    continue;
+                }
+                let kids = adj[v].iter().map(|&(w, _)| w).filter(|&w| w != p);
+                let kids = kids.collect_vec();
+                let missing = kids
+                    .iter()
+                    .filter(|&&w| !sides.contains_key(&(v, w)))
+                    .map(|&w| (v, w))
+                    .collect_vec();
+                if missing.is_empty() {
+                    let side = join_sides(&index, sides[&(v, kids[0])], sides[&(v, kids[1])]);
+                    sides.insert((p, v), side);
+                    stack.pop();
+                } else {
+                    stack.extend(missing);
+                }
+            }
+        }
+    }
+
+    let cost =
+        |side: Side| side.duplications as f64 * costs.duplication + side.losses as f64 * costs.loss;
+    let (best, _) = edges
+        .iter()
+        .enumerate()
+        .map(|(e, &(u, v, _))| (e, cost(join_sides(&index, sides[&(v, u)], sides[&(u, v)]))))
+        .fold((0, f64::INFINITY), |best, (e, c)| match c < best.1 {
+            true => (e, c),
+            false => best,
+        });
+    if best == 0 && root_children.len() == 2 {
+        return Ok((gene.clone(), lca_reconciliation(gene, &index, map)?));
+    }
+
+    let (u, v, length) = edges[best];
+    let mut rooted = PhyloTree::new(0);
+    let top = rooted.get_root_id();
+    let half = length.map(|l| l / 2.0);
+    let mut stack = vec![(u, v, top, half), (v, u, top, half)];
+    while let Some((node, from, parent, length)) = stack.pop() {
+        let child = Node::new(rooted.next_id());
+        let id = child.get_id();
+        rooted.add_child(parent, child);
+        rooted.set_edge_weight((parent, id), length);
+        rooted.set_node_taxa(id, gene.get_node_taxa(node).cloned());
+        for &(next, w) in adj[node].iter().filter(|&&(next, _)| next != from) {
+            stack.push((next, node, id, w));
+        }
+    }
+    let reconciliation = lca_reconciliation(&rooted, &index, map)?;
+    Ok((rooted, reconciliation))
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+fn join_sides(index: &SpeciesIndex<'_>, a: Side, b: Side) -> Side {
+    let (species, duplication, losses) = index.join(a.species, b.species);
+    Side {
+        species,
+        duplications: a.duplications + b.duplications + usize::from(duplication),
+        losses: a.losses + b.losses + losses,
+    }
+}
+
+/// A most parsimonious DTL reconciliation of `gene` with `species`.
+///
+/// Ties between events are broken towards speciation, then duplication, then
+/// transfer, and towards mapping a gene node higher in the species tree.
+///
+/// # Errors
+///
+/// As for [`compute_reconciliation`], and
+/// [`ReconciliationError::InvalidCost`] for a negative or non-finite cost.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_dtl_reconciliation(
+    gene: &PhyloTree,
+    species: &PhyloTree,
+    map: &HashMap<String, String>,
+    costs: &EventCosts,
+) -> Result<Reconciliation, ReconciliationError> {
+    costs.check()?;
+    let index = SpeciesIndex::new(species)?;
+    let ns = index.len();
+    let genes = gene
+        .postord_ids(gene.get_root_id())
+        .expect("invariant: the root id always names a node")
+        .collect_vec();
+    // Children of each gene by post-order position, or its species if a leaf.
+    let mut position = HashMap::new();
+    let mut children = Vec::with_capacity(genes.len());
+    let mut leaf_species = vec![usize::MAX; genes.len()];
+    for (g, &node) in genes.iter().enumerate() {
+        position.insert(node, g);
+        let pair = gene_children(gene, node)?;
+        if pair.is_none() {
+            leaf_species[g] = index.species_of(gene, node, map)?;
+        }
+        children.push(pair.map(|(a, b)| (position[&a], position[&b])));
+    }
+
+    // `here[g][s]`: g maps to s. `within[g][s]`: g maps into the subtree of
+    // s, with a loss per species branch on the way down. `outside[g][s]`: g
+    // maps to a species unrelated to s.
+    let (d, t, l) = (costs.duplication, costs.transfer, costs.loss);
+    let mut here = vec![f64::INFINITY; genes.len() * ns];
+    let mut within = here.clone();
+    let mut outside = here.clone();
+    let mut landing = vec![f64::INFINITY; ns];
+    let speciation = |within: &[f64], g1: usize, g2: usize, s: usize| match index.children[s] {
+        Some((s1, s2)) => (within[g1 * ns + s1] + within[g2 * ns + s2])
+            .min(within[g2 * ns + s1] + within[g1 * ns + s2]),
+        None => f64::INFINITY,
+    };
+    for (g, pair) in children.iter().enumerate() {
+        let row = g * ns;
+        for s in 0..ns {
+            here[row + s] = match *pair {
+                None if leaf_species[g] == s => 0.0,
+                None => f64::INFINITY,
+                Some((g1, g2)) => {
+                    let (a, b) = (within[g1 * ns + s], within[g2 * ns + s]);
+                    let transfer = (a + outside[g2 * ns + s]).min(b + outside[g1 * ns + s]);
+                    speciation(&within, g1, g2, s)
+                        .min(d + a + b)
+                        .min(t + transfer)
+                }
+            };
+            within[row + s] = match index.children[s] {
+                Some((s1, s2)) => here[row + s].min(l + within[row + s1].min(within[row + s2])),
+                None => here[row + s],
+            };
+        }
+        // A transfer lands anywhere in the subtree, without the losses on the
+        // way down to it.
+        for s in 0..ns {
+            landing[s] = match index.children[s] {
+                Some((s1, s2)) => within[row + s].min(landing[s1]).min(landing[s2]),
+                None => within[row + s],
+            };
+        }
+        for s in (0..ns).rev() {
+            if let Some((s1, s2)) = index.children[s] {
+                outside[row + s1] = outside[row + s].min(landing[s2]);
+                outside[row + s2] = outside[row + s].min(landing[s1]);
+            }
+        }
+    }
+
+    let top = genes.len() - 1;
+    let start = (0..ns)
+        .rev()
+        .min_by(|&a, &b| here[top * ns + a].total_cmp(&here[top * ns + b]))
+        .expect("invariant: a species tree has a node");
+    let mut reconciliation = Reconciliation {
+        mapping: HashMap::new(),
+        events: HashMap::new(),
+        duplications: 0,
+        transfers: 0,
+        losses: 0,
+    };
+    let mut stack = vec![(top, start, true)];
+    while let Some((g, s, exact)) = stack.pop() {
+        let row = g * ns;
+        if !exact {
+            match index.children[s] {
+                Some((s1, s2)) if here[row + s] != within[row + s] => {
+                    let next = match l + within[row + s1] == within[row + s] {
+                        true => s1,
+                        false => s2,
+                    };
+                    reconciliation.losses += 1;
+                    stack.push((g, next, false));
+                }
+                _ => stack.push((g, s, true)),
+            }
+            continue;
+        }
+        reconciliation.mapping.insert(genes[g], index.ids[s]);
+        let Some((g1, g2)) = children[g] else {
+            reconciliation.events.insert(genes[g], GeneEvent::Leaf);
+            continue;
+        };
+        let (a, b) = (within[g1 * ns + s], within[g2 * ns + s]);
+        let event = if here[row + s] == speciation(&within, g1, g2, s) {
+            let (s1, s2) = index.children[s].expect("invariant: speciations are at splits");
+            match within[g1 * ns + s1] + within[g2 * ns + s2] == here[row + s] {
+                true => stack.extend([(g1, s1, false), (g2, s2, false)]),
+                false => stack.extend([(g1, s2, false), (g2, s1, false)]),
+            }
+            GeneEvent::Speciation
+        } else if here[row + s] == d + a + b {
+            reconciliation.duplications += 1;
+            stack.extend([(g1, s, false), (g2, s, false)]);
+            GeneEvent::Duplication
+        } else {
+            reconciliation.transfers += 1;
+            let (stay, moved) = match t + (a + outside[g2 * ns + s]) == here[row + s] {
+                true => (g1, g2),
+                false => (g2, g1),
+            };
+            let target = (0..ns)
+                .rev()
+                .find(|&r| {
+                    !index.comparable(s, r) && within[moved * ns + r] == outside[moved * ns + s]
+                })
+                .expect("invariant: a finite transfer has a recipient");
+            stack.extend([(stay, s, false), (moved, target, false)]);
+            GeneEvent::Transfer
+        };
+        reconciliation.events.insert(genes[g], event);
+    }
+    Ok(reconciliation)
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+
+    fn tree(newick: &str) -> PhyloTree {
+        PhyloTree::from_newick(newick.as_bytes()).unwrap()
+    }
+
+    /// Genes named `<species>_<copy>`.
+    fn by_prefix(gene: &PhyloTree) -> HashMap<String, String> {
+        gene.get_leaf_ids()
+            .map(|leaf| gene.get_node_taxa(leaf).unwrap().clone())
+            .map(|name| (name.clone(), name.split('_').next().unwrap().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_lca_reconciliation() {
+        let species = tree("((A,B),C);");
+        let gene = tree("((A_1,C_1),B_1);");
+        let r = gene.reconcile(&species, &by_prefix(&gene)).unwrap();
+        assert_eq!((r.duplications, r.losses), (1, 3));
+        assert_eq!(r.events[&gene.get_root_id()], GeneEvent::Duplication);
+        assert_eq!(r.mapping[&gene.get_root_id()], species.get_root_id());
+        assert_eq!(r.cost(&EventCosts::default()), 5.0);
+
+        let gene = tree("(((A_1,B_1),C_1),((A_2,B_2),C_2));");
+        let r = gene.reconcile(&species, &by_prefix(&gene)).unwrap();
+        assert_eq!((r.duplications, r.losses), (1, 0));
+        let speciations = r
+            .events
+            .values()
+            .filter(|&&e| e == GeneEvent::Speciation)
+            .count();
+        assert_eq!(speciations, 4);
+
+        let mut map = by_prefix(&gene);
+        map.remove("C_2");
+        assert_eq!(
+            gene.reconcile(&species, &map),
+            Err(ReconciliationError::UnmappedGene("C_2".into()))
+        );
+        map.insert("C_2".into(), "D".into());
+        assert_eq!(
+            gene.reconcile(&species, &map),
+            Err(ReconciliationError::UnknownSpecies("D".into()))
+        );
+    }
+
+    #[test]
+    fn test_best_rooting() {
+        let species = tree("(((A,B),C),(D,E));");
+        for newick in ["(A,(B,(C,(D,E))));", "(A_1,B_1,(C_1,(D_1,E_1)));"] {
+            let gene = tree(newick);
+            let map = by_prefix(&gene);
+            let (rooted, r) = gene
+                .best_rooting(&species, &map, &EventCosts::default())
+                .unwrap();
+            assert_eq!((r.duplications, r.losses), (0, 0));
+            assert_eq!(rooted.get_leaf_ids().count(), 5);
+            assert_eq!(r, rooted.reconcile(&species, &map).unwrap());
+        }
+
+        // Already optimal, so the root stays.
+        let gene = tree("(((A,B),C),(D,E));");
+        let (rooted, _) = gene
+            .best_rooting(&species, &by_prefix(&gene), &EventCosts::default())
+            .unwrap();
+        assert_eq!(rooted.to_newick().to_string(), gene.to_newick().to_string());
+    }
+
+    #[test]
+    fn test_dtl_reconciliation() {
+        let species = tree("((A,B),(C,D));");
+        let gene = tree("((A_1,(B_1,C_1)),D_1);");
+        let map = by_prefix(&gene);
+        let dl = gene.reconcile(&species, &map).unwrap();
+
+        // Transfers priced out of reach give back the LCA reconciliation.
+        let costs = EventCosts::new(2.0, 1e6, 1.0);
+        let r = gene.reconcile_dtl(&species, &map, &costs).unwrap();
+        assert_eq!(r.transfers, 0);
+        assert_eq!(r.cost(&costs), dl.cost(&costs));
+
+        // One transfer of C into B explains the gene tree.
+        let costs = EventCosts::default();
+        let r = gene.reconcile_dtl(&species, &map, &costs).unwrap();
+        assert_eq!((r.duplications, r.transfers, r.losses), (0, 1, 1));
+        assert!(r.cost(&costs) < dl.cost(&costs));
+        let c = gene.get_taxa_node_id(&"C_1".to_string()).unwrap();
+        let transfer = gene.get_node_parent_id(c).unwrap();
+        assert_eq!(r.events[&transfer], GeneEvent::Transfer);
+        assert_eq!(r.events.len(), gene.get_node_ids().count());
+
+        assert!(matches!(
+            gene.reconcile_dtl(&species, &map, &EventCosts::new(-1.0, 1.0, 1.0)),
+            Err(ReconciliationError::InvalidCost(_))
+        ));
+    }
+}
@@ -0,0 +1,772 @@
+//! Phylogenetic signal and tree-based regression.
+//!
+//! Most statistics here work on the tree's tip variance–covariance matrix `C`.
+//! Under Brownian motion, `C[i][j]` is the length of the path that tips `i`
+//! and `j` share from the root: the root-to-node distance of their LCA,
+//! answered in O(1) by the [`LcaOracle`](crate::iter::lca::LcaOracle). The
+//! diagonal holds each tip's distance from the root.
+//!
+//! * **Blomberg's K** compares the trait's spread around its GLS mean with the
+//!   spread Brownian motion predicts. `K = 1` is exactly Brownian. `K < 1`
+//!   means relatives resemble each other less than expected, and `K > 1`
+//!   means more.
+//! * **Pagel's lambda** scales the off-diagonal of `C` and is fitted by
+//!   maximum likelihood. It is tested against `lambda = 0`, which means no
+//!   signal.
+//! * **Moran's I** measures autocorrelation, weighting each pair of tips by
+//!   the inverse of their patristic distance.
+//! * **PGLS** fits a linear model with residuals correlated as `C`, or as the
+//!   lambda-scaled `C`. Predictors can be continuous, or discrete and
+//!   dummy-coded against their first level.
+//!
+//! Permutation tests shuffle trait values across tips with a seeded generator,
+//! so results are reproducible.
+
+use std::collections::HashMap;
+
+use nalgebra::DMatrix;
+
+use crate::error::ComparativeError;
+
+#[cfg(feature = "simple_rooted_tree")]
+use {
+    super::continuous::{factor, shared_paths, TraitData},
+    crate::models::gamma::{incomplete_beta, incomplete_gamma},
+    crate::prelude::*,
+    crate::tree::PhyloTree,
+    nalgebra::DVector,
+    rand::{rngs::StdRng, seq::SliceRandom, SeedableRng},
+};
+
+/// Phylogenetic signal statistics and PGLS regression.
+///
+/// Feature-free (like [`crate::tree::continuous::ContinuousTraits`]) so a
+/// caller bringing its own tree type can implement it without
+/// `simple_rooted_tree`.
+pub trait PhylogeneticSignal {
+    /// The Brownian tip variance–covariance matrix, with the taxon labelling
+    /// each row and column.
+    fn vcv(&self) -> Result<(Vec<String>, DMatrix<f64>), ComparativeError>;
+
+    /// Blomberg's K. With `permutations > 0`, also a one-sided p-value for
+    /// more signal than shuffled tips show.
+    fn blomberg_k(
+        &self,
+        traits: &HashMap<String, f64>,
+        permutations: usize,
+        seed: u64,
+    ) -> Result<SignalTest, ComparativeError>;
+
+    /// Pagel's lambda, estimated by maximum likelihood on `[0, 1]`.
+    fn pagel_lambda(&self, traits: &HashMap<String, f64>) -> Result<LambdaFit, ComparativeError>;
+
+    /// Moran's I with inverse patristic-distance weights. With
+    /// `permutations > 0`, also a one-sided p-value for positive
+    /// autocorrelation.
+    fn morans_i(
+        &self,
+        traits: &HashMap<String, f64>,
+        permutations: usize,
+        seed: u64,
+    ) -> Result<SignalTest, ComparativeError>;
+
+    /// Phylogenetic generalized least squares regression of `response` on the
+    /// predictors in `model`.
+    fn pgls(
+        &self,
+        response: &HashMap<String, f64>,
+        model: &Pgls,
+    ) -> Result<PglsFit, ComparativeError>;
+}
+
+/// A signal statistic and its permutation test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SignalTest {
+    /// The observed statistic.
+    pub statistic: f64,
+    /// The statistic's value under the null: 1 for Blomberg's K (Brownian
+    /// motion), `-1 / (n - 1)` for Moran's I (no autocorrelation).
+    pub expected: f64,
+    /// Share of permutations (counting the observed arrangement) at least as
+    /// large as `statistic`. `None` when no permutations were run.
+    pub p_value: Option<f64>,
+}
+
+/// Pagel's lambda fitted by maximum likelihood.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LambdaFit {
+    /// The ML estimate of lambda.
+    pub lambda: f64,
+    /// Log-likelihood at `lambda`.
+    pub log_likelihood: f64,
+    /// The ML Brownian rate at `lambda`.
+    pub sigma2: f64,
+    /// The GLS estimate of the root state at `lambda`.
+    pub root_state: f64,
+    /// Log-likelihood at `lambda = 0`.
+    pub log_likelihood_zero: f64,
+    /// Likelihood-ratio test p-value against `lambda = 0` (chi-squared, one
+    /// degree of freedom).
+    pub p_value: f64,
+}
+
+/// A PGLS predictor: one value per taxon.
+#[derive(Clone, Debug)]
+pub enum Predictor {
+    /// A numeric predictor, entered as one column.
+    Continuous(HashMap<String, f64>),
+    /// A categorical predictor, entered as one indicator column per level
+    /// except the first in sorted order, which is the reference.
+    Discrete(HashMap<String, String>),
+}
+
+/// How PGLS treats Pagel's lambda.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LambdaSetting {
+    /// Use this lambda. `Fixed(1.0)` is plain Brownian PGLS.
+    Fixed(f64),
+    /// Estimate lambda on `[0, 1]` by maximum likelihood.
+    Estimate,
+}
+
+/// A PGLS model specification: an intercept plus named predictors.
+#[derive(Clone, Debug)]
+pub struct Pgls {
+    predictors: Vec<(String, Predictor)>,
+    lambda: LambdaSetting,
+}
+
+impl Default for Pgls {
+    fn default() -> Self {
+        Pgls {
+            predictors: Vec::new(),
+            lambda: LambdaSetting::Fixed(1.0),
+        }
+    }
+}
+
+impl Pgls {
+    /// An intercept-only model under Brownian motion.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a continuous predictor.
+    pub fn with_continuous(mut self, name: &str, values: HashMap<String, f64>) -> Self {
+        self.predictors
+            .push((name.to_string(), Predictor::Continuous(values)));
+        self
+    }
+
+    /// Adds a discrete predictor.
+    pub fn with_discrete(mut self, name: &str, values: HashMap<String, String>) -> Self {
+        self.predictors
+            .push((name.to_string(), Predictor::Discrete(values)));
+        self
+    }
+
+    /// Sets how lambda is treated.
+    pub fn with_lambda(mut self, lambda: LambdaSetting) -> Self {
+        self.lambda = lambda;
+        self
+    }
+}
+
+/// One fitted PGLS coefficient.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coefficient {
+    /// `"(Intercept)"`, a continuous predictor's name, or `name[level]` for a
+    /// level of a discrete predictor.
+    pub name: String,
+    /// The GLS estimate.
+    pub estimate: f64,
+    /// Its standard error.
+    pub std_error: f64,
+    /// `estimate / std_error`.
+    pub t_value: f64,
+    /// Two-sided p-value from the t distribution with `df_residual` degrees
+    /// of freedom.
+    pub p_value: f64,
+}
+
+/// A fitted PGLS regression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PglsFit {
+    /// Coefficients, intercept first, in the order the predictors were added.
+    pub coefficients: Vec<Coefficient>,
+    /// The lambda used, fixed or estimated.
+    pub lambda: f64,
+    /// Residual variance, `r' C^-1 r / df_residual`.
+    pub sigma2: f64,
+    /// Maximum log-likelihood of the model.
+    pub log_likelihood: f64,
+    /// Number of taxa minus number of coefficients.
+    pub df_residual: usize,
+}
+
+/// Upper tail of the chi-squared distribution with one degree of freedom.
+#[cfg(feature = "simple_rooted_tree")]
+fn chi2_1_upper(stat: f64) -> f64 {
+    if stat <= 0.0 {
+        return 1.0;
+    }
+    1.0 - incomplete_gamma(stat / 2.0, 0.5)
+}
+
+/// Two-sided p-value of `t` under Student's t with `df` degrees of freedom.
+#[cfg(feature = "simple_rooted_tree")]
+fn t_two_sided(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// Tip values checked against the tree, with the shared-path matrix in the
+/// same tip order.
+#[cfg(feature = "simple_rooted_tree")]
+fn prepare(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+    min_taxa: usize,
+) -> Result<(TraitData, DMatrix<f64>, DVector<f64>), ComparativeError> {
+    let data = TraitData::new(tree, traits, min_taxa)?;
+    let (shared, _) = shared_paths(tree, &data);
+    let values = DVector::from_iterator(data.tips.len(), data.tips.iter().map(|t| t.1));
+    Ok((data, shared, values))
+}
+
+/// `C` with its off-diagonal scaled by `lambda`.
+#[cfg(feature = "simple_rooted_tree")]
+fn lambda_scaled(c: &DMatrix<f64>, lambda: f64) -> DMatrix<f64> {
+    DMatrix::from_fn(c.nrows(), c.ncols(), |i, j| {
+        if i == j {
+            c[(i, j)]
+        } else {
+            lambda * c[(i, j)]
+        }
+    })
+}
+
+/// Generalized least squares of `y` on `x` with residual covariance `c`.
+#[cfg(feature = "simple_rooted_tree")]
+struct Gls {
+    beta: DVector<f64>,
+    /// `(X' C^-1 X)^-1`.
+    xtcx_inv: DMatrix<f64>,
+    /// `r' C^-1 r` for the residuals `r = y - X beta`.
+    quad: f64,
+    log_det: f64,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+impl Gls {
+    fn fit(c: DMatrix<f64>, x: &DMatrix<f64>, y: &DVector<f64>) -> Result<Gls, ComparativeError> {
+        let (chol, log_det) = factor(c)?;
+        let c_inv_x = chol.solve(x);
+        let xtcx_inv = (x.transpose() * &c_inv_x)
+            .try_inverse()
+            .ok_or(ComparativeError::SingularCovariance)?;
+        let beta = &xtcx_inv * (c_inv_x.transpose() * y);
+        let residuals = y - x * &beta;
+        let quad = residuals.dot(&chol.solve(&residuals));
+        Ok(Gls {
+            beta,
+            xtcx_inv,
+            quad,
+            log_det,
+        })
+    }
+
+    /// Log-likelihood with `sigma2` at its ML value `quad / n`.
+    fn log_likelihood(&self, n: usize) -> f64 {
+        let n = n as f64;
+        let sigma2 = self.quad / n;
+        -0.5 * (n * ((2.0 * std::f64::consts::PI * sigma2).ln() + 1.0) + self.log_det)
+    }
+}
+
+/// Maximizes `profile(lambda)` on `[0, 1]`: a coarse grid, then
+/// golden-section search around the best grid point.
+#[cfg(feature = "simple_rooted_tree")]
+fn maximize_lambda<F>(profile: F) -> Result<(f64, f64), ComparativeError>
+where
+    F: Fn(f64) -> Result<f64, ComparativeError>,
+{
+    const GRID: usize = 21;
+    let step = 1.0 / (GRID - 1) as f64;
+    let mut best: Option<(usize, f64)> = None;
+    for k in 0..GRID {
+        let ll = profile(step * k as f64)?;
+        if best.is_none_or(|(_, b)| ll > b) {
+            best = Some((k, ll));
+        }
+    }
+    let (k, mut best_ll) = best.expect("invariant: the grid is not empty");
+    let mut best_lambda = step * k as f64;
+
+    let inv_phi = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut a, mut b) = (
+        step * k.saturating_sub(1) as f64,
+        step * (k + 1).min(GRID - 1) as f64,
+    );
+    let neg = |x: f64| profile(x).map_or(f64::INFINITY, |ll| -ll);
+    let mut c = b - inv_phi * (b - a);
+    let mut d = a + inv_phi * (b - a);
+    let (mut fc, mut fd) = (neg(c), neg(d));
+    for _ in 0..50 {
+        if fc < fd {
+            b = d;
+            d = c;
+            fd = fc;
+            c = b - inv_phi * (b - a);
+            fc = neg(c);
+        } else {
+            a = c;
+            c = d;
+            fc = fd;
+            d = a + inv_phi * (b - a);
+            fd = neg(d);
+        }
+    }
+    let mid = (a + b) / 2.0;
+    if let Ok(ll) = profile(mid) {
+        if ll > best_ll {
+            best_ll = ll;
+            best_lambda = mid;
+        }
+    }
+    Ok((best_lambda, best_ll))
+}
+
+/// Draws `permutations` shuffles of `values` and counts how many give a
+/// statistic at least `observed`, returning the permutation p-value.
+#[cfg(feature = "simple_rooted_tree")]
+fn permutation_p_value<F>(
+    values: &DVector<f64>,
+    observed: f64,
+    permutations: usize,
+    seed: u64,
+    statistic: F,
+) -> Result<Option<f64>, ComparativeError>
+where
+    F: Fn(&DVector<f64>) -> Result<f64, ComparativeError>,
+{
+    if permutations == 0 {
+        return Ok(None);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut shuffled = values.as_slice().to_vec();
+    let mut at_least = 0;
+    for _ in 0..permutations {
+        shuffled.shuffle(&mut rng);
+        if statistic(&DVector::from_column_slice(&shuffled))? >= observed - 1e-12 {
+            at_least += 1;
+        }
+    }
+    Ok(Some((at_least + 1) as f64 / (permutations + 1) as f64))
+}
+
+/// The tip variance–covariance matrix of `tree` under Brownian motion.
+///
+/// # Errors
+///
+/// Returns [`ComparativeError::MissingBranchLength`] if a branch has no
+/// length and [`ComparativeError::MissingTrait`] for an unlabelled leaf.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_vcv(tree: &PhyloTree) -> Result<(Vec<String>, DMatrix<f64>), ComparativeError> {
+    let placeholder: HashMap<String, f64> = tree
+        .get_leaf_ids()
+        .filter_map(|leaf| tree.get_node_taxa(leaf).cloned())
+        .map(|taxon| (taxon, 0.0))
+        .collect();
+    let data = TraitData::new(tree, &placeholder, 1)?;
+    let (shared, _) = shared_paths(tree, &data);
+    let names = data
+        .tips
+        .iter()
+        .map(|&(leaf, _)| {
+            tree.get_node_taxa(leaf)
+                .cloned()
+                .expect("invariant: TraitData only admits labelled leaves")
+        })
+        .collect();
+    Ok((names, shared))
+}
+
+/// Blomberg's K for `traits` on `tree`.
+///
+/// # Errors
+///
+/// Returns the input errors of
+/// [`compute_independent_contrasts`],
+/// [`ComparativeError::TooFewTaxa`] for fewer than three taxa, and
+/// [`ComparativeError::SingularCovariance`] if `C` is singular or every tip
+/// has the same value.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_blomberg_k(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+    permutations: usize,
+    seed: u64,
+) -> Result<SignalTest, ComparativeError> {
+    let (data, c, values) = prepare(tree, traits, 3)?;
+    let n = data.tips.len();
+    let (chol, _) = factor(c.clone())?;
+    let ones = DVector::from_element(n, 1.0);
+    let ones_c_ones = ones.dot(&chol.solve(&ones));
+    let expected_ratio = (c.trace() - n as f64 / ones_c_ones) / (n - 1) as f64;
+
+    let k = |x: &DVector<f64>| -> Result<f64, ComparativeError> {
+        let mean = x.dot(&chol.solve(&ones)) / ones_c_ones;
+        let r = x.add_scalar(-mean);
+        let gls = r.dot(&chol.solve(&r));
+        if gls <= 0.0 {
+            return Err(ComparativeError::SingularCovariance);
+        }
+        Ok((r.dot(&r) / gls) / expected_ratio)
+    };
+    let statistic = k(&values)?;
+    Ok(SignalTest {
+        statistic,
+        expected: 1.0,
+        p_value: permutation_p_value(&values, statistic, permutations, seed, k)?,
+    })
+}
+
+/// ML estimate of Pagel's lambda for `traits` on `tree`.
+///
+/// # Errors
+///
+/// See [`compute_blomberg_k`].
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_pagel_lambda(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+) -> Result<LambdaFit, ComparativeError> {
+    let (data, c, values) = prepare(tree, traits, 3)?;
+    let n = data.tips.len();
+    let x = DMatrix::from_element(n, 1, 1.0);
+    let profile = |lambda: f64| -> Result<f64, ComparativeError> {
+        let gls = Gls::fit(lambda_scaled(&c, lambda), &x, &values)?;
+        if gls.quad <= 0.0 {
+            return Err(ComparativeError::SingularCovariance);
+        }
+        Ok(gls.log_likelihood(n))
+    };
+    let (lambda, log_likelihood) = maximize_lambda(profile)?;
+    let log_likelihood_zero = profile(0.0)?;
+    let gls = Gls::fit(lambda_scaled(&c, lambda), &x, &values)?;
+    Ok(LambdaFit {
+        lambda,
+        log_likelihood,
+        sigma2: gls.quad / n as f64,
+        root_state: gls.beta[0],
+        log_likelihood_zero,
+        p_value: chi2_1_upper(2.0 * (log_likelihood - log_likelihood_zero)),
+    })
+}
+
+/// Moran's I for `traits` on `tree`, weighting tips by inverse patristic
+/// distance.
+///
+/// # Errors
+///
+/// Returns the input errors of [`compute_blomberg_k`],
+/// [`ComparativeError::ZeroLengthContrast`] if two tips are at distance zero
+/// (their weight would be infinite), and
+/// [`ComparativeError::SingularCovariance`] if every tip has the same value.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_morans_i(
+    tree: &PhyloTree,
+    traits: &HashMap<String, f64>,
+    permutations: usize,
+    seed: u64,
+) -> Result<SignalTest, ComparativeError> {
+    let (data, c, values) = prepare(tree, traits, 3)?;
+    let n = data.tips.len();
+    let lca = tree.lca();
+    let mut w = DMatrix::zeros(n, n);
+    for i in 0..n {
+        for j in 0..i {
+            let distance = c[(i, i)] + c[(j, j)] - 2.0 * c[(i, j)];
+            if distance <= 0.0 {
+                return Err(ComparativeError::ZeroLengthContrast(
+                    lca.get_lca_id(&[data.tips[i].0, data.tips[j].0]),
+                ));
+            }
+            w[(i, j)] = 1.0 / distance;
+            w[(j, i)] = 1.0 / distance;
+        }
+    }
+    let s0 = w.sum();
+
+    let moran = |x: &DVector<f64>| -> Result<f64, ComparativeError> {
+        let z = x.add_scalar(-x.mean());
+        let spread = z.dot(&z);
+        if spread <= 0.0 {
+            return Err(ComparativeError::SingularCovariance);
+        }
+        Ok(n as f64 / s0 * z.dot(&(&w * &z)) / spread)
+    };
+    let statistic = moran(&values)?;
+    Ok(SignalTest {
+        statistic,
+        expected: -1.0 / (n - 1) as f64,
+        p_value: permutation_p_value(&values, statistic, permutations, seed, moran)?,
+    })
+}
+
+/// PGLS regression of `response` on `model`'s predictors over `tree`.
+///
+/// Coefficients and their standard errors are the GLS estimates with the
+/// residual variance on `n - p` degrees of freedom, as in `nlme::gls` and
+/// `caper::pgls`. With [`LambdaSetting::Estimate`] lambda is chosen by
+/// maximum likelihood first.
+///
+/// # Errors
+///
+/// Returns the input errors of [`compute_blomberg_k`] for the response and
+/// every predictor, [`ComparativeError::InvalidParameter`] for a fixed lambda
+/// outside `[0, 1]`, [`ComparativeError::TooFewTaxa`] if there are no
+/// residual degrees of freedom, and [`ComparativeError::SingularCovariance`]
+/// if the predictors are collinear.
+#[cfg(feature = "simple_rooted_tree")]
+pub fn compute_pgls(
+    tree: &PhyloTree,
+    response: &HashMap<String, f64>,
+    model: &Pgls,
+) -> Result<PglsFit, ComparativeError> {
+    let (data, c, y) = prepare(tree, response, 1)?;
+    let n = data.tips.len();
+    let names: Vec<String> = data
+        .tips
+        .iter()
+        .map(|&(leaf, _)| tree.get_node_taxa(leaf).cloned().unwrap_or_default())
+        .collect();
+
+    let unknown = |keys: Vec<&String>| {
+        keys.into_iter()
+            .find(|k| !response.contains_key(*k))
+            .map(|k| ComparativeError::UnknownTaxon(k.clone()))
+    };
+    let mut columns: Vec<(String, Vec<f64>)> = vec![("(Intercept)".to_string(), vec![1.0; n])];
+    for (name, predictor) in &model.predictors {
+        match predictor {
+            Predictor::Continuous(values) => {
+                if let Some(err) = unknown(values.keys().collect()) {
+                    return Err(err);
+                }
+                let mut column = Vec::with_capacity(n);
+                for taxon in &names {
+                    let v = *values
+                        .get(taxon)
+                        .ok_or_else(|| ComparativeError::MissingTrait(taxon.clone()))?;
+                    if !v.is_finite() {
+                        return Err(ComparativeError::NonFiniteTrait(taxon.clone()));
+                    }
+                    column.push(v);
+                }
+                columns.push((name.clone(), column));
+            }
+            Predictor::Discrete(values) => {
+                if let Some(err) = unknown(values.keys().collect()) {
+                    return Err(err);
+                }
+                let mut levels = Vec::with_capacity(n);
+                for taxon in &names {
+                    levels.push(
+                        values
+                            .get(taxon)
+                            .ok_or_else(|| ComparativeError::MissingTrait(taxon.clone()))?,
+                    );
+                }
+                let mut distinct = levels.clone();
+                distinct.sort();
+                distinct.dedup();
+                for level in distinct.iter().skip(1) {
+                    let column = levels
+                        .iter()
+                        .map(|l| if l == level { 1.0 } else { 0.0 })
+                        .collect();
+                    columns.push((format!("{name}[{level}]"), column));
+                }
+            }
+        }
+    }
+    let p = columns.len();
+    if n <= p {
+        return Err(ComparativeError::TooFewTaxa {
+            expected: p + 1,
+            actual: n,
+        });
+    }
+    let x = DMatrix::from_fn(n, p, |i, j| columns[j].1[i]);
+
+    let lambda = match model.lambda {
+        LambdaSetting::Fixed(lambda) => {
+            if !(0.0..=1.0).contains(&lambda) {
+                return Err(ComparativeError::InvalidParameter(format!(
+                    "lambda must be in [0, 1], got {lambda}"
+                )));
+            }
+            lambda
+        }
+        LambdaSetting::Estimate => {
+            maximize_lambda(|lambda| {
+                let gls = Gls::fit(lambda_scaled(&c, lambda), &x, &y)?;
+                if gls.quad <= 0.0 {
+                    return Err(ComparativeError::SingularCovariance);
+                }
+                Ok(gls.log_likelihood(n))
+            })?
+            .0
+        }
+    };
+
+    let gls = Gls::fit(lambda_scaled(&c, lambda), &x, &y)?;
+    let df_residual = n - p;
+    let sigma2 = gls.quad / df_residual as f64;
+    let coefficients = columns
+        .into_iter()
+        .enumerate()
+        .map(|(j, (name, _))| {
+            let estimate = gls.beta[j];
+            let std_error = (sigma2 * gls.xtcx_inv[(j, j)]).sqrt();
+            let t_value = estimate / std_error;
+            Coefficient {
+                name,
+                estimate,
+                std_error,
+                t_value,
+                p_value: t_two_sided(t_value, df_residual as f64),
+            }
+        })
+        .collect();
+    Ok(PglsFit {
+        coefficients,
+        lambda,
+        sigma2,
+        log_likelihood: gls.log_likelihood(n),
+        df_residual,
+    })
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+
+    fn tree() -> PhyloTree {
+        PhyloTree::from_newick(
+            "(((A:1,B:1):1,(C:1,D:1):1):2,((E:1.5,F:1.5):1,(G:0.5,H:0.5):2):1.5);".as_bytes(),
+        )
+        .unwrap()
+    }
+
+    fn traits(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v)).collect()
+    }
+
+    fn clustered() -> HashMap<String, f64> {
+        traits(&[
+            ("A", 1.0),
+            ("B", 1.2),
+            ("C", 1.5),
+            ("D", 1.4),
+            ("E", 5.0),
+            ("F", 5.3),
+            ("G", 6.1),
+            ("H", 6.0),
+        ])
+    }
+
+    #[test]
+    fn test_vcv_is_shared_path_length() {
+        let (names, c) = tree().vcv().unwrap();
+        let idx = |t: &str| names.iter().position(|n| n == t).unwrap();
+        assert_eq!(c[(idx("A"), idx("A"))], 4.0);
+        assert_eq!(c[(idx("A"), idx("B"))], 3.0);
+        assert_eq!(c[(idx("A"), idx("C"))], 2.0);
+        assert_eq!(c[(idx("A"), idx("E"))], 0.0);
+        assert_eq!(c[(idx("G"), idx("H"))], 3.5);
+    }
+
+    #[test]
+    fn test_strong_signal_is_detected() {
+        let tree = tree();
+        let k = tree.blomberg_k(&clustered(), 199, 1).unwrap();
+        assert!(k.statistic > 1.0);
+        assert!(k.p_value.unwrap() < 0.05);
+
+        let moran = tree.morans_i(&clustered(), 199, 1).unwrap();
+        assert!(moran.statistic > moran.expected);
+        assert!(moran.p_value.unwrap() < 0.05);
+
+        let lambda = tree.pagel_lambda(&clustered()).unwrap();
+        assert!(lambda.lambda > 0.9);
+        assert!(lambda.log_likelihood >= lambda.log_likelihood_zero);
+        assert!(lambda.p_value < 0.05);
+    }
+
+    #[test]
+    fn test_pgls_intercept_matches_lambda_fit() {
+        let tree = tree();
+        let fit = tree.pgls(&clustered(), &Pgls::new()).unwrap();
+        let bm = tree.bm_ancestral_states(&clustered()).unwrap().fit;
+        assert_eq!(fit.coefficients.len(), 1);
+        assert!((fit.coefficients[0].estimate - bm.root_state).abs() < 1e-9);
+        assert!((fit.log_likelihood - bm.log_likelihood).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pgls_recovers_exact_relationship() {
+        let tree = tree();
+        let x = clustered();
+        let noise = traits(&[
+            ("A", 0.1),
+            ("B", -0.2),
+            ("C", 0.05),
+            ("D", 0.0),
+            ("E", -0.1),
+            ("F", 0.2),
+            ("G", -0.05),
+            ("H", 0.1),
+        ]);
+        let groups: HashMap<String, String> = ["A", "B", "C", "D", "E", "F", "G", "H"]
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                (
+                    t.to_string(),
+                    if i % 2 == 0 { "a" } else { "b" }.to_string(),
+                )
+            })
+            .collect();
+        let y: HashMap<String, f64> = x
+            .iter()
+            .map(|(t, v)| {
+                let shift = if groups[t] == "b" { 0.5 } else { 0.0 };
+                (t.clone(), 2.0 + 3.0 * v + shift + noise[t])
+            })
+            .collect();
+        let fit = tree
+            .pgls(
+                &y,
+                &Pgls::new()
+                    .with_continuous("x", x)
+                    .with_discrete("group", groups)
+                    .with_lambda(LambdaSetting::Estimate),
+            )
+            .unwrap();
+        assert_eq!(fit.df_residual, 5);
+        let names: Vec<&str> = fit.coefficients.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["(Intercept)", "x", "group[b]"]);
+        assert!((fit.coefficients[1].estimate - 3.0).abs() < 0.2);
+        assert!(fit.coefficients[1].p_value < 1e-4);
+        assert!((0.0..=1.0).contains(&fit.lambda));
+    }
+
+    #[test]
+    fn test_t_and_chi2_tails() {
+        assert!((t_two_sided(2.0, 10.0) - 0.073_388).abs() < 1e-5);
+        assert!((t_two_sided(0.0, 5.0) - 1.0).abs() < 1e-12);
+        assert!((chi2_1_upper(3.841_459) - 0.05).abs() < 1e-6);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::node::simple_rnode::*;
 use itertools::Itertools;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 /// A type alias for Tree Node ID
 pub type TreeNodeID<T> = <<T as RootedTree>::Node as RootedTreeNode>::NodeID;
@@ -467,3 +468,71 @@ where
             .get_weight()
     }
 }
+
+/// A trait describing the behaviour of a rooted tree whose edges may carry
+/// support values
+pub trait RootedSupportTree: RootedTree
+where
+    Self::Node: RootedSupportNode,
+{
+    /// Returns the support values of the edge leading into a node
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is not a node of this tree.
+    fn get_node_support(&self, node_id: TreeNodeID<Self>) -> Option<&[f32]> {
+        self.get_node(node_id)
+            .expect("node_id is not a node of this tree")
+            .get_support()
+    }
+
+    /// Sets the support values of the edge leading into a node
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node_id` is not a node of this tree.
+    fn set_node_support(&mut self, node_id: TreeNodeID<Self>, support: Option<Arc<[f32]>>) {
+        self.get_node_mut(node_id)
+            .expect("node_id is not a node of this tree")
+            .set_support(support);
+    }
+
+    /// Collapses every internal edge whose support value at position `index`
+    /// is below `threshold`, handing the children of the node below it to its
+    /// parent in its place. The collapsed edge's weight, and any taxon of the
+    /// removed node, are dropped. Edges without a value at `index` are kept.
+    /// Returns the number of edges collapsed.
+    fn collapse_below_support(&mut self, threshold: f32, index: usize) -> usize {
+        let root_id = self.get_root_id();
+        let weak = self
+            .get_node_ids()
+            .filter(|&node_id| {
+                node_id != root_id
+                    && !self.is_leaf(node_id)
+                    && self
+                        .get_node_support(node_id)
+                        .and_then(|support| support.get(index))
+                        .is_some_and(|&value| value < threshold)
+            })
+            .collect_vec();
+        for &node_id in &weak {
+            let parent_id = self
+                .get_node_parent_id(node_id)
+                .expect("invariant: the root is never collapsed");
+            let children = self.get_node_children_ids(node_id).collect_vec();
+            let siblings = self.get_node_children_ids(parent_id).collect_vec();
+            self.remove_node(node_id);
+            self.remove_all_children(parent_id);
+            // Keep the children where the collapsed node was.
+            for sibling_id in siblings {
+                match sibling_id == node_id {
+                    true => children
+                        .iter()
+                        .for_each(|&child_id| self.set_child(parent_id, child_id)),
+                    false => self.set_child(parent_id, sibling_id),
+                }
+            }
+        }
+        weak.len()
+    }
+}
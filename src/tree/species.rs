@@ -0,0 +1,695 @@
+//! Species trees from gene trees, by quartet support.
+//!
+//! [`SpeciesTreeSearch`](crate::tree::species::SpeciesTreeSearch) follows
+//! ASTRAL (Mirarab et al. 2014; Mirarab & Warnow 2015). It looks for the
+//! unrooted species tree that shares the most quartet topologies with the
+//! gene trees of a [`TreeSet`](crate::tree::treeset::TreeSet), which is a
+//! statistically consistent estimate under the multispecies coalescent.
+//!
+//! The search is limited to species trees whose clusters all come from a
+//! candidate set. The set holds both sides of every gene tree split, with a
+//! caterpillar resolution of every gene tree polytomy, so each candidate can
+//! be split into two others. A dynamic program over the candidates, from
+//! small to large, resolves each into the two-way split that maximises the
+//! quartet score of the subtree. The score of a node is read off the
+//! tripartition it makes of the taxa, with ASTRAL-II's closed form over the
+//! tripartition at every gene tree node, in `O(k n)` for `k` gene trees on `n`
+//! taxa. That takes `O(|X|² n / 64 + s k n)` time for `|X|` candidates that
+//! can be split into two other candidates `s` ways in all.
+//!
+//! Every internal branch of the result is annotated as in Sayyari & Mirarab
+//! (2016). The quartets around a branch take one taxon from each of the four
+//! subtrees it joins. Their frequencies in the gene trees, normalised to one
+//! per gene, give the quartet support of the branch's resolution and its two
+//! alternatives. Each resolution also gets a local posterior probability,
+//! under a multinomial with an exponential prior on the branch length. The
+//! branch length, in coalescent units, is read off the main frequency.
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
+
+use {
+    crate::models::gamma::{incomplete_beta, ln_gamma},
+    crate::node::{Node, NodeID},
+    crate::prelude::*,
+    crate::tree::splits::Topology,
+    crate::tree::PhyloTree,
+    itertools::Itertools,
+};
+
+/// Quartet support for the branch above an internal node of a
+/// [`SpeciesTree`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BranchSupport {
+    /// The node below the branch.
+    pub node: NodeID,
+    /// Share of the gene quartets around the branch resolved as in the
+    /// species tree, then as each alternative; together they sum to one.
+    pub quartet_support: [f64; 3],
+    /// Local posterior probability of each of the three resolutions.
+    pub local_posterior: [f64; 3],
+    /// Gene trees informing the branch: their quartet frequencies summed,
+    /// each gene counting up to one.
+    pub effective_genes: f64,
+    /// Length of the branch in coalescent units.
+    pub length: f64,
+}
+
+/// A species tree found by a [`SpeciesTreeSearch`].
+#[derive(Debug, Clone)]
+pub struct SpeciesTree {
+    /// The species tree, rooted where the first taxon attaches. Internal
+    /// branches have their length in coalescent units; pendant branches have
+    /// none.
+    pub tree: PhyloTree,
+    /// Support for each internal branch, by the node below it.
+    pub branches: Vec<BranchSupport>,
+    /// Gene tree quartets, summed over the gene trees, that the species tree
+    /// resolves the same way.
+    pub quartet_score: f64,
+    /// `quartet_score` over the gene tree quartets that are resolved; zero
+    /// when none are.
+    pub normalized_score: f64,
+}
+
+/// Quartet-based species tree search over the gene trees of a [`TreeSet`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesTreeSearch {
+    prior_rate: f64,
+}
+
+impl Default for SpeciesTreeSearch {
+    fn default() -> Self {
+        SpeciesTreeSearch::new()
+    }
+}
+
+impl SpeciesTreeSearch {
+    /// A search with ASTRAL's prior on branch lengths, of rate one half.
+    pub fn new() -> Self {
+        SpeciesTreeSearch { prior_rate: 0.5 }
+    }
+
+    /// Rate of the exponential prior on branch lengths behind the local
+    /// posterior probabilities.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `rate` is finite and positive.
+    pub fn with_prior_rate(mut self, rate: f64) -> Self {
+        assert!(
+            rate > 0.0 && rate.is_finite(),
+            "the prior rate must be finite and positive"
+        );
+        self.prior_rate = rate;
+        self
+    }
+
+    /// Finds the species tree for the gene trees of `set`, which are treated
+    /// as unrooted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `set` is empty.
+    pub fn search(&self, set: &TreeSet) -> SpeciesTree {
+        assert!(!set.is_empty(), "a species tree needs gene trees");
+        let n = set.taxa().len();
+        let genes = set
+            .topologies()
+            .map(|topology| Gene {
+                topology,
+                taxa: topology.taxa(),
+            })
+            .collect_vec();
+        let mut counts = vec![];
+
+        let (splits, shared) = best_splits(&genes, n, &mut counts);
+        // Every resolved quartet is met at two gene tree nodes.
+        let mut twice_resolved = 0;
+        for gene in &genes {
+            gene.for_each_node::<1>(&vec![0; n], &mut counts, |parts| {
+                twice_resolved += resolved_quartets(parts)
+            });
+        }
+        let resolved = twice_resolved / 2;
+
+        let (tree, nodes) = build(&splits, set.taxa());
+        let mut species = SpeciesTree {
+            tree,
+            branches: Vec::with_capacity(nodes.len()),
+            quartet_score: shared as f64,
+            normalized_score: 0.0,
+        };
+        if resolved > 0 {
+            species.normalized_score = species.quartet_score / resolved as f64;
+        }
+
+        let clusters = node_clusters(&species.tree, set.taxa());
+        let root = species.tree.get_root_id();
+        let mut label = vec![0u8; n];
+        for &node in &nodes {
+            let parent = species
+                .tree
+                .get_node_parent_id(node)
+                .expect("invariant: split nodes hang below the root");
+            let mut groups = species
+                .tree
+                .get_node_children_ids(node)
+                .chain(
+                    species
+                        .tree
+                        .get_node_children_ids(parent)
+                        .filter(|&c| c != node),
+                )
+                .map(|c| clusters[&c].clone())
+                .collect_vec();
+            if parent != root {
+                groups.push(clusters[&parent].iter().map(|w| !w).collect());
+            }
+            for (t, l) in label.iter_mut().enumerate() {
+                *l = groups
+                    .iter()
+                    .position(|g| g[t / 64] >> (t % 64) & 1 == 1)
+                    .expect("invariant: the four groups cover the taxa") as u8;
+            }
+            let sizes = (0..4)
+                .map(|g| label.iter().filter(|&&l| l == g).count() as f64)
+                .product::<f64>();
+            let mut frequency = [0.0; 3];
+            for gene in &genes {
+                let mut twice = [0; 3];
+                gene.for_each_node::<4>(&label, &mut counts, |parts| {
+                    for (r, [x, y, z, w]) in RESOLUTIONS.into_iter().enumerate() {
+                        twice[r] += paired_quartets(parts, [x, y], [z, w]);
+                    }
+                });
+                for (f, twice) in frequency.iter_mut().zip(twice) {
+                    *f += twice as f64 / 2.0 / sizes;
+                }
+            }
+            let effective_genes = frequency.iter().sum::<f64>();
+            let support = match effective_genes > 0.0 {
+                true => frequency.map(|f| f / effective_genes),
+                false => [1.0 / 3.0; 3],
+            };
+            let length = branch_length(frequency[0], effective_genes);
+            species
+                .tree
+                .set_edge_weight((parent, node), Some(length as f32));
+            species.branches.push(BranchSupport {
+                node,
+                quartet_support: support,
+                local_posterior: local_posterior(frequency, self.prior_rate),
+                effective_genes,
+                length,
+            });
+        }
+        species
+    }
+}
+
+/// The groups paired by each resolution of the quartets around a branch,
+/// the species tree's first.
+const RESOLUTIONS: [[usize; 4]; 3] = [[0, 1, 2, 3], [0, 2, 1, 3], [0, 3, 1, 2]];
+
+/// A gene tree, with the taxon of each of its leaves.
+struct Gene<'a> {
+    topology: &'a Topology,
+    taxa: Vec<u32>,
+}
+
+impl Gene<'_> {
+    /// Calls `visit` at every node of the unrooted gene tree with, for each
+    /// of the parts the node divides the taxa into, how many taxa of each
+    /// label it holds. Taxa labelled `L` or above are not counted.
+    fn for_each_node<const L: usize>(
+        &self,
+        label: &[u8],
+        counts: &mut Vec<[u64; 4]>,
+        mut visit: impl FnMut(&[[u64; 4]]),
+    ) {
+        let len = self.topology.len();
+        counts.clear();
+        counts.resize(len, [0; 4]);
+        for v in 0..len {
+            match self.topology.children(v) {
+                [] => {
+                    let l = label[self.taxa[v] as usize] as usize;
+                    if l < L {
+                        counts[v][l] = 1;
+                    }
+                }
+                children => {
+                    for &c in children {
+                        let child = counts[c as usize];
+                        for (count, child) in counts[v].iter_mut().zip(child).take(L) {
+                            *count += child;
+                        }
+                    }
+                }
+            }
+        }
+        let total = counts[len - 1];
+        let mut parts = vec![];
+        for v in 0..len {
+            let children = self.topology.children(v);
+            parts.clear();
+            parts.extend(children.iter().map(|&c| counts[c as usize]));
+            if v + 1 < len {
+                parts.push(std::array::from_fn(|l| total[l] - counts[v][l]));
+            }
+            if parts.len() >= 3 {
+                visit(&parts);
+            }
+        }
+    }
+}
+
+/// Twice the quartets met at a gene tree node that a species tree node
+/// resolves the same way, where labels 0 to 2 give the tripartition at the
+/// species tree node. A quartet is met at a node when two of its taxa share
+/// a part and the other two lie in two more; summed over the pairs of nodes,
+/// each shared quartet is met twice.
+fn tripartition_quartets(parts: &[[u64; 4]]) -> u128 {
+    let mut twice = 0u128;
+    for (i, j, k) in (0..parts.len()).tuple_combinations() {
+        for [a, b, c] in [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ] {
+            let (a, b, c) = (parts[i][a], parts[j][b], parts[k][c]);
+            if a * b * c > 0 {
+                twice += (a * b * c) as u128 * (a + b + c - 3) as u128;
+            }
+        }
+    }
+    twice
+}
+
+/// Resolved quartets met at a gene tree node, with all taxa under label 0.
+fn resolved_quartets(parts: &[[u64; 4]]) -> u128 {
+    let total = parts.iter().map(|p| p[0] as u128).sum::<u128>();
+    let squares = parts.iter().map(|p| (p[0] * p[0]) as u128).sum::<u128>();
+    parts
+        .iter()
+        .map(|p| {
+            let s = p[0] as u128;
+            let rest = total - s;
+            // Pairs from two different parts other than this one.
+            let across = (rest * rest - (squares - s * s)) / 2;
+            s * s.saturating_sub(1) / 2 * across
+        })
+        .sum()
+}
+
+/// Quartets met at a gene tree node resolved as `xy|zw`, taking one taxon
+/// from each of the labels.
+fn paired_quartets(parts: &[[u64; 4]], [x, y]: [usize; 2], [z, w]: [usize; 2]) -> u128 {
+    // One side's two taxa share part `i`, the other's lie in two others.
+    let half = |[x, y]: [usize; 2], [z, w]: [usize; 2]| {
+        let (total_z, total_w) = (
+            parts.iter().map(|p| p[z] as u128).sum::<u128>(),
+            parts.iter().map(|p| p[w] as u128).sum::<u128>(),
+        );
+        let same = parts.iter().map(|p| (p[z] * p[w]) as u128).sum::<u128>();
+        parts
+            .iter()
+            .map(|p| {
+                let (pz, pw) = (p[z] as u128, p[w] as u128);
+                let apart = (total_z - pz) * (total_w - pw) - (same - pz * pw);
+                (p[x] * p[y]) as u128 * apart
+            })
+            .sum::<u128>()
+    };
+    half([x, y], [z, w]) + half([z, w], [x, y])
+}
+
+/// The splits of a species tree maximising the quartet score over the
+/// candidate clusters, as sides without taxon 0, and that score.
+fn best_splits(genes: &[Gene<'_>], n: usize, counts: &mut Vec<[u64; 4]>) -> (Vec<Vec<u64>>, u128) {
+    let words = n.div_ceil(64);
+    let mut all = vec![0u64; words];
+    for t in 0..n {
+        all[t / 64] |= 1 << (t % 64);
+    }
+    let complement = |c: &[u64]| c.iter().zip(&all).map(|(c, a)| !c & a).collect_vec();
+    let size = |c: &[u64]| c.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+
+    // Candidates: every gene tree cluster and its complement, with the
+    // unions of a polytomy's first children, every taxon and the whole set.
+    let mut candidates: HashMap<Vec<u64>, usize> = HashMap::default();
+    let mut add = |c: Vec<u64>| {
+        let other = complement(&c);
+        for c in [c, other] {
+            if size(&c) > 0 {
+                let next = candidates.len();
+                candidates.entry(c).or_insert(next);
+            }
+        }
+    };
+    add(all.clone());
+    for t in 0..n {
+        let mut single = vec![0u64; words];
+        single[t / 64] |= 1 << (t % 64);
+        add(single);
+    }
+    for gene in genes {
+        let mut below: Vec<Vec<u64>> = Vec::with_capacity(gene.topology.len());
+        for v in 0..gene.topology.len() {
+            let mut cluster = vec![0u64; words];
+            match gene.topology.children(v) {
+                [] => {
+                    let t = gene.taxa[v] as usize;
+                    cluster[t / 64] |= 1 << (t % 64);
+                }
+                children => {
+                    for (i, &c) in children.iter().enumerate() {
+                        for (w, x) in cluster.iter_mut().zip(&below[c as usize]) {
+                            *w |= x;
+                        }
+                        if i > 0 && i + 1 < children.len() {
+                            add(cluster.clone());
+                        }
+                    }
+                }
+            }
+            add(cluster.clone());
+            below.push(cluster);
+        }
+    }
+    let mut clusters = vec![vec![]; candidates.len()];
+    for (c, i) in candidates.iter() {
+        clusters[*i] = c.clone();
+    }
+    let mut order = (0..clusters.len()).collect_vec();
+    order.sort_by_key(|&i| (size(&clusters[i]), i));
+
+    // `best[i]`: the best subtree on cluster `i`, by its tripartition scores
+    // summed over its nodes, and the smaller part of its split at the top.
+    let mut best: Vec<Option<(u128, usize)>> = vec![None; clusters.len()];
+    let mut label = vec![2u8; n];
+    for (at, &i) in order.iter().enumerate() {
+        let cluster = &clusters[i];
+        let len = size(cluster);
+        if len == 1 {
+            best[i] = Some((0, usize::MAX));
+            continue;
+        }
+        for &j in &order[..at] {
+            let part = &clusters[j];
+            let part_len = size(part);
+            if 2 * part_len > len {
+                break;
+            }
+            if part.iter().zip(cluster).any(|(p, c)| p & !c != 0) {
+                continue;
+            }
+            let rest = part.iter().zip(cluster).map(|(p, c)| c & !p).collect_vec();
+            let Some(&k) = candidates.get(&rest) else {
+                continue;
+            };
+            if 2 * part_len == len && k < j {
+                continue;
+            }
+            let (Some((below_j, _)), Some((below_k, _))) = (best[j], best[k]) else {
+                continue;
+            };
+            let mut score = below_j + below_k;
+            if len < n {
+                for t in 0..n {
+                    label[t] = match (part[t / 64] >> (t % 64) & 1, rest[t / 64] >> (t % 64) & 1) {
+                        (1, _) => 0,
+                        (_, 1) => 1,
+                        _ => 2,
+                    };
+                }
+                for gene in genes {
+                    gene.for_each_node::<3>(&label, counts, |parts| {
+                        score += tripartition_quartets(parts)
+                    });
+                }
+            }
+            if best[i].is_none_or(|(s, _)| score > s) {
+                best[i] = Some((score, j));
+            }
+        }
+    }
+
+    // Unwind the splits from the whole set down.
+    let top = candidates[&all];
+    let total = best[top].map_or(0, |(s, _)| s);
+    let mut splits = vec![];
+    let mut pending = vec![top];
+    while let Some(i) = pending.pop() {
+        let Some((_, j)) = best[i] else { continue };
+        if j == usize::MAX {
+            continue;
+        }
+        let rest = clusters[j]
+            .iter()
+            .zip(&clusters[i])
+            .map(|(p, c)| c & !p)
+            .collect_vec();
+        let k = candidates[&rest];
+        for part in [j, k] {
+            let side = match clusters[part][0] & 1 {
+                1 => complement(&clusters[part]),
+                _ => clusters[part].clone(),
+            };
+            let len = size(&side);
+            if len > 1 && len + 1 < n && !splits.contains(&side) {
+                splits.push(side);
+            }
+            pending.push(part);
+        }
+    }
+    // Each shared quartet is counted twice at each of two species tree nodes.
+    (splits, total / 4)
+}
+
+/// The tree with the given splits, rooted where taxon 0 attaches, and the
+/// node below each split.
+fn build(splits: &[Vec<u64>], taxa: &[String]) -> (PhyloTree, Vec<NodeID>) {
+    let mut kept = splits.iter().collect_vec();
+    // Larger sides first, so every cluster comes after those containing it,
+    // and the last one containing it is its parent.
+    kept.sort_by_key(|side| std::cmp::Reverse(side.iter().map(|w| w.count_ones()).sum::<u32>()));
+    let contains = |outer: &[u64], inner: &[u64]| inner.iter().zip(outer).all(|(i, o)| i & !o == 0);
+    let mut tree = PhyloTree::new(0);
+    let root = tree.get_root_id();
+    let attach = |tree: &mut PhyloTree, parent: NodeID| {
+        let node = Node::new(tree.next_id());
+        let id = node.get_id();
+        tree.add_child(parent, node);
+        id
+    };
+    let mut nodes: Vec<NodeID> = Vec::with_capacity(kept.len());
+    for (p, side) in kept.iter().enumerate() {
+        let parent = (0..p)
+            .rev()
+            .find(|&q| contains(kept[q], side))
+            .map_or(root, |q| nodes[q]);
+        nodes.push(attach(&mut tree, parent));
+    }
+    for (t, name) in taxa.iter().enumerate() {
+        let parent = (0..kept.len())
+            .rev()
+            .find(|&q| kept[q][t / 64] >> (t % 64) & 1 == 1)
+            .map_or(root, |q| nodes[q]);
+        let id = attach(&mut tree, parent);
+        tree.set_node_taxa(id, Some(name.clone()));
+    }
+    // Back in the order of `splits`.
+    let nodes = splits
+        .iter()
+        .map(|side| {
+            nodes[kept
+                .iter()
+                .position(|k| *k == side)
+                .expect("invariant: kept all")]
+        })
+        .collect();
+    (tree, nodes)
+}
+
+/// Taxa below every node of `tree`, by their position in `taxa`.
+fn node_clusters(tree: &PhyloTree, taxa: &[String]) -> HashMap<NodeID, Vec<u64>> {
+    let words = taxa.len().div_ceil(64);
+    let mut clusters: HashMap<NodeID, Vec<u64>> = HashMap::default();
+    let postord = tree
+        .postord_ids(tree.get_root_id())
+        .expect("invariant: the root id always names a node");
+    for node in postord {
+        let mut cluster = vec![0u64; words];
+        if let Some(name) = tree.get_node_taxa(node) {
+            let t = taxa
+                .binary_search(name)
+                .expect("invariant: leaves carry the set's taxa");
+            cluster[t / 64] |= 1 << (t % 64);
+        }
+        for child in tree.get_node_children_ids(node) {
+            for (w, x) in cluster.iter_mut().zip(&clusters[&child]) {
+                *w |= x;
+            }
+        }
+        clusters.insert(node, cluster);
+    }
+    clusters
+}
+
+/// Coalescent length of a branch whose resolution has frequency `main` out
+/// of `total`. A resolution every gene holds is taken as held by all but
+/// half a gene, so the length stays finite.
+fn branch_length(main: f64, total: f64) -> f64 {
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let share = main.min(total - 0.5) / total;
+    match share > 1.0 / 3.0 {
+        true => -(1.5 * (1.0 - share)).ln(),
+        false => 0.0,
+    }
+}
+
+/// Local posterior probabilities of the three resolutions of a branch with
+/// quartet frequencies `frequency`, under an exponential prior of rate
+/// `rate` on the branch length.
+///
+/// The true resolution has frequency `p = 1 - 2/3 e^{-t}` and the others
+/// `(1 - p) / 2` each. Integrating the multinomial likelihood over the prior
+/// on `t` gives resolution `i` the weight
+/// `2^{x_i - s} B(x_i + 1, s - x_i + rate) I_{2/3}(s - x_i + rate, x_i + 1)`
+/// for frequencies `x` summing to `s`.
+fn local_posterior(frequency: [f64; 3], rate: f64) -> [f64; 3] {
+    let s = frequency.iter().sum::<f64>();
+    let weight = frequency.map(|x| {
+        let (a, b) = (x + 1.0, s - x + rate);
+        (x - s) * std::f64::consts::LN_2 + ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b)
+            + incomplete_beta(2.0 / 3.0, b, a).ln()
+    });
+    let top = weight.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let scaled = weight.map(|w| (w - top).exp());
+    let sum = scaled.iter().sum::<f64>();
+    scaled.map(|w| w / sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(newick: &str) -> PhyloTree {
+        PhyloTree::from_newick(newick.as_bytes()).unwrap()
+    }
+
+    /// Resolution of every quartet of sorted taxa, as the index of the taxon
+    /// paired with the first, or 0 if unresolved.
+    fn quartets(tree: &PhyloTree) -> Vec<u8> {
+        let splits = tree.splits();
+        let n = splits.num_taxa();
+        (0..n)
+            .combinations(4)
+            .map(|q| {
+                (1..4)
+                    .find(|&p| {
+                        splits.iter().any(|s| {
+                            let side = |t: usize| s.contains(q[t]);
+                            let others = (1..4).filter(|&o| o != p).collect_vec();
+                            side(0) == side(p)
+                                && side(others[0]) == side(others[1])
+                                && side(0) != side(others[0])
+                        })
+                    })
+                    .map_or(0, |p| p as u8)
+            })
+            .collect()
+    }
+
+    fn shared(species: &PhyloTree, genes: &[PhyloTree]) -> usize {
+        let own = quartets(species);
+        genes
+            .iter()
+            .map(|gene| {
+                quartets(gene)
+                    .iter()
+                    .zip(&own)
+                    .filter(|&(g, s)| g == s && *g != 0)
+                    .count()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_identical_genes() {
+        let gene = tree("((A,B),((C,D),(E,F)));");
+        let genes = vec![gene.clone(); 4];
+        let species = SpeciesTreeSearch::new().search(&TreeSet::from_trees(&genes).unwrap());
+        assert_eq!(gene.normalized_rf(&species.tree).unwrap().distance, 0.0);
+        assert_eq!(species.quartet_score, 4.0 * 15.0);
+        assert_eq!(species.normalized_score, 1.0);
+        assert_eq!(species.branches.len(), 3);
+        for branch in &species.branches {
+            assert_eq!(branch.quartet_support, [1.0, 0.0, 0.0]);
+            assert_eq!(branch.effective_genes, 4.0);
+            assert!(branch.local_posterior[0] > 0.9);
+            assert!(branch.length > 0.0 && branch.length.is_finite());
+        }
+    }
+
+    #[test]
+    fn test_quartet_score_matches_brute_force() {
+        for round in 0..10 {
+            let genes = (0..5).map(|_| PhyloTree::yule(7)).collect_vec();
+            let set = TreeSet::from_trees(&genes).unwrap();
+            let species = SpeciesTreeSearch::new().search(&set);
+            let score = shared(&species.tree, &genes);
+            assert_eq!(species.quartet_score, score as f64, "round {round}");
+            assert_eq!(species.normalized_score, score as f64 / (5.0 * 35.0));
+            // Each gene tree is a candidate species tree.
+            for gene in &genes {
+                assert!(shared(gene, &genes) <= score, "round {round}");
+            }
+            assert_eq!(species.branches.len(), 4);
+            for branch in &species.branches {
+                assert!((branch.quartet_support.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+                assert!((branch.local_posterior.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+                assert!((branch.effective_genes - 5.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_majority_and_polytomies() {
+        let main = "(((A,B),C),((D,E),F));";
+        let mut genes = vec![tree(main); 6];
+        genes.push(tree("(((A,C),B),((D,F),E));"));
+        genes.push(tree("(((A,D),C),((B,E),F));"));
+        genes.push(tree("((A,B,C),(D,E,F));"));
+        let species = SpeciesTreeSearch::new().search(&TreeSet::from_trees(&genes).unwrap());
+        assert_eq!(
+            tree(main).normalized_rf(&species.tree).unwrap().distance,
+            0.0
+        );
+        assert_eq!(species.quartet_score, shared(&species.tree, &genes) as f64);
+        for branch in &species.branches {
+            assert!(branch.quartet_support[0] > branch.quartet_support[1]);
+            assert!(branch.local_posterior[0] > branch.local_posterior[2]);
+            // The polytomies leave some quartets unresolved.
+            assert!(branch.effective_genes <= 9.0);
+        }
+        let edges = species.branches.iter().map(|b| b.length).collect_vec();
+        let weights = species
+            .branches
+            .iter()
+            .map(|b| f64::from(species.tree.get_node(b.node).unwrap().get_weight().unwrap()))
+            .collect_vec();
+        for (e, w) in edges.iter().zip(&weights) {
+            assert!((e - w).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,1305 @@
+//! Split-based tree distances over one shared bipartition encoding.
+//!
+//! Every metric here compares two trees on the same taxa through their
+//! non-trivial splits. [`SplitSet`](crate::tree::splits::SplitSet) is that encoding: taxa are numbered by
+//! their sorted order, and each split is stored as the bitset of the side
+//! *not* holding taxon 0, so two trees on the same taxa produce directly
+//! comparable splits whatever their rooting or arena layout.
+//!
+//! Results come back as a [`TreeComparison`](crate::tree::splits::TreeComparison), carrying the similarity, the
+//! distance and the largest distance the two trees could have, so that any
+//! metric normalizes the same way. The split metrics follow the generalized
+//! Robinson-Foulds scheme of Smith (2020): a similarity `s` between trees
+//! gives the distance `s(T1, T1) + s(T2, T2) - 2 s(T1, T2)` with maximum
+//! `s(T1, T1) + s(T2, T2)`.
+//!
+//! | Method | Similarity between splits | Cost |
+//! |---|---|---|
+//! | [`normalized_rf`](crate::tree::splits::SplitDistances::normalized_rf) | identity | O(n²/64) |
+//! | [`nye_similarity`](crate::tree::splits::SplitDistances::nye_similarity) | Nye et al. (2006) pairing score | O(n³) |
+//! | [`clustering_info_distance`](crate::tree::splits::SplitDistances::clustering_info_distance) | mutual clustering information | O(n³) |
+//! | [`phylogenetic_info_distance`](crate::tree::splits::SplitDistances::phylogenetic_info_distance) | shared phylogenetic information | O(n³) |
+//! | [`matching_split_distance`](crate::tree::splits::SplitDistances::matching_split_distance) | taxa to move (a cost) | O(n³) |
+//!
+//! The matching metrics pair the splits of one tree with those of the other
+//! by an optimal assignment (Hungarian algorithm). The quartet and triplet
+//! distances count four- and three-taxon subtrees whose induced topologies
+//! differ, without enumerating them: every pair of internal nodes contributes
+//! a closed-form count over the sizes of their subtree intersections. Both
+//! visit all such pairs, so they take O(n²) time for trees of bounded degree;
+//! they do not implement tqDist's O(n log n) hierarchical decompositions. At a
+//! pair of nodes with `d1` and `d2` subtrees the count takes O(d1 d2) for
+//! triplets and O(d1 d2 min(d1, d2)) for quartets, whose sets unresolved at
+//! both nodes need the products of every two subtrees of one node.
+
+use std::f64::consts::LN_2;
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::{FxHashMap as HashMap, FxHashSet as HashSet};
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::{HashMap, HashSet};
+
+use crate::models::gamma::ln_gamma;
+use crate::prelude::*;
+
+/// Similarity, distance and maximum distance between two trees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TreeComparison {
+    /// How much the trees share, in the metric's own units.
+    pub similarity: f64,
+    /// How far apart the trees are; zero for identical topologies.
+    pub distance: f64,
+    /// Upper bound on `distance` for these two trees.
+    pub maximum: f64,
+}
+
+impl TreeComparison {
+    /// `distance / maximum`, in `[0, 1]`. Zero when the maximum is zero, as
+    /// for trees with no non-trivial splits.
+    pub fn normalized(&self) -> f64 {
+        if self.maximum > 0.0 {
+            self.distance / self.maximum
+        } else {
+            0.0
+        }
+    }
+}
+
+/// One non-trivial bipartition of the taxa.
+///
+/// Stored as the side not containing taxon 0, so a split and its complement
+/// encode identically.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Split {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Split {
+    pub(crate) fn from_side(mut words: Vec<u64>, len: usize, num_taxa: usize) -> Self {
+        if words[0] & 1 == 0 {
+            return Split { words, len };
+        }
+        for word in words.iter_mut() {
+            *word = !*word;
+        }
+        let tail = num_taxa % 64;
+        if tail != 0 {
+            *words.last_mut().expect("invariant: num_taxa > 0") &= (1 << tail) - 1;
+        }
+        Split {
+            words,
+            len: num_taxa - len,
+        }
+    }
+
+    /// Number of taxa on the side without taxon 0.
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Whether taxon `taxon`, by sorted-order index, is on the side without
+    /// taxon 0.
+    pub fn contains(&self, taxon: usize) -> bool {
+        self.words
+            .get(taxon / 64)
+            .is_some_and(|word| word >> (taxon % 64) & 1 == 1)
+    }
+
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub(crate) fn intersection_len(&self, other: &Split) -> usize {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .map(|(a, b)| (a & b).count_ones() as usize)
+            .sum()
+    }
+}
+
+/// The non-trivial splits of a tree, over its taxa in sorted order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitSet {
+    num_taxa: usize,
+    splits: Vec<Split>,
+}
+
+impl SplitSet {
+    /// Number of taxa the splits partition.
+    pub fn num_taxa(&self) -> usize {
+        self.num_taxa
+    }
+
+    /// Number of distinct non-trivial splits.
+    pub fn len(&self) -> usize {
+        self.splits.len()
+    }
+
+    /// True for a star tree, or one with fewer than four taxa.
+    pub fn is_empty(&self) -> bool {
+        self.splits.is_empty()
+    }
+
+    /// The splits, in a fixed order.
+    pub fn iter(&self) -> impl Iterator<Item = &Split> {
+        self.splits.iter()
+    }
+
+    /// Whether `split` is one of these.
+    pub fn contains(&self, split: &Split) -> bool {
+        self.splits.binary_search(split).is_ok()
+    }
+}
+
+/// Cell counts of one split against another: how the taxa fall across
+/// `A1|B1` and `A2|B2`, where `A` is the side without taxon 0.
+struct SplitPair {
+    n: usize,
+    a1: usize,
+    a2: usize,
+    a1a2: usize,
+}
+
+impl SplitPair {
+    fn new(s1: &Split, s2: &Split, n: usize) -> Self {
+        SplitPair {
+            n,
+            a1: s1.len,
+            a2: s2.len,
+            a1a2: s1.intersection_len(s2),
+        }
+    }
+
+    /// `[|A1∩A2|, |A1∩B2|, |B1∩A2|, |B1∩B2|]`.
+    fn cells(&self) -> [usize; 4] {
+        let a1b2 = self.a1 - self.a1a2;
+        let b1a2 = self.a2 - self.a1a2;
+        [self.a1a2, a1b2, b1a2, self.n - self.a1 - b1a2]
+    }
+}
+
+/// Split-based distances between two trees on the same taxa.
+pub trait SplitDistances
+where
+    Self: RootedTree + RootedMetaTree + Clusters,
+    <Self as RootedTree>::Node: RootedMetaNode,
+{
+    /// Non-trivial splits of the tree, treating it as unrooted.
+    fn splits(&self) -> SplitSet {
+        let index = taxon_index(self);
+        let num_taxa = index.len();
+        let words = num_taxa.div_ceil(64);
+        let mut sides: HashMap<TreeNodeID<Self>, (Vec<u64>, usize)> = HashMap::default();
+        let mut splits: HashSet<Split> = HashSet::default();
+        for node_id in self
+            .postord_ids(self.get_root_id())
+            .expect("invariant: the root id always names a node")
+        {
+            let mut side = vec![0; words];
+            let mut len = 0;
+            if self.is_leaf(node_id) {
+                let taxon = index[self
+                    .get_node_taxa(node_id)
+                    .expect("invariant: leaves carry a taxon")];
+                side[taxon / 64] |= 1 << (taxon % 64);
+                len = 1;
+            } else {
+                for child_id in self.get_node_children_ids(node_id) {
+                    let (child, child_len) = sides
+                        .remove(&child_id)
+                        .expect("invariant: children precede parents in post-order");
+                    side.iter_mut().zip(child).for_each(|(w, c)| *w |= c);
+                    len += child_len;
+                }
+                if len >= 2 && len + 2 <= num_taxa {
+                    splits.insert(Split::from_side(side.clone(), len, num_taxa));
+                }
+            }
+            sides.insert(node_id, (side, len));
+        }
+        let mut splits = splits.into_iter().collect::<Vec<_>>();
+        splits.sort_unstable();
+        SplitSet { num_taxa, splits }
+    }
+
+    /// Robinson-Foulds distance over non-trivial splits, with the number of
+    /// splits the trees hold between them as its maximum.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn normalized_rf(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let (s1, s2) = split_pair(self, tree)?;
+        let shared = s1.iter().filter(|split| s2.contains(split)).count() as f64;
+        Ok(generalized(s1.len() as f64, s2.len() as f64, shared))
+    }
+
+    /// Nye et al. (2006) similarity: splits are paired to maximize the sum,
+    /// over pairs, of how well the two sides of one split overlap the sides of
+    /// the other. Each split scores one against itself, so the distance is
+    /// `|S1| + |S2| - 2 similarity`.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn nye_similarity(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let (s1, s2) = split_pair(self, tree)?;
+        let shared = max_weight_matching(&s1, &s2, |pair| {
+            let [aa, ab, ba, bb] = pair.cells();
+            let (b1, b2) = (pair.n - pair.a1, pair.n - pair.a2);
+            let jaccard = |both: usize, x: usize, y: usize| both as f64 / (x + y - both) as f64;
+            let same = jaccard(aa, pair.a1, pair.a2).min(jaccard(bb, b1, b2));
+            let crossed = jaccard(ab, pair.a1, b2).min(jaccard(ba, b1, pair.a2));
+            same.max(crossed)
+        });
+        Ok(generalized(s1.len() as f64, s2.len() as f64, shared))
+    }
+
+    /// Clustering information distance (Smith 2020), in bits.
+    ///
+    /// Each split is read as a two-cluster partition of the taxa; its entropy
+    /// is its clustering information, and splits are paired to maximize their
+    /// total mutual information.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn clustering_info_distance(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let (s1, s2) = split_pair(self, tree)?;
+        let n = s1.num_taxa();
+        let information = |set: &SplitSet| {
+            set.iter()
+                .map(|split| entropy(&[split.len, n - split.len], n))
+                .sum::<f64>()
+        };
+        let shared = max_weight_matching(&s1, &s2, |pair| {
+            let [aa, ab, ba, bb] = pair.cells();
+            entropy(&[pair.a1, n - pair.a1], n) + entropy(&[pair.a2, n - pair.a2], n)
+                - entropy(&[aa, ab, ba, bb], n)
+        });
+        Ok(generalized(information(&s1), information(&s2), shared))
+    }
+
+    /// Shared phylogenetic information distance (Smith 2020), in bits.
+    ///
+    /// A split's phylogenetic information is `-log2` of the fraction of
+    /// unrooted binary trees containing it. Two compatible splits share the
+    /// information of each minus that of both together; incompatible splits
+    /// share none. Splits are paired to maximize the total.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn phylogenetic_info_distance(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let (s1, s2) = split_pair(self, tree)?;
+        let n = s1.num_taxa();
+        let info = |set: &SplitSet| {
+            set.iter()
+                .map(|split| split_information(split.len, n))
+                .sum::<f64>()
+        };
+        let shared = max_weight_matching(&s1, &s2, |pair| {
+            // Compatible splits nest, leaving three parts of sizes `inner`,
+            // `middle` and `outer`; incompatible ones share nothing.
+            let cells = pair.cells();
+            if !cells.contains(&0) {
+                return 0.0;
+            }
+            let (inner, outer) = nested_sides(pair, cells);
+            let middle = n - inner - outer;
+            let joint = (ln_odd_factorial(n - 2)
+                - ln_odd_factorial(inner - 1)
+                - ln_odd_factorial(middle)
+                - ln_odd_factorial(outer - 1))
+                / LN_2;
+            split_information(pair.a1, n) + split_information(pair.a2, n) - joint
+        });
+        Ok(generalized(info(&s1), info(&s2), shared))
+    }
+
+    /// Matching split distance (Bogdanowicz & Giaro 2012): splits are paired
+    /// to minimize the total number of taxa that must change sides to turn
+    /// one split into its partner. A split left unpaired costs its smaller
+    /// side.
+    ///
+    /// The maximum is the sum of every split's smaller side over both trees,
+    /// which bounds any pairing's cost.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn matching_split_distance(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let (s1, s2) = split_pair(self, tree)?;
+        let n = s1.num_taxa();
+        let smaller = |split: &Split| split.len.min(n - split.len) as f64;
+        let size = s1.len().max(s2.len());
+        let mut cost = vec![0.0; size * size];
+        for i in 0..size {
+            for j in 0..size {
+                cost[i * size + j] = match (s1.splits.get(i), s2.splits.get(j)) {
+                    (Some(x), Some(y)) => {
+                        let [aa, ab, ba, bb] = SplitPair::new(x, y, n).cells();
+                        (ab + ba).min(aa + bb) as f64
+                    }
+                    (Some(x), None) => smaller(x),
+                    (None, Some(y)) => smaller(y),
+                    (None, None) => 0.0,
+                };
+            }
+        }
+        let distance = min_cost_assignment(&cost, size);
+        let maximum = s1.iter().chain(s2.iter()).map(smaller).sum::<f64>();
+        Ok(TreeComparison {
+            similarity: maximum - distance,
+            distance,
+            maximum,
+        })
+    }
+
+    /// Number of three-taxon subsets whose rooted topologies differ between
+    /// the trees. Unresolved triplets agree only with unresolved triplets.
+    /// The maximum is `C(n, 3)`. Takes O(n²) time for trees of bounded
+    /// degree.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn triplet_distance(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let n = shared_taxa(self, tree)?;
+        let (t1, t2) = topology_pair(self, tree);
+        let mut agree: i128 = 0;
+        node_pairs(&t1, &t2, false, |cells| {
+            agree += cells.shared_triplets() + cells.star_sets(3);
+        });
+        Ok(counted(agree, choose(n as i128, 3)))
+    }
+
+    /// Number of four-taxon subsets whose unrooted topologies differ between
+    /// the trees, treating both as unrooted. Unresolved quartets agree only
+    /// with unresolved quartets. The maximum is `C(n, 4)`. Takes O(n²) time
+    /// for trees of bounded degree.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::TaxaSetMismatch`] if the trees have different taxa.
+    fn quartet_distance(&self, tree: &Self) -> Result<TreeComparison, TreeError> {
+        let n = shared_taxa(self, tree)?;
+        let (t1, t2) = topology_pair(self, tree);
+        let (mut resolved, mut stars): (i128, i128) = (0, 0);
+        node_pairs(&t1, &t2, true, |cells| {
+            resolved += cells.shared_quartets();
+            stars += cells.star_sets(4);
+        });
+        Ok(counted(resolved / 4 + stars, choose(n as i128, 4)))
+    }
+}
+
+/// Sides of two nested splits as `(inner, outer)`: the part inside both
+/// smaller sides and the part outside both larger ones.
+fn nested_sides(pair: &SplitPair, cells: [usize; 4]) -> (usize, usize) {
+    let n = pair.n;
+    let (a1, b1, a2, b2) = (pair.a1, n - pair.a1, pair.a2, n - pair.a2);
+    // The empty cell names the two sides that do not meet; each of them lies
+    // inside the opposite side of the other split.
+    match cells.iter().position(|&c| c == 0) {
+        // A1 ∩ A2 empty: A1 ⊆ B2.
+        Some(0) => (a1, n - b2),
+        // A1 ∩ B2 empty: A1 ⊆ A2.
+        Some(1) => (a1, n - a2),
+        // B1 ∩ A2 empty: B1 ⊆ B2.
+        Some(2) => (b1, n - b2),
+        // B1 ∩ B2 empty: B1 ⊆ A2.
+        _ => (b1, n - a2),
+    }
+}
+
+fn generalized(self_1: f64, self_2: f64, shared: f64) -> TreeComparison {
+    let maximum = self_1 + self_2;
+    TreeComparison {
+        similarity: shared,
+        distance: (maximum - 2.0 * shared).max(0.0),
+        maximum,
+    }
+}
+
+fn counted(agree: i128, total: i128) -> TreeComparison {
+    TreeComparison {
+        similarity: agree as f64,
+        distance: (total - agree) as f64,
+        maximum: total as f64,
+    }
+}
+
+/// Entropy in bits of a partition of `n` items into parts of the given sizes.
+fn entropy(parts: &[usize], n: usize) -> f64 {
+    parts
+        .iter()
+        .filter(|&&part| part > 0)
+        .map(|&part| {
+            let p = part as f64 / n as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// `ln((2k - 1)!!)`, with `(-1)!! = 1`.
+fn ln_odd_factorial(k: usize) -> f64 {
+    let k = k as f64;
+    ln_gamma(2.0 * k + 1.0) - k * LN_2 - ln_gamma(k + 1.0)
+}
+
+/// Phylogenetic information in bits of a split with `a` taxa on one side:
+/// `-log2` of the fraction of unrooted binary trees on `n` taxa with it.
+fn split_information(a: usize, n: usize) -> f64 {
+    (ln_odd_factorial(n - 2) - ln_odd_factorial(a - 1) - ln_odd_factorial(n - a - 1)) / LN_2
+}
+
+fn choose(n: i128, k: i128) -> i128 {
+    if n < k {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+/// Every set partition of `size` positions, each as the block of every
+/// position, with blocks numbered in order of first appearance.
+fn set_partitions(size: usize) -> Vec<Vec<usize>> {
+    let mut partitions = vec![vec![]];
+    for _ in 0..size {
+        partitions = partitions
+            .into_iter()
+            .flat_map(|blocks: Vec<usize>| {
+                let fresh = blocks.iter().max().map_or(0, |&b| b + 1);
+                (0..=fresh).map(move |b| [blocks.as_slice(), &[b]].concat())
+            })
+            .collect();
+    }
+    partitions
+}
+
+/// The Möbius function of the partition lattice from the finest partition to
+/// `blocks`: `(-1)^(m - 1) (m - 1)!` for every block of `m` positions.
+fn mobius(blocks: &[usize]) -> i128 {
+    let num_blocks = blocks.iter().max().map_or(0, |&b| b + 1);
+    (0..num_blocks)
+        .map(|b| {
+            let m = blocks.iter().filter(|&&x| x == b).count() as i128;
+            (1..m).fold(1, |acc, i| -acc * i)
+        })
+        .product()
+}
+
+/// Leaf taxa of `tree`, numbered by sorted order.
+fn taxon_index<T>(tree: &T) -> HashMap<&TreeNodeMeta<T>, usize>
+where
+    T: RootedMetaTree,
+    <T as RootedTree>::Node: RootedMetaNode,
+{
+    let mut taxa = tree
+        .get_leaf_ids()
+        .map(|leaf| {
+            tree.get_node_taxa(leaf)
+                .expect("invariant: leaves carry a taxon")
+        })
+        .collect::<Vec<_>>();
+    taxa.sort_unstable();
+    taxa.into_iter().enumerate().map(|(i, t)| (t, i)).collect()
+}
+
+/// Number of leaf taxa, if both trees have the same ones.
+fn shared_taxa<T>(t1: &T, t2: &T) -> Result<usize, TreeError>
+where
+    T: RootedMetaTree,
+    <T as RootedTree>::Node: RootedMetaNode,
+{
+    let sorted = |tree: &T| {
+        let mut taxa = tree
+            .get_leaf_ids()
+            .map(|leaf| {
+                tree.get_node_taxa(leaf)
+                    .expect("invariant: leaves carry a taxon")
+            })
+            .collect::<Vec<_>>();
+        taxa.sort_unstable();
+        taxa.into_iter().cloned().collect::<Vec<_>>()
+    };
+    let taxa = sorted(t1);
+    match taxa == sorted(t2) {
+        true => Ok(taxa.len()),
+        false => Err(TreeError::TaxaSetMismatch),
+    }
+}
+
+fn split_pair<T>(t1: &T, t2: &T) -> Result<(SplitSet, SplitSet), TreeError>
+where
+    T: SplitDistances,
+    <T as RootedTree>::Node: RootedMetaNode,
+{
+    shared_taxa(t1, t2)?;
+    Ok((t1.splits(), t2.splits()))
+}
+
+/// Largest total `weight` over a pairing of the splits of `s1` with those of
+/// `s2`. Splits left over pair with nothing and score zero.
+fn max_weight_matching<F>(s1: &SplitSet, s2: &SplitSet, mut weight: F) -> f64
+where
+    F: FnMut(&SplitPair) -> f64,
+{
+    let size = s1.len().max(s2.len());
+    let mut cost = vec![0.0; size * size];
+    for (i, x) in s1.iter().enumerate() {
+        for (j, y) in s2.iter().enumerate() {
+            cost[i * size + j] = -weight(&SplitPair::new(x, y, s1.num_taxa));
+        }
+    }
+    -min_cost_assignment(&cost, size)
+}
+
+/// Cost of a minimum-cost perfect assignment on a `size` × `size` row-major
+/// matrix, by the Hungarian algorithm with potentials. O(size³).
+fn min_cost_assignment(cost: &[f64], size: usize) -> f64 {
+    // 1-based throughout, with row and column 0 as the virtual start.
+    let mut u = vec![0.0; size + 1];
+    let mut v = vec![0.0; size + 1];
+    let mut owner = vec![0usize; size + 1];
+    let mut way = vec![0usize; size + 1];
+    for row in 1..=size {
+        owner[0] = row;
+        let mut col = 0;
+        let mut min_to = vec![f64::INFINITY; size + 1];
+        let mut used = vec![false; size + 1];
+        loop {
+            used[col] = true;
+            let i = owner[col];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+            for j in 1..=size {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[(i - 1) * size + j - 1] - u[i] - v[j];
+                if reduced < min_to[j] {
+                    min_to[j] = reduced;
+                    way[j] = col;
+                }
+                if min_to[j] < delta {
+                    delta = min_to[j];
+                    next = j;
+                }
+            }
+            for j in 0..=size {
+                if used[j] {
+                    u[owner[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+            col = next;
+            if owner[col] == 0 {
+                break;
+            }
+        }
+        while col != 0 {
+            let prev = way[col];
+            owner[col] = owner[prev];
+            col = prev;
+        }
+    }
+    (1..=size)
+        .map(|j| cost[(owner[j] - 1) * size + j - 1])
+        .sum()
+}
+
+/// Intersection sizes between the subtrees around one node of each tree.
+///
+/// Row `k` is the `k`-th subtree of the first node and column `l` the `l`-th
+/// of the second; `x[k][l]` counts the taxa in both. In the rooted reading
+/// the subtrees are the children; in the unrooted one, a non-root node also
+/// has the rest of the tree above it as a last subtree.
+struct Cells {
+    x: Vec<i128>,
+    rows: usize,
+    cols: usize,
+}
+
+impl Cells {
+    fn at(&self, k: usize, l: usize) -> i128 {
+        self.x[k * self.cols + l]
+    }
+
+    fn row_sums(&self) -> Vec<i128> {
+        (0..self.rows)
+            .map(|k| (0..self.cols).map(|l| self.at(k, l)).sum())
+            .collect()
+    }
+
+    fn col_sums(&self) -> Vec<i128> {
+        (0..self.cols)
+            .map(|l| (0..self.rows).map(|k| self.at(k, l)).sum())
+            .collect()
+    }
+
+    /// Resolved triplets `ab|c` with `a, b` sharing a subtree at both nodes
+    /// and `c` in another subtree at both.
+    #[allow(clippy::needless_range_loop)]
+    fn shared_triplets(&self) -> i128 {
+        let (r, c) = (self.row_sums(), self.col_sums());
+        let s: i128 = r.iter().sum();
+        let mut total = 0;
+        for k in 0..self.rows {
+            for l in 0..self.cols {
+                let x = self.at(k, l);
+                total += x * (x - 1) / 2 * (s - r[k] - c[l] + x);
+            }
+        }
+        total
+    }
+
+    /// Resolved quartets with the same topology at both nodes, where each
+    /// node is one of the two points at which the quartet's halves join.
+    ///
+    /// A quartet `ab|cd` joins where `a` and `b` part with `c, d` together in
+    /// a third subtree, and again where `c` and `d` part. Summed over all
+    /// pairs of nodes, a shared quartet is counted four times, so callers
+    /// divide the total by four.
+    fn shared_quartets(&self) -> i128 {
+        let (r, c) = (self.row_sums(), self.col_sums());
+        let s: i128 = r.iter().sum();
+        let row_sq: Vec<i128> = (0..self.rows)
+            .map(|k| (0..self.cols).map(|l| self.at(k, l).pow(2)).sum())
+            .collect();
+        let col_sq: Vec<i128> = (0..self.cols)
+            .map(|l| (0..self.rows).map(|k| self.at(k, l).pow(2)).sum())
+            .collect();
+        // Row sums weighted down each column, and column sums across each row.
+        let col_by_rows: Vec<i128> = (0..self.cols)
+            .map(|l| (0..self.rows).map(|k| r[k] * self.at(k, l)).sum())
+            .collect();
+        let row_by_cols: Vec<i128> = (0..self.rows)
+            .map(|k| (0..self.cols).map(|l| c[l] * self.at(k, l)).sum())
+            .collect();
+        let r2: i128 = r.iter().map(|x| x * x).sum();
+        let c2: i128 = c.iter().map(|x| x * x).sum();
+        let x2: i128 = row_sq.iter().sum();
+
+        let mut total = 0;
+        for k in 0..self.rows {
+            for l in 0..self.cols {
+                let x = self.at(k, l);
+                // c, d together in cell (k, l) at both nodes; a, b in distinct
+                // rows and columns of what remains.
+                let s_ = s - r[k] - c[l] + x;
+                let r2_ =
+                    (r2 - r[k] * r[k]) - 2 * (col_by_rows[l] - r[k] * x) + (col_sq[l] - x * x);
+                let c2_ =
+                    (c2 - c[l] * c[l]) - 2 * (row_by_cols[k] - c[l] * x) + (row_sq[k] - x * x);
+                let x2_ = x2 - row_sq[k] - col_sq[l] + x * x;
+                total += x * (x - 1) / 2 * ((s_ * s_ - r2_ - c2_ + x2_) / 2);
+                // c, d together in row k but parted across columns, and a, b
+                // together in column l but parted across rows.
+                let in_row = ((r[k] - x).pow(2) - (row_sq[k] - x * x)) / 2;
+                let in_col = ((c[l] - x).pow(2) - (col_sq[l] - x * x)) / 2;
+                total += in_row * in_col;
+            }
+        }
+        total
+    }
+
+    /// Sets of `size` taxa, three or four, in pairwise distinct rows and
+    /// pairwise distinct columns: unresolved at both nodes.
+    ///
+    /// Ordered tuples in distinct rows and columns are counted by Möbius
+    /// inversion over two set partitions of the tuple's positions, one naming
+    /// the positions that share a row and one those that share a column.
+    fn star_sets(&self, size: usize) -> i128 {
+        if self.rows < size || self.cols < size {
+            return 0;
+        }
+        let partitions = set_partitions(size);
+        let mut ordered = 0;
+        for rows in &partitions {
+            for cols in &partitions {
+                ordered += mobius(rows) * mobius(cols) * self.coarser_tuples(rows, cols);
+            }
+        }
+        ordered / (1..=size as i128).product::<i128>()
+    }
+
+    /// Ordered tuples of taxa whose positions share a row wherever `rows`
+    /// puts them in one block, and a column wherever `cols` does. Either may
+    /// also coincide elsewhere.
+    ///
+    /// Blocks are the vertices of a bipartite graph with an edge per
+    /// position, and the count is the sum over placements of the blocks of
+    /// the product of the cells the edges land in. Forests are summed leaf
+    /// to root; the only cycle four positions can close is a square.
+    fn coarser_tuples(&self, rows: &[usize], cols: &[usize]) -> i128 {
+        let num_rows = rows.iter().max().map_or(0, |&b| b + 1);
+        let num_cols = cols.iter().max().map_or(0, |&b| b + 1);
+        // Row blocks are vertices `0..num_rows`, column blocks follow.
+        let mut edges: Vec<(usize, usize, u32)> = vec![];
+        for (&k, &l) in rows.iter().zip(cols) {
+            match edges.iter_mut().find(|e| (e.0, e.1) == (k, num_rows + l)) {
+                Some(edge) => edge.2 += 1,
+                None => edges.push((k, num_rows + l, 1)),
+            }
+        }
+        let mut seen = vec![false; num_rows + num_cols];
+        let mut total = 1;
+        for root in 0..seen.len() {
+            if seen[root] {
+                continue;
+            }
+            let mut component = vec![root];
+            seen[root] = true;
+            let mut i = 0;
+            while i < component.len() {
+                let v = component[i];
+                for &(a, b, _) in &edges {
+                    let u = match (a == v, b == v) {
+                        (true, _) => b,
+                        (_, true) => a,
+                        _ => continue,
+                    };
+                    if !seen[u] {
+                        seen[u] = true;
+                        component.push(u);
+                    }
+                }
+                i += 1;
+            }
+            let inside = edges.iter().filter(|e| component.contains(&e.0)).count();
+            total *= match inside + 1 == component.len() {
+                true => self
+                    .placements(root, None, &edges, num_rows)
+                    .into_iter()
+                    .sum::<i128>(),
+                false => self.squares(),
+            };
+        }
+        total
+    }
+
+    /// For each row, or column, that block `v` may take, the weight of the
+    /// subtree hanging from it away from `parent`.
+    fn placements(
+        &self,
+        v: usize,
+        parent: Option<usize>,
+        edges: &[(usize, usize, u32)],
+        num_rows: usize,
+    ) -> Vec<i128> {
+        let is_row = v < num_rows;
+        let mut weights = vec![1; if is_row { self.rows } else { self.cols }];
+        for &(a, b, times) in edges {
+            let u = match (a == v, b == v) {
+                (true, _) => b,
+                (_, true) => a,
+                _ => continue,
+            };
+            if Some(u) == parent {
+                continue;
+            }
+            let below = self.placements(u, Some(v), edges, num_rows);
+            for (i, weight) in weights.iter_mut().enumerate() {
+                let cell = |j: usize| match is_row {
+                    true => self.at(i, j),
+                    false => self.at(j, i),
+                };
+                *weight *= below
+                    .iter()
+                    .enumerate()
+                    .map(|(j, w)| cell(j).pow(times) * w)
+                    .sum::<i128>();
+            }
+        }
+        weights
+    }
+
+    /// `Σ x[k][l] x[k][l'] x[k'][l] x[k'][l']` over all rows `k, k'` and
+    /// columns `l, l'`, through the products of pairs of rows or of columns,
+    /// whichever are fewer.
+    fn squares(&self) -> i128 {
+        let (outer, inner) = match self.rows <= self.cols {
+            true => (self.rows, self.cols),
+            false => (self.cols, self.rows),
+        };
+        let cell = |a: usize, b: usize| match self.rows <= self.cols {
+            true => self.at(a, b),
+            false => self.at(b, a),
+        };
+        let mut total = 0;
+        for a in 0..outer {
+            for b in 0..outer {
+                let product: i128 = (0..inner).map(|c| cell(a, c) * cell(b, c)).sum();
+                total += product * product;
+            }
+        }
+        total
+    }
+}
+
+/// The shape of a tree over dense node ids, for passes over pairs of nodes.
+///
+/// Nodes are numbered in post-order, entering larger subtrees first, so
+/// children precede their parent and the root is last. A pass that keeps one
+/// row per finished node on a stack, popping a node's children when it
+/// reaches the node, then holds O(log n) rows at once: a row waits only
+/// while a smaller sibling subtree is processed.
+pub(crate) struct Topology {
+    /// Children of `v` are `children[offsets[v]..offsets[v + 1]]`, in the
+    /// order they were numbered.
+    offsets: Vec<u32>,
+    children: Vec<u32>,
+    /// Parent of each node; `u32::MAX` for the root.
+    parent: Vec<u32>,
+    /// Number of leaves at or below each node.
+    size: Vec<u32>,
+    /// Node holding each taxon.
+    leaf: Vec<u32>,
+}
+
+impl Topology {
+    /// Numbers the nodes of `tree`; `taxon` gives each leaf's index among
+    /// the `num_taxa` taxa.
+    pub(crate) fn new<T, F>(tree: &T, num_taxa: usize, mut taxon: F) -> Self
+    where
+        T: RootedTree,
+        F: FnMut(TreeNodeID<T>) -> usize,
+    {
+        let max_id = tree
+            .get_node_ids()
+            .map(Into::<usize>::into)
+            .max()
+            .expect("a tree always has at least a root node");
+        let mut size = vec![0u32; max_id + 1];
+        let mut order = vec![];
+        let mut stack = vec![tree.get_root_id()];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(tree.get_node_children_ids(id));
+        }
+        for &id in order.iter().rev() {
+            size[id.into()] = match tree.is_leaf(id) {
+                true => 1,
+                false => tree.get_node_children_ids(id).map(|c| size[c.into()]).sum(),
+            };
+        }
+
+        let mut dense = vec![u32::MAX; max_id + 1];
+        let mut postorder = Vec::with_capacity(order.len());
+        let mut stack = vec![(tree.get_root_id(), false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if expanded {
+                dense[id.into()] = postorder.len() as u32;
+                postorder.push(id);
+                continue;
+            }
+            stack.push((id, true));
+            let mut children = tree.get_node_children_ids(id).collect::<Vec<_>>();
+            // Stable, so equal subtrees keep the tree's own child order.
+            children.sort_by_key(|&c| std::cmp::Reverse(size[c.into()]));
+            stack.extend(children.into_iter().rev().map(|c| (c, false)));
+        }
+
+        let mut topology = Topology {
+            offsets: Vec::with_capacity(postorder.len() + 1),
+            children: Vec::with_capacity(postorder.len()),
+            parent: vec![u32::MAX; postorder.len()],
+            size: Vec::with_capacity(postorder.len()),
+            leaf: vec![u32::MAX; num_taxa],
+        };
+        topology.offsets.push(0);
+        for (v, &id) in postorder.iter().enumerate() {
+            let mut children = tree
+                .get_node_children_ids(id)
+                .map(|c| dense[c.into()])
+                .collect::<Vec<_>>();
+            children.sort_unstable();
+            for &c in &children {
+                topology.parent[c as usize] = v as u32;
+            }
+            topology.children.extend(children);
+            topology.offsets.push(topology.children.len() as u32);
+            topology.size.push(size[id.into()]);
+            if tree.is_leaf(id) {
+                topology.leaf[taxon(id)] = v as u32;
+            }
+        }
+        topology
+    }
+
+    /// Number of nodes; the root is the last of them.
+    pub(crate) fn len(&self) -> usize {
+        self.size.len()
+    }
+
+    pub(crate) fn children(&self, v: usize) -> &[u32] {
+        &self.children[self.offsets[v] as usize..self.offsets[v + 1] as usize]
+    }
+
+    pub(crate) fn parent(&self, v: usize) -> Option<usize> {
+        match self.parent[v] {
+            u32::MAX => None,
+            p => Some(p as usize),
+        }
+    }
+
+    pub(crate) fn size(&self, v: usize) -> usize {
+        self.size[v] as usize
+    }
+
+    /// Overlap of `taxon`'s leaf with every node of this tree: one on the
+    /// path from the leaf to the root, zero elsewhere.
+    pub(crate) fn leaf_row(&self, taxon: usize) -> Vec<u32> {
+        let mut row = vec![0; self.len()];
+        let mut at = Some(self.leaf[taxon] as usize);
+        while let Some(node) = at {
+            row[node] = 1;
+            at = self.parent(node);
+        }
+        row
+    }
+
+    /// Leaf taxa, by node.
+    pub(crate) fn taxa(&self) -> Vec<u32> {
+        let mut taxa = vec![u32::MAX; self.len()];
+        for (taxon, &leaf) in self.leaf.iter().enumerate() {
+            taxa[leaf as usize] = taxon as u32;
+        }
+        taxa
+    }
+}
+
+/// Post-order walk of `t1` that carries, for each node, its overlap with
+/// every node of `t2`. `visit` is called at each node of `t1` with the node,
+/// the rows of its children, and its own row, summed from theirs.
+pub(crate) fn overlap_rows<F>(t1: &Topology, t2: &Topology, mut visit: F)
+where
+    F: FnMut(usize, &[Vec<u32>], &[u32]),
+{
+    let mut stack: Vec<Vec<u32>> = vec![];
+    for (v, taxon) in t1.taxa().into_iter().enumerate() {
+        let arity = t1.children(v).len();
+        let children = stack.split_off(stack.len() - arity);
+        let row = match arity {
+            0 => t2.leaf_row(taxon as usize),
+            _ => {
+                let mut row = vec![0; t2.len()];
+                for child in &children {
+                    row.iter_mut().zip(child).for_each(|(r, c)| *r += c);
+                }
+                row
+            }
+        };
+        visit(v, &children, &row);
+        stack.push(row);
+    }
+}
+
+/// Calls `visit` with the subtree intersections of every pair of internal
+/// nodes, one from each tree.
+fn node_pairs<F>(t1: &Topology, t2: &Topology, unrooted: bool, mut visit: F)
+where
+    F: FnMut(&Cells),
+{
+    let n = t1.size(t1.len() - 1) as i128;
+    let internal2: Vec<usize> = (0..t2.len())
+        .filter(|&v| !t2.children(v).is_empty())
+        .collect();
+    let mut cells = Cells {
+        x: vec![],
+        rows: 0,
+        cols: 0,
+    };
+    overlap_rows(t1, t2, |u, child_rows, row| {
+        if child_rows.is_empty() {
+            return;
+        }
+        let above1 = unrooted && t1.parent(u).is_some();
+        for &v in &internal2 {
+            let above2 = unrooted && t2.parent(v).is_some();
+            let kids = t2.children(v);
+            cells.rows = child_rows.len() + above1 as usize;
+            cells.cols = kids.len() + above2 as usize;
+            cells.x.clear();
+            for (&child, child_row) in t1.children(u).iter().zip(child_rows) {
+                cells
+                    .x
+                    .extend(kids.iter().map(|&l| child_row[l as usize] as i128));
+                if above2 {
+                    cells
+                        .x
+                        .push(t1.size(child as usize) as i128 - child_row[v] as i128);
+                }
+            }
+            if above1 {
+                cells.x.extend(
+                    kids.iter()
+                        .map(|&l| t2.size(l as usize) as i128 - row[l as usize] as i128),
+                );
+                if above2 {
+                    cells
+                        .x
+                        .push(n - t1.size(u) as i128 - t2.size(v) as i128 + row[v] as i128);
+                }
+            }
+            visit(&cells);
+        }
+    });
+}
+
+/// Topologies of two trees over the taxon numbering of the first.
+fn topology_pair<T>(t1: &T, t2: &T) -> (Topology, Topology)
+where
+    T: RootedMetaTree,
+    <T as RootedTree>::Node: RootedMetaNode,
+{
+    let index = taxon_index(t1);
+    let topology = |tree: &T| {
+        Topology::new(tree, index.len(), |leaf| {
+            index[tree
+                .get_node_taxa(leaf)
+                .expect("invariant: leaves carry a taxon")]
+        })
+    };
+    (topology(t1), topology(t2))
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::tree::PhyloTree;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    fn tree(newick: &str) -> PhyloTree {
+        PhyloTree::from_newick(newick.as_bytes()).unwrap()
+    }
+
+    /// A random tree on `taxa` with nodes of two to `max_degree` children.
+    fn random_newick(taxa: &mut [String], max_degree: usize, rng: &mut StdRng) -> String {
+        if taxa.len() == 1 {
+            return taxa[0].clone();
+        }
+        taxa.shuffle(rng);
+        let parts = rng.gen_range(2..=taxa.len().min(max_degree));
+        let mut cuts = (1..taxa.len()).collect::<Vec<_>>();
+        cuts.shuffle(rng);
+        let mut cuts = cuts[..parts - 1].to_vec();
+        cuts.sort_unstable();
+        cuts.push(taxa.len());
+        let mut start = 0;
+        let mut children = vec![];
+        for cut in cuts {
+            children.push(random_newick(&mut taxa[start..cut], max_degree, rng));
+            start = cut;
+        }
+        format!("({})", children.join(","))
+    }
+
+    fn random_tree(num_taxa: usize, rng: &mut StdRng) -> PhyloTree {
+        wide_tree(num_taxa, 4, rng)
+    }
+
+    fn wide_tree(num_taxa: usize, max_degree: usize, rng: &mut StdRng) -> PhyloTree {
+        let mut taxa = (0..num_taxa).map(|i| format!("t{i}")).collect::<Vec<_>>();
+        tree(&format!("{};", random_newick(&mut taxa, max_degree, rng)))
+    }
+
+    fn leaves_by_taxon(tree: &PhyloTree) -> Vec<usize> {
+        let mut leaves = tree
+            .get_leaf_ids()
+            .map(|id| (tree.get_node_taxa_cloned(id).unwrap(), id))
+            .collect::<Vec<_>>();
+        leaves.sort();
+        leaves.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Topology of each quartet, by the four-point condition on edge counts.
+    fn brute_quartets(tree: &PhyloTree) -> Vec<u8> {
+        let oracle = tree.path_oracle();
+        let leaves = leaves_by_taxon(tree);
+        let d = |i: usize, j: usize| oracle.path_edges(leaves[i], leaves[j]);
+        let n = leaves.len();
+        let mut topologies = vec![];
+        for a in 0..n {
+            for b in a + 1..n {
+                for c in b + 1..n {
+                    for e in c + 1..n {
+                        let sums = [d(a, b) + d(c, e), d(a, c) + d(b, e), d(a, e) + d(b, c)];
+                        let min = *sums.iter().min().unwrap();
+                        topologies.push(match sums.iter().filter(|&&s| s == min).count() {
+                            1 => sums.iter().position(|&s| s == min).unwrap() as u8,
+                            _ => 3,
+                        });
+                    }
+                }
+            }
+        }
+        topologies
+    }
+
+    /// Topology of each rooted triplet, by which pair has the deepest LCA.
+    fn brute_triplets(tree: &PhyloTree) -> Vec<u8> {
+        let oracle = tree.lca();
+        let leaves = leaves_by_taxon(tree);
+        let depth =
+            |i: usize, j: usize| oracle.get_node_depth(oracle.get_lca_id(&[leaves[i], leaves[j]]));
+        let n = leaves.len();
+        let mut topologies = vec![];
+        for a in 0..n {
+            for b in a + 1..n {
+                for c in b + 1..n {
+                    let depths = [depth(a, b), depth(a, c), depth(b, c)];
+                    let max = *depths.iter().max().unwrap();
+                    topologies.push(match depths.iter().filter(|&&s| s == max).count() {
+                        1 => depths.iter().position(|&s| s == max).unwrap() as u8,
+                        _ => 3,
+                    });
+                }
+            }
+        }
+        topologies
+    }
+
+    fn differing(x: &[u8], y: &[u8]) -> f64 {
+        x.iter().zip(y).filter(|(a, b)| a != b).count() as f64
+    }
+
+    #[test]
+    fn test_quartet_and_triplet_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for round in 0..40 {
+            let n = 4 + round % 9;
+            let (t1, t2) = (random_tree(n, &mut rng), random_tree(n, &mut rng));
+            let quartets = t1.quartet_distance(&t2).unwrap();
+            let expected = differing(&brute_quartets(&t1), &brute_quartets(&t2));
+            assert_eq!(quartets.distance, expected, "quartets, round {round}");
+            assert_eq!(quartets.distance + quartets.similarity, quartets.maximum);
+            let triplets = t1.triplet_distance(&t2).unwrap();
+            let expected = differing(&brute_triplets(&t1), &brute_triplets(&t2));
+            assert_eq!(triplets.distance, expected, "triplets, round {round}");
+        }
+    }
+
+    #[test]
+    fn test_unresolved_sets_at_wide_polytomies() {
+        let mut rng = StdRng::seed_from_u64(33);
+        for round in 0..20 {
+            let n = 6 + round % 6;
+            let (t1, t2) = (wide_tree(n, 8, &mut rng), wide_tree(n, 8, &mut rng));
+            let quartets = t1.quartet_distance(&t2).unwrap();
+            let expected = differing(&brute_quartets(&t1), &brute_quartets(&t2));
+            assert_eq!(quartets.distance, expected, "quartets, round {round}");
+            let triplets = t1.triplet_distance(&t2).unwrap();
+            let expected = differing(&brute_triplets(&t1), &brute_triplets(&t2));
+            assert_eq!(triplets.distance, expected, "triplets, round {round}");
+        }
+        // Two stars agree on every set of taxa.
+        let star = tree("(a,b,c,d,e,f,g);");
+        assert_eq!(star.quartet_distance(&star).unwrap().similarity, 35.0);
+        assert_eq!(star.triplet_distance(&star).unwrap().similarity, 35.0);
+    }
+
+    #[test]
+    fn test_identical_trees_are_at_distance_zero() {
+        let t1 = tree("((A,B),(C,(D,E)),(F,G,H));");
+        // Same rooted topology, written in another order.
+        let t2 = tree("((H,G,F),(B,A),((E,D),C));");
+        for comparison in [
+            t1.normalized_rf(&t2),
+            t1.nye_similarity(&t2),
+            t1.clustering_info_distance(&t2),
+            t1.phylogenetic_info_distance(&t2),
+            t1.matching_split_distance(&t2),
+            t1.quartet_distance(&t2),
+            t1.triplet_distance(&t2),
+        ] {
+            let comparison = comparison.unwrap();
+            assert!(comparison.distance.abs() < 1e-9, "{comparison:?}");
+        }
+        assert_eq!(t1.splits(), t2.splits());
+    }
+
+    #[test]
+    fn test_four_taxon_split_metrics() {
+        let t1 = tree("((A,B),(C,D));");
+        let t2 = tree("((A,C),(B,D));");
+        let t3 = tree("(A,(B,(C,D)));");
+        assert_eq!(t1.splits().len(), 1);
+        assert_eq!(t1.splits(), t3.splits());
+
+        let rf = t1.normalized_rf(&t2).unwrap();
+        assert_eq!((rf.distance, rf.maximum, rf.normalized()), (2.0, 2.0, 1.0));
+        assert_eq!(t1.normalized_rf(&t3).unwrap().distance, 0.0);
+
+        // AB|CD against AC|BD: every side overlaps every other in one taxon.
+        let nye = t1.nye_similarity(&t2).unwrap();
+        assert!((nye.similarity - 1.0 / 3.0).abs() < 1e-12);
+
+        // Each four-taxon split holds log2(3) bits, and the two share none.
+        let spi = t1.phylogenetic_info_distance(&t2).unwrap();
+        assert!((spi.maximum - 2.0 * 3f64.log2()).abs() < 1e-12);
+        assert!(spi.similarity.abs() < 1e-12);
+        assert!((spi.normalized() - 1.0).abs() < 1e-12);
+
+        // Two even splits crossing each other are independent: one bit each,
+        // no mutual information.
+        let cid = t1.clustering_info_distance(&t2).unwrap();
+        assert!((cid.maximum - 2.0).abs() < 1e-12);
+        assert!(cid.similarity.abs() < 1e-12);
+
+        let msd = t1.matching_split_distance(&t2).unwrap();
+        assert_eq!((msd.distance, msd.maximum), (2.0, 4.0));
+
+        let quartets = t1.quartet_distance(&t2).unwrap();
+        assert_eq!((quartets.distance, quartets.maximum), (1.0, 1.0));
+        // Rooted differently, the unrooted quartet is still the same.
+        assert_eq!(t1.quartet_distance(&t3).unwrap().distance, 0.0);
+        assert_eq!(t1.triplet_distance(&t3).unwrap().distance, 2.0);
+
+        // Unresolved quartets agree with each other, and with nothing else.
+        let star = tree("(A,B,C,D);");
+        assert_eq!(star.quartet_distance(&star).unwrap().distance, 0.0);
+        assert_eq!(star.quartet_distance(&t1).unwrap().distance, 1.0);
+        assert_eq!(star.triplet_distance(&star).unwrap().distance, 0.0);
+    }
+
+    #[test]
+    fn test_matching_metrics_stay_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let (t1, t2) = (random_tree(15, &mut rng), random_tree(15, &mut rng));
+            for comparison in [
+                t1.normalized_rf(&t2),
+                t1.nye_similarity(&t2),
+                t1.clustering_info_distance(&t2),
+                t1.phylogenetic_info_distance(&t2),
+                t1.matching_split_distance(&t2),
+            ] {
+                let comparison = comparison.unwrap();
+                assert!(comparison.distance >= 0.0, "{comparison:?}");
+                assert!(comparison.distance <= comparison.maximum + 1e-9);
+            }
+            // Symmetric, as the pairing is.
+            let (ab, ba) = (
+                t1.phylogenetic_info_distance(&t2).unwrap(),
+                t2.phylogenetic_info_distance(&t1).unwrap(),
+            );
+            assert!((ab.distance - ba.distance).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_taxa_mismatch() {
+        let t1 = tree("((A,B),(C,D));");
+        let t2 = tree("((A,B),(C,E));");
+        assert_eq!(t1.normalized_rf(&t2), Err(TreeError::TaxaSetMismatch));
+        assert_eq!(t1.quartet_distance(&t2), Err(TreeError::TaxaSetMismatch));
+    }
+}
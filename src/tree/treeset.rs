@@ -0,0 +1,878 @@
+//! Collections of trees on one taxon set, and their all-pairs distances.
+//!
+//! Comparing every pair of trees in a posterior sample through the per-pair
+//! methods of [`RobinsonFoulds`](crate::tree::distances::RobinsonFoulds) and
+//! [`ClusterAffinity`](crate::tree::distances::ClusterAffinity) rebuilds the
+//! taxon map and every split for each of the `t²` pairs.
+//! [`TreeSet`](crate::tree::treeset::TreeSet) encodes each tree once, on
+//! insertion, against a taxon index shared by the whole collection.
+//!
+//! Splits are hashed globally, as in HashRF (Sul & Williams 2008): each taxon
+//! draws a random 128-bit key, and a split is fingerprinted by the wrapping
+//! sum of the keys on its side without the first taxon. Fingerprints are
+//! computed in one post-order pass without materializing a bitset, and two
+//! distinct splits collide with probability about `2⁻¹²⁸`. Every distinct
+//! split gets a dense id and a list of the trees holding it, so a row of the
+//! Robinson-Foulds matrix costs the total length of its tree's lists rather
+//! than `t` set intersections.
+//!
+//! [`matrix`](crate::tree::treeset::TreeSet::matrix) returns the whole matrix;
+//! [`write_phylip`](crate::tree::treeset::TreeSet::write_phylip) streams it
+//! to a writer a block of rows at a time, so memory stays at the encoded trees
+//! plus one block. Under the `parallel` feature the `_par` variants fill rows
+//! with `rayon`.
+//!
+//! Each distinct split also keeps its taxa, so a subset of the trees can be
+//! summarized by a greedy majority-rule
+//! [`consensus`](crate::tree::treeset::TreeSet::consensus), and the clusters
+//! of a [`Clustering`](crate::tree::treespace::Clustering) by their medoid
+//! and consensus with
+//! [`summarize`](crate::tree::treeset::TreeSet::summarize).
+
+use std::io::{self, Write};
+
+#[cfg(feature = "non_crypto_hash")]
+use fxhash::FxHashMap as HashMap;
+#[cfg(not(feature = "non_crypto_hash"))]
+use std::collections::HashMap;
+
+use num::ToPrimitive;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::prelude::*;
+use crate::tree::matrix::push_phylip_row;
+use crate::tree::splits::{overlap_rows, Split, Topology};
+
+#[cfg(feature = "simple_rooted_tree")]
+use crate::{node::Node, tree::PhyloTree};
+
+/// Seed for the per-taxon split keys. Fixed, so fingerprints (and with them
+/// split ids) are reproducible across runs.
+const KEY_SEED: u64 = 0x7472_6565_7365_7473;
+
+/// Rows computed, and held, at once by the streaming writers.
+const ROW_BLOCK: usize = 64;
+
+/// A distance between the trees of a [`TreeSet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SetMetric {
+    /// Number of non-trivial splits in one tree but not the other.
+    #[default]
+    RobinsonFoulds,
+    /// Sum over all splits, pendant edges included, of the difference in the
+    /// length of the edge inducing the split; a split missing from a tree has
+    /// length zero there. A missing edge weight counts as one.
+    WeightedRobinsonFoulds,
+    /// Cluster affinity cost from the row tree to the column tree, as in
+    /// [`ClusterAffinity::ca`].
+    /// Not symmetric.
+    ClusterAffinity,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+/// A consensus tree, with the support of its splits.
+#[derive(Debug, Clone)]
+pub struct Consensus {
+    /// The consensus tree.
+    pub tree: PhyloTree,
+    /// Fraction of the summarized trees holding the split below each
+    /// internal node other than the root, by node id.
+    pub support: Vec<(usize, f64)>,
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+/// One cluster of a [`TreeSet`], as reported by [`TreeSet::summarize`].
+#[derive(Debug, Clone)]
+pub struct ClusterSummary {
+    /// Position of the medoid, the tree representing the cluster.
+    pub medoid: usize,
+    /// Positions of the trees in the cluster, ascending.
+    pub members: Vec<usize>,
+    /// Consensus of the members.
+    pub consensus: Consensus,
+}
+
+/// One tree, as encoded on insertion.
+struct EncodedTree {
+    /// Non-trivial splits by global id, ascending, with their edge lengths.
+    splits: Vec<(u32, f64)>,
+    /// Sum of the lengths in `splits`.
+    length: f64,
+    /// Length of each taxon's pendant edge.
+    pendant: Vec<f64>,
+    /// Rooted shape, for cluster affinity.
+    topology: Topology,
+}
+
+/// A collection of trees on one taxon set, encoded once for all-pairs
+/// comparison.
+///
+/// The first tree inserted fixes the taxa; every later tree must have the
+/// same ones. Taxa are compared by their `Display` form.
+pub struct TreeSet {
+    /// Taxa, sorted.
+    taxa: Vec<String>,
+    /// Position of each taxon in `taxa`.
+    index: HashMap<String, usize>,
+    /// Fingerprint key of each taxon.
+    keys: Vec<u128>,
+    /// Taxon whose singleton side has a given fingerprint.
+    singletons: HashMap<u128, usize>,
+    /// Dense id of each distinct non-trivial split.
+    split_ids: HashMap<u128, u32>,
+    /// Trees holding each split, ascending, with the split's length there.
+    postings: Vec<Vec<(u32, f64)>>,
+    /// Taxa of each split.
+    sides: Vec<Split>,
+    labels: Vec<String>,
+    trees: Vec<EncodedTree>,
+}
+
+impl Default for TreeSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TreeSet {
+    /// An empty collection.
+    pub fn new() -> Self {
+        TreeSet {
+            taxa: vec![],
+            index: HashMap::default(),
+            keys: vec![],
+            singletons: HashMap::default(),
+            split_ids: HashMap::default(),
+            postings: vec![],
+            sides: vec![],
+            labels: vec![],
+            trees: vec![],
+        }
+    }
+
+    /// Encodes every tree of `trees`, labelled by position as in
+    /// [`push`](Self::push).
+    ///
+    /// # Errors
+    ///
+    /// As for [`push`](Self::push).
+    pub fn from_trees<'a, T>(trees: impl IntoIterator<Item = &'a T>) -> Result<Self, TreeError>
+    where
+        T: RootedWeightedTree + RootedMetaTree + 'a,
+        <T as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+    {
+        let mut set = Self::new();
+        for tree in trees {
+            set.push(tree)?;
+        }
+        Ok(set)
+    }
+
+    /// Encodes `tree` and adds it, labelled `tree{i}` for its position `i`.
+    ///
+    /// # Errors
+    ///
+    /// As for [`push_labelled`](Self::push_labelled).
+    pub fn push<T>(&mut self, tree: &T) -> Result<(), TreeError>
+    where
+        T: RootedWeightedTree + RootedMetaTree,
+        <T as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+    {
+        let label = format!("tree{}", self.len());
+        self.push_labelled(label, tree)
+    }
+
+    /// Encodes `tree` and adds it under `label`.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::UnlabelledLeaf`] if a leaf has no taxon, and
+    /// [`TreeError::TaxaSetMismatch`] if the tree's leaf taxa differ from
+    /// those of the trees already in the set.
+    pub fn push_labelled<T>(&mut self, label: impl Into<String>, tree: &T) -> Result<(), TreeError>
+    where
+        T: RootedWeightedTree + RootedMetaTree,
+        <T as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+    {
+        let taxa = sorted_taxa(tree)?;
+        if self.trees.is_empty() {
+            self.set_taxa(taxa);
+        } else if taxa != self.taxa {
+            return Err(TreeError::TaxaSetMismatch);
+        }
+
+        let encoded = self.encode(tree);
+        let tree_id = self.trees.len() as u32;
+        for &(split, length) in &encoded.splits {
+            self.postings[split as usize].push((tree_id, length));
+        }
+        self.labels.push(label.into());
+        self.trees.push(encoded);
+        Ok(())
+    }
+
+    fn set_taxa(&mut self, taxa: Vec<String>) {
+        let mut rng = StdRng::seed_from_u64(KEY_SEED);
+        self.keys = taxa.iter().map(|_| rng.gen()).collect();
+        self.singletons = self.keys.iter().enumerate().map(|(i, &k)| (k, i)).collect();
+        self.index = taxa
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.clone(), i))
+            .collect();
+        self.taxa = taxa;
+    }
+
+    fn encode<T>(&mut self, tree: &T) -> EncodedTree
+    where
+        T: RootedWeightedTree + RootedMetaTree,
+        <T as RootedTree>::Node: RootedWeightedNode + RootedMetaNode,
+    {
+        let n = self.taxa.len();
+        let total = self.keys.iter().fold(0u128, |acc, &k| acc.wrapping_add(k));
+        let taxon = |leaf| {
+            self.index[&tree
+                .get_node_taxa(leaf)
+                .expect("invariant: push_labelled checked that leaves carry a taxon")
+                .to_string()]
+        };
+
+        // Reversed preorder finishes children before their parent.
+        let mut order = vec![];
+        let mut stack = vec![tree.get_root_id()];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(tree.get_node_children_ids(id));
+        }
+        let mut sides: HashMap<TreeNodeID<T>, (u128, usize, bool)> = HashMap::default();
+        let mut lengths: HashMap<u128, (f64, TreeNodeID<T>)> = HashMap::default();
+        let mut pendant = vec![0.0; n];
+        for &id in order.iter().rev() {
+            let side = match tree.is_leaf(id) {
+                true => {
+                    let t = taxon(id);
+                    (self.keys[t], 1, t == 0)
+                }
+                false => tree
+                    .get_node_children_ids(id)
+                    .fold((0u128, 0, false), |acc, c| {
+                        let (key, size, first) = sides[&c];
+                        (acc.0.wrapping_add(key), acc.1 + size, acc.2 || first)
+                    }),
+            };
+            sides.insert(id, side);
+            let Some(parent) = tree.get_node_parent_id(id) else {
+                continue;
+            };
+            let length = tree
+                .get_edge_weight(parent, id)
+                .map(|w| {
+                    w.to_f64()
+                        .expect("invariant: float edge weights convert to f64")
+                })
+                .unwrap_or(1.0);
+            // Orient to the side without taxon 0.
+            let (key, size) = match side.2 {
+                true => (total.wrapping_sub(side.0), n - side.1),
+                false => (side.0, side.1),
+            };
+            match size {
+                0 => {}
+                1 => pendant[self.singletons[&key]] += length,
+                s if s + 1 == n => pendant[0] += length,
+                _ => lengths.entry(key).or_insert((0.0, id)).0 += length,
+            }
+        }
+
+        let mut splits = lengths
+            .into_iter()
+            .map(|(key, (length, node))| {
+                let next = self.split_ids.len() as u32;
+                let id = *self.split_ids.entry(key).or_insert(next);
+                if id == next {
+                    self.postings.push(vec![]);
+                    self.sides.push(side_of(tree, node, n, taxon));
+                }
+                (id, length)
+            })
+            .collect::<Vec<_>>();
+        splits.sort_unstable_by_key(|&(id, _)| id);
+        EncodedTree {
+            length: splits.iter().map(|&(_, l)| l).sum(),
+            splits,
+            pendant,
+            topology: Topology::new(tree, n, taxon),
+        }
+    }
+
+    /// Number of trees.
+    pub fn len(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// True before the first tree is added.
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty()
+    }
+
+    /// The shared taxa, sorted.
+    pub fn taxa(&self) -> &[String] {
+        &self.taxa
+    }
+
+    /// Tree labels, in insertion order.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Number of distinct non-trivial splits across all trees.
+    pub fn num_splits(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Each distinct non-trivial split, with the trees holding it and its
+    /// edge length in each.
+    pub(crate) fn split_postings(&self) -> impl Iterator<Item = (&Split, &[(u32, f64)])> {
+        self.sides
+            .iter()
+            .zip(&self.postings)
+            .map(|(side, trees)| (side, trees.as_slice()))
+    }
+
+    /// Rooted shape of each tree, in insertion order.
+    #[cfg(feature = "simple_rooted_tree")]
+    pub(crate) fn topologies(&self) -> impl Iterator<Item = &Topology> {
+        self.trees.iter().map(|tree| &tree.topology)
+    }
+
+    /// Fraction of the trees holding the split below each internal node of
+    /// `tree`, by node id, such as the bootstrap support of a reference tree
+    /// from a set of replicate trees. Nodes whose split is trivial, like the
+    /// root, are left out.
+    ///
+    /// # Errors
+    ///
+    /// [`TreeError::UnlabelledLeaf`] if a leaf of `tree` has no taxon, and
+    /// [`TreeError::TaxaSetMismatch`] if the tree's leaf taxa differ from
+    /// those of the set, which holds for any tree when the set is empty.
+    pub fn split_support<T>(&self, tree: &T) -> Result<Vec<(TreeNodeID<T>, f64)>, TreeError>
+    where
+        T: RootedMetaTree,
+        <T as RootedTree>::Node: RootedMetaNode,
+    {
+        let taxa = sorted_taxa(tree)?;
+        if self.is_empty() || taxa != self.taxa {
+            return Err(TreeError::TaxaSetMismatch);
+        }
+
+        let n = self.taxa.len();
+        let total = self.keys.iter().fold(0u128, |acc, &k| acc.wrapping_add(k));
+        let mut order = vec![];
+        let mut stack = vec![tree.get_root_id()];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(tree.get_node_children_ids(id));
+        }
+        // Fingerprinted as in `encode`, then looked up without inserting.
+        let mut sides: HashMap<TreeNodeID<T>, (u128, usize, bool)> = HashMap::default();
+        let mut support = vec![];
+        for &id in order.iter().rev() {
+            let side = match tree.is_leaf(id) {
+                true => {
+                    let t = self.index[&tree
+                        .get_node_taxa(id)
+                        .expect("invariant: checked by sorted_taxa above")
+                        .to_string()];
+                    (self.keys[t], 1, t == 0)
+                }
+                false => tree
+                    .get_node_children_ids(id)
+                    .fold((0u128, 0, false), |acc, c| {
+                        let (key, size, first) = sides[&c];
+                        (acc.0.wrapping_add(key), acc.1 + size, acc.2 || first)
+                    }),
+            };
+            sides.insert(id, side);
+            if tree.is_leaf(id) || tree.get_node_parent_id(id).is_none() {
+                continue;
+            }
+            let (key, size) = match side.2 {
+                true => (total.wrapping_sub(side.0), n - side.1),
+                false => (side.0, side.1),
+            };
+            if size < 2 || size + 1 >= n {
+                continue;
+            }
+            let held = self
+                .split_ids
+                .get(&key)
+                .map_or(0, |&split| self.postings[split as usize].len());
+            support.push((id, held as f64 / self.len() as f64));
+        }
+        support.reverse();
+        Ok(support)
+    }
+
+    /// Distance from tree `i` to tree `j`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` or `j` is not below [`len`](Self::len).
+    pub fn distance(&self, i: usize, j: usize, metric: SetMetric) -> f64 {
+        let (a, b) = (&self.trees[i], &self.trees[j]);
+        match metric {
+            SetMetric::RobinsonFoulds | SetMetric::WeightedRobinsonFoulds => {
+                // Merge the two sorted split lists.
+                let (mut x, mut y) = (a.splits.iter().peekable(), b.splits.iter().peekable());
+                let (mut shared, mut overlap) = (0, 0.0);
+                while let (Some(&&(s, u)), Some(&&(t, v))) = (x.peek(), y.peek()) {
+                    match s.cmp(&t) {
+                        std::cmp::Ordering::Less => {
+                            x.next();
+                        }
+                        std::cmp::Ordering::Greater => {
+                            y.next();
+                        }
+                        std::cmp::Ordering::Equal => {
+                            shared += 1;
+                            overlap += u.min(v);
+                            x.next();
+                            y.next();
+                        }
+                    }
+                }
+                self.combine(i, j, shared, overlap, metric)
+            }
+            SetMetric::ClusterAffinity => cluster_affinity(&a.topology, &b.topology) as f64,
+        }
+    }
+
+    /// Distances from tree `i` to every tree, in insertion order.
+    ///
+    /// Robinson-Foulds rows walk the tree lists of `i`'s splits once; cluster
+    /// affinity rows cost O(n²) per tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not below [`len`](Self::len).
+    pub fn row(&self, i: usize, metric: SetMetric) -> Vec<f64> {
+        match metric {
+            SetMetric::ClusterAffinity => (0..self.len())
+                .map(|j| self.distance(i, j, metric))
+                .collect(),
+            _ => {
+                let mut shared = vec![0usize; self.len()];
+                let mut overlap = vec![0.0; self.len()];
+                for &(split, length) in &self.trees[i].splits {
+                    for &(j, other) in &self.postings[split as usize] {
+                        shared[j as usize] += 1;
+                        overlap[j as usize] += length.min(other);
+                    }
+                }
+                (0..self.len())
+                    .map(|j| self.combine(i, j, shared[j], overlap[j], metric))
+                    .collect()
+            }
+        }
+    }
+
+    /// Robinson-Foulds distance from the number of shared splits, or the
+    /// weighted distance from the sum of the shorter length of each.
+    fn combine(&self, i: usize, j: usize, shared: usize, overlap: f64, metric: SetMetric) -> f64 {
+        if i == j {
+            return 0.0;
+        }
+        let (a, b) = (&self.trees[i], &self.trees[j]);
+        match metric {
+            SetMetric::RobinsonFoulds => (a.splits.len() + b.splits.len() - 2 * shared) as f64,
+            _ => {
+                let pendant: f64 = a
+                    .pendant
+                    .iter()
+                    .zip(&b.pendant)
+                    .map(|(x, y)| (x - y).abs())
+                    .sum();
+                // |u - v| = u + v - 2 min(u, v) over shared splits; a split in
+                // one tree only contributes its whole length.
+                (a.length + b.length - 2.0 * overlap).max(0.0) + pendant
+            }
+        }
+    }
+
+    /// The full distance matrix, row `i` holding distances from tree `i`.
+    pub fn matrix(&self, metric: SetMetric) -> Vec<Vec<f64>> {
+        (0..self.len()).map(|i| self.row(i, metric)).collect()
+    }
+
+    /// The full distance matrix, labelled as in [`labels`](Self::labels).
+    pub fn labelled_matrix(&self, metric: SetMetric) -> LabelledMatrix {
+        LabelledMatrix::new(self.labels.clone(), self.matrix(metric))
+            .expect("invariant: tree labels are unique and the matrix is square")
+    }
+
+    #[cfg(feature = "parallel")]
+    /// The full distance matrix, filling rows in parallel.
+    pub fn matrix_par(&self, metric: SetMetric) -> Vec<Vec<f64>> {
+        (0..self.len())
+            .into_par_iter()
+            .map(|i| self.row(i, metric))
+            .collect()
+    }
+
+    /// Streams the distance matrix to `writer` as PHYLIP, rows labelled as in
+    /// [`labels`](Self::labels), in the format of
+    /// [`LabelledMatrix::to_phylip`]. Holds at most one block of rows in
+    /// memory.
+    ///
+    /// The lower-triangular layout assumes a symmetric metric; for cluster
+    /// affinity it keeps the cost from each tree to the trees before it.
+    ///
+    /// # Errors
+    ///
+    /// Any error from `writer`.
+    pub fn write_phylip<W: Write>(
+        &self,
+        metric: SetMetric,
+        layout: PhylipLayout,
+        writer: W,
+    ) -> io::Result<()> {
+        self.write_blocks(layout, writer, |rows| {
+            rows.map(|i| self.row(i, metric)).collect()
+        })
+    }
+
+    #[cfg(feature = "parallel")]
+    /// Streams the distance matrix as [`write_phylip`](Self::write_phylip)
+    /// does, filling each block of rows in parallel.
+    ///
+    /// # Errors
+    ///
+    /// Any error from `writer`.
+    pub fn write_phylip_par<W: Write>(
+        &self,
+        metric: SetMetric,
+        layout: PhylipLayout,
+        writer: W,
+    ) -> io::Result<()> {
+        self.write_blocks(layout, writer, |rows| {
+            rows.into_par_iter().map(|i| self.row(i, metric)).collect()
+        })
+    }
+
+    #[cfg(feature = "simple_rooted_tree")]
+    /// Greedy majority-rule consensus of the trees at positions `members`.
+    ///
+    /// Splits held by more than a `threshold` fraction of the members are
+    /// taken by decreasing frequency, each kept if compatible with those
+    /// kept before. From one half up every candidate is compatible and this
+    /// is the plain majority-rule consensus. An internal edge is as long as
+    /// its split's mean length over the members holding it, and a pendant
+    /// edge as its mean over all members. The tree is rooted where the first
+    /// taxon attaches.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `members` is empty or holds a position not below
+    /// [`len`](Self::len).
+    pub fn consensus(&self, members: &[usize], threshold: f64) -> Consensus {
+        assert!(!members.is_empty(), "a consensus needs at least one tree");
+        let count = members.len() as f64;
+        let mut held: HashMap<u32, (usize, f64)> = HashMap::default();
+        let mut pendant = vec![0.0; self.taxa.len()];
+        for &i in members {
+            let tree = &self.trees[i];
+            for &(split, length) in &tree.splits {
+                let entry = held.entry(split).or_insert((0, 0.0));
+                entry.0 += 1;
+                entry.1 += length;
+            }
+            for (mean, length) in pendant.iter_mut().zip(&tree.pendant) {
+                *mean += length / count;
+            }
+        }
+        let mut candidates = held
+            .into_iter()
+            .filter(|&(_, (k, _))| k as f64 / count > threshold)
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by_key(|&(split, (k, _))| (std::cmp::Reverse(k), split));
+        let mut kept: Vec<(&Split, usize, f64)> = vec![];
+        for (split, (k, total)) in candidates {
+            let side = &self.sides[split as usize];
+            let compatible = kept.iter().all(|&(other, ..)| {
+                let shared = side.intersection_len(other);
+                shared == 0 || shared == side.size() || shared == other.size()
+            });
+            if compatible {
+                kept.push((side, k, total / k as f64));
+            }
+        }
+        // Larger sides first, so every cluster comes after those containing
+        // it, and the last one containing it is its parent.
+        kept.sort_by_key(|&(side, ..)| std::cmp::Reverse(side.size()));
+
+        let mut tree = PhyloTree::new(0);
+        let root = tree.get_root_id();
+        let mut nodes: Vec<usize> = Vec::with_capacity(kept.len());
+        let mut support = Vec::with_capacity(kept.len());
+        for (p, &(side, k, length)) in kept.iter().enumerate() {
+            let parent = (0..p)
+                .rev()
+                .find(|&q| side.intersection_len(kept[q].0) == side.size())
+                .map_or(root, |q| nodes[q]);
+            let id = attach(&mut tree, parent, length);
+            nodes.push(id);
+            support.push((id, k as f64 / count));
+        }
+        for (t, name) in self.taxa.iter().enumerate() {
+            let parent = (0..kept.len())
+                .rev()
+                .find(|&q| kept[q].0.contains(t))
+                .map_or(root, |q| nodes[q]);
+            let id = attach(&mut tree, parent, pendant[t]);
+            tree.set_node_taxa(id, Some(name.clone()));
+        }
+        Consensus { tree, support }
+    }
+
+    #[cfg(feature = "simple_rooted_tree")]
+    /// The medoid, members and [`consensus`](Self::consensus) of every
+    /// cluster of `clustering`, which must cluster the rows of a matrix of
+    /// this set, such as one from [`labelled_matrix`](Self::labelled_matrix).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `clustering` does not assign exactly [`len`](Self::len)
+    /// rows.
+    pub fn summarize(&self, clustering: &Clustering, threshold: f64) -> Vec<ClusterSummary> {
+        assert_eq!(
+            clustering.assignment().len(),
+            self.len(),
+            "the clustering must cover every tree of the set"
+        );
+        clustering
+            .medoids()
+            .iter()
+            .enumerate()
+            .map(|(c, &medoid)| {
+                let members = clustering.members(c);
+                ClusterSummary {
+                    medoid,
+                    consensus: self.consensus(&members, threshold),
+                    members,
+                }
+            })
+            .collect()
+    }
+
+    fn write_blocks<W, F>(&self, layout: PhylipLayout, mut writer: W, mut fill: F) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(std::ops::Range<usize>) -> Vec<Vec<f64>>,
+    {
+        writeln!(writer, "{}", self.len())?;
+        let mut out = String::new();
+        for start in (0..self.len()).step_by(ROW_BLOCK) {
+            let rows = start..(start + ROW_BLOCK).min(self.len());
+            for (i, row) in rows.clone().zip(fill(rows)) {
+                let width = match layout {
+                    PhylipLayout::Square => row.len(),
+                    PhylipLayout::LowerTriangular => i,
+                };
+                out.clear();
+                push_phylip_row(&mut out, &self.labels[i], &row[..width]);
+                writer.write_all(out.as_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(feature = "simple_rooted_tree")]
+/// Adds a child below `parent` on an edge of `length`, returning its id.
+fn attach(tree: &mut PhyloTree, parent: usize, length: f64) -> usize {
+    let node = Node::new(tree.next_id());
+    let id = node.get_id();
+    tree.add_child(parent, node);
+    tree.set_edge_weight((parent, id), Some(length as f32));
+    id
+}
+
+/// The leaf taxa of `tree`, sorted.
+fn sorted_taxa<T>(tree: &T) -> Result<Vec<String>, TreeError>
+where
+    T: RootedMetaTree,
+    <T as RootedTree>::Node: RootedMetaNode,
+{
+    let mut taxa = tree
+        .get_leaf_ids()
+        .map(|leaf| {
+            tree.get_node_taxa(leaf)
+                .map(|taxon| taxon.to_string())
+                .ok_or_else(|| TreeError::UnlabelledLeaf(leaf.into()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    taxa.sort_unstable();
+    Ok(taxa)
+}
+
+/// The taxa below `node`, as a split.
+fn side_of<T, F>(tree: &T, node: TreeNodeID<T>, num_taxa: usize, taxon: F) -> Split
+where
+    T: RootedTree,
+    F: Fn(TreeNodeID<T>) -> usize,
+{
+    let mut words = vec![0u64; num_taxa.div_ceil(64)];
+    let mut len = 0;
+    let mut stack = vec![node];
+    while let Some(id) = stack.pop() {
+        match tree.is_leaf(id) {
+            true => {
+                let t = taxon(id);
+                words[t / 64] |= 1 << (t % 64);
+                len += 1;
+            }
+            false => stack.extend(tree.get_node_children_ids(id)),
+        }
+    }
+    Split::from_side(words, len, num_taxa)
+}
+
+/// Cluster affinity cost from `t1` to `t2`: for every cluster of `t1`, the
+/// smallest symmetric difference with a cluster of `t2`, summed.
+fn cluster_affinity(t1: &Topology, t2: &Topology) -> usize {
+    let mut cost = 0;
+    overlap_rows(t1, t2, |v, _, row| {
+        let size = t1.size(v);
+        cost += row
+            .iter()
+            .enumerate()
+            .map(|(c, &overlap)| size + t2.size(c) - 2 * overlap as usize)
+            .min()
+            .expect("invariant: a tree has at least one node");
+    });
+    cost
+}
+
+#[cfg(all(test, feature = "simple_rooted_tree"))]
+mod tests {
+    use super::*;
+    use crate::tree::PhyloTree;
+
+    const TREES: [&str; 4] = [
+        "((A:1,B:2):1,(C:1,(D:2,E:1):3):2);",
+        "((A:1,C:2):1,(B:1,(D:2,E:1):1):1);",
+        "(A:1,(B:1,(C:1,(D:1,E:1):1):1):1);",
+        "((D:2,E:1):1.5,(C:1,(A:1,B:2):3):1.5);",
+    ];
+
+    fn trees() -> Vec<PhyloTree> {
+        TREES
+            .iter()
+            .map(|t| PhyloTree::from_newick(t.as_bytes()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_matrices_match_pairwise_metrics() {
+        let trees = trees();
+        let set = TreeSet::from_trees(&trees).unwrap();
+        assert_eq!(set.len(), 4);
+        let rf = set.matrix(SetMetric::RobinsonFoulds);
+        let ca = set.matrix(SetMetric::ClusterAffinity);
+        for (i, a) in trees.iter().enumerate() {
+            for (j, b) in trees.iter().enumerate() {
+                assert_eq!(rf[i][j], a.rf(b) as f64, "rf ({i}, {j})");
+                assert_eq!(ca[i][j], a.ca(b) as f64, "ca ({i}, {j})");
+                assert_eq!(set.distance(i, j, SetMetric::RobinsonFoulds), rf[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_rf_merges_root_edges() {
+        let set = TreeSet::from_trees(&trees()).unwrap();
+        // Tree 3 is tree 0 rerooted on another edge: the same unrooted tree
+        // with the same lengths, once the two root edges are joined.
+        assert_eq!(set.distance(0, 3, SetMetric::WeightedRobinsonFoulds), 0.0);
+        // Tree 1 shares split DE (3 against 1) and differs in AB|CDE (3),
+        // AC|BDE (2), and the pendant edges of C (1 against 2) and B (2
+        // against 1).
+        let wrf = set.row(0, SetMetric::WeightedRobinsonFoulds);
+        assert_eq!(wrf[1], 2.0 + 3.0 + 2.0 + 1.0 + 1.0);
+        // AB|CDE, DE|ABC and AC|BDE.
+        assert_eq!(set.num_splits(), 3);
+    }
+
+    #[test]
+    fn test_streams_phylip_matching_labelled_matrix() {
+        let set = TreeSet::from_trees(&trees()).unwrap();
+        let mut out = vec![];
+        set.write_phylip(
+            SetMetric::RobinsonFoulds,
+            PhylipLayout::LowerTriangular,
+            &mut out,
+        )
+        .unwrap();
+        let matrix =
+            LabelledMatrix::new(set.labels().to_vec(), set.matrix(SetMetric::RobinsonFoulds))
+                .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            matrix.to_phylip(PhylipLayout::LowerTriangular)
+        );
+    }
+
+    #[test]
+    fn test_consensus_and_cluster_summaries() {
+        let set = TreeSet::from_trees(&trees()).unwrap();
+        // DE is in every tree, AB in all but tree 1, and AC, in tree 1 only,
+        // conflicts with AB.
+        for threshold in [0.5, 0.0] {
+            let consensus = set.consensus(&[0, 1, 2, 3], threshold);
+            let support = consensus
+                .support
+                .iter()
+                .map(|&(_, s)| s)
+                .collect::<Vec<_>>();
+            assert_eq!(support, vec![0.75, 1.0]);
+            let expected = PhyloTree::from_newick("(A,B,(C,(D,E)));".as_bytes()).unwrap();
+            let pair = TreeSet::from_trees([&consensus.tree, &expected]).unwrap();
+            assert_eq!(pair.distance(0, 1, SetMetric::RobinsonFoulds), 0.0);
+            // DE is 3 long in trees 0 and 3 (across the root), 1 in the others.
+            let (de, _) = consensus.support[1];
+            let parent = consensus.tree.get_node_parent_id(de).unwrap();
+            assert_eq!(consensus.tree.get_edge_weight(parent, de), Some(2.0));
+        }
+
+        let matrix = set.labelled_matrix(SetMetric::RobinsonFoulds);
+        let clustering = Clustering::k_medoids(&matrix, 2).unwrap();
+        let summaries = set.summarize(&clustering, 0.5);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].medoid, 0);
+        assert_eq!(summaries[0].members, vec![0, 2, 3]);
+        assert_eq!(summaries[1].members, vec![1]);
+        let lone = &summaries[1].consensus;
+        let pair = TreeSet::from_trees([&lone.tree, &trees()[1]]).unwrap();
+        assert_eq!(pair.distance(0, 1, SetMetric::WeightedRobinsonFoulds), 0.0);
+    }
+
+    #[test]
+    fn test_rejects_other_taxa() {
+        let mut set = TreeSet::new();
+        set.push(&trees()[0]).unwrap();
+        let other = PhyloTree::from_newick("((A,B),(C,F));".as_bytes()).unwrap();
+        assert_eq!(set.push(&other), Err(TreeError::TaxaSetMismatch));
+        assert_eq!(set.len(), 1);
+
+        // A leaf without a taxon is an error rather than a panic.
+        let unlabelled = PhyloTree::from_newick(b"((a,b),(c,));").unwrap();
+        assert!(matches!(
+            TreeSet::from_trees(&[unlabelled]),
+            Err(TreeError::UnlabelledLeaf(_))
+        ));
+    }
+}
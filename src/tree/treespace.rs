@@ -0,0 +1,396 @@
+//! Embedding and clustering of tree collections by their pairwise distances.
+//!
+//! Both tools work on any square
+//! [`LabelledMatrix`](crate::tree::matrix::LabelledMatrix), typically one
+//! built by [`TreeSet::labelled_matrix`](crate::tree::treeset::TreeSet::labelled_matrix).
+//!
+//! * [`Embedding::classical_mds`](crate::tree::treespace::Embedding::classical_mds)
+//!   places the trees in a few Euclidean dimensions by classical
+//!   (Torgerson) multidimensional scaling: the squared distances are
+//!   double-centered into a Gram matrix, whose leading eigenvectors, scaled
+//!   by the square roots of their eigenvalues, are the coordinates. Tree
+//!   metrics are rarely Euclidean, so some eigenvalues come out negative;
+//!   [`explained`](crate::tree::treespace::Embedding::explained) reports the
+//!   share of the positive spectrum the kept axes capture. The full
+//!   eigendecomposition costs O(t³) for `t` trees.
+//! * [`Clustering::k_medoids`](crate::tree::treespace::Clustering::k_medoids)
+//!   partitions the trees around `k` medoids with PAM (Kaufman & Rousseeuw
+//!   1990): a greedy build, then swaps of a medoid for a non-medoid while any
+//!   swap lowers the total distance to the nearest medoid. Each swap pass
+//!   costs O(k t²). Medoids are trees of the collection, so each cluster has
+//!   an actual representative;
+//!   [`TreeSet::summarize`](crate::tree::treeset::TreeSet::summarize) pairs
+//!   it with the cluster's consensus.
+//!
+//! Neither uses randomness, so results are reproducible.
+
+use std::fmt::Write;
+
+use nalgebra::{DMatrix, SymmetricEigen};
+
+use crate::error::MatrixError;
+use crate::tree::matrix::{quote_field, LabelledMatrix};
+
+/// Coordinates of the rows of a distance matrix in a few Euclidean
+/// dimensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embedding {
+    labels: Vec<String>,
+    /// One point per row, `dimensions` coordinates each.
+    coordinates: Vec<Vec<f64>>,
+    /// Every eigenvalue of the centered Gram matrix, descending.
+    eigenvalues: Vec<f64>,
+}
+
+impl Embedding {
+    /// Embeds the rows of `matrix` in `dimensions` dimensions by classical
+    /// multidimensional scaling.
+    ///
+    /// The matrix is symmetrized by averaging it with its transpose. Axes
+    /// whose eigenvalue is not positive, up to rounding, carry no variance
+    /// and get zero coordinates. Each axis is signed so that its largest coordinate in
+    /// absolute value is positive.
+    pub fn classical_mds(matrix: &LabelledMatrix, dimensions: usize) -> Self {
+        let n = matrix.len();
+        let squared = DMatrix::from_fn(n, n, |i, j| {
+            let d = (matrix.get(i, j) + matrix.get(j, i)) / 2.0;
+            -0.5 * d * d
+        });
+        let row_means = squared.row_mean();
+        let grand_mean = if n > 0 { squared.mean() } else { 0.0 };
+        let gram = DMatrix::from_fn(n, n, |i, j| {
+            squared[(i, j)] - row_means[i] - row_means[j] + grand_mean
+        });
+
+        let eigen = SymmetricEigen::new(gram);
+        let mut order = (0..n).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+
+        // Eigenvalues this small next to the largest are rounding noise.
+        let noise = order
+            .first()
+            .map_or(0.0, |&k| eigen.eigenvalues[k].abs() * 1e-12);
+        let mut coordinates = vec![vec![0.0; dimensions]; n];
+        for (axis, &k) in order.iter().take(dimensions).enumerate() {
+            let value = eigen.eigenvalues[k];
+            if value <= noise {
+                break;
+            }
+            let vector = eigen.eigenvectors.column(k);
+            let pivot = vector
+                .iter()
+                .fold(0.0f64, |acc, &x| match x.abs() > acc.abs() {
+                    true => x,
+                    false => acc,
+                });
+            let scale = value.sqrt() * pivot.signum();
+            for (point, &x) in coordinates.iter_mut().zip(vector.iter()) {
+                point[axis] = x * scale;
+            }
+        }
+        Embedding {
+            labels: matrix.labels().to_vec(),
+            coordinates,
+            eigenvalues: order.iter().map(|&k| eigen.eigenvalues[k]).collect(),
+        }
+    }
+
+    /// Row labels, in the order of the source matrix.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Number of coordinates per point.
+    pub fn dimensions(&self) -> usize {
+        self.coordinates.first().map_or(0, Vec::len)
+    }
+
+    /// One point per row of the source matrix.
+    pub fn coordinates(&self) -> &[Vec<f64>] {
+        &self.coordinates
+    }
+
+    /// Every eigenvalue of the centered Gram matrix, descending. The first
+    /// [`dimensions`](Self::dimensions) are the variances along the axes.
+    pub fn eigenvalues(&self) -> &[f64] {
+        &self.eigenvalues
+    }
+
+    /// Share of the sum of positive eigenvalues captured by the kept axes,
+    /// between 0 and 1. Zero when no eigenvalue is positive.
+    pub fn explained(&self) -> f64 {
+        let positive = |values: &[f64]| values.iter().filter(|&&x| x > 0.0).sum::<f64>();
+        let total = positive(&self.eigenvalues);
+        match total > 0.0 {
+            true => {
+                let kept = self.dimensions().min(self.eigenvalues.len());
+                positive(&self.eigenvalues[..kept]) / total
+            }
+            false => 0.0,
+        }
+    }
+
+    /// Encodes the coordinates as tab-separated text: a header row naming
+    /// the axes `axis1`, `axis2`, …, then one labelled row per point.
+    pub fn to_tsv(&self) -> String {
+        let mut out = String::from("label");
+        for axis in 1..=self.dimensions() {
+            write!(out, "\taxis{axis}").expect("writing to a String cannot fail");
+        }
+        out.push('\n');
+        for (label, point) in self.labels.iter().zip(&self.coordinates) {
+            out.push_str(&quote_field(label, '\t'));
+            for x in point {
+                write!(out, "\t{x}").expect("writing to a String cannot fail");
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// A partition of the rows of a distance matrix around medoid rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clustering {
+    /// Medoid row of each cluster, ascending.
+    medoids: Vec<usize>,
+    /// Cluster of each row.
+    assignment: Vec<usize>,
+    /// Sum of the distances from each row to its medoid.
+    cost: f64,
+}
+
+impl Clustering {
+    /// Partitions the rows of `matrix` into `k` clusters with PAM.
+    ///
+    /// Distances are read from medoid to row, so the matrix should be
+    /// symmetric. Clusters are numbered by ascending medoid row; a row at
+    /// equal distance from several medoids joins the first. Ties between
+    /// candidate medoids go to the lowest row.
+    ///
+    /// # Errors
+    ///
+    /// [`MatrixError::ClusterCount`] if `k` is zero or exceeds the number of
+    /// rows.
+    pub fn k_medoids(matrix: &LabelledMatrix, k: usize) -> Result<Self, MatrixError> {
+        let n = matrix.len();
+        if k == 0 || k > n {
+            return Err(MatrixError::ClusterCount {
+                requested: k,
+                rows: n,
+            });
+        }
+
+        // Build: start from the most central row, then add the row that most
+        // reduces the total distance to the nearest medoid.
+        let mut medoids = vec![];
+        let mut nearest = vec![f64::INFINITY; n];
+        while medoids.len() < k {
+            let gain = |o: usize| -> f64 {
+                (0..n)
+                    .map(|j| match nearest[j].is_finite() {
+                        true => (nearest[j] - matrix.get(o, j)).max(0.0),
+                        false => -matrix.get(o, j),
+                    })
+                    .sum()
+            };
+            let best = (0..n)
+                .filter(|o| !medoids.contains(o))
+                .map(|o| (o, gain(o)))
+                .fold(None, |best: Option<(usize, f64)>, (o, g)| match best {
+                    Some((_, b)) if b >= g => best,
+                    _ => Some((o, g)),
+                })
+                .map(|(o, _)| o)
+                .expect("invariant: fewer than n medoids leave a candidate");
+            for (j, d) in nearest.iter_mut().enumerate() {
+                *d = d.min(matrix.get(best, j));
+            }
+            medoids.push(best);
+        }
+
+        // Swap: apply the best improving exchange until none is left. The
+        // cost strictly decreases, so this terminates.
+        loop {
+            let (first, second) = Self::nearest_two(matrix, &medoids);
+            let mut best = (0.0f64, 0, 0);
+            for (m, _) in medoids.iter().enumerate() {
+                for o in (0..n).filter(|o| !medoids.contains(o)) {
+                    let delta: f64 = (0..n)
+                        .map(|j| {
+                            let to_o = matrix.get(o, j);
+                            let (c, d) = first[j];
+                            let replaced = match c == m {
+                                true => to_o.min(second[j]),
+                                false => to_o.min(d),
+                            };
+                            replaced - d
+                        })
+                        .sum();
+                    if delta < best.0 - 1e-12 * (1.0 + best.0.abs()) {
+                        best = (delta, m, o);
+                    }
+                }
+            }
+            if best.0 >= 0.0 {
+                break;
+            }
+            medoids[best.1] = best.2;
+        }
+
+        medoids.sort_unstable();
+        let (first, _) = Self::nearest_two(matrix, &medoids);
+        Ok(Clustering {
+            assignment: first.iter().map(|&(c, _)| c).collect(),
+            cost: first.iter().map(|&(_, d)| d).sum(),
+            medoids,
+        })
+    }
+
+    /// For each row, its nearest medoid (by position in `medoids`) with the
+    /// distance to it, and the distance to the second nearest.
+    fn nearest_two(matrix: &LabelledMatrix, medoids: &[usize]) -> (Vec<(usize, f64)>, Vec<f64>) {
+        (0..matrix.len())
+            .map(|j| {
+                let mut first = (0, f64::INFINITY);
+                let mut second = f64::INFINITY;
+                for (c, &m) in medoids.iter().enumerate() {
+                    let d = matrix.get(m, j);
+                    if d < first.1 {
+                        second = first.1;
+                        first = (c, d);
+                    } else if d < second {
+                        second = d;
+                    }
+                }
+                (first, second)
+            })
+            .unzip()
+    }
+
+    /// Number of clusters.
+    pub fn len(&self) -> usize {
+        self.medoids.len()
+    }
+
+    /// Returns true if there are no clusters.
+    pub fn is_empty(&self) -> bool {
+        self.medoids.is_empty()
+    }
+
+    /// Medoid row of each cluster, ascending.
+    pub fn medoids(&self) -> &[usize] {
+        &self.medoids
+    }
+
+    /// Cluster of each row.
+    pub fn assignment(&self) -> &[usize] {
+        &self.assignment
+    }
+
+    /// Rows in cluster `c`, ascending.
+    pub fn members(&self, c: usize) -> Vec<usize> {
+        (0..self.assignment.len())
+            .filter(|&i| self.assignment[i] == c)
+            .collect()
+    }
+
+    /// Sum of the distances from each row to its medoid.
+    pub fn cost(&self) -> f64 {
+        self.cost
+    }
+
+    /// Mean silhouette width of the rows under `matrix`, between -1 and 1.
+    ///
+    /// A row's width compares its mean distance to the rest of its cluster
+    /// with its mean distance to the nearest other cluster; rows alone in
+    /// their cluster score zero. Zero for a single cluster. Useful for
+    /// choosing `k`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `matrix` has fewer rows than were clustered.
+    pub fn silhouette(&self, matrix: &LabelledMatrix) -> f64 {
+        let n = self.assignment.len();
+        if self.len() < 2 || n == 0 {
+            return 0.0;
+        }
+        let sizes = (0..self.len())
+            .map(|c| self.assignment.iter().filter(|&&a| a == c).count())
+            .collect::<Vec<_>>();
+        let total: f64 = (0..n)
+            .map(|i| {
+                let own = self.assignment[i];
+                if sizes[own] == 1 {
+                    return 0.0;
+                }
+                let mut sums = vec![0.0; self.len()];
+                for j in 0..n {
+                    sums[self.assignment[j]] += matrix.get(i, j);
+                }
+                let a = sums[own] / (sizes[own] - 1) as f64;
+                let b = (0..self.len())
+                    .filter(|&c| c != own && sizes[c] > 0)
+                    .map(|c| sums[c] / sizes[c] as f64)
+                    .fold(f64::INFINITY, f64::min);
+                match a.max(b) > 0.0 {
+                    true => (b - a) / a.max(b),
+                    false => 0.0,
+                }
+            })
+            .sum();
+        total / n as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on a line: two tight groups far apart, plus an outlier.
+    fn line() -> LabelledMatrix {
+        let xs = [0.0, 1.0, 2.0, 20.0, 21.0, 22.0, 50.0f64];
+        let labels = (0..xs.len()).map(|i| format!("t{i}")).collect();
+        LabelledMatrix::from_fn(labels, |i, j| (xs[i] - xs[j]).abs()).unwrap()
+    }
+
+    #[test]
+    fn test_mds_recovers_euclidean_points() {
+        let matrix = line();
+        let embedding = Embedding::classical_mds(&matrix, 2);
+        assert_eq!(embedding.dimensions(), 2);
+        // Points on a line are exactly one-dimensional.
+        assert!((embedding.explained() - 1.0).abs() < 1e-9);
+        assert!(embedding.eigenvalues()[1].abs() < 1e-6);
+        let points = embedding.coordinates();
+        for i in 0..matrix.len() {
+            for j in 0..matrix.len() {
+                let d = (points[i][0] - points[j][0]).abs();
+                assert!((d - matrix.get(i, j)).abs() < 1e-6, "({i}, {j})");
+            }
+            assert_eq!(points[i][1], 0.0);
+        }
+        // The outlier has the largest coordinate, made positive.
+        assert!(points[6][0] > 0.0);
+        assert!(embedding.to_tsv().starts_with("label\taxis1\taxis2\nt0\t"));
+    }
+
+    #[test]
+    fn test_k_medoids_finds_groups() {
+        let matrix = line();
+        let clustering = Clustering::k_medoids(&matrix, 3).unwrap();
+        assert_eq!(clustering.medoids(), &[1, 4, 6]);
+        assert_eq!(clustering.assignment(), &[0, 0, 0, 1, 1, 1, 2]);
+        assert_eq!(clustering.members(1), vec![3, 4, 5]);
+        assert_eq!(clustering.cost(), 4.0);
+        let two = Clustering::k_medoids(&matrix, 2).unwrap();
+        assert!(clustering.silhouette(&matrix) > two.silhouette(&matrix));
+        assert_eq!(
+            Clustering::k_medoids(&matrix, 8),
+            Err(MatrixError::ClusterCount {
+                requested: 8,
+                rows: 7
+            })
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Minimal XML pull reader shared by the PhyloXML and NeXML parsers.
+//!
+//! Reads the well-formed XML those formats use: elements and attributes,
+//! character data with the predefined and numeric character references, CDATA
+//! sections, comments, processing instructions and a document type
+//! declaration, which are skipped. Namespaces are not resolved; callers match
+//! on the local part of a name. Like the Newick parser it is iterative, so
+//! nesting depth costs heap rather than stack, and every [`XmlError`] carries
+//! a byte offset.
+//!
+//! Documents arrive as bytes and are decoded by [`document_text`] as their XML
+//! declaration says: UTF-8 (the default, with US-ASCII as a subset) or
+//! ISO-8859-1. Offsets then count bytes of the decoded text.
+//!
+//! The reader hands out byte offsets with its start tags, so a caller can keep
+//! an element verbatim as the slice of the source from its start tag to the
+//! offset [`XmlReader::skip_element`] returns.
+
+use std::borrow::Cow;
+
+use crate::error::XmlError;
+
+/// One step through an XML document.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Event<'a> {
+    /// A start tag, with its attributes, and the byte offset of its `<`. An
+    /// empty element `<x/>` reads as a start tag followed by an end tag.
+    Start {
+        name: &'a str,
+        attributes: Vec<(&'a str, String)>,
+        idx: usize,
+    },
+    /// An end tag.
+    End,
+    /// Character data, with references replaced.
+    Text(String),
+}
+
+/// Pull reader over an XML document held in memory.
+pub(crate) struct XmlReader<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Names of the open elements, innermost last.
+    open: Vec<&'a str>,
+    /// Whether the last start tag was an empty element, still to be closed.
+    pending_end: bool,
+}
+
+impl<'a> XmlReader<'a> {
+    pub(crate) fn new(src: &'a str) -> Self {
+        XmlReader {
+            src,
+            pos: 0,
+            open: vec![],
+            pending_end: false,
+        }
+    }
+
+    /// The next event, or `None` at the end of the document.
+    pub(crate) fn next(&mut self) -> Result<Option<Event<'a>>, XmlError> {
+        loop {
+            if self.pending_end {
+                self.pending_end = false;
+                self.open.pop();
+                return Ok(Some(Event::End));
+            }
+            let rest = &self.src[self.pos..];
+            if rest.is_empty() {
+                return match self.open.last() {
+                    Some(name) => Err(self.malformed(format!("<{name}> is never closed"))),
+                    None => Ok(None),
+                };
+            }
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(data) = rest.strip_prefix("<![CDATA[") {
+                let len = data
+                    .find("]]>")
+                    .ok_or_else(|| self.malformed("unterminated CDATA section"))?;
+                self.pos += "<![CDATA[".len() + len + "]]>".len();
+                return Ok(Some(Event::Text(data[..len].to_string())));
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!") {
+                self.skip_declaration()?;
+            } else if let Some(tag) = rest.strip_prefix("</") {
+                let idx = self.pos;
+                let len = tag
+                    .find('>')
+                    .ok_or_else(|| self.malformed("unterminated end tag"))?;
+                let found = tag[..len].trim_end();
+                let expected = self.open.pop().ok_or_else(|| XmlError::MismatchedTag {
+                    idx,
+                    expected: String::new(),
+                    found: found.to_string(),
+                })?;
+                if found != expected {
+                    return Err(XmlError::MismatchedTag {
+                        idx,
+                        expected: expected.to_string(),
+                        found: found.to_string(),
+                    });
+                }
+                self.pos += "</".len() + len + 1;
+                return Ok(Some(Event::End));
+            } else if rest.starts_with('<') {
+                return self.start_tag().map(Some);
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                let text = decode(&rest[..len], self.pos)?;
+                if self.open.is_empty() && !text.trim().is_empty() {
+                    return Err(self.malformed("text outside the root element"));
+                }
+                self.pos += len;
+                if !self.open.is_empty() {
+                    return Ok(Some(Event::Text(text.into_owned())));
+                }
+            }
+        }
+    }
+
+    /// The next event inside an open element, which always exists in a
+    /// well-formed document.
+    pub(crate) fn next_inside(&mut self) -> Result<Event<'a>, XmlError> {
+        self.next()?
+            .ok_or_else(|| self.malformed("unexpected end of input"))
+    }
+
+    /// Reads up to the end tag of the element whose start tag was just read,
+    /// and returns the offset just past it.
+    pub(crate) fn skip_element(&mut self) -> Result<usize, XmlError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next_inside()? {
+                Event::Start { .. } => depth += 1,
+                Event::End => depth -= 1,
+                Event::Text(_) => {}
+            }
+        }
+        Ok(self.pos)
+    }
+
+    /// Reads the character data of the element whose start tag was just
+    /// read, up to its end tag, skipping any elements inside it.
+    pub(crate) fn read_text(&mut self) -> Result<String, XmlError> {
+        let mut text = String::new();
+        loop {
+            match self.next_inside()? {
+                Event::Start { .. } => {
+                    self.skip_element()?;
+                }
+                Event::End => return Ok(text),
+                Event::Text(t) => text.push_str(&t),
+            }
+        }
+    }
+
+    fn malformed(&self, reason: impl Into<String>) -> XmlError {
+        XmlError::Malformed {
+            idx: self.pos,
+            reason: reason.into(),
+        }
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), XmlError> {
+        let len = self.src[self.pos..]
+            .find(end)
+            .ok_or_else(|| self.malformed(format!("missing {end:?}")))?;
+        self.pos += len + end.len();
+        Ok(())
+    }
+
+    /// Skips a `<!DOCTYPE ...>`, including any `[...]` internal subset.
+    fn skip_declaration(&mut self) -> Result<(), XmlError> {
+        let mut bracketed = false;
+        for (at, c) in self.src[self.pos..].char_indices() {
+            match c {
+                '[' => bracketed = true,
+                ']' => bracketed = false,
+                '>' if !bracketed => {
+                    self.pos += at + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err(self.malformed("unterminated declaration"))
+    }
+
+    fn start_tag(&mut self) -> Result<Event<'a>, XmlError> {
+        let idx = self.pos;
+        self.pos += 1;
+        let name = self.read_name();
+        if name.is_empty() {
+            return Err(self.malformed("missing element name"));
+        }
+        let mut attributes = vec![];
+        loop {
+            self.skip_whitespace();
+            let rest = &self.src[self.pos..];
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                self.pending_end = true;
+                break;
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let key = self.read_name();
+            if key.is_empty() {
+                return Err(self.malformed(format!("malformed start tag <{name}>")));
+            }
+            self.skip_whitespace();
+            if !self.src[self.pos..].starts_with('=') {
+                return Err(self.malformed(format!("attribute {key} has no value")));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self.src[self.pos..]
+                .chars()
+                .next()
+                .filter(|&c| c == '"' || c == '\'')
+                .ok_or_else(|| self.malformed(format!("attribute {key} is not quoted")))?;
+            let value_at = self.pos + 1;
+            let len = self.src[value_at..]
+                .find(quote)
+                .ok_or_else(|| self.malformed(format!("attribute {key} is not closed")))?;
+            let value = decode(&self.src[value_at..value_at + len], value_at)?;
+            attributes.push((key, value.into_owned()));
+            self.pos = value_at + len + 1;
+        }
+        self.open.push(name);
+        Ok(Event::Start {
+            name,
+            attributes,
+            idx,
+        })
+    }
+
+    fn read_name(&mut self) -> &'a str {
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+}
+
+/// The text of the document in `bytes`, decoded as its XML declaration says.
+///
+/// A leading UTF-8 byte order mark is skipped. Without a declaration, or
+/// without an `encoding` in it, the document is UTF-8.
+pub(crate) fn document_text(bytes: &[u8]) -> Result<Cow<'_, str>, XmlError> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    // The declaration itself is ASCII in every encoding accepted here, so it
+    // can be read before the rest is decoded.
+    let declared = bytes
+        .strip_prefix(b"<?xml")
+        .and_then(|rest| {
+            let end = rest.windows(2).position(|w| w == b"?>")?;
+            let declaration = std::str::from_utf8(&rest[..end]).ok()?;
+            let value = declaration.split("encoding").nth(1)?.trim_start();
+            let value = value.strip_prefix('=')?.trim_start();
+            let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            let value = &value[1..];
+            Some(value[..value.find(quote)?].to_string())
+        })
+        .unwrap_or_else(|| "UTF-8".to_string());
+    match declared.to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" | "us-ascii" | "ascii" => std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|e| XmlError::Malformed {
+                idx: e.valid_up_to(),
+                reason: format!("invalid {declared}"),
+            }),
+        // Latin-1 bytes are the first 256 code points, so decoding is a map.
+        "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "latin-1" | "l1" => {
+            Ok(Cow::Owned(bytes.iter().map(|&b| char::from(b)).collect()))
+        }
+        _ => Err(XmlError::UnsupportedEncoding(declared)),
+    }
+}
+
+/// The part of a name after its namespace prefix.
+pub(crate) fn local(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// The value of the attribute with local name `key`.
+pub(crate) fn attribute<'b>(attributes: &'b [(&str, String)], key: &str) -> Option<&'b str> {
+    attributes
+        .iter()
+        .find(|(k, _)| local(k) == key)
+        .map(|(_, v)| v.as_str())
+}
+
+/// The text of the first element named `name` inside `fragment`, a
+/// well-formed piece of XML.
+pub(crate) fn first_text(fragment: &str, name: &str) -> Option<String> {
+    let mut reader = XmlReader::new(fragment);
+    while let Ok(Some(event)) = reader.next() {
+        if let Event::Start { name: found, .. } = event {
+            if local(found) == name {
+                return reader.read_text().ok();
+            }
+        }
+    }
+    None
+}
+
+/// Replaces character references in `text`, which starts at byte `idx`.
+fn decode(text: &str, idx: usize) -> Result<Cow<'_, str>, XmlError> {
+    if !text.contains('&') {
+        return Ok(Cow::Borrowed(text));
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        let malformed = || XmlError::Malformed {
+            idx: idx + (text.len() - rest.len()) + at,
+            reason: "malformed character reference".to_string(),
+        };
+        let end = rest[at..].find(';').ok_or_else(malformed)?;
+        let entity = &rest[at + 1..at + end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|d| d.parse().ok()),
+                };
+                code.and_then(char::from_u32).ok_or_else(malformed)?
+            }
+        };
+        out.push(c);
+        rest = &rest[at + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(Cow::Owned(out))
+}
+
+/// Escapes `text` for use as character data or an attribute value.
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
@@ -9,6 +9,8 @@ use itertools::Itertools;
 use phylo::node::PhyloNode;
 use phylo::prelude::*;
 use phylo::tree::PhyloTree;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::fs::{read_to_string, File};
 use std::io::Write;
 
@@ -89,6 +91,53 @@ fn distance_matrix() {
     dbg!(&matrix);
 }
 
+#[test]
+fn path_oracle_matches_root_walks() {
+    let input_str = String::from("(((A:0.5,B:1.5):1,(C:2,D):0.25):0.75,(E:1,F:3):2);");
+    let tree = PhyloTree::from_newick(input_str.as_bytes()).unwrap();
+    let oracle = tree.path_oracle();
+    let root_walk = |id: usize| -> f32 {
+        tree.node_to_root_ids(id)
+            .unwrap()
+            .filter(|&x| x != tree.get_root_id())
+            .map(|x| tree.get_edge_weight(0, x).unwrap_or(1.0))
+            .sum()
+    };
+    let node_ids = tree.get_node_ids().collect_vec();
+    for &u in &node_ids {
+        assert!((oracle.root_distance(u) - root_walk(u)).abs() < 1e-6);
+        for &v in &node_ids {
+            let lca = tree.get_lca_id(&[u, v]).unwrap();
+            let expected = root_walk(u) + root_walk(v) - 2.0 * root_walk(lca);
+            assert!((oracle.path_length(u, v) - expected).abs() < 1e-6);
+            assert_eq!(
+                oracle.path_edges(u, v),
+                oracle.depth(u) + oracle.depth(v) - 2 * oracle.depth(lca)
+            );
+            assert_eq!(
+                tree.path_distance(&oracle, u, v).unwrap(),
+                oracle.path_length(u, v)
+            );
+            let by_walks = tree.pairwise_distance(oracle.lca(), u, v).unwrap();
+            assert!((by_walks - oracle.path_length(u, v)).abs() < 1e-6);
+        }
+    }
+
+    let leaves = oracle.leaf_ids();
+    let matrix = oracle.leaf_distance_matrix();
+    assert_eq!(matrix.len(), 6);
+    let a = tree.get_taxa_node_id(&"A".to_string()).unwrap();
+    let f = tree.get_taxa_node_id(&"F".to_string()).unwrap();
+    let (i, j) = (
+        leaves.iter().position(|&x| x == a).unwrap(),
+        leaves.iter().position(|&x| x == f).unwrap(),
+    );
+    assert!((matrix[i][j] - (0.5 + 1.0 + 0.75 + 2.0 + 3.0)).abs() < 1e-6);
+    assert_eq!(matrix[i][i], 0.0);
+    #[cfg(feature = "parallel")]
+    assert_eq!(oracle.leaf_distance_matrix_par(), matrix);
+}
+
 #[test]
 fn build_small_tree() {
     let mut tree = PhyloTree::new(1);
@@ -319,6 +368,246 @@ fn newick_to_newick_annotation_writers() {
     assert_eq!(tree.to_newick().to_string(), input);
 }
 
+#[test]
+fn newick_support_labels() {
+    let input = "((A:0.1,B:0.2)95.3/98:0.05,(C,D)'x':0.1,(E,F)80:0.3)100;";
+
+    // By default internal labels are taxa, support values included.
+    let labelled = PhyloTree::from_newick(input.as_bytes()).unwrap();
+    assert_eq!(labelled.num_taxa(), 10);
+    assert!(labelled.get_taxa_node_id(&"95.3/98".to_string()).is_some());
+
+    // Read as supports, they stay out of the taxa map.
+    let tree = PhyloTree::from_newick_with_labels(
+        input.as_bytes(),
+        KeepRawAnnotations,
+        InternalLabels::Support,
+    )
+    .unwrap();
+    assert_eq!(tree.num_taxa(), 7);
+    assert!(tree.get_taxa_node_id(&"95.3/98".to_string()).is_none());
+    let a = tree.get_taxa_node_id(&"A".to_string()).unwrap();
+    let ab = tree.get_node_parent_id(a).unwrap();
+    assert_eq!(tree.get_node_support(ab), Some(&[95.3, 98.0][..]));
+    assert_eq!(
+        tree.get_node_support(tree.get_root_id()),
+        Some(&[100.0][..])
+    );
+    // A quoted label is a name, not a support value.
+    let x = tree.get_taxa_node_id(&"x".to_string()).unwrap();
+    assert_eq!(tree.get_node_support(x), None);
+    assert_eq!(tree.get_node_support(a), None);
+
+    // The writer puts supports back, or leaves them out.
+    assert_eq!(tree.to_newick().to_string(), input.replace("'x'", "x"));
+    assert_eq!(
+        tree.to_newick_formatted(KeepRawAnnotations, NewickFormat::new().with_supports(false))
+            .to_string(),
+        "((A:0.1,B:0.2):0.05,(C,D)x:0.1,(E,F):0.3);"
+    );
+    assert_eq!(
+        tree.to_newick_formatted(
+            KeepRawAnnotations,
+            NewickFormat::new()
+                .with_precision(3)
+                .with_quoting(Quoting::Always)
+        )
+        .to_string(),
+        "(('A':0.100,'B':0.200)95.3/98:0.050,('C','D')'x':0.100,('E','F')80:0.300)100;"
+    );
+    let spaced = PhyloTree::from_newick(b"('a b',c);").unwrap();
+    let format = NewickFormat::new().with_quoting(Quoting::AsNeeded);
+    assert_eq!(
+        spaced
+            .to_newick_formatted(KeepRawAnnotations, format)
+            .to_string(),
+        "('a b',c);"
+    );
+}
+
+#[test]
+fn phyloxml_sample_round_trip() {
+    let trees = PhyloTree::from_phyloxml_file("tests/data/primates.xml".as_ref()).unwrap();
+    assert_eq!(trees.len(), 2);
+    let plain = |tree: &PhyloTree| tree.to_newick_with(DiscardAnnotations).to_string();
+    assert_eq!(
+        plain(&trees[0]),
+        "(((Homo sapiens:0.0145,Pan troglodytes:0.0231)94/0.99:0.102,Gorilla gorilla:0.0571)89:0.06,Pongo & relatives:0.1244);"
+    );
+    assert_eq!(plain(&trees[1]), "(A,B,C);");
+
+    // Elements other than names, lengths and confidences are kept verbatim.
+    let human = trees[0]
+        .get_taxa_node_id(&"Homo sapiens".to_string())
+        .unwrap();
+    let annotation = trees[0].get_node(human).unwrap().get_annotation().unwrap();
+    assert!(annotation.starts_with("<taxonomy>"));
+    assert!(annotation.contains("<common_name>human</common_name>"));
+    assert!(annotation.ends_with("</color>"));
+
+    let written = PhyloTree::to_phyloxml(&trees);
+    let reread = PhyloTree::from_phyloxml(written.as_bytes()).unwrap();
+    for (tree, copy) in trees.iter().zip(&reread) {
+        assert_eq!(tree.to_newick().to_string(), copy.to_newick().to_string());
+        assert_eq!(annotations(tree), annotations(copy));
+    }
+    assert_eq!(PhyloTree::to_phyloxml(&reread), written);
+
+    // Every kind of clade content in the sample is written back out.
+    for element in [
+        "<confidence type=\"bootstrap\">94</confidence>",
+        "<confidence type=\"probability\">0.99</confidence>",
+        "<id provider=\"ncbi\">9598</id>",
+        "<red>255</red>",
+        "<speciations>1</speciations>",
+        "<type>speciation_or_duplication</type>",
+        "<property ref=\"bold:size\" datatype=\"xsd:integer\" applies_to=\"clade\">10</property>",
+        "<name>Pongo &amp; relatives</name>",
+    ] {
+        assert!(
+            written.contains(element),
+            "{element} missing from {written}"
+        );
+    }
+}
+
+/// The annotations of `tree` in depth-first order.
+fn annotations(tree: &PhyloTree) -> Vec<Option<String>> {
+    tree.dfs(tree.get_root_id())
+        .unwrap()
+        .map(|node| node.get_annotation().map(str::to_string))
+        .collect()
+}
+
+#[test]
+fn nexml_sample_round_trip() {
+    let trees = PhyloTree::from_nexml_file("tests/data/treebase.xml".as_ref()).unwrap();
+    assert_eq!(trees.len(), 2);
+    assert_eq!(
+        trees[0].to_newick_with(DiscardAnnotations).to_string(),
+        "((Arabis alpina:0.1,Arabis hirsuta:0.125)87:0.25,Arabis nordmanniana:0.5,Tl4:0.75):0.01;"
+    );
+    assert_eq!(trees[1].to_newick().to_string(), "(A:1,B:2,C:3);");
+
+    // Metas that are not support values stay on the node below their edge.
+    let ab = trees[0]
+        .get_node_parent_id(
+            trees[0]
+                .get_taxa_node_id(&"Arabis alpina".to_string())
+                .unwrap(),
+        )
+        .unwrap();
+    let annotation = trees[0].get_node(ab).unwrap().get_annotation().unwrap();
+    assert!(annotation.contains("property=\"ot:posterior\""));
+
+    let written = PhyloTree::to_nexml(&trees);
+    let reread = PhyloTree::from_nexml(written.as_bytes()).unwrap();
+    for (tree, copy) in trees.iter().zip(&reread) {
+        assert_eq!(tree.to_newick().to_string(), copy.to_newick().to_string());
+        assert_eq!(annotations(tree), annotations(copy));
+    }
+    assert_eq!(PhyloTree::to_nexml(&reread), written);
+    assert!(written.contains("property=\"ot:posterior\""));
+    assert!(written.contains("content=\"0.98\""));
+
+    // Trees move between the two formats with taxa, lengths and supports.
+    let converted = PhyloTree::from_phyloxml(PhyloTree::to_phyloxml(&trees).as_bytes()).unwrap();
+    assert_eq!(
+        converted[0].to_newick_with(DiscardAnnotations).to_string(),
+        trees[0].to_newick_with(DiscardAnnotations).to_string()
+    );
+}
+
+#[test]
+fn xml_declared_encodings() {
+    let document = |encoding: &str, name: &[u8]| {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding='{encoding}'?>\
+             <phyloxml><phylogeny><clade><clade><name>"
+        )
+        .into_bytes();
+        xml.extend_from_slice(name);
+        xml.extend_from_slice(b"</name></clade><clade/></clade></phylogeny></phyloxml>");
+        xml
+    };
+    // Latin-1 bytes map to the code points of the same value.
+    let trees = PhyloTree::from_phyloxml(&document("ISO-8859-1", b"M\xfcller")).unwrap();
+    assert!(trees[0].get_taxa_node_id(&"Müller".to_string()).is_some());
+    let trees = PhyloTree::from_phyloxml(&document("utf-8", "Müller".as_bytes())).unwrap();
+    assert!(trees[0].get_taxa_node_id(&"Müller".to_string()).is_some());
+
+    // Latin-1 read as UTF-8 is an error, as is an encoding not supported.
+    let err = PhyloTree::from_phyloxml(&document("UTF-8", b"M\xfcller")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let err = PhyloTree::from_nexml(&document("Shift_JIS", b"A")).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("unsupported encoding \"Shift_JIS\""));
+}
+
+#[test]
+fn xml_readers_reject_malformed_input() {
+    // Not well-formed.
+    assert!(
+        PhyloTree::from_phyloxml(b"<phyloxml><phylogeny><clade></phylogeny></phyloxml>").is_err()
+    );
+    assert!(PhyloTree::from_nexml(b"<nexml><trees>").is_err());
+    // Well-formed, but without a tree.
+    assert!(PhyloTree::from_phyloxml(b"<phyloxml/>").is_err());
+    // A bad branch length.
+    assert!(PhyloTree::from_phyloxml(
+        b"<phyloxml><phylogeny><clade branch_length=\"x\"/></phylogeny></phyloxml>"
+    )
+    .is_err());
+    // An edge to a node that does not exist, and a node with two parents.
+    let tree = |edges: &str| {
+        format!(
+            "<nexml><trees><tree><node id=\"a\"/><node id=\"b\"/><node id=\"c\"/>{edges}</tree></trees></nexml>"
+        )
+    };
+    assert!(PhyloTree::from_nexml(tree("<edge source=\"a\" target=\"z\"/>").as_bytes()).is_err());
+    assert!(PhyloTree::from_nexml(
+        tree("<edge source=\"a\" target=\"c\"/><edge source=\"b\" target=\"c\"/>").as_bytes()
+    )
+    .is_err());
+    assert!(PhyloTree::from_nexml(
+        tree("<edge source=\"a\" target=\"b\"/><edge source=\"a\" target=\"c\"/>").as_bytes()
+    )
+    .is_ok());
+}
+
+#[test]
+fn collapse_below_support() {
+    let input = "((A,B)95/60:0.1,((C,D)40/99,E)80,(F,G)70/85)100;";
+    let read = || {
+        PhyloTree::from_newick_with_labels(
+            input.as_bytes(),
+            DiscardAnnotations,
+            InternalLabels::Support,
+        )
+        .unwrap()
+    };
+
+    let mut tree = read();
+    assert_eq!(tree.collapse_below_support(75.0, 0), 2);
+    assert_eq!(
+        tree.to_newick().to_string(),
+        "((A,B)95/60:0.1,(C,D,E)80,F,G)100;"
+    );
+
+    // The second value decides here; the root's single value is left alone.
+    let mut tree = read();
+    assert_eq!(tree.collapse_below_support(75.0, 1), 1);
+    assert_eq!(
+        tree.to_newick().to_string(),
+        "(A,B,((C,D)40/99,E)80,(F,G)70/85)100;"
+    );
+
+    let mut tree = read();
+    assert_eq!(tree.collapse_below_support(0.0, 0), 0);
+    assert_eq!(tree.to_newick().to_string(), input);
+}
+
 #[test]
 fn newick_deep_tree_does_not_overflow_stack() {
     // A pectinate tree nested `n` deep would overflow a recursive parser or
@@ -785,6 +1074,100 @@ fn cophenetic_dist_par() {
     assert_eq!(t1.cophen_dist_par(&t2, 1).unwrap(), 4_f32);
 }
 
+#[test]
+fn bhv_distance() {
+    let parse = |newick: &str| PhyloTree::from_newick(newick.as_bytes()).unwrap();
+    // One orthant: Euclidean distance between the edge lengths.
+    let t1 = parse("((A:1,B:1):2,C:1);");
+    let t2 = parse("((A:1,B:2):1,C:3);");
+    assert!((t1.bhv_dist(&t2).unwrap() - 6_f64.sqrt()).abs() < 1e-9);
+    // One conflicting edge each: the path runs through the star tree.
+    let t3 = parse("((A:1,C:1):3,B:1);");
+    assert!((t1.bhv_dist(&t3).unwrap() - 5.0).abs() < 1e-9);
+    assert_eq!(
+        t1.bhv_dist(&parse("((A:1,B:1):1,D:1);")),
+        Err(TreeError::TaxaSetMismatch)
+    );
+
+    // AB and ABC against CD and BCD: the geodesic passes through the tree
+    // with AB and CD, shorter than the cone path of length 2 sqrt(10).
+    let t4 = parse("(((A:1,B:1):3,C:1):1,D:1);");
+    let t5 = parse("(A:1,(B:1,(C:1,D:1):3):1);");
+    assert!((t4.bhv_dist(&t5).unwrap() - 32_f64.sqrt()).abs() < 1e-9);
+    let path = t4
+        .to_bhv()
+        .unwrap()
+        .geodesic(&t5.to_bhv().unwrap())
+        .unwrap();
+    let middle = path.point_at(0.5);
+    assert_eq!(
+        middle.edges().collect_vec(),
+        vec![(vec!["A", "B"], 1.0), (vec!["C", "D"], 1.0)]
+    );
+    let rebuilt = BhvTree::from_tree(&middle.to_tree()).unwrap();
+    assert_eq!(rebuilt, middle);
+}
+
+#[test]
+fn bhv_geodesic_is_consistent() {
+    // Random coalescent topologies with random branch lengths, drawn from a
+    // seeded generator so that a failure can be reproduced.
+    let mut rng = StdRng::seed_from_u64(36);
+    let mut random_tree = || {
+        let mut clades = (0..9).map(|i| format!("T{i}")).collect_vec();
+        while clades.len() > 1 {
+            let a = clades.swap_remove(rng.gen_range(0..clades.len()));
+            let b = clades.swap_remove(rng.gen_range(0..clades.len()));
+            let (wa, wb): (f32, f32) = (rng.gen_range(0.1..2.0), rng.gen_range(0.1..2.0));
+            clades.push(format!("({a}:{wa},{b}:{wb})"));
+        }
+        PhyloTree::from_newick(format!("{};", clades[0]).as_bytes())
+            .unwrap()
+            .to_bhv()
+            .unwrap()
+    };
+    for _ in 0..20 {
+        let (a, b, c) = (random_tree(), random_tree(), random_tree());
+        let d = a.distance(&b).unwrap();
+        assert!((d - b.distance(&a).unwrap()).abs() < 1e-9);
+        assert!(d <= a.distance(&c).unwrap() + c.distance(&b).unwrap() + 1e-9);
+        // Points along the geodesic split its length.
+        let path = a.geodesic(&b).unwrap();
+        for t in [0.0, 0.25, 0.6, 1.0] {
+            let point = path.point_at(t);
+            assert!((a.distance(&point).unwrap() - t * d).abs() < 1e-9);
+            assert!((point.distance(&b).unwrap() - (1.0 - t) * d).abs() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn frechet_mean_and_median() {
+    let bhv = |newick: &str| {
+        PhyloTree::from_newick(newick.as_bytes())
+            .unwrap()
+            .to_bhv()
+            .unwrap()
+    };
+    let flat = [
+        "((A:1,B:1):1,C:1);",
+        "((A:1,B:1):2,C:1);",
+        "((A:1,B:1):9,C:1);",
+    ]
+    .map(bhv);
+    let mean = Frechet::new().mean(&flat).unwrap();
+    assert!((mean.edges().next().unwrap().1 - 4.0).abs() < 1e-9);
+    let median = Frechet::new().median(&flat).unwrap();
+    assert!((median.edges().next().unwrap().1 - 2.0).abs() < 0.1);
+
+    // Two conflicting trees: the mean is the midpoint of their geodesic.
+    let pair = ["((A:1,B:1):2,C:1);", "((A:1,C:1):3,B:1);"].map(bhv);
+    let mean = Frechet::new().mean(&pair).unwrap();
+    for tree in &pair {
+        assert!((mean.distance(tree).unwrap() - 2.5).abs() < 0.05);
+    }
+}
+
 // ── OLA tests ──────────────────────────────────────────────────────────────
 
 /// Helper: parse a Newick string, call to_vec, return (taxa, indices).